@@ -0,0 +1,203 @@
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequestParts, Query, State},
+    http::{header, request::Parts, StatusCode},
+    response::Response,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    io::{Read, Write},
+};
+
+use crate::{
+    artifacts::{Artifact, Delivery},
+    credentials::Credential,
+    error::ApiError,
+    flags::Flag,
+    futures::FuturesSpec,
+    jobs::Job,
+    locale::Locale,
+    portfolios::Portfolio,
+    storage::Bar,
+    templates::ReportTemplate,
+    version::{self, Engine},
+    watchlists::Watchlist,
+    AppState,
+};
+
+const ARCHIVE_VERSION: u32 = 1;
+
+/// Guard for admin endpoints: requires `Authorization: Bearer $ADMIN_TOKEN`.
+pub struct Admin;
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Admin {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Ok(expected) = env::var("ADMIN_TOKEN") else {
+            return Err(ApiError::new(StatusCode::FORBIDDEN, "admin endpoints are disabled (ADMIN_TOKEN not set)"));
+        };
+        let supplied = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        match supplied {
+            Some(token) if token_matches(token, &expected) => Ok(Admin),
+            _ => Err(ApiError::new(StatusCode::UNAUTHORIZED, "invalid admin token")),
+        }
+    }
+}
+
+// Compares in constant time so response timing doesn't leak how much of the
+// token a guess got right
+#[allow(deprecated)]
+fn token_matches(supplied: &str, expected: &str) -> bool {
+    ring::constant_time::verify_slices_are_equal(supplied.as_bytes(), expected.as_bytes()).is_ok()
+}
+
+// Disaster-recovery archive; new stores add fields with serde defaults
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    exported_at: DateTime<Utc>,
+    // Engine that wrote the archive; absent in archives from older builds
+    #[serde(default)]
+    engine: Option<Engine>,
+    #[serde(default)]
+    jobs: Vec<Job>,
+    #[serde(default)]
+    prices: BTreeMap<String, Vec<Bar>>,
+    #[serde(default)]
+    watchlists: Vec<Watchlist>,
+    #[serde(default)]
+    flags: BTreeMap<String, Flag>,
+    #[serde(default)]
+    portfolios: Vec<Portfolio>,
+    #[serde(default)]
+    templates: Vec<ReportTemplate>,
+    #[serde(default)]
+    locales: HashMap<String, Locale>,
+    #[serde(default)]
+    futures: Vec<FuturesSpec>,
+    // Still sealed; importing them needs the same master keys
+    #[serde(default)]
+    credentials: Vec<Credential>,
+}
+
+fn internal(e: impl ToString) -> ApiError {
+    ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+// Query for /api/admin/export
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    delivery: Delivery,
+}
+
+/// Export all persisted state as a gzipped JSON archive, or a link to it
+/// once stored when it is large
+pub async fn export_handler(
+    _: Admin,
+    State(state): State<AppState>,
+    Query(q): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    let mut prices = BTreeMap::new();
+    for ticker in state.prices.tickers().await.map_err(internal)? {
+        let bars = state.prices.range(&ticker, None, None).await.map_err(internal)?;
+        prices.insert(ticker, bars);
+    }
+    let snapshot = Snapshot {
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now(),
+        engine: Some(version::current()),
+        jobs: state.jobs.all(),
+        prices,
+        watchlists: state.watchlists.all(),
+        flags: state.flags.all(),
+        portfolios: state.portfolios.all(),
+        templates: state.templates.all(),
+        locales: state.locales.all(),
+        futures: state.futures.all(),
+        credentials: state.credentials.all(),
+    };
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    serde_json::to_writer(&mut encoder, &snapshot).map_err(internal)?;
+    let archive = encoder.flush().and_then(|_| encoder.finish()).map_err(internal)?;
+    println!(
+        "📦 Exported snapshot: {} jobs, {} tickers, {} bytes",
+        snapshot.jobs.len(),
+        snapshot.prices.len(),
+        archive.len()
+    );
+
+    let filename = format!("riskvar-snapshot-{}.json.gz", snapshot.exported_at.format("%Y%m%dT%H%M%S"));
+    state.artifacts.deliver(q.delivery, Artifact::new(filename, "application/gzip", archive), Vec::new()).await
+}
+
+/// Import an archive produced by the export endpoint
+pub async fn import_handler(_: Admin, State(state): State<AppState>, body: Bytes) -> Result<Json<Value>, ApiError> {
+    let mut json = Vec::new();
+    GzDecoder::new(&body[..])
+        .read_to_end(&mut json)
+        .map_err(|e| ApiError::bad_request(format!("not a gzip archive: {e}")))?;
+    let snapshot: Snapshot =
+        serde_json::from_slice(&json).map_err(|e| ApiError::bad_request(format!("invalid snapshot: {e}")))?;
+    if snapshot.version > ARCHIVE_VERSION {
+        return Err(ApiError::bad_request(format!("unsupported snapshot version {}", snapshot.version)));
+    }
+
+    let jobs = snapshot.jobs.len();
+    for job in snapshot.jobs {
+        state.jobs.restore(job);
+    }
+    let watchlists = snapshot.watchlists.len();
+    for list in snapshot.watchlists {
+        state.watchlists.restore(list);
+    }
+    let portfolios = snapshot.portfolios.len();
+    for book in snapshot.portfolios {
+        state.portfolios.restore(book);
+    }
+    let templates = snapshot.templates.len();
+    for template in snapshot.templates {
+        state.templates.restore(template);
+    }
+    for (tenant, locale) in snapshot.locales {
+        state.locales.set(&tenant, locale);
+    }
+    let futures = snapshot.futures.len();
+    for spec in snapshot.futures {
+        state.futures.restore(spec);
+    }
+    let credentials = snapshot.credentials.len();
+    for credential in snapshot.credentials {
+        state.credentials.restore(credential);
+    }
+    let flags = snapshot.flags.len();
+    for (name, flag) in snapshot.flags {
+        state.flags.set(&name, flag);
+    }
+    let mut bars = 0;
+    for (ticker, series) in &snapshot.prices {
+        bars += state.prices.insert(ticker, series).await.map_err(internal)?;
+        state.rolling.ingest(&*state.prices, ticker, series).await;
+    }
+    println!(
+        "📦 Imported snapshot from {}: {} jobs, {} bars, {} watchlists",
+        snapshot.exported_at, jobs, bars, watchlists
+    );
+    Ok(Json(json!({
+        "imported": { "jobs": jobs, "tickers": snapshot.prices.len(), "bars": bars, "watchlists": watchlists, "flags": flags, "portfolios": portfolios, "templates": templates, "futures": futures, "credentials": credentials },
+        "archive_engine": snapshot.engine,
+    })))
+}
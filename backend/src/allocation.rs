@@ -0,0 +1,259 @@
+use axum::{extract::State, Json};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    budget, cancel,
+    covariance::{correlation_matrix, covariance_matrix, portfolio_variance},
+    error::{ApiError, VarError},
+    report::aligned_returns,
+    sanity,
+    tenant::Tenant,
+    validation::{self, cross_field, Valid},
+    var::{compute_var_es, portfolio_montecarlo, MethodParams},
+    version, AppState,
+};
+
+// Payload for /api/compute_portfolio_var: tickers and their weights, with
+// the return series either supplied or loaded like /api/fetch_returns
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "check_allocation"))]
+pub struct AllocationRequest {
+    #[validate(length(min = 1, message = "tickers must not be empty"))]
+    tickers: Vec<String>,
+    // Fraction of the portfolio's value in each ticker, in the same order;
+    // negative for shorts
+    #[validate(custom(function = "validation::finite"))]
+    weights: Vec<f64>,
+    // One return series per ticker, aligned and oldest first; the tickers
+    // then only label them
+    #[serde(default)]
+    returns: Option<Vec<Vec<f64>>>,
+    #[serde(default = "default_method")]
+    #[validate(custom(function = "validation::known_method"))]
+    method: String,
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+    #[serde(default = "default_horizon")]
+    #[validate(range(min = 1, message = "horizon_days must be at least 1"))]
+    horizon_days: u32,
+    // Portfolio market value; adds the VaR and ES in money terms
+    #[serde(default)]
+    #[validate(range(exclusive_min = 0.0, message = "value must be positive"))]
+    value: Option<f64>,
+    // Most recent aligned returns to use (default: all)
+    #[serde(default)]
+    #[validate(range(min = 2, message = "window must be at least 2"))]
+    window: Option<usize>,
+    // Tuning of the VaR method, as for compute_var
+    #[serde(default)]
+    #[validate(nested)]
+    params: MethodParams,
+}
+
+fn default_method() -> String {
+    "historical".into()
+}
+
+fn default_horizon() -> u32 {
+    1
+}
+
+fn check_allocation(req: &AllocationRequest) -> Result<(), ValidationError> {
+    if req.weights.len() != req.tickers.len() {
+        return Err(cross_field(
+            "weights",
+            format!("expected {} weights (one per ticker), got {}", req.tickers.len(), req.weights.len()),
+        ));
+    }
+    if req.weights.iter().all(|w| *w == 0.0) {
+        return Err(cross_field("weights", "weights must not all be zero".into()));
+    }
+    if req.method == "montecarlo" {
+        // The series and their covariance, then one portfolio return per path
+        let k = req.tickers.len();
+        let fixed = k * k + 2 * req.returns.as_ref().map_or(0, |r| r.iter().map(Vec::len).sum());
+        let paths = req.params.mc_paths();
+        let advice = format!("lower params.n_sims to at most {}, or use method historical", budget::fits(fixed, 1));
+        budget::check(fixed + paths, "params", advice)?;
+    }
+    let Some(returns) = &req.returns else { return Ok(()) };
+    if returns.len() != req.tickers.len() {
+        return Err(cross_field(
+            "returns",
+            format!("expected {} series (one per ticker), got {}", req.tickers.len(), returns.len()),
+        ));
+    }
+    let n = returns[0].len();
+    if n < 2 || returns.iter().any(|r| r.len() != n) {
+        return Err(cross_field("returns", "series must all have the same length, at least 2".into()));
+    }
+    if returns.iter().any(|r| validation::finite(r).is_err()) {
+        return Err(cross_field("returns", "returns must be finite".into()));
+    }
+    Ok(())
+}
+
+/// VaR of a weighted multi-asset portfolio. The series are aligned on common
+/// dates and combined into the portfolio's return, r_p = Σ wᵢ rᵢ, which the
+/// chosen method then runs on; the covariance matrix and the portfolio
+/// volatility √(wᵀΣw) are reported alongside, with each asset's standalone
+/// VaR for comparison.
+pub async fn compute_portfolio_var_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Valid(req): Valid<AllocationRequest>,
+) -> Result<Json<Value>, ApiError> {
+    state.flags.check_method(&req.method, &tenant)?;
+    let tickers: Vec<String> = req.tickers.iter().map(|t| t.trim().to_uppercase()).collect();
+    let (dates, mut columns) = match &req.returns {
+        Some(returns) => (None, returns.clone()),
+        None => {
+            let (dates, columns) = aligned_returns(&state, &tenant, &tickers).await?;
+            (Some(dates), columns)
+        }
+    };
+    let total = columns[0].len();
+    let first = req.window.map_or(0, |w| total.saturating_sub(w));
+    if total - first < 2 {
+        return Err(ApiError::bad_request(format!(
+            "only {total} returns are common to every ticker; need at least 2"
+        )));
+    }
+    for column in &mut columns {
+        column.drain(..first);
+    }
+    let n = total - first;
+    req.params.check_weights(n).map_err(|e| ApiError::bad_request(e.message.unwrap_or_default()))?;
+
+    let cov = covariance_matrix(&columns, req.params.summation());
+    let portfolio: Vec<f64> =
+        (0..n).map(|t| req.weights.iter().zip(&columns).map(|(w, c)| w * c[t]).sum()).collect();
+    // Square-root-of-time rule, as for compute_var
+    let scale = f64::from(req.horizon_days).sqrt();
+    // Monte Carlo draws the assets jointly rather than the aggregate
+    let (var, es) = match req.method.as_str() {
+        "montecarlo" => {
+            let (columns, weights, params) = (columns.clone(), req.weights.clone(), req.params.clone());
+            let confidence = req.confidence;
+            let cancel = params.cancel.clone();
+            cancel::spawn_blocking(&cancel, move || portfolio_montecarlo(&columns, &weights, confidence, &params))
+                .await
+                .map_err(|_| ApiError::aborted("simulation"))??
+        }
+        method => compute_var_es(method, &mut portfolio.clone(), req.confidence, &req.params)?,
+    };
+    let (var, es) = (var * scale, es * scale);
+
+    // Euler allocation along the covariance: ∂VaR/∂wᵢ = VaR·(Σw)ᵢ/σ²ₚ when
+    // VaR scales with σₚ, so the components wᵢ·∂VaR/∂wᵢ sum to the VaR
+    let variance = portfolio_variance(&req.weights, &cov);
+    let marginal: Vec<f64> = cov
+        .iter()
+        .map(|row| {
+            let sigma_w: f64 = row.iter().zip(&req.weights).map(|(c, w)| c * w).sum();
+            if variance > 0.0 {
+                var * sigma_w / variance
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    let assets = tickers
+        .iter()
+        .zip(&req.weights)
+        .zip(&columns)
+        .enumerate()
+        .map(|(i, ((ticker, weight), column))| {
+            let component = weight * marginal[i];
+            let standalone = compute_var_es(&req.method, &mut column.clone(), req.confidence, &req.params)?.0;
+            let mut asset = json!({
+                "ticker": ticker,
+                "weight": weight,
+                "volatility": cov[i][i].sqrt(),
+                "standalone_var": standalone * scale,
+                "marginal_var": marginal[i],
+                "component_var": component,
+                "contribution": if var != 0.0 { component / var } else { 0.0 },
+            });
+            if let Some(value) = req.value {
+                asset["component_var_amount"] = json!(component * value);
+            }
+            Ok(asset)
+        })
+        .collect::<Result<Vec<Value>, VarError>>()?;
+
+    // Subadditivity: the book's risk against the sum of its components'
+    // (each position's P&L wᵢrᵢ on its own), for VaR and ES
+    let components = req
+        .weights
+        .iter()
+        .zip(&columns)
+        .map(|(w, column)| {
+            let mut pnl: Vec<f64> = column.iter().map(|r| w * r).collect();
+            let (var, es) = compute_var_es(&req.method, &mut pnl, req.confidence, &req.params)?;
+            Ok((var * scale, es * scale))
+        })
+        .collect::<Result<Vec<(f64, f64)>, VarError>>()?;
+    // Beyond rounding: a measure is never exactly additive by accident
+    let exceeds = |measure: f64, parts: f64| measure > parts + 1e-12 * parts.abs();
+    let check = |measure: f64, parts: f64| {
+        json!({
+            "portfolio": measure,
+            "sum_of_components": parts,
+            "diversification": parts - measure,
+            "violated": exceeds(measure, parts),
+        })
+    };
+    let var_parts: f64 = components.iter().map(|c| c.0).sum();
+    let es_parts: f64 = components.iter().map(|c| c.1).sum();
+    let subadditivity = json!({ "var": check(var, var_parts), "es": check(es, es_parts) });
+
+    let mut warnings = Vec::new();
+    if exceeds(var, var_parts) {
+        warnings.push(format!(
+            "VaR is not subadditive on this portfolio: the book's VaR {var:.6} exceeds the sum of its components' \
+             {var_parts:.6}, so splitting it would appear to cut risk. ES, a coherent measure, never does this \
+             and is the safer basis for aggregating risk"
+        ));
+    }
+    let weight_sum: f64 = req.weights.iter().sum();
+    if (weight_sum - 1.0).abs() > 1e-6 {
+        warnings.push(format!("weights sum to {weight_sum}, not 1; VaR is a fraction of the portfolio's value"));
+    }
+    let mut body = json!({
+        "method": req.method,
+        "confidence": req.confidence,
+        "horizon_days": req.horizon_days,
+        "observations": n,
+        "var": var,
+        "es": es,
+        "portfolio_volatility": variance.sqrt(),
+        "assets": assets,
+        "tickers": tickers,
+        "covariance": cov,
+        "correlation": correlation_matrix(&cov),
+        "subadditivity": subadditivity,
+    });
+    if req.params.deterministic {
+        body["summation"] = json!("fixed_order");
+    }
+    if let Some(dates) = dates {
+        body["start"] = json!(dates[first]);
+        body["end"] = json!(dates[dates.len() - 1]);
+    }
+    if let Some(value) = req.value {
+        body["var_amount"] = json!(var * value);
+        body["es_amount"] = json!(es * value);
+    }
+    if !warnings.is_empty() {
+        body["warnings"] = json!(warnings);
+    }
+    let sanity = sanity::check(var, Some(es));
+    if !sanity.is_empty() {
+        body["sanity"] = json!(sanity);
+    }
+    body["engine"] = json!(version::current());
+    Ok(Json(body))
+}
@@ -0,0 +1,234 @@
+//! Minimal Apache Arrow IPC stream writer: one schema and one record batch of
+//! non-nullable Int64 / Float64 columns, which is all the exports need. The
+//! flatbuffer metadata is laid out front to back, each table's vtable just
+//! before it, so every offset points forward as the format requires.
+
+// Message.fbs / Schema.fbs constants
+const METADATA_V5: i16 = 4;
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_RECORD_BATCH: u8 = 3;
+const TYPE_INT: u8 = 2;
+const TYPE_FLOATING_POINT: u8 = 3;
+const PRECISION_DOUBLE: i16 = 2;
+const CONTINUATION: u32 = 0xFFFF_FFFF;
+
+pub enum Column {
+    Int64(Vec<i64>),
+    Float64(Vec<f64>),
+}
+
+impl Column {
+    fn len(&self) -> usize {
+        match self {
+            Column::Int64(v) => v.len(),
+            Column::Float64(v) => v.len(),
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Column::Int64(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Column::Float64(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+        }
+    }
+}
+
+// A flatbuffer value: inline scalars, or offsets to objects written after
+enum Value {
+    U8(u8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Table(Vec<(usize, Value)>),
+    String(String),
+    Tables(Vec<Value>),
+    // (i64, i64) structs: FieldNode and Buffer
+    Pairs(Vec<(i64, i64)>),
+}
+
+impl Value {
+    // Inline size, which is also the alignment
+    fn size(&self) -> usize {
+        match self {
+            Value::U8(_) => 1,
+            Value::I16(_) => 2,
+            Value::I32(_) => 4,
+            Value::I64(_) => 8,
+            _ => 4,
+        }
+    }
+}
+
+struct Builder {
+    buf: Vec<u8>,
+}
+
+impl Builder {
+    fn align(&mut self, to: usize, remainder: usize) {
+        while self.buf.len() % to != remainder {
+            self.buf.push(0);
+        }
+    }
+
+    fn patch_offset(&mut self, at: usize, target: usize) {
+        self.buf[at..at + 4].copy_from_slice(&((target - at) as u32).to_le_bytes());
+    }
+
+    // Writes an object, returning where offsets to it should point
+    fn object(&mut self, value: &Value) -> usize {
+        match value {
+            Value::Table(fields) => self.table(fields),
+            Value::String(s) => {
+                self.align(4, 0);
+                let at = self.buf.len();
+                self.buf.extend((s.len() as u32).to_le_bytes());
+                self.buf.extend(s.as_bytes());
+                self.buf.push(0);
+                at
+            }
+            Value::Tables(items) => {
+                self.align(4, 0);
+                let at = self.buf.len();
+                self.buf.extend((items.len() as u32).to_le_bytes());
+                let slots: Vec<usize> = items
+                    .iter()
+                    .map(|_| {
+                        self.buf.extend([0; 4]);
+                        self.buf.len() - 4
+                    })
+                    .collect();
+                for (slot, item) in slots.into_iter().zip(items) {
+                    let target = self.object(item);
+                    self.patch_offset(slot, target);
+                }
+                at
+            }
+            Value::Pairs(pairs) => {
+                // Elements 8-aligned after the 4-byte length
+                self.align(8, 4);
+                let at = self.buf.len();
+                self.buf.extend((pairs.len() as u32).to_le_bytes());
+                for (a, b) in pairs {
+                    self.buf.extend(a.to_le_bytes());
+                    self.buf.extend(b.to_le_bytes());
+                }
+                at
+            }
+            _ => unreachable!("scalars are written inline"),
+        }
+    }
+
+    fn table(&mut self, fields: &[(usize, Value)]) -> usize {
+        // Inline layout: the vtable offset, then each field at its alignment
+        let mut layout = Vec::with_capacity(fields.len());
+        let mut end: usize = 4;
+        for (_, value) in fields {
+            let size = value.size();
+            end = end.div_ceil(size) * size;
+            layout.push(end);
+            end += size;
+        }
+        let slots = fields.iter().map(|(i, _)| i + 1).max().unwrap_or(0);
+        self.align(2, 0);
+        let vtable = self.buf.len();
+        let mut entries = vec![0u16; slots];
+        for ((index, _), at) in fields.iter().zip(&layout) {
+            entries[*index] = *at as u16;
+        }
+        self.buf.extend(((4 + 2 * slots) as u16).to_le_bytes());
+        self.buf.extend((end as u16).to_le_bytes());
+        for entry in entries {
+            self.buf.extend(entry.to_le_bytes());
+        }
+        self.align(8, 0);
+        let start = self.buf.len();
+        self.buf.resize(start + end, 0);
+        self.buf[start..start + 4].copy_from_slice(&((start - vtable) as i32).to_le_bytes());
+        let mut children = Vec::new();
+        for ((_, value), at) in fields.iter().zip(&layout) {
+            let at = start + at;
+            match value {
+                Value::U8(v) => self.buf[at] = *v,
+                Value::I16(v) => self.buf[at..at + 2].copy_from_slice(&v.to_le_bytes()),
+                Value::I32(v) => self.buf[at..at + 4].copy_from_slice(&v.to_le_bytes()),
+                Value::I64(v) => self.buf[at..at + 8].copy_from_slice(&v.to_le_bytes()),
+                child => children.push((at, child)),
+            }
+        }
+        for (slot, child) in children {
+            let target = self.object(child);
+            self.patch_offset(slot, target);
+        }
+        start
+    }
+}
+
+// A Message flatbuffer: root offset, then the table
+fn message(header_type: u8, header: Value, body_length: usize) -> Vec<u8> {
+    let mut builder = Builder { buf: vec![0; 4] };
+    let root = builder.table(&[
+        (0, Value::I16(METADATA_V5)),
+        (1, Value::U8(header_type)),
+        (2, header),
+        (3, Value::I64(body_length as i64)),
+    ]);
+    builder.patch_offset(0, root);
+    builder.buf
+}
+
+// Continuation marker, padded metadata length, metadata, then the body
+fn encapsulate(out: &mut Vec<u8>, metadata: Vec<u8>, body: &[u8]) {
+    let padded = (8 + metadata.len()).div_ceil(8) * 8 - 8;
+    out.extend(CONTINUATION.to_le_bytes());
+    out.extend((padded as u32).to_le_bytes());
+    out.extend(&metadata);
+    out.resize(out.len() + padded - metadata.len(), 0);
+    out.extend(body);
+}
+
+/// An Arrow IPC stream of one record batch holding `columns`, which must
+/// all have the same length.
+pub fn stream(columns: &[(String, Column)]) -> Vec<u8> {
+    let rows = columns.first().map_or(0, |(_, c)| c.len());
+    let fields = columns
+        .iter()
+        .map(|(name, column)| {
+            let (type_type, kind) = match column {
+                Column::Int64(_) => (TYPE_INT, Value::Table(vec![(0, Value::I32(64)), (1, Value::U8(1))])),
+                Column::Float64(_) => (TYPE_FLOATING_POINT, Value::Table(vec![(0, Value::I16(PRECISION_DOUBLE))])),
+            };
+            Value::Table(vec![
+                (0, Value::String(name.clone())),
+                (1, Value::U8(0)),
+                (2, Value::U8(type_type)),
+                (3, kind),
+                (5, Value::Tables(Vec::new())),
+            ])
+        })
+        .collect();
+    let schema = Value::Table(vec![(0, Value::I16(0)), (1, Value::Tables(fields))]);
+
+    // Each column: an empty validity bitmap (no nulls) and its values
+    let mut body = Vec::new();
+    let mut buffers = Vec::new();
+    for (_, column) in columns {
+        buffers.push((body.len() as i64, 0));
+        let bytes = column.bytes();
+        buffers.push((body.len() as i64, bytes.len() as i64));
+        body.extend(bytes);
+        body.resize(body.len().div_ceil(8) * 8, 0);
+    }
+    let batch = Value::Table(vec![
+        (0, Value::I64(rows as i64)),
+        (1, Value::Pairs(columns.iter().map(|_| (rows as i64, 0)).collect())),
+        (2, Value::Pairs(buffers)),
+    ]);
+
+    let mut out = Vec::new();
+    encapsulate(&mut out, message(HEADER_SCHEMA, schema, 0), &[]);
+    encapsulate(&mut out, message(HEADER_RECORD_BATCH, batch, body.len()), &body);
+    // End of stream
+    out.extend(CONTINUATION.to_le_bytes());
+    out.extend(0u32.to_le_bytes());
+    out
+}
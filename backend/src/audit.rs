@@ -0,0 +1,398 @@
+use axum::{
+    extract::{Query, Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+};
+use tokio::sync::oneshot;
+
+use crate::{admin::Admin, cache, error::ApiError, tenant::Tenant, var::VarRequest, AppState};
+
+// prev_hash of the first record
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+const DEFAULT_LIST_LIMIT: usize = 100;
+const MAX_LIST_LIMIT: usize = 1_000;
+
+/// One audit entry. `hash` covers every other field, `prev_hash` included,
+/// so altering, dropping or reordering a record breaks every hash after it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    pub tenant: String,
+    pub action: String,
+    pub subject: String,
+    pub data: Value,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditRecord {
+    /// SHA-256 of the record's fields as a JSON array, in a fixed order.
+    fn digest(&self) -> String {
+        let canonical = json!([self.seq, self.at, self.tenant, self.action, self.subject, self.data, self.prev_hash]);
+        Sha256::digest(canonical.to_string()).iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+/// Audit data of a VaR result: digests of the request and the full result,
+/// which prove a stored copy of either unaltered, and its headline figures.
+pub fn computation(request: &VarRequest, result: &Value) -> Value {
+    json!({
+        "request_sha256": cache::digest(request),
+        "result_sha256": cache::digest(result),
+        "method": request.method,
+        "confidence": request.confidence,
+        "horizon_days": request.horizon_days,
+        "var": result["var"],
+        "es": result["es"],
+        "seed": result.get("seed"),
+        "engine": result.get("engine"),
+    })
+}
+
+/// What a handler did, for `record_requests` to log in place of its generic
+/// `request` record, so each request leaves exactly one record.
+#[derive(Clone)]
+pub struct Event {
+    pub action: &'static str,
+    pub subject: String,
+    pub data: Value,
+    // Tenant the record is filed under, when not the caller's
+    pub tenant: Option<String>,
+}
+
+impl Event {
+    pub fn new(action: &'static str, subject: impl Into<String>, data: Value) -> Self {
+        Event { action, subject: subject.into(), data, tenant: None }
+    }
+}
+
+// Head of the chain the next record links to
+struct Tail {
+    seq: u64,
+    hash: String,
+    file: Option<File>,
+}
+
+// A record waiting for the writer, and whoever waits for it to be synced
+struct Pending {
+    tenant: String,
+    action: String,
+    subject: String,
+    data: Value,
+    synced: Option<oneshot::Sender<()>>,
+}
+
+// First record that fails verification
+#[derive(Serialize)]
+pub struct Break {
+    pub line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    pub reason: String,
+}
+
+#[derive(Serialize)]
+pub struct Verification {
+    pub valid: bool,
+    pub records: usize,
+    // Last record that verified
+    pub head: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_invalid: Option<Break>,
+}
+
+/// Append-only, hash-chained audit log in a JSON-lines file. Records are
+/// only ever appended; nothing in the service rewrites or purges the file.
+/// A dedicated writer thread owns the file, so no request thread blocks on
+/// disk I/O; readers see the prefix it has synced, never a half-written line.
+pub struct AuditLog {
+    path: PathBuf,
+    queue: mpsc::Sender<Pending>,
+    // Bytes of the file that hold synced records
+    synced: Arc<AtomicU64>,
+}
+
+impl AuditLog {
+    /// Log file from `AUDIT_LOG` (default `audit.jsonl`), verified on load;
+    /// new records chain onto the last one even when an earlier break was
+    /// found, so the break stays visible to later verification.
+    pub fn from_env() -> Self {
+        let path = PathBuf::from(env::var("AUDIT_LOG").unwrap_or_else(|_| "audit.jsonl".into()));
+        let mut last = None;
+        let check = scan(&path, u64::MAX, |r| last = Some((r.seq, r.hash.clone())));
+        if let Some(b) = &check.first_invalid {
+            eprintln!("⚠️ Audit log {} fails verification at line {}: {}", path.display(), b.line, b.reason);
+            last = last_record(&path).map(|r| (r.seq, r.hash)).or(last);
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path);
+        if let Err(e) = &file {
+            eprintln!("⚠️ Cannot open audit log {}: {}", path.display(), e);
+        }
+        println!("🧾 Audit log {} holds {} records", path.display(), check.records);
+        let (seq, hash) = last.map_or((0, GENESIS.to_string()), |(seq, hash)| (seq + 1, hash));
+        let length = file.as_ref().ok().and_then(|f| f.metadata().ok()).map_or(0, |m| m.len());
+        let synced = Arc::new(AtomicU64::new(length));
+        let (queue, pending) = mpsc::channel();
+        let tail = Tail { seq, hash, file: file.ok() };
+        let progress = synced.clone();
+        thread::Builder::new()
+            .name("audit-writer".into())
+            .spawn(move || write_records(tail, pending, &progress))
+            .expect("spawning the audit writer");
+        AuditLog { path, queue, synced }
+    }
+
+    fn enqueue(&self, tenant: &str, action: &str, subject: &str, data: Value, synced: Option<oneshot::Sender<()>>) {
+        let pending = Pending {
+            tenant: tenant.to_string(),
+            action: action.to_string(),
+            subject: subject.to_string(),
+            data,
+            synced,
+        };
+        if self.queue.send(pending).is_err() {
+            eprintln!("⚠️ Audit writer stopped, dropped {} {}", action, subject);
+        }
+    }
+
+    /// Queues a record for the writer and returns at once; records keep the
+    /// order they were queued in.
+    pub fn record(&self, tenant: &str, action: &str, subject: &str, data: Value) {
+        self.enqueue(tenant, action, subject, data, None);
+    }
+
+    /// Queues a record and waits, without holding a worker thread, until it
+    /// has been synced to disk.
+    pub async fn commit(&self, tenant: &str, action: &str, subject: &str, data: Value) {
+        let (synced, done) = oneshot::channel();
+        self.enqueue(tenant, action, subject, data, Some(synced));
+        let _ = done.await;
+    }
+
+    /// Verifies the records synced so far. Appends carry on meanwhile: the
+    /// file only grows, so the synced prefix read here cannot change.
+    fn verify(&self, visit: impl FnMut(&AuditRecord)) -> Verification {
+        scan(&self.path, self.synced.load(Ordering::Acquire), visit)
+    }
+
+    fn list(&self, filter: &AuditQuery) -> Vec<AuditRecord> {
+        let limit = filter.limit.unwrap_or(DEFAULT_LIST_LIMIT).min(MAX_LIST_LIMIT);
+        let mut records = Vec::new();
+        scan(&self.path, self.synced.load(Ordering::Acquire), |r| {
+            let wanted = filter.after.is_none_or(|after| r.seq > after)
+                && filter.tenant.as_ref().is_none_or(|t| *t == r.tenant)
+                && filter.action.as_ref().is_none_or(|a| *a == r.action);
+            if wanted && records.len() < limit {
+                records.push(r.clone());
+            }
+        });
+        records
+    }
+}
+
+/// The writer thread: chains and appends whatever is queued, syncing once
+/// per batch, then tells the waiting requests. A failed write leaves the
+/// head where it was, so the next batch chains onto the last synced record.
+fn write_records(mut tail: Tail, pending: mpsc::Receiver<Pending>, synced: &AtomicU64) {
+    while let Ok(first) = pending.recv() {
+        let batch: Vec<Pending> = std::iter::once(first).chain(pending.try_iter()).collect();
+        let (mut seq, mut hash) = (tail.seq, tail.hash.clone());
+        let mut lines = Vec::new();
+        let mut waiting = Vec::new();
+        for p in batch {
+            let mut record = AuditRecord {
+                seq,
+                at: Utc::now(),
+                tenant: p.tenant,
+                action: p.action,
+                subject: p.subject,
+                data: p.data,
+                prev_hash: hash,
+                hash: String::new(),
+            };
+            record.hash = record.digest();
+            lines.extend(serde_json::to_vec(&record).unwrap_or_default());
+            lines.push(b'\n');
+            (seq, hash) = (seq + 1, record.hash);
+            waiting.extend(p.synced);
+        }
+        match tail.file.as_mut() {
+            Some(file) => match file.write_all(&lines).and_then(|_| file.sync_data()) {
+                Ok(()) => {
+                    (tail.seq, tail.hash) = (seq, hash);
+                    synced.fetch_add(lines.len() as u64, Ordering::Release);
+                }
+                Err(e) => eprintln!("⚠️ Failed to append audit records {}..{}: {}", tail.seq, seq, e),
+            },
+            None => eprintln!("⚠️ Audit log unavailable, dropped records {}..{}", tail.seq, seq),
+        }
+        for done in waiting {
+            let _ = done.send(());
+        }
+    }
+}
+
+/// The file's last readable record, whether or not the chain up to it holds.
+fn last_record(path: &PathBuf) -> Option<AuditRecord> {
+    let file = File::open(path).ok()?;
+    BufReader::new(file).lines().map_while(Result::ok).filter_map(|l| serde_json::from_str(&l).ok()).last()
+}
+
+/// Reads the first `length` bytes of the log front to back, checking each
+/// record's sequence number, link to its predecessor and hash, and hands
+/// every record that verifies to `visit`. Stops at the first break.
+fn scan(path: &PathBuf, length: u64, mut visit: impl FnMut(&AuditRecord)) -> Verification {
+    let mut check = Verification { valid: true, records: 0, head: None, first_invalid: None };
+    let Ok(file) = File::open(path) else {
+        return check;
+    };
+    let (mut seq, mut prev) = (0, GENESIS.to_string());
+    for (i, line) in BufReader::new(file.take(length)).lines().enumerate() {
+        let fail = |seq: Option<u64>, reason: &str| Break { line: i + 1, seq, reason: reason.to_string() };
+        let record = match line.map_err(|e| e.to_string()).and_then(|l| {
+            serde_json::from_str::<AuditRecord>(&l).map_err(|e| e.to_string())
+        }) {
+            Ok(record) => record,
+            Err(e) => {
+                check.first_invalid = Some(fail(None, &format!("unreadable record: {e}")));
+                break;
+            }
+        };
+        let reason = if record.seq != seq {
+            Some(format!("sequence {} where {} was expected", record.seq, seq))
+        } else if record.prev_hash != prev {
+            Some("prev_hash does not match the previous record".to_string())
+        } else if record.hash != record.digest() {
+            Some("hash does not match the record's contents".to_string())
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            check.first_invalid = Some(fail(Some(record.seq), &reason));
+            break;
+        }
+        visit(&record);
+        check.records += 1;
+        check.head = Some(json!({ "seq": record.seq, "hash": record.hash, "at": record.at }));
+        seq = record.seq + 1;
+        prev = record.hash;
+    }
+    check.valid = check.first_invalid.is_none();
+    check
+}
+
+/// Middleware recording every state-changing or computing request (any
+/// method but GET, HEAD and OPTIONS) with the caller's tenant and the
+/// response status, once it is synced. A handler that returns an `Event`
+/// has that recorded instead. Panicked requests are recorded with their 500.
+pub async fn record_requests(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().clone();
+    if matches!(method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+    let path = request.uri().path().to_string();
+    let mut response = next.run(request).await;
+    let status = response.status().as_u16();
+    let (tenant, action, subject, mut data) = match response.extensions_mut().remove::<Event>() {
+        Some(event) => (event.tenant.unwrap_or(tenant), event.action, event.subject, event.data),
+        None => (tenant, "request", path, json!({ "method": method.as_str() })),
+    };
+    data["status"] = json!(status);
+    state.audit.commit(&tenant, action, &subject, data).await;
+    response
+}
+
+// Filters for /api/admin/audit
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    // Records after this sequence number
+    #[serde(default)]
+    after: Option<u64>,
+    #[serde(default)]
+    tenant: Option<String>,
+    #[serde(default)]
+    action: Option<String>,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// Audit records, oldest first, up to the first break in the chain
+pub async fn list_audit_handler(
+    _: Admin,
+    State(state): State<AppState>,
+    Query(q): Query<AuditQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let audit = state.audit.clone();
+    let records = tokio::task::spawn_blocking(move || audit.list(&q))
+        .await
+        .map_err(|_| ApiError::aborted("audit read"))?;
+    Ok(Json(json!({ "records": records })))
+}
+
+// Hash a caller recorded earlier, e.g. a head published to another system
+#[derive(Deserialize)]
+pub struct AnchorQuery {
+    #[serde(default)]
+    anchor_seq: Option<u64>,
+    #[serde(default)]
+    anchor_hash: Option<String>,
+}
+
+/// Re-verify the whole chain, optionally against an externally kept anchor
+pub async fn verify_audit_handler(
+    _: Admin,
+    State(state): State<AppState>,
+    Query(q): Query<AnchorQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let anchor = match (q.anchor_seq, q.anchor_hash.clone()) {
+        (Some(seq), Some(hash)) => Some((seq, hash.to_lowercase())),
+        (None, None) => None,
+        _ => return Err(ApiError::bad_request("anchor_seq and anchor_hash go together")),
+    };
+    let audit = state.audit.clone();
+    let (check, found) = tokio::task::spawn_blocking(move || {
+        let mut found = None;
+        let check = audit.verify(|r| {
+            if let Some((seq, hash)) = &anchor {
+                if r.seq == *seq {
+                    found = Some((*seq, *hash == r.hash));
+                }
+            }
+        });
+        (check, found)
+    })
+    .await
+    .map_err(|_| ApiError::aborted("audit verification"))?;
+
+    let mut body = json!(check);
+    if let Some(seq) = q.anchor_seq {
+        // An anchor past the first break, or past the end, is not confirmed
+        let matches = found.is_some_and(|(_, m)| m);
+        body["valid"] = json!(check.valid && matches);
+        body["anchor"] = json!({ "seq": seq, "matches": matches, "present": found.is_some() });
+    }
+    Ok(Json(body))
+}
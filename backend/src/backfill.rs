@@ -0,0 +1,44 @@
+use serde::Serialize;
+
+use crate::{cleaning, storage::Bar};
+
+// Flag for a series extended back in time with a proxy's moves
+#[derive(Clone, Serialize)]
+pub struct Backfill {
+    pub ticker: String,
+    pub proxy: String,
+    pub own_observations: usize,
+    pub backfilled: usize,
+    pub from: String,
+    pub to: String,
+}
+
+/// Returns the ticker's own history, i.e. returns from valid closes.
+pub fn own_observations(bars: &[Bar]) -> usize {
+    cleaning::closes_by_date(bars).len().saturating_sub(1)
+}
+
+/// Prepends synthetic closes for the proxy's dates before the ticker's first
+/// close: close(d) = first close × proxy(d) / proxy(anchor), anchored on the
+/// proxy's last close on or before that date. The ticker thus inherits the
+/// proxy's returns for the missing period. `None` if the proxy has nothing
+/// to add.
+pub fn splice(ticker: &str, bars: &[Bar], proxy: &str, proxy_bars: &[Bar]) -> Option<(Vec<Bar>, Backfill)> {
+    let own = cleaning::closes_by_date(bars);
+    let proxy_closes = cleaning::closes_by_date(proxy_bars);
+    let (first_date, first_close) = own.iter().next()?;
+    let (_, anchor) = proxy_closes.range(..=first_date.clone()).next_back()?;
+    let synthetic: Vec<Bar> = proxy_closes
+        .range(..first_date.clone())
+        .map(|(date, close)| (date.clone(), first_close * close / anchor))
+        .collect();
+    let info = Backfill {
+        ticker: ticker.to_string(),
+        proxy: proxy.to_string(),
+        own_observations: own.len() - 1,
+        backfilled: synthetic.len(),
+        from: synthetic.first()?.0.clone(),
+        to: synthetic.last()?.0.clone(),
+    };
+    Some((synthetic.into_iter().chain(bars.iter().cloned()).collect(), info))
+}
@@ -0,0 +1,601 @@
+use axum::{extract::State, response::Response, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use statrs::distribution::{ChiSquared, ContinuousCDF, Normal, StudentsT};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    cancel,
+    error::{ApiError, VarError},
+    garch,
+    reduce::Summation,
+    scoring,
+    stream,
+    tenant::Tenant,
+    units::{self, Units},
+    validation::{self, cross_field, Valid},
+    var::{
+        compute_var_es, ewma_volatility, filtered_returns, higher_moments, historical_weights, mean_std, student_t_dof,
+        MethodParams,
+    },
+    version, AppState,
+};
+
+// Payload for /api/backtest
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "backtest_window_fits"))]
+pub struct BacktestRequest {
+    #[validate(custom(function = "validation::known_method"))]
+    pub method: String,
+    #[validate(custom(function = "validation::finite"))]
+    pub returns: Vec<f64>,
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    pub confidence: f64,
+    #[serde(default)]
+    pub units: Units,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub params: MethodParams,
+    // Estimation window preceding each forecast
+    #[serde(default = "default_window")]
+    #[validate(range(min = 2, message = "window must be at least 2"))]
+    pub window: usize,
+    // Histogram bins for the PIT diagnostic
+    #[serde(default = "default_pit_bins")]
+    #[validate(range(min = 2, max = 1000, message = "pit_bins must be between 2 and 1000"))]
+    pub pit_bins: usize,
+}
+
+/// The series must extend past the estimation window, whose observations
+/// any explicit weights apply to.
+fn window_fits(returns: usize, window: usize, params: &MethodParams) -> Result<(), ValidationError> {
+    if returns <= window {
+        return Err(cross_field("returns", format!("need more than window={window} returns, got {returns}")));
+    }
+    params.check_weights(window)
+}
+
+fn backtest_window_fits(req: &BacktestRequest) -> Result<(), ValidationError> {
+    window_fits(req.returns.len(), req.window, &req.params)
+}
+
+fn default_window() -> usize {
+    250
+}
+
+fn default_pit_bins() -> usize {
+    10
+}
+
+/// Out-of-sample forecasts and outcomes, one entry per forecast day.
+pub struct Backtest {
+    pub var: Vec<f64>,
+    pub es: Vec<f64>,
+    pub realized: Vec<f64>,
+    pub hits: Vec<bool>,
+}
+
+/// Rolls the estimation window through the series, forecasting each day's
+/// VaR and ES from the preceding `window` returns only.
+pub fn run_backtest(
+    method: &str,
+    returns: &[f64],
+    confidence: f64,
+    window: usize,
+    params: &MethodParams,
+) -> Result<Backtest, VarError> {
+    let mut bt = Backtest { var: Vec::new(), es: Vec::new(), realized: Vec::new(), hits: Vec::new() };
+    for t in window..returns.len() {
+        params.cancel.check()?;
+        let mut sample = returns[t - window..t].to_vec();
+        let (var, es) = compute_var_es(method, &mut sample, confidence, params)?;
+        bt.var.push(var);
+        bt.es.push(es);
+        bt.realized.push(returns[t]);
+        bt.hits.push(returns[t] < -var);
+    }
+    Ok(bt)
+}
+
+fn mean(values: &[f64]) -> f64 {
+    Summation::Sequential.sum(values) / values.len() as f64
+}
+
+impl Backtest {
+    /// Daily pinball losses of the VaR forecasts.
+    pub fn pinball_losses(&self, confidence: f64) -> Vec<f64> {
+        let alpha = 1.0 - confidence;
+        self.realized.iter().zip(&self.var).map(|(y, v)| scoring::pinball(*y, *v, alpha)).collect()
+    }
+
+    /// Daily FZ0 losses of the joint VaR/ES forecasts.
+    pub fn fz_losses(&self, confidence: f64) -> Vec<f64> {
+        let alpha = 1.0 - confidence;
+        (0..self.realized.len())
+            .map(|i| scoring::fz0(self.realized[i], self.var[i], self.es[i], alpha))
+            .collect()
+    }
+}
+
+// Kupiec's proportion-of-failures test of the breach count
+#[derive(Serialize)]
+pub struct Kupiec {
+    pub exceptions: usize,
+    pub observations: usize,
+    pub expected_rate: f64,
+    pub observed_rate: f64,
+    pub lr_stat: f64,
+    pub p_value: f64,
+    // Coverage rejected at 5%: too many breaches, or too few
+    pub rejected: bool,
+}
+
+/// Kupiec (1995) POF test: the likelihood ratio of the observed breach rate
+/// x/N against the model's 1 − confidence,
+/// LR = −2 ln[(1 − p)^(N−x) p^x] + 2 ln[(1 − x/N)^(N−x) (x/N)^x],
+/// χ² with one degree of freedom under correct coverage.
+pub fn kupiec(hits: &[bool], confidence: f64) -> Kupiec {
+    let n = hits.len() as f64;
+    let x = hits.iter().filter(|h| **h).count() as f64;
+    let p = 1.0 - confidence;
+    let observed = x / n;
+    // k ln q, taking 0 ln 0 as 0
+    let term = |k: f64, q: f64| if k == 0.0 { 0.0 } else { k * q.ln() };
+    let null = term(n - x, 1.0 - p) + term(x, p);
+    let fitted = term(n - x, 1.0 - observed) + term(x, observed);
+    let lr_stat = (2.0 * (fitted - null)).max(0.0);
+    let p_value = 1.0 - ChiSquared::new(1.0).unwrap().cdf(lr_stat);
+    Kupiec {
+        exceptions: x as usize,
+        observations: hits.len(),
+        expected_rate: p,
+        observed_rate: observed,
+        lr_stat,
+        p_value,
+        rejected: p_value < 0.05,
+    }
+}
+
+// Christoffersen's Markov independence and conditional coverage tests
+#[derive(Serialize)]
+pub struct Christoffersen {
+    // Day-to-day transitions: n01 counts a quiet day followed by a breach
+    pub n00: usize,
+    pub n01: usize,
+    pub n10: usize,
+    pub n11: usize,
+    // Breach probability after a quiet day and after a breach
+    pub pi01: f64,
+    pub pi11: f64,
+    pub lr_ind: f64,
+    pub p_value_ind: f64,
+    // Kupiec's LR plus lr_ind, χ² with two degrees of freedom
+    pub lr_cc: f64,
+    pub p_value_cc: f64,
+    // Breaches depend on yesterday's at 5%
+    pub clustered: bool,
+    // Frequency and independence jointly rejected at 5%
+    pub rejected: bool,
+}
+
+/// Christoffersen (1998): breaches as a two-state Markov chain. The
+/// independence test compares the chain's fitted transition probabilities
+/// π₀₁ and π₁₁ against a single π by likelihood ratio (χ²(1)); conditional
+/// coverage adds Kupiec's test of the overall rate (χ²(2)).
+pub fn christoffersen(hits: &[bool], confidence: f64) -> Christoffersen {
+    let mut n = [[0usize; 2]; 2];
+    for pair in hits.windows(2) {
+        n[usize::from(pair[0])][usize::from(pair[1])] += 1;
+    }
+    let [[n00, n01], [n10, n11]] = n;
+    let rate = |k: usize, total: usize| if total == 0 { 0.0 } else { k as f64 / total as f64 };
+    let (pi01, pi11, pi) = (rate(n01, n00 + n01), rate(n11, n10 + n11), rate(n01 + n11, n00 + n01 + n10 + n11));
+    // k ln q, taking 0 ln 0 as 0
+    let term = |k: usize, q: f64| if k == 0 { 0.0 } else { k as f64 * q.ln() };
+    let restricted = term(n00 + n10, 1.0 - pi) + term(n01 + n11, pi);
+    let unrestricted = term(n00, 1.0 - pi01) + term(n01, pi01) + term(n10, 1.0 - pi11) + term(n11, pi11);
+    let lr_ind = (2.0 * (unrestricted - restricted)).max(0.0);
+    let lr_cc = kupiec(hits, confidence).lr_stat + lr_ind;
+    let p_value_ind = 1.0 - ChiSquared::new(1.0).unwrap().cdf(lr_ind);
+    let p_value_cc = 1.0 - ChiSquared::new(2.0).unwrap().cdf(lr_cc);
+    Christoffersen {
+        n00,
+        n01,
+        n10,
+        n11,
+        pi01,
+        pi11,
+        lr_ind,
+        p_value_ind,
+        lr_cc,
+        p_value_cc,
+        clustered: p_value_ind < 0.05,
+        rejected: p_value_cc < 0.05,
+    }
+}
+
+// Spacing of VaR breaches and the duration-based independence test
+#[derive(Serialize)]
+pub struct Clustering {
+    pub durations: Vec<usize>,
+    pub mean_duration: Option<f64>,
+    pub expected_duration: f64,
+    pub min_duration: Option<usize>,
+    pub max_duration: Option<usize>,
+    pub weibull_shape: Option<f64>,
+    pub lr_stat: Option<f64>,
+    pub p_value: Option<f64>,
+    pub clustered: bool,
+}
+
+/// Profile log-likelihood of a Weibull(a, b) duration model at shape `b`,
+/// with the scale `a` at its closed-form MLE.
+fn weibull_loglik(durations: &[f64], b: f64) -> f64 {
+    let n = durations.len() as f64;
+    let a = (n / durations.iter().map(|d| d.powf(b)).sum::<f64>()).powf(1.0 / b);
+    durations
+        .iter()
+        .map(|d| b.ln() + b * a.ln() + (b - 1.0) * d.ln() - (a * d).powf(b))
+        .sum()
+}
+
+/// Durations between breaches and a Weibull-vs-exponential likelihood-ratio
+/// test (Christoffersen–Pelletier, ignoring censoring). Under independence the
+/// hazard is flat (shape 1); a shape below 1 means breaches bunch together.
+pub fn clustering(hits: &[bool], confidence: f64) -> Clustering {
+    let days: Vec<usize> = hits.iter().enumerate().filter(|(_, h)| **h).map(|(i, _)| i).collect();
+    let durations: Vec<usize> = days.windows(2).map(|w| w[1] - w[0]).collect();
+    let expected_duration = 1.0 / (1.0 - confidence);
+
+    let mut report = Clustering {
+        mean_duration: None,
+        expected_duration,
+        min_duration: durations.iter().min().copied(),
+        max_duration: durations.iter().max().copied(),
+        weibull_shape: None,
+        lr_stat: None,
+        p_value: None,
+        clustered: false,
+        durations,
+    };
+    if report.durations.is_empty() {
+        return report;
+    }
+    let d: Vec<f64> = report.durations.iter().map(|&x| x as f64).collect();
+    report.mean_duration = Some(d.iter().sum::<f64>() / d.len() as f64);
+    if d.len() < 2 {
+        return report;
+    }
+
+    // Golden-section search for the shape maximising the profile likelihood
+    let (mut lo, mut hi) = (0.05_f64, 5.0_f64);
+    let phi = (5.0_f64.sqrt() - 1.0) / 2.0;
+    for _ in 0..100 {
+        let x1 = hi - phi * (hi - lo);
+        let x2 = lo + phi * (hi - lo);
+        if weibull_loglik(&d, x1) < weibull_loglik(&d, x2) {
+            lo = x1;
+        } else {
+            hi = x2;
+        }
+    }
+    let shape = (lo + hi) / 2.0;
+    let lr = (2.0 * (weibull_loglik(&d, shape) - weibull_loglik(&d, 1.0))).max(0.0);
+    let p_value = 1.0 - ChiSquared::new(1.0).unwrap().cdf(lr);
+
+    report.weibull_shape = Some(shape);
+    report.lr_stat = Some(lr);
+    report.p_value = Some(p_value);
+    report.clustered = shape < 1.0 && p_value < 0.05;
+    report
+}
+
+// Standardised returns beyond which the Cornish–Fisher CDF is taken as 0 or 1
+const CF_RANGE: f64 = 10.0;
+
+/// Cornish–Fisher CDF at the standardised return `x`: Φ(z) for the z whose
+/// adjusted quantile z + (z² − 1)S/6 + (z³ − 3z)K/24 − (2z³ − 5z)S²/36 is
+/// `x`, found by bisection. Where strong skew or kurtosis fold the expansion
+/// over, this is the crossing bisection lands on.
+fn cornish_fisher_cdf(x: f64, skewness: f64, excess_kurtosis: f64) -> f64 {
+    let (s, k) = (skewness, excess_kurtosis);
+    let adjusted = |z: f64| {
+        z + (z * z - 1.0) * s / 6.0 + (z.powi(3) - 3.0 * z) * k / 24.0 - (2.0 * z.powi(3) - 5.0 * z) * s * s / 36.0
+    };
+    let (mut lo, mut hi) = (-CF_RANGE, CF_RANGE);
+    if x <= adjusted(lo) {
+        return 0.0;
+    }
+    if x >= adjusted(hi) {
+        return 1.0;
+    }
+    for _ in 0..100 {
+        let mid = 0.5 * (lo + hi);
+        if adjusted(mid) < x {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Normal::new(0.0, 1.0).unwrap().cdf(0.5 * (lo + hi))
+}
+
+/// Predictive CDF of `method` fitted on `sample`, evaluated at `y`: the
+/// empirical CDF for historical simulation and the bootstrap (whose
+/// resamples are drawn from it), the Cornish–Fisher expansion inverted for
+/// `cornish_fisher`, the fitted normal or Student-t otherwise. `None` when
+/// the fit is degenerate, e.g. a window of identical returns.
+fn predictive_cdf(method: &str, sample: &[f64], y: f64, params: &MethodParams) -> Option<f64> {
+    match method {
+        "historical" | "bootstrap" => Some(sample.iter().filter(|r| **r <= y).count() as f64 / sample.len() as f64),
+        "filtered_historical" => {
+            let scenarios = filtered_returns(sample, params).scenarios;
+            Some(scenarios.iter().filter(|r| **r <= y).count() as f64 / scenarios.len() as f64)
+        }
+        "weighted_historical" => {
+            let weights = historical_weights(sample.len(), params);
+            Some(sample.iter().zip(&weights).filter(|(r, _)| **r <= y).map(|(_, w)| w).sum())
+        }
+        "parametric" | "montecarlo" => {
+            let (mean, std) = mean_std(sample);
+            Normal::new(mean, std).ok().map(|n| n.cdf(y))
+        }
+        "ewma" => Normal::new(0.0, ewma_volatility(sample, params)).ok().map(|n| n.cdf(y)),
+        "garch" => {
+            let fit = garch::fit(sample, params);
+            Normal::new(fit.mean, fit.forecast_volatility).ok().map(|n| n.cdf(y))
+        }
+        "parametric_t" => {
+            let (mean, std) = mean_std(sample);
+            let dof = student_t_dof(sample, params);
+            StudentsT::new(mean, std * ((dof - 2.0) / dof).sqrt(), dof).ok().map(|t| t.cdf(y))
+        }
+        "cornish_fisher" => {
+            let (mean, std) = params.summation().mean_std(sample);
+            let (s, k) = higher_moments(sample, params);
+            (std > 0.0 && s.is_finite() && k.is_finite()).then(|| cornish_fisher_cdf((y - mean) / std, s, k))
+        }
+        _ => None,
+    }
+}
+
+// Probability integral transform diagnostic over the whole distribution
+#[derive(Serialize)]
+pub struct PitReport {
+    pub bins: Vec<usize>,
+    pub expected_per_bin: f64,
+    pub chi2_stat: f64,
+    pub p_value: f64,
+    pub uniform: bool,
+}
+
+/// PIT values u_t = F_t(y_t) for each out-of-sample day, binned and tested
+/// for uniformity with Pearson's chi-square (bins - 1 degrees of freedom).
+/// Calibrated forecasts give a flat histogram; a U shape means tails are too
+/// thin, a hump means they are too fat.
+pub fn pit(method: &str, returns: &[f64], window: usize, bins: usize, params: &MethodParams) -> Option<PitReport> {
+    let bins = bins.max(2);
+    let mut counts = vec![0usize; bins];
+    for t in window..returns.len() {
+        let u = predictive_cdf(method, &returns[t - window..t], returns[t], params)?;
+        counts[((u * bins as f64) as usize).min(bins - 1)] += 1;
+    }
+    let n: usize = counts.iter().sum();
+    let expected = n as f64 / bins as f64;
+    let chi2: f64 = counts.iter().map(|&c| (c as f64 - expected).powi(2) / expected).sum();
+    let p_value = 1.0 - ChiSquared::new((bins - 1) as f64).ok()?.cdf(chi2);
+    Some(PitReport {
+        bins: counts,
+        expected_per_bin: expected,
+        chi2_stat: chi2,
+        p_value,
+        uniform: p_value >= 0.05,
+    })
+}
+
+/// Rolling out-of-sample VaR backtest with breach clustering analysis
+pub async fn backtest_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Valid(mut payload): Valid<BacktestRequest>,
+) -> Result<Response, ApiError> {
+    state.flags.check_method(&payload.method, &tenant)?;
+    let (units, mut warnings) = units::normalize(&mut payload.returns, payload.units);
+    let cancel = payload.params.cancel.clone();
+    let bt = cancel::spawn_blocking(&cancel, move || {
+        let bt = run_backtest(&payload.method, &payload.returns, payload.confidence, payload.window, &payload.params)?;
+        let pit = pit(&payload.method, &payload.returns, payload.window, payload.pit_bins, &payload.params);
+        Ok::<_, VarError>((payload, bt, pit))
+    })
+    .await;
+    let (payload, bt, pit) = bt.map_err(|_| ApiError::aborted("backtest"))??;
+    if pit.is_none() {
+        warnings.push(format!(
+            "no PIT histogram: {} could not be fitted to every window (e.g. one of constant returns)",
+            payload.method
+        ));
+    }
+
+    let observations = bt.hits.len();
+    let breaches = bt.hits.iter().filter(|h| **h).count();
+    let trailer = stream::trailer([
+        ("method", json!(payload.method)),
+        ("confidence", json!(payload.confidence)),
+        ("window", json!(payload.window)),
+        ("observations", json!(observations)),
+        ("breaches", json!(breaches)),
+        ("expected_breaches", json!(observations as f64 * (1.0 - payload.confidence))),
+        ("breach_rate", json!(breaches as f64 / observations as f64)),
+        ("kupiec", json!(kupiec(&bt.hits, payload.confidence))),
+        ("christoffersen", json!(christoffersen(&bt.hits, payload.confidence))),
+        ("clustering", json!(clustering(&bt.hits, payload.confidence))),
+        ("pit", json!(pit)),
+        ("units", json!(units)),
+        ("warnings", json!(warnings)),
+        ("engine", json!(version::current())),
+    ]);
+    // Indices into `returns` of the breached days, serialised as the client
+    // reads the body
+    let window = payload.window;
+    let breach_days = bt.hits.into_iter().enumerate().filter(|(_, h)| *h).map(move |(i, _)| i + window);
+    Ok(stream::json_array_stream("breach_days", breach_days, trailer))
+}
+
+// Payload for /api/compare_models
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "compare_window_fits"))]
+pub struct CompareRequest {
+    #[validate(length(min = 1, message = "methods must not be empty"), custom(function = "validation::known_methods"))]
+    pub methods: Vec<String>,
+    #[validate(custom(function = "validation::finite"))]
+    pub returns: Vec<f64>,
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    pub confidence: f64,
+    #[serde(default)]
+    pub units: Units,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub params: MethodParams,
+    #[serde(default = "default_window")]
+    #[validate(range(min = 2, message = "window must be at least 2"))]
+    pub window: usize,
+}
+
+fn compare_window_fits(req: &CompareRequest) -> Result<(), ValidationError> {
+    window_fits(req.returns.len(), req.window, &req.params)
+}
+
+// One method's out-of-sample scores
+#[derive(Serialize)]
+struct ModelScore {
+    method: String,
+    breaches: usize,
+    breach_rate: f64,
+    kupiec_p_value: f64,
+    christoffersen_p_value: f64,
+    pinball_loss: f64,
+    fz_loss: f64,
+    rank: usize,
+    #[serde(skip)]
+    fz_losses: Vec<f64>,
+}
+
+// Diebold–Mariano comparison of two methods' FZ0 losses
+#[derive(Serialize)]
+struct PairTest {
+    a: String,
+    b: String,
+    mean_loss_diff: f64,
+    dm_stat: f64,
+    p_value: f64,
+    // Method with significantly lower loss at 5%, if any
+    better: Option<String>,
+}
+
+fn pairwise_tests(scores: &[ModelScore]) -> Vec<PairTest> {
+    let mut tests = Vec::new();
+    for (i, a) in scores.iter().enumerate() {
+        for b in &scores[i + 1..] {
+            let Some(dm) = scoring::diebold_mariano(&a.fz_losses, &b.fz_losses) else {
+                continue;
+            };
+            let better = match (dm.p_value < 0.05, dm.stat < 0.0) {
+                (true, true) => Some(a.method.clone()),
+                (true, false) => Some(b.method.clone()),
+                _ => None,
+            };
+            tests.push(PairTest {
+                a: a.method.clone(),
+                b: b.method.clone(),
+                mean_loss_diff: dm.mean_diff,
+                dm_stat: dm.stat,
+                p_value: dm.p_value,
+                better,
+            });
+        }
+    }
+    tests
+}
+
+/// Backtests several methods on the same series, ranks them by the FZ0
+/// joint VaR/ES loss (pinball loss reported alongside for VaR alone) and runs
+/// Diebold–Mariano tests between every pair
+pub async fn compare_models_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Valid(mut payload): Valid<CompareRequest>,
+) -> Result<Json<Value>, ApiError> {
+    for method in &payload.methods {
+        state.flags.check_method(method, &tenant)?;
+    }
+    let (units, warnings) = units::normalize(&mut payload.returns, payload.units);
+    let cancel = payload.params.cancel.clone();
+    let scored = cancel::spawn_blocking(&cancel, move || {
+        let mut scores: Vec<ModelScore> = payload
+            .methods
+            .iter()
+            .map(|method| {
+                let bt = run_backtest(method, &payload.returns, payload.confidence, payload.window, &payload.params)?;
+                let breaches = bt.hits.iter().filter(|h| **h).count();
+                let fz_losses = bt.fz_losses(payload.confidence);
+                Ok(ModelScore {
+                    method: method.clone(),
+                    breaches,
+                    breach_rate: breaches as f64 / bt.hits.len() as f64,
+                    kupiec_p_value: kupiec(&bt.hits, payload.confidence).p_value,
+                    christoffersen_p_value: christoffersen(&bt.hits, payload.confidence).p_value_cc,
+                    pinball_loss: mean(&bt.pinball_losses(payload.confidence)),
+                    fz_loss: mean(&fz_losses),
+                    rank: 0,
+                    fz_losses,
+                })
+            })
+            .collect::<Result<_, VarError>>()?;
+        scores.sort_by(|a, b| a.fz_loss.total_cmp(&b.fz_loss));
+        for (i, s) in scores.iter_mut().enumerate() {
+            s.rank = i + 1;
+        }
+        Ok::<_, VarError>((payload, scores))
+    })
+    .await;
+    let (payload, scores) = scored.map_err(|_| ApiError::aborted("model comparison"))??;
+
+    Ok(Json(json!({
+        "confidence": payload.confidence,
+        "window": payload.window,
+        "observations": payload.returns.len() - payload.window,
+        "ranking": "fz_loss",
+        "diebold_mariano": pairwise_tests(&scores),
+        "models": scores,
+        "units": units,
+        "warnings": warnings,
+        "engine": version::current(),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cornish_fisher_cdf_inverts_the_adjusted_quantile() {
+        for (s, k) in [(0.0, 0.0), (-0.6, 2.5), (0.4, 1.0)] {
+            for confidence in [0.9, 0.95, 0.99] {
+                let z = -Normal::new(0.0, 1.0).unwrap().inverse_cdf(confidence);
+                let adjusted = z + (z * z - 1.0) * s / 6.0 + (z.powi(3) - 3.0 * z) * k / 24.0
+                    - (2.0 * z.powi(3) - 5.0 * z) * s * s / 36.0;
+                let u = cornish_fisher_cdf(adjusted, s, k);
+                assert!((u - (1.0 - confidence)).abs() < 1e-9, "S = {s}, K = {k}, c = {confidence}: {u}");
+            }
+        }
+    }
+
+    #[test]
+    fn every_method_has_a_pit_histogram() {
+        let returns: Vec<f64> = (0..400).map(|i| 0.01 * ((i * 37 % 101) as f64 / 50.0 - 1.0)).collect();
+        let params = MethodParams::default();
+        for method in crate::var::METHODS {
+            assert!(pit(method, &returns, 250, 10, &params).is_some(), "{method}");
+        }
+    }
+}
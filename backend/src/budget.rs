@@ -0,0 +1,50 @@
+//! Memory budget per computation. Simulation requests are sized from their
+//! shape (paths × assets × horizon) when they are validated, before anything
+//! is allocated, and refused with a 422 when they would hold more than
+//! `MEMORY_BUDGET_MB`, so one oversized request can't get the process killed.
+
+use std::{env, sync::OnceLock};
+use validator::ValidationError;
+
+use crate::validation::cross_field;
+
+const DEFAULT_BUDGET_MB: usize = 512;
+const MB: usize = 1024 * 1024;
+const F64: usize = std::mem::size_of::<f64>();
+
+/// Bytes one computation may hold, from `MEMORY_BUDGET_MB` (default 512).
+pub fn limit() -> usize {
+    static LIMIT: OnceLock<usize> = OnceLock::new();
+    *LIMIT.get_or_init(|| {
+        let mb = env::var("MEMORY_BUDGET_MB").ok().and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_BUDGET_MB);
+        mb.saturating_mul(MB)
+    })
+}
+
+/// Bytes held by `values` f64s.
+pub fn bytes(values: usize) -> usize {
+    values.saturating_mul(F64)
+}
+
+/// How many units of `per_unit` f64s each fit in the budget next to `fixed`
+/// f64s: the most paths (say) a request can ask for.
+pub fn fits(fixed: usize, per_unit: usize) -> usize {
+    (limit() / F64).saturating_sub(fixed) / per_unit.max(1)
+}
+
+/// Refuses a computation holding `values` f64s beyond the budget. `field` is
+/// the input that sizes it and `advice` how to bring it within budget.
+pub fn check(values: usize, field: &'static str, advice: String) -> Result<(), ValidationError> {
+    let needed = bytes(values);
+    if needed <= limit() {
+        return Ok(());
+    }
+    Err(cross_field(
+        field,
+        format!(
+            "needs about {} MB, above the memory budget of {} MB per computation; {advice}",
+            needed.div_ceil(MB),
+            limit() / MB
+        ),
+    ))
+}
@@ -0,0 +1,325 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex, OnceLock, Weak},
+    time::{Duration, Instant},
+};
+
+use crate::{admin::Admin, error::ApiError, AppState};
+
+/// Key/value cache for fetched price data and computed results. Values are
+/// JSON strings so every backend stores the same representation.
+#[async_trait]
+pub trait Cache: Send + Sync {
+    async fn get(&self, key: &str) -> Option<String>;
+    async fn set(&self, key: &str, value: String);
+    /// `set`, with its own lifetime instead of the cache's.
+    async fn set_for(&self, key: &str, value: String, ttl: Duration);
+    /// Live entries whose key starts with `prefix`, by key.
+    async fn entries(&self, prefix: &str) -> Vec<CacheEntry>;
+    /// Drops `keys`, returning how many were present.
+    async fn evict(&self, keys: &[String]) -> usize;
+}
+
+// A cached value as listed to admins
+#[derive(Serialize)]
+pub struct CacheEntry {
+    pub key: String,
+    pub ttl_secs: u64,
+    pub bytes: usize,
+}
+
+/// Per-process cache; fine for a single instance.
+pub struct MemoryCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, String)>>,
+}
+
+impl MemoryCache {
+    pub fn new(ttl: Duration) -> Self {
+        MemoryCache { ttl, entries: Mutex::new(HashMap::new()) }
+    }
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    async fn get(&self, key: &str) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|(expires, _)| *expires > Instant::now())
+            .map(|(_, value)| value.clone())
+    }
+
+    async fn set(&self, key: &str, value: String) {
+        self.set_for(key, value, self.ttl).await
+    }
+
+    async fn set_for(&self, key: &str, value: String, ttl: Duration) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (expires, _)| *expires > now);
+        entries.insert(key.to_string(), (now + ttl, value));
+    }
+
+    async fn entries(&self, prefix: &str) -> Vec<CacheEntry> {
+        let now = Instant::now();
+        let entries = self.entries.lock().unwrap();
+        let mut live: Vec<CacheEntry> = entries
+            .iter()
+            .filter(|(key, (expires, _))| key.starts_with(prefix) && *expires > now)
+            .map(|(key, (expires, value))| CacheEntry {
+                key: key.clone(),
+                ttl_secs: (*expires - now).as_secs(),
+                bytes: value.len(),
+            })
+            .collect();
+        live.sort_by(|a, b| a.key.cmp(&b.key));
+        live
+    }
+
+    async fn evict(&self, keys: &[String]) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        keys.iter().filter(|key| entries.remove(*key).is_some()).count()
+    }
+}
+
+/// Cache shared by every API instance pointing at the same Redis.
+#[cfg(feature = "redis")]
+pub struct RedisCache {
+    ttl: Duration,
+    conn: redis::aio::MultiplexedConnection,
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl Cache for RedisCache {
+    async fn get(&self, key: &str) -> Option<String> {
+        use redis::AsyncCommands;
+        let mut conn = self.conn.clone();
+        conn.get(format!("{REDIS_PREFIX}{key}")).await.unwrap_or_else(|e| {
+            eprintln!("⚠️ Redis cache read failed: {}", e);
+            None
+        })
+    }
+
+    async fn set(&self, key: &str, value: String) {
+        self.set_for(key, value, self.ttl).await
+    }
+
+    async fn set_for(&self, key: &str, value: String, ttl: Duration) {
+        use redis::AsyncCommands;
+        let mut conn = self.conn.clone();
+        let written: redis::RedisResult<()> =
+            conn.set_ex(format!("{REDIS_PREFIX}{key}"), value, ttl.as_secs().max(1)).await;
+        if let Err(e) = written {
+            eprintln!("⚠️ Redis cache write failed: {}", e);
+        }
+    }
+
+    async fn entries(&self, prefix: &str) -> Vec<CacheEntry> {
+        use redis::AsyncCommands;
+        let mut conn = self.conn.clone();
+        // SCAN patterns are globs, so the prefix is matched literally
+        let escaped: String = prefix
+            .chars()
+            .flat_map(|c| if matches!(c, '*' | '?' | '[' | ']' | '\\') { vec!['\\', c] } else { vec![c] })
+            .collect();
+        let mut keys: Vec<String> = Vec::new();
+        match conn.scan_match::<_, String>(format!("{REDIS_PREFIX}{escaped}*")).await {
+            Ok(mut iter) => {
+                while let Some(key) = iter.next_item().await {
+                    keys.push(key);
+                }
+            }
+            Err(e) => eprintln!("⚠️ Redis cache scan failed: {}", e),
+        }
+        keys.sort();
+        let mut pipe = redis::pipe();
+        for key in &keys {
+            pipe.ttl(key).strlen(key);
+        }
+        let sizes: Vec<(i64, usize)> = match pipe.query_async::<Vec<i64>>(&mut conn).await {
+            Ok(values) => values.chunks(2).map(|v| (v[0], v[1].max(0) as usize)).collect(),
+            Err(e) => {
+                eprintln!("⚠️ Redis cache scan failed: {}", e);
+                return Vec::new();
+            }
+        };
+        keys.iter()
+            .zip(sizes)
+            // A negative TTL means the key expired between the scan and the lookup
+            .filter(|(_, (ttl, _))| *ttl >= 0)
+            .map(|(key, (ttl, bytes))| CacheEntry {
+                key: key.trim_start_matches(REDIS_PREFIX).to_string(),
+                ttl_secs: ttl as u64,
+                bytes,
+            })
+            .collect()
+    }
+
+    async fn evict(&self, keys: &[String]) -> usize {
+        use redis::AsyncCommands;
+        if keys.is_empty() {
+            return 0;
+        }
+        let mut conn = self.conn.clone();
+        let full: Vec<String> = keys.iter().map(|key| format!("{REDIS_PREFIX}{key}")).collect();
+        conn.del(full).await.unwrap_or_else(|e| {
+            eprintln!("⚠️ Redis cache eviction failed: {}", e);
+            0
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+const REDIS_PREFIX: &str = "riskvar:cache:";
+
+/// Builds the configured cache: Redis when compiled with the `redis` feature
+/// and `REDIS_URL` is set, otherwise in-memory. Entries live for
+/// `CACHE_TTL_SECS` (default 900) unless set with their own TTL.
+pub async fn from_env() -> Box<dyn Cache> {
+    let ttl = env_secs("CACHE_TTL_SECS", Duration::from_secs(DEFAULT_TTL_SECS));
+
+    #[cfg(feature = "redis")]
+    if let Ok(url) = env::var("REDIS_URL") {
+        let conn = match redis::Client::open(url.as_str()) {
+            Ok(client) => client.get_multiplexed_async_connection().await,
+            Err(e) => Err(e),
+        };
+        match conn {
+            Ok(conn) => {
+                println!("🗄️ Using Redis cache at {}", url);
+                return Box::new(RedisCache { ttl, conn });
+            }
+            Err(e) => eprintln!("⚠️ Redis cache unavailable ({}), falling back to memory", e),
+        }
+    }
+
+    Box::new(MemoryCache::new(ttl))
+}
+
+const DEFAULT_TTL_SECS: u64 = 900;
+const DEFAULT_MISS_TTL_SECS: u64 = 60;
+
+fn env_secs(key: &str, default: Duration) -> Duration {
+    env::var(key).ok().and_then(|s| s.parse().ok()).map_or(default, Duration::from_secs)
+}
+
+// Lifetimes of cached provider lookups
+#[derive(Clone, Copy)]
+pub struct PriceTtl {
+    pub prices: Duration,
+    // Lookups no provider had prices for
+    pub misses: Duration,
+}
+
+/// `PRICE_CACHE_TTL_SECS` for fetched prices (default: `CACHE_TTL_SECS`) and
+/// `PRICE_MISS_TTL_SECS` (default 60, 0 to disable) for lookups that came back
+/// empty, so unknown tickers and provider outages aren't retried on every call.
+pub fn price_ttl() -> PriceTtl {
+    static TTL: OnceLock<PriceTtl> = OnceLock::new();
+    *TTL.get_or_init(|| {
+        let shared = env_secs("CACHE_TTL_SECS", Duration::from_secs(DEFAULT_TTL_SECS));
+        PriceTtl {
+            prices: env_secs("PRICE_CACHE_TTL_SECS", shared),
+            misses: env_secs("PRICE_MISS_TTL_SECS", Duration::from_secs(DEFAULT_MISS_TTL_SECS)),
+        }
+    })
+}
+
+/// Per-key locks, so concurrent cache misses on one key make a single
+/// provider call while the others wait for its result. Per process: API
+/// instances sharing a Redis cache may each fetch once.
+#[derive(Default)]
+pub struct Flights(Mutex<HashMap<String, Weak<tokio::sync::Mutex<()>>>>);
+
+impl Flights {
+    /// Holds `key` until the guard is dropped.
+    pub async fn lock(&self, key: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = {
+            let mut flights = self.0.lock().unwrap();
+            flights.retain(|_, flight| flight.strong_count() > 0);
+            match flights.get(key).and_then(Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    let lock = Arc::new(tokio::sync::Mutex::new(()));
+                    flights.insert(key.to_string(), Arc::downgrade(&lock));
+                    lock
+                }
+            }
+        };
+        lock.lock_owned().await
+    }
+}
+
+pub async fn get_json<T: DeserializeOwned>(cache: &dyn Cache, key: &str) -> Option<T> {
+    serde_json::from_str(&cache.get(key).await?).ok()
+}
+
+pub async fn set_json<T: Serialize>(cache: &dyn Cache, key: &str, value: &T) {
+    if let Ok(json) = serde_json::to_string(value) {
+        cache.set(key, json).await;
+    }
+}
+
+pub async fn set_json_for<T: Serialize>(cache: &dyn Cache, key: &str, value: &T, ttl: Duration) {
+    if let Ok(json) = serde_json::to_string(value) {
+        cache.set_for(key, json, ttl).await;
+    }
+}
+
+/// Hex SHA-256 of a value's JSON encoding.
+pub fn digest<T: Serialize>(value: &T) -> String {
+    let json = serde_json::to_vec(value).unwrap_or_default();
+    Sha256::digest(&json).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Stable cache key for a serialisable request.
+pub fn key_for<T: Serialize>(prefix: &str, value: &T) -> String {
+    format!("{prefix}:{}", digest(value))
+}
+
+// Cache entries to list or evict: one `key`, or every key with `prefix`
+#[derive(Deserialize)]
+pub struct CacheQuery {
+    #[serde(default)]
+    key: Option<String>,
+    #[serde(default)]
+    prefix: Option<String>,
+}
+
+/// Live cache entries with their remaining TTL and size
+pub async fn list_cache_handler(
+    _: Admin,
+    State(state): State<AppState>,
+    Query(q): Query<CacheQuery>,
+) -> Json<Value> {
+    let entries = state.cache.entries(q.prefix.as_deref().unwrap_or("")).await;
+    let bytes: usize = entries.iter().map(|e| e.bytes).sum();
+    Json(json!({ "count": entries.len(), "bytes": bytes, "entries": entries }))
+}
+
+/// Evict a cache key, or every key with a prefix (an empty one clears the cache)
+pub async fn evict_cache_handler(
+    _: Admin,
+    State(state): State<AppState>,
+    Query(q): Query<CacheQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let keys = match (q.key, q.prefix) {
+        (Some(key), None) => vec![key],
+        (None, Some(prefix)) => state.cache.entries(&prefix).await.into_iter().map(|e| e.key).collect(),
+        _ => return Err(ApiError::bad_request("give either key or prefix")),
+    };
+    let evicted = state.cache.evict(&keys).await;
+    println!("🧹 Evicted {} cache entries", evicted);
+    Ok(Json(json!({ "evicted": evicted })))
+}
@@ -0,0 +1,58 @@
+//! Cooperative cancellation of long computations. Simulation and backtest
+//! loops poll a token between units of work (a chunk of paths, a resample,
+//! a backtest day) and give up with `VarError::Cancelled` once it fires, so
+//! a cancelled job or an abandoned request frees its cores promptly.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tokio::task::JoinError;
+
+use crate::error::VarError;
+
+/// Shared flag; clones observe the same cancellation.
+#[derive(Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn check(&self) -> Result<(), VarError> {
+        match self.is_cancelled() {
+            true => Err(VarError::Cancelled),
+            false => Ok(()),
+        }
+    }
+}
+
+/// `tokio::task::spawn_blocking` for work that polls `cancel`: if the caller
+/// stops awaiting it, as when axum drops a handler's future because the
+/// client went away, the token fires and the work stops at its next check.
+pub async fn spawn_blocking<T, F>(cancel: &Cancel, f: F) -> Result<T, JoinError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let mut abandoned = Abandoned(Some(cancel.clone()));
+    let outcome = tokio::task::spawn_blocking(f).await;
+    abandoned.0 = None;
+    outcome
+}
+
+// Cancels its token if dropped while still armed
+struct Abandoned(Option<Cancel>);
+
+impl Drop for Abandoned {
+    fn drop(&mut self) {
+        if let Some(cancel) = &self.0 {
+            cancel.cancel();
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use validator::Validate;
+
+use crate::{
+    error::ApiError, load_prices, storage::Bar, tenant::Tenant, units::ReturnType, validation::ValidQuery, AppState,
+};
+
+// Options controlling how raw closes become the return series
+#[derive(Clone, Deserialize, Validate)]
+pub struct CleanOptions {
+    // Carry the previous close forward over missing/invalid prints instead of dropping them
+    #[serde(default = "default_fill")]
+    pub fill: bool,
+    // Clip returns to the [q, 1 - q] empirical quantiles
+    #[serde(default)]
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 0.5, message = "winsorize must be in (0, 0.5)"))]
+    pub winsorize: Option<f64>,
+    // Simple or log returns
+    #[serde(default)]
+    pub return_type: ReturnType,
+}
+
+fn default_fill() -> bool {
+    true
+}
+
+impl Default for CleanOptions {
+    fn default() -> Self {
+        CleanOptions { fill: default_fill(), winsorize: None, return_type: ReturnType::Simple }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flag {
+    Filled,
+    Dropped,
+    Winsorized,
+}
+
+// One raw bar next to what the pipeline made of it
+#[derive(Serialize)]
+pub struct CleanRow {
+    pub date: String,
+    pub raw_close: f64,
+    pub close: Option<f64>,
+    pub raw_return: Option<f64>,
+    #[serde(rename = "return")]
+    pub ret: Option<f64>,
+    pub flags: Vec<Flag>,
+}
+
+/// Turns raw closes into returns, flagging every intervention. Duplicate
+/// dates keep the last print; non-finite or non-positive closes are filled
+/// from the previous close (or dropped when `fill` is off or nothing precedes
+/// them); returns are then optionally winsorized.
+pub fn clean(bars: &[Bar], opts: &CleanOptions) -> Vec<CleanRow> {
+    let mut rows: Vec<CleanRow> = Vec::with_capacity(bars.len());
+    let mut prev: Option<f64> = None;
+    for (i, (date, raw)) in bars.iter().enumerate() {
+        let duplicate = bars.get(i + 1).is_some_and(|(next, _)| next == date);
+        let valid = raw.is_finite() && *raw > 0.0;
+        let mut row = CleanRow {
+            date: date.clone(),
+            raw_close: *raw,
+            close: None,
+            raw_return: None,
+            ret: None,
+            flags: Vec::new(),
+        };
+        if duplicate || (!valid && (!opts.fill || prev.is_none())) {
+            row.flags.push(Flag::Dropped);
+            rows.push(row);
+            continue;
+        }
+        let close = if valid {
+            *raw
+        } else {
+            row.flags.push(Flag::Filled);
+            prev.unwrap()
+        };
+        if let Some(p) = prev {
+            let r = opts.return_type.between(p, close);
+            row.raw_return = Some(r);
+            row.ret = Some(r);
+        }
+        row.close = Some(close);
+        prev = Some(close);
+        rows.push(row);
+    }
+
+    if let Some((lo, hi)) = opts.winsorize.and_then(|q| winsorize_bounds(rows.iter().filter_map(|r| r.ret), q)) {
+        for row in &mut rows {
+            if let Some(r) = row.ret {
+                if r < lo || r > hi {
+                    row.ret = Some(r.clamp(lo, hi));
+                    row.flags.push(Flag::Winsorized);
+                }
+            }
+        }
+    }
+    rows
+}
+
+/// The [q, 1 - q] empirical quantiles returns are clipped to; `None` for no returns.
+pub fn winsorize_bounds(returns: impl Iterator<Item = f64>, q: f64) -> Option<(f64, f64)> {
+    let mut sorted: Vec<f64> = returns.collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let last = sorted.len() - 1;
+    Some((sorted[(q * last as f64).floor() as usize], sorted[((1.0 - q) * last as f64).ceil() as usize]))
+}
+
+/// The processed return series, in order
+pub fn returns(rows: &[CleanRow]) -> Vec<f64> {
+    rows.iter().filter_map(|r| r.ret).collect()
+}
+
+/// Cleaned closes by calendar date; bars stamped with a time of day are
+/// keyed by their date so sources with different timestamps line up.
+pub fn closes_by_date(bars: &[Bar]) -> BTreeMap<String, f64> {
+    clean(bars, &CleanOptions::default())
+        .into_iter()
+        .filter_map(|row| Some((row.date.get(..10).unwrap_or(&row.date).to_string(), row.close?)))
+        .collect()
+}
+
+/// Raw prices vs the processed returns, row by row
+pub async fn cleaning_report_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(ticker): Path<String>,
+    ValidQuery(opts): ValidQuery<CleanOptions>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let ticker = ticker.to_uppercase();
+    let bars = load_prices(&state, &tenant, &ticker).await;
+    if bars.is_empty() {
+        return Err(ApiError::not_found(format!("no prices for {ticker}")));
+    }
+    let rows = clean(&bars, &opts);
+    let count = |flag: Flag| rows.iter().filter(|r| r.flags.contains(&flag)).count();
+    Ok(Json(serde_json::json!({
+        "ticker": ticker,
+        "observations": returns(&rows).len(),
+        "filled": count(Flag::Filled),
+        "dropped": count(Flag::Dropped),
+        "winsorized": count(Flag::Winsorized),
+        "rows": rows,
+    })))
+}
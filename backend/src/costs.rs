@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+/// Simple trading-friction model: a per-trade commission plus half the
+/// quoted spread, charged on the fraction of the position traded each period.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Validate)]
+pub struct TransactionCosts {
+    #[serde(default)]
+    #[validate(range(min = 0.0, message = "bps_per_trade must not be negative"))]
+    pub bps_per_trade: f64,
+    #[serde(default)]
+    #[validate(range(min = 0.0, message = "spread_bps must not be negative"))]
+    pub spread_bps: f64,
+    #[serde(default = "default_turnover")]
+    #[validate(range(min = 0.0, message = "turnover must not be negative"))]
+    pub turnover: f64,
+}
+
+fn default_turnover() -> f64 {
+    1.0
+}
+
+impl TransactionCosts {
+    /// Cost of trading one unit of value: the commission plus half the spread.
+    pub fn rate(&self) -> f64 {
+        (self.bps_per_trade + self.spread_bps / 2.0) / 10_000.0
+    }
+
+    /// Cost of a trade of `traded` value, bought or sold.
+    pub fn charge(&self, traded: f64) -> f64 {
+        traded.abs() * self.rate()
+    }
+
+    /// Cost per period as a fraction of position value.
+    pub fn drag(&self) -> f64 {
+        self.turnover * self.rate()
+    }
+
+    /// Deducts the per-period drag from every return and returns the drag applied.
+    pub fn apply(&self, returns: &mut [f64]) -> f64 {
+        let drag = self.drag();
+        for r in returns.iter_mut() {
+            *r -= drag;
+        }
+        drag
+    }
+}
@@ -0,0 +1,61 @@
+use crate::reduce::Summation;
+
+/// Sample covariance matrix of equally long return series, normalised by n
+/// like `var::mean_std`, so wᵀΣw is exactly the variance of the weighted sum.
+pub fn covariance_matrix(columns: &[Vec<f64>], sum: Summation) -> Vec<Vec<f64>> {
+    let n = columns.first().map_or(0, |c| c.len()) as f64;
+    let means: Vec<f64> = columns.iter().map(|c| sum.sum(c) / n).collect();
+    let k = columns.len();
+    let mut cov = vec![vec![0.0; k]; k];
+    for i in 0..k {
+        for j in 0..=i {
+            let products: Vec<f64> =
+                columns[i].iter().zip(&columns[j]).map(|(a, b)| (a - means[i]) * (b - means[j])).collect();
+            let c = sum.sum(&products) / n;
+            cov[i][j] = c;
+            cov[j][i] = c;
+        }
+    }
+    cov
+}
+
+/// Correlations from a covariance matrix; a constant series correlates 0
+/// with everything but itself.
+pub fn correlation_matrix(cov: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let vols: Vec<f64> = (0..cov.len()).map(|i| cov[i][i].sqrt()).collect();
+    (0..cov.len())
+        .map(|i| {
+            (0..cov.len())
+                .map(|j| match (i == j, vols[i] * vols[j]) {
+                    (true, _) => 1.0,
+                    (false, v) if v > 0.0 => cov[i][j] / v,
+                    _ => 0.0,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// wᵀΣw: the variance of a portfolio with weights `w`.
+pub fn portfolio_variance(weights: &[f64], cov: &[Vec<f64>]) -> f64 {
+    weights.iter().zip(cov).map(|(wi, row)| wi * row.iter().zip(weights).map(|(c, wj)| c * wj).sum::<f64>()).sum()
+}
+
+/// Lower-triangular L with LLᵀ = Σ. A pivot that rounding leaves at or
+/// below zero (a positive semi-definite Σ, e.g. perfectly correlated
+/// series) zeroes its column, so L still reproduces Σ on its range.
+pub fn cholesky(cov: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let k = cov.len();
+    let mut l = vec![vec![0.0; k]; k];
+    for i in 0..k {
+        for j in 0..=i {
+            let dot: f64 = (0..j).map(|m| l[i][m] * l[j][m]).sum();
+            if i == j {
+                l[i][i] = (cov[i][i] - dot).max(0.0).sqrt();
+            } else if l[j][j] > 0.0 {
+                l[i][j] = (cov[i][j] - dot) / l[j][j];
+            }
+        }
+    }
+    l
+}
@@ -0,0 +1,319 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use crate::{
+    admin::Admin,
+    error::ApiError,
+    keyring::{self, KeyManager, LocalKeyring, WrappedKey, KEY_LEN},
+    tenant::{Tenant, DEFAULT_TENANT},
+    AppState,
+};
+
+// A secret encrypted under its own data key, which is wrapped by a master key
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Sealed {
+    pub data_key: WrappedKey,
+    // Base64 of nonce ‖ ciphertext ‖ tag, bound to "{tenant}/{name}"
+    pub ciphertext: String,
+}
+
+/// A stored provider or broker credential. Only `sealed` holds the secret;
+/// the rest is safe to list.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Credential {
+    pub tenant: String,
+    pub name: String,
+    // Field names of an object secret, e.g. ["api_key", "api_secret"]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    // Truncated SHA-256 of the secret, to tell keys apart without revealing
+    // any of their characters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    pub sealed: Sealed,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Credential {
+    fn summary(&self) -> Value {
+        json!({
+            "name": self.name,
+            "fields": self.fields,
+            "fingerprint": self.fingerprint,
+            "key_id": self.sealed.data_key.key_id,
+            "created_at": self.created_at,
+            "updated_at": self.updated_at,
+        })
+    }
+}
+
+fn aad(tenant: &str, name: &str) -> Vec<u8> {
+    format!("{tenant}/{name}").into_bytes()
+}
+
+fn valid_name(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || matches!(b, b'_' | b'-' | b'.'))
+}
+
+/// Credentials encrypted at rest with envelope encryption under the master
+/// keys of a `KeyManager`, persisted to a single JSON file.
+pub struct CredentialStore {
+    keyring: Option<Box<dyn KeyManager>>,
+    entries: Mutex<BTreeMap<(String, String), Credential>>,
+    path: PathBuf,
+}
+
+impl CredentialStore {
+    /// Master keys from `MASTER_KEYS` (storage is disabled without them),
+    /// credentials kept in `CREDENTIALS_FILE` (default `credentials.json`).
+    pub fn from_env() -> Self {
+        let keyring: Option<Box<dyn KeyManager>> = match LocalKeyring::from_env() {
+            Some(Ok(keyring)) => Some(Box::new(keyring)),
+            Some(Err(e)) => {
+                eprintln!("⚠️ Invalid master keys ({}), credential storage disabled", e);
+                None
+            }
+            None => None,
+        };
+        let path = PathBuf::from(env::var("CREDENTIALS_FILE").unwrap_or_else(|_| "credentials.json".into()));
+        let entries = match fs::read(&path) {
+            Ok(bytes) => match serde_json::from_slice::<Vec<Credential>>(&bytes) {
+                Ok(list) => list.into_iter().map(|c| ((c.tenant.clone(), c.name.clone()), c)).collect(),
+                Err(e) => {
+                    eprintln!("⚠️ Cannot read credentials from {}: {}", path.display(), e);
+                    BTreeMap::new()
+                }
+            },
+            Err(_) => BTreeMap::new(),
+        };
+        match &keyring {
+            Some(k) => println!("🔐 {} credentials encrypted under master key {}", entries.len(), k.active_key()),
+            None if !entries.is_empty() => {
+                eprintln!("⚠️ {} stored credentials cannot be decrypted without MASTER_KEYS", entries.len())
+            }
+            None => {}
+        }
+        CredentialStore { keyring, entries: Mutex::new(entries), path }
+    }
+
+    fn keyring(&self) -> Result<&dyn KeyManager, ApiError> {
+        self.keyring.as_deref().ok_or_else(|| {
+            ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "credential storage is disabled (MASTER_KEYS not set)")
+        })
+    }
+
+    fn persist(&self, entries: &BTreeMap<(String, String), Credential>) {
+        let tmp = self.path.with_extension("tmp");
+        let written = serde_json::to_vec(&entries.values().collect::<Vec<_>>())
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| fs::write(&tmp, bytes).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&tmp, &self.path).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            eprintln!("⚠️ Failed to persist credentials: {}", e);
+        }
+    }
+
+    pub fn all(&self) -> Vec<Credential> {
+        self.entries.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn restore(&self, credential: Credential) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert((credential.tenant.clone(), credential.name.clone()), credential);
+        self.persist(&entries);
+    }
+
+    fn list(&self, tenant: &str) -> Vec<Credential> {
+        self.entries.lock().unwrap().values().filter(|c| c.tenant == tenant).cloned().collect()
+    }
+
+    fn remove(&self, tenant: &str, name: &str) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        let removed = entries.remove(&(tenant.to_string(), name.to_string())).is_some();
+        if removed {
+            self.persist(&entries);
+        }
+        removed
+    }
+
+    /// Encrypts and stores `secret`, replacing any credential of that name.
+    fn put(&self, tenant: &str, name: &str, secret: &Value) -> Result<Credential, ApiError> {
+        let keyring = self.keyring()?;
+        let data_key = keyring::random::<KEY_LEN>();
+        let plaintext = serde_json::to_vec(secret).map_err(internal)?;
+        let sealed = Sealed {
+            data_key: keyring.wrap(&data_key).map_err(internal)?,
+            ciphertext: BASE64.encode(keyring::seal(&data_key, &aad(tenant, name), &plaintext).map_err(internal)?),
+        };
+        let fields = secret.as_object().map(|o| o.keys().cloned().collect()).unwrap_or_default();
+        let digest: String = Sha256::digest(&plaintext)[..6].iter().map(|b| format!("{b:02x}")).collect();
+        let fingerprint = Some(format!("sha256:{digest}"));
+
+        let mut entries = self.entries.lock().unwrap();
+        let key = (tenant.to_string(), name.to_string());
+        let now = Utc::now();
+        let created_at = entries.get(&key).map_or(now, |c| c.created_at);
+        let credential = Credential {
+            tenant: key.0.clone(),
+            name: key.1.clone(),
+            fields,
+            fingerprint,
+            sealed,
+            created_at,
+            updated_at: now,
+        };
+        entries.insert(key, credential.clone());
+        self.persist(&entries);
+        Ok(credential)
+    }
+
+    /// Decrypts a stored credential; `Ok(None)` when there is none.
+    pub fn reveal(&self, tenant: &str, name: &str) -> Result<Option<Value>, String> {
+        let Some(credential) = self.entries.lock().unwrap().get(&(tenant.to_string(), name.to_string())).cloned()
+        else {
+            return Ok(None);
+        };
+        let keyring = self.keyring.as_deref().ok_or("MASTER_KEYS not set")?;
+        let data_key = keyring.unwrap(&credential.sealed.data_key)?;
+        let sealed = BASE64.decode(&credential.sealed.ciphertext).map_err(|e| e.to_string())?;
+        let plaintext = keyring::open(&data_key, &aad(tenant, name), &sealed)?;
+        serde_json::from_slice(&plaintext).map(Some).map_err(|e| e.to_string())
+    }
+
+    /// A provider's API key: the tenant's stored string credential `name`,
+    /// else the default tenant's, else the `fallback` environment variable.
+    pub fn provider_key(&self, tenant: &str, name: &str, fallback: &str) -> Option<String> {
+        let mut owners = vec![tenant];
+        if tenant != DEFAULT_TENANT {
+            owners.push(DEFAULT_TENANT);
+        }
+        for owner in owners {
+            match self.reveal(owner, name) {
+                Ok(Some(Value::String(key))) => return Some(key),
+                Ok(Some(_)) => eprintln!("⚠️ Credential {}/{} is not a plain key, skipping it", owner, name),
+                Ok(None) => {}
+                Err(e) => eprintln!("⚠️ Cannot decrypt credential {}/{} ({}), skipping it", owner, name, e),
+            }
+        }
+        env::var(fallback).ok()
+    }
+
+    /// Re-wraps every data key not under the active master key, so retired
+    /// keys can be dropped from `MASTER_KEYS`. Secrets are not re-encrypted.
+    fn rotate(&self) -> Result<Value, ApiError> {
+        let keyring = self.keyring()?;
+        let active = keyring.active_key();
+        let mut entries = self.entries.lock().unwrap();
+        let (mut rewrapped, mut failed) = (0, Vec::new());
+        for credential in entries.values_mut().filter(|c| c.sealed.data_key.key_id != active) {
+            match keyring.unwrap(&credential.sealed.data_key).and_then(|key| keyring.wrap(&key)) {
+                Ok(wrapped) => {
+                    credential.sealed.data_key = wrapped;
+                    rewrapped += 1;
+                }
+                Err(e) => failed.push(json!({ "tenant": credential.tenant, "name": credential.name, "error": e })),
+            }
+        }
+        if rewrapped > 0 {
+            self.persist(&entries);
+        }
+        let in_use: BTreeSet<&str> = entries.values().map(|c| c.sealed.data_key.key_id.as_str()).collect();
+        let retired: Vec<String> = keyring.key_ids().into_iter().filter(|id| !in_use.contains(id.as_str())).collect();
+        println!("🔐 Re-wrapped {} credentials under master key {}", rewrapped, active);
+        Ok(json!({
+            "active_key": active,
+            "rewrapped": rewrapped,
+            "failed": failed,
+            "credentials": entries.len(),
+            "keys_in_use": in_use,
+            "unused_keys": retired,
+        }))
+    }
+}
+
+fn internal(e: impl ToString) -> ApiError {
+    ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+// Payload to store a credential: a key string or an object of fields
+#[derive(Deserialize)]
+pub struct CredentialBody {
+    secret: Value,
+}
+
+/// The caller's stored credentials, without their secrets
+pub async fn list_credentials_handler(State(state): State<AppState>, Tenant(tenant): Tenant) -> Json<Value> {
+    let list: Vec<Value> = state.credentials.list(&tenant).iter().map(Credential::summary).collect();
+    Json(json!({ "credentials": list }))
+}
+
+// The default tenant's credentials back every other tenant's (see
+// `provider_key`), so only an admin may change them
+fn check_owner(tenant: &str, admin: Result<Admin, ApiError>) -> Result<(), ApiError> {
+    match (tenant, admin) {
+        (DEFAULT_TENANT, Err(e)) => Err(ApiError::new(
+            e.status,
+            format!("the {DEFAULT_TENANT} tenant's credentials are shared and need the admin token: {}", e.message),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Store or replace one of the caller's credentials
+pub async fn put_credential_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    admin: Result<Admin, ApiError>,
+    Path(name): Path<String>,
+    Json(body): Json<CredentialBody>,
+) -> Result<Json<Value>, ApiError> {
+    check_owner(&tenant, admin)?;
+    if !valid_name(&name) {
+        return Err(ApiError::bad_request("credential names are 1 to 64 of a-z, 0-9, '_', '-' and '.'"));
+    }
+    let usable = match &body.secret {
+        Value::String(s) => !s.is_empty(),
+        Value::Object(o) => !o.is_empty() && o.values().all(Value::is_string),
+        _ => false,
+    };
+    if !usable {
+        return Err(ApiError::bad_request("secret must be a non-empty string or an object of string fields"));
+    }
+    let credential = state.credentials.put(&tenant, &name, &body.secret)?;
+    Ok(Json(credential.summary()))
+}
+
+/// Delete one of the caller's credentials
+pub async fn delete_credential_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    admin: Result<Admin, ApiError>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    check_owner(&tenant, admin)?;
+    match state.credentials.remove(&tenant, &name) {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(ApiError::not_found(format!("no credential named {name}"))),
+    }
+}
+
+/// Re-wrap all credentials under the active master key
+pub async fn rotate_credentials_handler(_: Admin, State(state): State<AppState>) -> Result<Json<Value>, ApiError> {
+    state.credentials.rotate().map(Json)
+}
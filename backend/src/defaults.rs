@@ -0,0 +1,164 @@
+//! Data-driven defaults for `compute_var`. Parameters a request leaves out
+//! are picked from the series and the confidence level rather than fixed
+//! constants, and every parameter the method used is echoed back with where
+//! it came from, so no default is hidden.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use crate::{
+    budget,
+    var::{
+        VarRequest, VolatilityModel, DEFAULT_BOOTSTRAP_SAMPLES, DEFAULT_BRW_LAMBDA, DEFAULT_EWMA_LAMBDA,
+        DEFAULT_MAX_PATHS, MC_PATHS,
+    },
+};
+
+// Most recent returns used by default: four years of trading days, enough
+// to span a full cycle without reaching back to stale regimes
+const DEFAULT_LOOKBACK: usize = 1_000;
+// Observations beyond the VaR the default lookback keeps at the very least
+const MIN_TAIL_OBSERVATIONS: f64 = 10.0;
+// Paths beyond the VaR a default Monte Carlo run draws at the very least
+const MIN_TAIL_PATHS: f64 = 100.0;
+const MAX_PATHS: usize = 1_000_000;
+// Share of the age weights a default decay may leave before the sample's
+// oldest return
+const MAX_TRUNCATED_WEIGHT: f64 = 0.01;
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    // Sent with the request
+    Supplied,
+    // Picked from the series and the confidence level
+    Adaptive,
+    // A fixed default
+    Default,
+    // Estimated from the returns
+    Fitted,
+}
+
+/// Parameters a computation ran with, by name: `{ "value", "source" }`.
+pub type Parameters = BTreeMap<&'static str, Value>;
+
+pub fn effective(value: impl Serialize, source: Source) -> Value {
+    json!({ "value": value, "source": source })
+}
+
+/// Returns used by default out of `n`: the most recent `DEFAULT_LOOKBACK`,
+/// or more when fewer would leave under ten observations beyond the VaR.
+pub fn lookback(n: usize, confidence: f64) -> usize {
+    let tail = (MIN_TAIL_OBSERVATIONS / (1.0 - confidence)).ceil() as usize;
+    n.min(DEFAULT_LOOKBACK.max(tail))
+}
+
+/// Warning for a series the default lookback cut short. A `lookback` the
+/// request sets, or explicit weights, is taken as meant.
+pub fn truncation_warning(req: &VarRequest) -> Option<String> {
+    let n = req.returns.len();
+    let used = lookback(n, req.confidence);
+    (req.lookback.is_none() && req.params.weights.is_none() && used < n).then(|| {
+        format!("only the most recent {used} of {n} returns were used (the default lookback); set lookback to use more")
+    })
+}
+
+/// Age-weight decay for `n` returns: `standard` (0.94 for EWMA, 0.98 for
+/// BRW), lowered on short samples until at most 1% of the weight would fall
+/// before the oldest return, λⁿ ≤ 0.01.
+pub fn decay(standard: f64, n: usize) -> f64 {
+    standard.min(MAX_TRUNCATED_WEIGHT.powf(1.0 / n.max(1) as f64))
+}
+
+/// Paths enough for a hundred beyond the VaR at `confidence` (10,000 at
+/// 99%, 100,000 at 99.9%), between 10,000 and 1,000,000.
+pub fn paths(confidence: f64) -> usize {
+    let tail = (MIN_TAIL_PATHS / (1.0 - confidence)).ceil() as usize;
+    tail.clamp(MC_PATHS, MAX_PATHS)
+}
+
+/// Draws of a fixed-size Monte Carlo run: `n_sims` if sent, else `paths`
+/// for the confidence, as many as the memory budget allows.
+pub fn mc_paths(req: &VarRequest) -> usize {
+    req.params.n_sims.unwrap_or_else(|| {
+        // Importance sampling holds four values a path; a simulated horizon one more
+        let per_path = if req.importance.is_some() { 4 } else { 1 } + usize::from(req.simulates_horizon());
+        paths(req.confidence).min(budget::fits(4 * req.returns.len(), per_path))
+    })
+}
+
+/// `req` with every parameter its method uses and the request left out
+/// filled in, and those parameters as used.
+pub fn resolve(req: &VarRequest) -> (VarRequest, Parameters) {
+    let mut resolved = req.clone();
+    let mut used = Parameters::new();
+    let n = req.returns.len();
+
+    // Explicit weights cover the whole sample
+    let (lookback, source) = match req.lookback {
+        Some(lookback) => (lookback.min(n), Source::Supplied),
+        None if req.params.weights.is_some() => (n, Source::Default),
+        None => (lookback(n, req.confidence), Source::Adaptive),
+    };
+    resolved.returns.drain(..n - lookback);
+    resolved.lookback = Some(lookback);
+    let mut entry = effective(lookback, source);
+    // Older returns were left out; `available` is how many were sent
+    if lookback < n {
+        entry["truncated"] = json!(true);
+        entry["available"] = json!(n);
+    }
+    used.insert("lookback", entry);
+
+    let filters_ewma = req.method == "filtered_historical"
+        && req.params.volatility_model.unwrap_or_default() == VolatilityModel::Ewma;
+    let standard = match req.method.as_str() {
+        "ewma" => Some(DEFAULT_EWMA_LAMBDA),
+        "weighted_historical" if req.params.weights.is_none() => Some(DEFAULT_BRW_LAMBDA),
+        _ if filters_ewma => Some(DEFAULT_EWMA_LAMBDA),
+        _ => None,
+    };
+    if let Some(standard) = standard {
+        let (lambda, source) = match req.params.lambda {
+            Some(lambda) => (lambda, Source::Supplied),
+            None => (decay(standard, lookback), Source::Adaptive),
+        };
+        resolved.params.lambda = Some(lambda);
+        used.insert("lambda", effective(lambda, source));
+    }
+    if req.method == "filtered_historical" {
+        let source = if req.params.volatility_model.is_some() { Source::Supplied } else { Source::Default };
+        used.insert("volatility_model", effective(req.params.volatility_model.unwrap_or_default(), source));
+    }
+    if req.method == "bootstrap" {
+        let (samples, source) = match req.params.bootstrap_samples {
+            Some(samples) => (samples, Source::Supplied),
+            None => (DEFAULT_BOOTSTRAP_SAMPLES, Source::Default),
+        };
+        used.insert("bootstrap_samples", effective(samples, source));
+    }
+
+    let source = |given: bool, otherwise| if given { Source::Supplied } else { otherwise };
+    match (req.method.as_str(), req.target_se) {
+        ("montecarlo", Some(target_se)) => {
+            used.insert("target_se", effective(target_se, Source::Supplied));
+            let max_paths = req.max_paths.unwrap_or(DEFAULT_MAX_PATHS);
+            resolved.max_paths = Some(max_paths);
+            used.insert("max_paths", effective(max_paths, source(req.max_paths.is_some(), Source::Default)));
+        }
+        ("montecarlo", None) if req.importance.is_some() => {
+            let paths = mc_paths(&resolved);
+            let max_paths = req.max_paths.unwrap_or(paths);
+            resolved.max_paths = Some(max_paths);
+            used.insert("max_paths", effective(max_paths, source(req.max_paths.is_some(), Source::Adaptive)));
+        }
+        _ if req.method == "montecarlo" || req.simulates_horizon() => {
+            let paths = mc_paths(&resolved);
+            resolved.params.n_sims = Some(paths);
+            used.insert("n_sims", effective(paths, source(req.params.n_sims.is_some(), Source::Adaptive)));
+        }
+        _ => {}
+    }
+    (resolved, used)
+}
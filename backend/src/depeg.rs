@@ -0,0 +1,218 @@
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use validator::Validate;
+
+use crate::{portfolios::Position, var::weighted_var_es};
+
+/// Historical or hypothetical peg break: the trough move of each coin.
+/// `ALL_PEGGED` shocks every pegged holding the request names.
+#[derive(Serialize)]
+pub struct DepegScenario {
+    pub name: &'static str,
+    pub date: &'static str,
+    pub description: &'static str,
+    pub shocks: &'static [(&'static str, f64)],
+}
+
+const ALL_PEGGED: &str = "*";
+
+pub const SCENARIOS: &[DepegScenario] = &[
+    DepegScenario {
+        name: "terra_ust_2022",
+        date: "2022-05-09",
+        description: "UST algorithmic peg collapse; USDT briefly traded at 0.95 in the contagion",
+        shocks: &[("UST", -0.90), ("USDT", -0.05), ("DAI", -0.02), ("FRAX", -0.03)],
+    },
+    DepegScenario {
+        name: "usdc_svb_2023",
+        date: "2023-03-11",
+        description: "USDC reserves stuck at Silicon Valley Bank; DAI and FRAX, backed by USDC, followed it",
+        shocks: &[("USDC", -0.12), ("DAI", -0.10), ("FRAX", -0.12), ("USDT", 0.01)],
+    },
+    DepegScenario {
+        name: "dai_black_thursday_2020",
+        date: "2020-03-12",
+        description: "ETH crash and failed Maker liquidations; DAI traded above its peg",
+        shocks: &[("DAI", 0.06)],
+    },
+    DepegScenario {
+        name: "peg_break_10",
+        date: "",
+        description: "Every pegged holding loses 10%",
+        shocks: &[(ALL_PEGGED, -0.10)],
+    },
+    DepegScenario {
+        name: "peg_break_50",
+        date: "",
+        description: "Every pegged holding loses half its value",
+        shocks: &[(ALL_PEGGED, -0.50)],
+    },
+];
+
+/// Peg-break jump model of one pegged asset: at most one break per
+/// horizon, arriving with `annual_probability`, costing `severity`.
+#[derive(Clone, Serialize, Deserialize, Validate)]
+pub struct PegModel {
+    // Coin name used to look up scenario shocks (default: the ticker with
+    // any -USD / USD quote suffix removed)
+    #[serde(default)]
+    pub coin: Option<String>,
+    #[serde(default = "default_probability")]
+    #[validate(range(min = 0.0, max = 1.0, message = "annual_probability must be in [0, 1]"))]
+    pub annual_probability: f64,
+    #[serde(default = "default_severity")]
+    #[validate(range(exclusive_min = 0.0, max = 1.0, message = "severity must be in (0, 1]"))]
+    pub severity: f64,
+}
+
+// Roughly one large depeg of a major stablecoin every twenty years of coin history
+fn default_probability() -> f64 {
+    0.05
+}
+
+fn default_severity() -> f64 {
+    0.10
+}
+
+impl PegModel {
+    fn coin(&self, ticker: &str) -> String {
+        match &self.coin {
+            Some(coin) => coin.trim().to_uppercase(),
+            None => ticker.trim_end_matches("-USD").trim_end_matches("USD").to_string(),
+        }
+    }
+}
+
+// Depeg settings of a portfolio_var request
+#[derive(Clone, Serialize, Deserialize, Validate)]
+pub struct DepegConfig {
+    // Ticker → jump model of each pegged holding
+    #[validate(nested)]
+    pub assets: BTreeMap<String, PegModel>,
+    #[serde(default = "default_horizon")]
+    #[validate(range(min = 1, max = 250, message = "horizon_days must be between 1 and 250"))]
+    pub horizon_days: u32,
+}
+
+fn default_horizon() -> u32 {
+    1
+}
+
+#[derive(Serialize)]
+pub struct ScenarioLoss {
+    pub scenario: &'static str,
+    pub pnl: f64,
+    pub by_ticker: BTreeMap<String, f64>,
+}
+
+#[derive(Serialize)]
+pub struct JumpRisk {
+    pub ticker: String,
+    pub exposure: f64,
+    // Chance of a break within the horizon and the loss it brings
+    pub probability: f64,
+    pub jump_pnl: f64,
+}
+
+#[derive(Serialize)]
+pub struct DepegRisk {
+    pub horizon_days: u32,
+    // Horizon VaR/ES of the book's P&L mixed with the peg-break jumps
+    pub var: f64,
+    pub es: f64,
+    // The same figures without jumps, for comparison
+    pub var_without_jumps: f64,
+    pub es_without_jumps: f64,
+    pub jumps: Vec<JumpRisk>,
+    pub scenarios: Vec<ScenarioLoss>,
+}
+
+/// Net exposure of the book to `ticker`, short spread legs included.
+fn exposure(positions: &[Position], ticker: &str) -> f64 {
+    positions
+        .iter()
+        .map(|p| match &p.against {
+            Some(leg) if leg.ticker == ticker => -p.value * leg.ratio,
+            _ if p.ticker == ticker => p.value,
+            _ => 0.0,
+        })
+        .sum()
+}
+
+/// Stress losses and jump-adjusted VaR/ES of pegged holdings. A stablecoin's
+/// price history is nearly flat, so historical VaR alone reports almost no
+/// risk; the jump model adds the break the history hasn't shown. Breaks of
+/// different coins are taken as mutually exclusive within the horizon (each
+/// is rare), which makes the P&L a mixture of the historical h-day
+/// outcomes and those outcomes shifted by one coin's break.
+pub fn assess(config: &DepegConfig, positions: &[Position], pnl: &[f64], confidence: f64) -> DepegRisk {
+    let h = config.horizon_days as usize;
+    let base: Vec<f64> = if pnl.len() >= h {
+        pnl.windows(h).map(|w| w.iter().sum()).collect()
+    } else {
+        // Too little history for overlapping windows: square-root-of-time
+        pnl.iter().map(|p| p * (h as f64).sqrt()).collect()
+    };
+
+    let assets: Vec<(String, &PegModel)> =
+        config.assets.iter().map(|(ticker, model)| (ticker.trim().to_uppercase(), model)).collect();
+    let jumps: Vec<JumpRisk> = assets
+        .iter()
+        .map(|(ticker, model)| {
+            let exposure = exposure(positions, ticker);
+            let daily = 1.0 - (1.0 - model.annual_probability).powf(1.0 / 252.0);
+            JumpRisk {
+                ticker: ticker.clone(),
+                exposure,
+                probability: 1.0 - (1.0 - daily).powi(h as i32),
+                jump_pnl: -exposure * model.severity,
+            }
+        })
+        .collect();
+
+    let n = base.len() as f64;
+    let no_jump = (1.0 - jumps.iter().map(|j| j.probability).sum::<f64>()).max(0.0);
+    let mut outcomes: Vec<f64> = base.clone();
+    let mut weights: Vec<f64> = vec![no_jump / n; base.len()];
+    for jump in &jumps {
+        outcomes.extend(base.iter().map(|b| b + jump.jump_pnl));
+        weights.extend(std::iter::repeat_n(jump.probability / n, base.len()));
+    }
+    let total: f64 = weights.iter().sum();
+    weights.iter_mut().for_each(|w| *w /= total);
+    let (var, es) = weighted_var_es(&outcomes, &weights, confidence);
+    let flat = vec![1.0 / n; base.len()];
+    let (var_without_jumps, es_without_jumps) = weighted_var_es(&base, &flat, confidence);
+
+    // Scenarios that touch none of the holdings are left out
+    let scenarios = SCENARIOS
+        .iter()
+        .filter_map(|s| {
+            let by_ticker: BTreeMap<String, f64> = assets
+                .iter()
+                .filter_map(|(ticker, model)| {
+                    let coin = model.coin(ticker);
+                    let shock = s.shocks.iter().find(|(c, _)| *c == coin || *c == ALL_PEGGED)?.1;
+                    Some((ticker.clone(), exposure(positions, ticker) * shock))
+                })
+                .collect();
+            (!by_ticker.is_empty()).then(|| ScenarioLoss { scenario: s.name, pnl: by_ticker.values().sum(), by_ticker })
+        })
+        .collect();
+
+    DepegRisk {
+        horizon_days: config.horizon_days,
+        var,
+        es,
+        var_without_jumps,
+        es_without_jumps,
+        jumps,
+        scenarios,
+    }
+}
+
+/// Predefined depeg stress scenarios
+pub async fn list_scenarios_handler() -> Json<&'static [DepegScenario]> {
+    Json(SCENARIOS)
+}
@@ -0,0 +1,126 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::{collections::BTreeMap, fmt};
+
+/// Handler error rendered as `{ "error": message }` with the given status,
+/// plus a machine-readable `code` when the failure has one and `fields`
+/// (per-field messages) for rejected request payloads.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+    pub code: Option<&'static str>,
+    pub fields: Option<BTreeMap<String, Vec<String>>>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError { status, message: message.into(), code: None, fields: None }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, message)
+    }
+
+    /// 500 for a computation that died on a worker thread instead of returning.
+    pub fn aborted(what: &str) -> Self {
+        let message = format!("{what} failed");
+        ApiError { code: Some("computation_aborted"), ..Self::new(StatusCode::INTERNAL_SERVER_ERROR, message) }
+    }
+
+    /// 422 listing every invalid field with its messages.
+    pub fn invalid(fields: BTreeMap<String, Vec<String>>) -> Self {
+        ApiError {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            message: "request failed validation".into(),
+            code: None,
+            fields: Some(fields),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut body = json!({ "error": self.message });
+        if let Some(code) = self.code {
+            body["code"] = json!(code);
+        }
+        if let Some(fields) = self.fields {
+            body["fields"] = json!(fields);
+        }
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// Why a risk computation could not produce an estimate. Endpoints validate
+/// their payloads up front, so these mostly surface for samples derived
+/// server-side (common histories, backtest windows) or unvalidated callers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VarError {
+    UnknownMethod(String),
+    // Fewer observations than the estimator needs
+    InsufficientData { needed: usize, got: usize },
+    // Inputs the estimator cannot use, such as non-finite returns
+    InvalidInput(String),
+    // A model that could not be fitted or evaluated on these inputs
+    Numerical(String),
+    // Stopped through its cancellation token (see `cancel`)
+    Cancelled,
+}
+
+impl VarError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            VarError::UnknownMethod(_) => "unknown_method",
+            VarError::InsufficientData { .. } => "insufficient_data",
+            VarError::InvalidInput(_) => "invalid_input",
+            VarError::Numerical(_) => "numerical_error",
+            VarError::Cancelled => "cancelled",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            VarError::UnknownMethod(_) => StatusCode::BAD_REQUEST,
+            VarError::InsufficientData { .. } | VarError::InvalidInput(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            VarError::Numerical(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            // Client Closed Request: only a caller that went away cancels one
+            VarError::Cancelled => StatusCode::from_u16(499).unwrap_or(StatusCode::CONFLICT),
+        }
+    }
+}
+
+impl fmt::Display for VarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VarError::UnknownMethod(method) => write!(f, "unknown method {method}"),
+            VarError::InsufficientData { needed, got } => {
+                write!(f, "need at least {needed} observations, got {got}")
+            }
+            VarError::InvalidInput(msg) | VarError::Numerical(msg) => f.write_str(msg),
+            VarError::Cancelled => f.write_str("computation cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for VarError {}
+
+impl From<VarError> for ApiError {
+    fn from(e: VarError) -> Self {
+        ApiError { code: Some(e.code()), ..ApiError::new(e.status(), e.to_string()) }
+    }
+}
+
+impl IntoResponse for VarError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
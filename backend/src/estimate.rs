@@ -0,0 +1,269 @@
+use axum::Json;
+use rand::SeedableRng;
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{ContinuousCDF, Normal as Gaussian};
+use serde_json::{json, Value};
+use std::{hint::black_box, sync::OnceLock, time::Instant};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    budget, defaults,
+    validation::{self, cross_field, Valid},
+    version,
+};
+
+// Monte Carlo draws per run without a precision target, and the batch size
+// of adaptive runs (see `var::montecarlo_adaptive`)
+const MC_PATHS: usize = 10_000;
+const MC_BATCH: usize = 10_000;
+const DEFAULT_MAX_PATHS: usize = 1_000_000;
+// Resamples of a bootstrap run (see `var::bootstrap_var_es`)
+const BOOTSTRAP_SAMPLES: usize = 1_000;
+const CALIBRATION_SIZE: usize = 200_000;
+// Likelihood passes of a typical GARCH(1,1) fit (see `garch::fit`)
+const GARCH_PASSES: f64 = 200.0;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    ComputeVar,
+    Backtest,
+    CompareModels,
+    PortfolioVar,
+}
+
+// Payload for /api/estimate_cost: the shape of a request, not its data
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "check_spec"))]
+pub struct EstimateRequest {
+    operation: Operation,
+    // Methods the operation runs (one, except for compare_models)
+    #[validate(length(min = 1, message = "methods must not be empty"), custom(function = "validation::known_methods"))]
+    methods: Vec<String>,
+    // Returns in the series (per ticker for portfolio_var)
+    #[validate(range(min = 2, message = "observations must be at least 2"))]
+    observations: usize,
+    #[serde(default = "default_confidence")]
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+    // Backtest estimation window
+    #[serde(default = "default_window")]
+    #[validate(range(min = 2, message = "window must be at least 2"))]
+    window: usize,
+    // portfolio_var: positions in the book
+    #[serde(default = "default_positions")]
+    #[validate(range(min = 1, message = "positions must be at least 1"))]
+    positions: usize,
+    // Monte Carlo precision target, as for compute_var, and the daily
+    // volatility it is judged against (default 2%)
+    #[serde(default)]
+    #[validate(range(exclusive_min = 0.0, message = "target_se must be positive"))]
+    target_se: Option<f64>,
+    #[serde(default)]
+    #[validate(range(min = 1, message = "max_paths must be at least 1"))]
+    max_paths: Option<usize>,
+    // Draws of a fixed-size Monte Carlo run, as for compute_var
+    #[serde(default)]
+    #[validate(range(min = 100, max = 1_000_000, message = "n_sims must be between 100 and 1000000"))]
+    n_sims: Option<usize>,
+    #[serde(default = "default_volatility")]
+    #[validate(range(exclusive_min = 0.0, message = "volatility must be positive"))]
+    volatility: f64,
+}
+
+fn default_confidence() -> f64 {
+    0.99
+}
+
+fn default_window() -> usize {
+    250
+}
+
+fn default_positions() -> usize {
+    1
+}
+
+fn default_volatility() -> f64 {
+    0.02
+}
+
+fn check_spec(req: &EstimateRequest) -> Result<(), ValidationError> {
+    let rolling = matches!(req.operation, Operation::Backtest | Operation::CompareModels);
+    if rolling && req.observations <= req.window {
+        return Err(cross_field(
+            "observations",
+            format!("need more than window={} observations, got {}", req.window, req.observations),
+        ));
+    }
+    if req.operation != Operation::CompareModels && req.methods.len() > 1 {
+        return Err(cross_field("methods", "only compare_models runs more than one method".into()));
+    }
+    if req.operation == Operation::PortfolioVar && req.methods.iter().any(|m| m != "historical") {
+        return Err(cross_field("methods", "portfolio_var is historical simulation only".into()));
+    }
+    Ok(())
+}
+
+/// Per-operation costs measured on this host, in nanoseconds.
+#[derive(Clone, Copy, Serialize)]
+pub struct Calibration {
+    // Per element per log2(n) of an f64 sort
+    pub sort_ns: f64,
+    // Per element of a quickselect for one order statistic
+    pub select_ns: f64,
+    // Per normal draw
+    pub sample_ns: f64,
+    // Per element of a linear pass (sums, copies)
+    pub pass_ns: f64,
+}
+
+/// Benchmarks the building blocks every method is made of, once per process.
+pub fn calibration() -> Calibration {
+    static CALIBRATION: OnceLock<Calibration> = OnceLock::new();
+    *CALIBRATION.get_or_init(|| {
+        let n = CALIBRATION_SIZE;
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+
+        let start = Instant::now();
+        let mut values: Vec<f64> = (0..n).map(|_| normal.sample(&mut rng)).collect();
+        let sample_ns = start.elapsed().as_nanos() as f64 / n as f64;
+
+        let start = Instant::now();
+        black_box(values.iter().map(|v| v * v).sum::<f64>());
+        let pass_ns = start.elapsed().as_nanos() as f64 / n as f64;
+
+        let start = Instant::now();
+        let mut shuffled = values.clone();
+        shuffled.select_nth_unstable_by(n / 20, |a, b| a.total_cmp(b));
+        black_box(&shuffled);
+        let select_ns = start.elapsed().as_nanos() as f64 / n as f64;
+
+        let start = Instant::now();
+        values.sort_by(|a, b| a.total_cmp(b));
+        black_box(&values);
+        let sort_ns = start.elapsed().as_nanos() as f64 / (n as f64 * (n as f64).log2());
+
+        Calibration { sort_ns, select_ns, sample_ns, pass_ns }
+    })
+}
+
+fn sort_cost(n: usize, cal: &Calibration) -> f64 {
+    let n = n.max(2) as f64;
+    n * n.log2() * cal.sort_ns
+}
+
+fn select_cost(n: usize, cal: &Calibration) -> f64 {
+    n as f64 * cal.select_ns
+}
+
+// Paths an adaptive Monte Carlo run needs: the quantile's standard error
+// sqrt(p(1-p)/n) / f(q) reaches the target at n = p(1-p) / (target · f(q))²,
+// with f the normal density at the quantile, rounded up to whole batches
+fn adaptive_paths(confidence: f64, target_se: f64, max_paths: usize, volatility: f64) -> usize {
+    let p = 1.0 - confidence;
+    let z = Gaussian::standard().inverse_cdf(p);
+    let density = (-0.5 * z * z).exp() / ((2.0 * std::f64::consts::PI).sqrt() * volatility);
+    let needed = (p * (1.0 - p) / (target_se * density).powi(2)).ceil() as usize;
+    needed.div_ceil(MC_BATCH).max(1).saturating_mul(MC_BATCH).min(max_paths.max(1))
+}
+
+// One VaR evaluation of `method` on `n` returns: nanoseconds, paths drawn
+// and f64 values held
+fn evaluation(method: &str, n: usize, req: &EstimateRequest, cal: &Calibration) -> (f64, usize, usize) {
+    match method {
+        "montecarlo" => match req.target_se {
+            Some(target_se) => {
+                let paths =
+                    adaptive_paths(req.confidence, target_se, req.max_paths.unwrap_or(DEFAULT_MAX_PATHS), req.volatility);
+                // Every batch selects over the batch and the kept tail
+                // twice: once to trim the tail, once for the quantile
+                let batches = paths.div_ceil(MC_BATCH);
+                let selects: f64 = (1..=batches).map(|b| 2.0 * select_cost(b * MC_BATCH, cal)).sum();
+                (paths as f64 * cal.sample_ns + selects, paths, paths)
+            }
+            None => {
+                // compute_var picks its paths from the confidence; backtests keep the fixed count
+                let paths = match req.operation {
+                    Operation::ComputeVar => req.n_sims.unwrap_or_else(|| defaults::paths(req.confidence)),
+                    _ => req.n_sims.unwrap_or(MC_PATHS),
+                };
+                (paths as f64 * cal.sample_ns + select_cost(paths, cal), paths, paths)
+            }
+        },
+        // ewma: the weights, then the weighted squares
+        "parametric" | "ewma" => (2.0 * n as f64 * cal.pass_ns, 0, n),
+        // Each likelihood evaluation of the fit is a pass over the residuals
+        "garch" => (GARCH_PASSES * n as f64 * cal.pass_ns, 0, 2 * n),
+        // The higher moments are one more pass
+        "parametric_t" | "cornish_fisher" => (3.0 * n as f64 * cal.pass_ns, 0, n),
+        // Every resample is drawn and its quantile selected
+        "bootstrap" => (
+            BOOTSTRAP_SAMPLES as f64 * (n as f64 * cal.sample_ns + select_cost(n, cal)),
+            BOOTSTRAP_SAMPLES * n,
+            n + 2 * BOOTSTRAP_SAMPLES,
+        ),
+        // EWMA filtering is two passes before the selection
+        "filtered_historical" => (select_cost(n, cal) + 3.0 * n as f64 * cal.pass_ns, 0, 3 * n),
+        // The weighted quantile needs the sample sorted with its weights
+        "weighted_historical" => (sort_cost(n, cal) + n as f64 * cal.pass_ns, 0, 2 * n),
+        _ => (select_cost(n, cal) + n as f64 * cal.pass_ns, 0, 2 * n),
+    }
+}
+
+/// Predicted runtime, paths and memory of a computation, so clients can
+/// warn before launching an expensive one. Figures are scaled from
+/// benchmarks of sorting, selection, sampling and linear passes on this
+/// host; they are order-of-magnitude guides, not guarantees.
+pub async fn estimate_cost_handler(Valid(req): Valid<EstimateRequest>) -> Json<Value> {
+    let cal = calibration();
+    let n = req.observations;
+    let (mut ns, mut paths, mut values) = (0.0, 0, 0);
+    let evaluations = match req.operation {
+        Operation::ComputeVar => {
+            // Only the default lookback of the series is used
+            let n = defaults::lookback(n, req.confidence);
+            (ns, paths, values) = evaluation(&req.methods[0], n, &req, &cal);
+            1
+        }
+        Operation::Backtest | Operation::CompareModels => {
+            // One forecast per day after the window; a single-method
+            // backtest runs a second pass for the PIT histogram
+            let days = n - req.window;
+            let passes = if req.operation == Operation::Backtest { 2 } else { 1 };
+            for method in &req.methods {
+                let (t, p, v) = evaluation(method, req.window, &req, &cal);
+                ns += t * (days * passes) as f64;
+                paths += p * days * passes;
+                values = values.max(v);
+            }
+            values += n;
+            days * passes * req.methods.len()
+        }
+        Operation::PortfolioVar => {
+            // Alignment, the book P&L, then the book and every position
+            let legs = req.positions + 1;
+            ns = (n * req.positions) as f64 * cal.pass_ns * 4.0 + (legs + 1) as f64 * select_cost(n, &cal);
+            values = n * (2 * req.positions + 2);
+            legs
+        }
+    };
+
+    let estimated_ms = ns / 1e6;
+    Json(json!({
+        "operation": req.operation,
+        "methods": req.methods,
+        "evaluations": evaluations,
+        "paths": paths,
+        "estimated_ms": estimated_ms,
+        "estimated_memory_bytes": budget::bytes(values),
+        // Requests over the budget are refused with 422
+        "memory_budget_bytes": budget::limit(),
+        "within_budget": budget::bytes(values) <= budget::limit(),
+        // Beyond a few seconds the request belongs in /api/jobs
+        "suggest_job": estimated_ms > 2_000.0,
+        "calibration": cal,
+        "engine": version::current(),
+    }))
+}
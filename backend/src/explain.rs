@@ -0,0 +1,409 @@
+//! Plain-language account of a finished computation: what data went in, how
+//! it was cleaned, which method ran with which parameters, and the formulas
+//! that turned the sample into the reported VaR and ES, with the numbers of
+//! that run filled in.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde_json::{json, Value};
+
+use crate::{
+    defaults,
+    error::ApiError,
+    horizon::HorizonScaling,
+    jobs::JobStatus,
+    tenant::Tenant,
+    units::{ReturnType, Units},
+    var::{self, VarRequest},
+    version, AppState,
+};
+
+// 0.99 → "99%", 0.995 → "99.5%"
+fn level(confidence: f64) -> String {
+    format!("{}%", (confidence * 1e4).round() / 1e2)
+}
+
+// A loss or return as a percentage of value
+fn pct(x: f64) -> String {
+    format!("{:.2}%", x * 100.0)
+}
+
+fn num(value: &Value) -> f64 {
+    value.as_f64().unwrap_or(f64::NAN)
+}
+
+// One part of the account, and the formula behind it if any
+struct Step {
+    title: &'static str,
+    text: String,
+    formula: Option<String>,
+}
+
+fn step(title: &'static str, text: String, formula: Option<String>) -> Step {
+    Step { title, text, formula }
+}
+
+/// The headline figures in a sentence.
+fn headline(req: &VarRequest, result: &Value) -> String {
+    let days = match req.horizon_days {
+        1 => "one day".to_string(),
+        h => format!("{h} days"),
+    };
+    let amount = |key: &str| {
+        let value = req.value.unwrap_or_default();
+        result["var_detail"][key].as_f64().map(|a| format!(" (about {a:.0} on a value of {value:.0})"))
+    };
+    format!(
+        "With {} confidence, the loss over {days} should not exceed {} of the position's value{}. In the worst {} \
+         of cases the loss averages {}{}.",
+        level(req.confidence),
+        pct(num(&result["var"])),
+        amount("loss_amount").unwrap_or_default(),
+        level(1.0 - req.confidence),
+        pct(num(&result["es"])),
+        amount("es_amount").unwrap_or_default(),
+    )
+}
+
+/// How the supplied returns became the sample the method saw.
+fn data_steps(req: &VarRequest, resolved: &VarRequest, sample: &[f64], result: &Value) -> Vec<Step> {
+    let (supplied, used) = (req.returns.len(), sample.len());
+    let worst = sample.iter().copied().fold(f64::INFINITY, f64::min);
+    let best = sample.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let window = if used < supplied {
+        let first = supplied - used + 1;
+        format!("Of the {supplied} returns supplied, the most recent {used} were used (returns {first} to {supplied})")
+    } else {
+        format!("All {supplied} returns supplied were used")
+    };
+    let mut steps = vec![step(
+        "data",
+        format!(
+            "{window}. Over that sample the average return was {} with a standard deviation of {}; \
+             the worst period lost {} and the best gained {}.",
+            pct(num(&result["mean"])),
+            pct(num(&result["std_dev"])),
+            pct(-worst),
+            pct(best),
+        ),
+        None,
+    )];
+
+    let mut cleaning = Vec::new();
+    match result["units"].as_str() {
+        Some("percent") if req.units == Units::Auto => cleaning
+            .push("the returns looked like percentages (1.0 meaning 1%), so each was divided by 100".to_string()),
+        Some("percent") => cleaning.push("the returns were given in percent and divided by 100".to_string()),
+        _ => cleaning.push("the returns were taken as decimals (0.01 meaning 1%)".to_string()),
+    }
+    if let Some(costs) = &resolved.costs {
+        cleaning.push(format!(
+            "trading costs of {} bp per trade plus half a {} bp spread on a turnover of {} were deducted from \
+             every return, {} a period",
+            costs.bps_per_trade,
+            costs.spread_bps,
+            costs.turnover,
+            pct(costs.drag())
+        ));
+    }
+    if resolved.return_type == ReturnType::Log {
+        cleaning.push("they are log returns, ln(P₁/P₀), which add up over time".to_string());
+    }
+    if resolved.params.deterministic {
+        cleaning.push("every sum was taken in a fixed order with compensated summation, so the figures reproduce \
+                       bit for bit"
+            .to_string());
+    }
+    steps.push(step("cleaning", format!("Before estimation, {}.", cleaning.join("; ")), None));
+    steps
+}
+
+/// What the method does with the sample, in words and as a formula.
+fn method_step(req: &VarRequest, n: usize, result: &Value) -> Step {
+    let c = req.confidence;
+    let tail = level(1.0 - c);
+    let z = var::parametric_z(c);
+    // Position of the VaR's order statistic, counted from 1
+    let rank = result["quantile_index"].as_u64().unwrap_or_default() + 1;
+    let lambda = result["weighting"]["lambda"].as_f64().or(result["filter"]["lambda"].as_f64());
+    let (name, text, formula) = match req.method.as_str() {
+        "historical" => (
+            "Historical simulation",
+            format!(
+                "Each of the {n} returns is treated as an equally likely outcome for the next period. They are \
+                 sorted from worst to best and the VaR is the loss at position {rank} from the bottom, the point \
+                 only {tail} of past periods fell below."
+            ),
+            format!("VaR = −r₍ₖ₊₁₎ of the {n} returns in ascending order, k = ⌊(1 − c)·n⌋ = {}", rank - 1),
+        ),
+        "weighted_historical" => match lambda {
+            Some(lambda) => (
+                "Age-weighted historical simulation",
+                format!(
+                    "Past returns are outcomes as in historical simulation, but recent ones count for more: each \
+                     weighs {lambda:.4} times the one after it (a half-life of {:.1} periods). The VaR is the loss \
+                     where the weights of the worse outcomes add up to {tail}.",
+                    num(&result["weighting"]["half_life"])
+                ),
+                format!("wᵢ ∝ λ^age with λ = {lambda:.4}; VaR = −r* where Σ wᵢ over rᵢ < r* reaches {tail}"),
+            ),
+            None => (
+                "Weighted historical simulation",
+                format!(
+                    "Past returns are outcomes weighted as the client specified (normalised to sum to one). The \
+                     VaR is the loss where the weights of the worse outcomes add up to {tail}."
+                ),
+                format!("VaR = −r* where Σ wᵢ over rᵢ < r* reaches {tail}"),
+            ),
+        },
+        "filtered_historical" => (
+            "Filtered historical simulation",
+            format!(
+                "Each past return is divided by the volatility of its day ({} model) and rescaled by the \
+                 volatility forecast for the next period, {}, so past shocks are replayed at today's level of \
+                 risk. The VaR is the loss at the {tail} quantile of those rescaled returns.",
+                result["filter"]["volatility_model"].as_str().unwrap_or("ewma"),
+                pct(num(&result["filter"]["forecast_volatility"]))
+            ),
+            "r̃ᵢ = rᵢ · σ̂ₜ₊₁ / σᵢ; VaR = −quantile(r̃, 1 − c)".to_string(),
+        ),
+        "bootstrap" => (
+            "Bootstrap historical simulation",
+            format!(
+                "The {n} returns are resampled with replacement {} times; historical simulation is run on each \
+                 resample and the VaR and ES averaged. The spread across resamples shows how much the figure \
+                 depends on the particular sample: its standard error is {}.",
+                result["bootstrap"]["samples"],
+                pct(num(&result["bootstrap"]["var_std_error"]))
+            ),
+            "VaR = mean over resamples b of −quantile(r⁽ᵇ⁾, 1 − c)".to_string(),
+        ),
+        "parametric" => (
+            "Parametric (normal) VaR",
+            format!(
+                "Returns are assumed to be normally distributed with the sample's mean and standard deviation. \
+                 At {} confidence the normal distribution puts the cut-off {z:.3} standard deviations below the \
+                 mean.",
+                level(c)
+            ),
+            format!(
+                "VaR = −(μ − z·σ) = −({} − {z:.3} × {})",
+                pct(num(&result["mean"])),
+                pct(num(&result["std_dev"]))
+            ),
+        ),
+        "ewma" => (
+            "RiskMetrics EWMA",
+            format!(
+                "Volatility is estimated with exponentially decaying weights, each return weighing {:.4} times \
+                 the one after it, so recent market conditions dominate. The VaR is that volatility times the \
+                 normal cut-off of {z:.3} at {} confidence.",
+                lambda.unwrap_or(var::DEFAULT_EWMA_LAMBDA),
+                level(c)
+            ),
+            format!("σ² = Σ wᵢ rᵢ², wᵢ ∝ λ^age with λ = {:.4}; VaR = z·σ, z = {z:.3}", lambda.unwrap_or_default()),
+        ),
+        "garch" => (
+            "GARCH(1,1)",
+            format!(
+                "A GARCH(1,1) model, in which volatility reacts to yesterday's shock and drifts back to a \
+                 long-run level, was fitted to the returns by maximum likelihood. Its forecast volatility for \
+                 the next period is {}, and the VaR is that times the normal cut-off of {z:.3}.",
+                pct(num(&result["garch"]["forecast_volatility"]))
+            ),
+            format!(
+                "σ²ₜ = ω + α ε²ₜ₋₁ + β σ²ₜ₋₁ with ω = {:.3e}, α = {:.4}, β = {:.4}; VaR = z·σₜ₊₁ − μ",
+                num(&result["garch"]["omega"]),
+                num(&result["garch"]["alpha"]),
+                num(&result["garch"]["beta"])
+            ),
+        ),
+        "parametric_t" => (
+            "Student-t VaR",
+            format!(
+                "Returns are modelled with a Student-t distribution, which has fatter tails than the normal, \
+                 scaled to the sample's variance. Its degrees of freedom, {:.2}, were {}.",
+                num(&result["dof"]),
+                result["dof_source"].as_str().unwrap_or("fitted")
+            ),
+            "VaR = −(μ − t_ν(c)·σ·√((ν − 2)/ν))".to_string(),
+        ),
+        "cornish_fisher" => (
+            "Cornish–Fisher (modified) VaR",
+            format!(
+                "The normal cut-off is adjusted for the sample's skewness ({:.3}) and excess kurtosis ({:.3}), so \
+                 lopsided or fat-tailed returns move the VaR.",
+                num(&result["skewness"]),
+                num(&result["excess_kurtosis"])
+            ),
+            "z_cf = z + (z² − 1)S/6 + (z³ − 3z)K/24 − (2z³ − 5z)S²/36; VaR = −(μ − z_cf·σ)".to_string(),
+        ),
+        _ => {
+            let paths = result["n_sims"].as_u64().unwrap_or_default();
+            let run = if req.target_se.is_some() {
+                format!(
+                    "Paths were drawn in batches until the VaR's standard error fell below {}; {paths} were needed.",
+                    pct(req.target_se.unwrap_or_default())
+                )
+            } else if req.importance.is_some() {
+                format!(
+                    "The {paths} draws were shifted {:.2} standard deviations towards losses and reweighted, which \
+                     puts far more of them in the tail.",
+                    num(&result["importance"]["tilt"])
+                )
+            } else {
+                format!("{paths} outcomes were drawn and sorted, and the VaR read off at the {tail} quantile.")
+            };
+            (
+                "Monte Carlo simulation",
+                format!(
+                    "Random outcomes are drawn from a normal distribution with the sample's mean and standard \
+                     deviation. {run}"
+                ),
+                "rⱼ = μ + σ·uⱼ, uⱼ ~ N(0, 1); VaR = −quantile(r, 1 − c)".to_string(),
+            )
+        }
+    };
+    step("method", format!("{name}: {text}"), Some(formula))
+}
+
+/// How the one-period figures reach the holding period.
+fn horizon_step(req: &VarRequest, result: &Value) -> Option<Step> {
+    let h = req.horizon_days;
+    if h == 1 {
+        return None;
+    }
+    Some(match req.horizon_scaling {
+        HorizonScaling::SqrtTime => step(
+            "horizon",
+            format!(
+                "The one-day figures were scaled to {h} days by the square-root-of-time rule, multiplying them by \
+                 √{h} = {:.3}. The rule assumes each day's return is independent of the last.",
+                f64::from(h).sqrt()
+            ),
+            Some(format!("VaR_{h}d = VaR_1d · √{h}")),
+        ),
+        HorizonScaling::Simulated => step(
+            "horizon",
+            format!(
+                "{} paths of {h} days were simulated day by day from the method's own model and {}, and the VaR \
+                 and ES read off those. The one-day VaR was {}; scaling it by √{h} would have given {}.",
+                result["horizon"]["paths"],
+                if req.return_type.is_simple() { "compounded" } else { "summed" },
+                pct(num(&result["horizon"]["one_day_var"])),
+                pct(num(&result["horizon"]["sqrt_time_var"]))
+            ),
+            Some(match req.return_type {
+                ReturnType::Simple => "R = Π(1 + rₜ) − 1 over the path; VaR = −quantile(R, 1 − c)".to_string(),
+                ReturnType::Log => "R = Σ rₜ over the path; VaR = −quantile(R, 1 − c)".to_string(),
+            }),
+        ),
+    })
+}
+
+/// The full account of `req` and the `result` it produced.
+pub fn explain(req: &VarRequest, result: &Value) -> Result<Value, ApiError> {
+    let (resolved, resolved_parameters) = defaults::resolve(req);
+    let prepared = var::prepare(&resolved).map_err(ApiError::from)?;
+    let n = prepared.returns.len();
+
+    let mut steps = data_steps(req, &resolved, &prepared.returns, result);
+    steps.push(method_step(&resolved, n, result));
+    steps.push(step(
+        "expected_shortfall",
+        format!(
+            "Expected shortfall (ES) answers how bad losses are once the VaR is breached: it is the average loss \
+             over the worst {} of outcomes, {} here against a VaR of {}.",
+            level(1.0 - req.confidence),
+            pct(num(&result["es"])),
+            pct(num(&result["var"]))
+        ),
+        Some("ES = −E[r | r ≤ −VaR]".to_string()),
+    ));
+    steps.extend(horizon_step(&resolved, result));
+    if resolved.return_type == ReturnType::Log {
+        steps.push(step(
+            "conversion",
+            format!(
+                "Being log returns, the losses L were converted to the share of value lost, 1 − e^(−L): a log \
+                 VaR of {} is a loss of {} of value.",
+                pct(num(&result["log_returns"]["var"])),
+                pct(num(&result["var"]))
+            ),
+            Some("loss = 1 − e^(−L)".to_string()),
+        ));
+    }
+    if req.is_simulated() {
+        steps.push(step(
+            "reproducibility",
+            format!("The random draws came from seed {}; sending it again reproduces the run exactly.", result["seed"]),
+            None,
+        ));
+    }
+
+    let mut warnings: Vec<Value> = result["warnings"].as_array().cloned().unwrap_or_default();
+    let methodology = result["engine"]["methodology"].as_str().unwrap_or_default();
+    if methodology != version::METHODOLOGY_VERSION {
+        warnings.push(json!(format!(
+            "computed under methodology {methodology}; this account describes methodology {}",
+            version::METHODOLOGY_VERSION
+        )));
+    }
+    // Results from before parameters were echoed get them worked out again
+    let parameters = match &result["parameters"] {
+        Value::Null => json!(resolved_parameters),
+        parameters => parameters.clone(),
+    };
+    let text = std::iter::once(headline(req, result))
+        .chain(steps.iter().map(|s| s.text.clone()))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    Ok(json!({
+        "headline": headline(req, result),
+        "method": req.method,
+        "parameters": parameters,
+        "data": {
+            "supplied": req.returns.len(),
+            "used": n,
+            // Positions in the supplied series, counted from 1
+            "range": [req.returns.len() - n + 1, req.returns.len()],
+            "units": result["units"],
+            "return_type": resolved.return_type,
+            "cost_drag": prepared.cost_drag,
+        },
+        "steps": steps.iter().map(|s| json!({ "step": s.title, "text": s.text })).collect::<Vec<_>>(),
+        "formulas": steps
+            .iter()
+            .filter_map(|s| Some(json!({ "step": s.title, "formula": s.formula.as_ref()? })))
+            .collect::<Vec<_>>(),
+        "warnings": warnings,
+        "engine": result["engine"],
+        "text": text,
+    }))
+}
+
+/// How one of the caller's finished jobs' VaR was computed, in plain language
+pub async fn explain_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let job = state.jobs.owned(&id, &tenant)?;
+    let result = match (job.status, &job.result) {
+        (JobStatus::Done, Some(result)) => result,
+        (status, _) => {
+            let status = serde_json::to_value(status).unwrap_or_default();
+            return Err(ApiError::new(
+                StatusCode::CONFLICT,
+                format!("job {id} is {}; only finished computations can be explained", status.as_str().unwrap_or("?")),
+            ));
+        }
+    };
+    let mut body = explain(&job.request, result)?;
+    body["id"] = json!(job.id);
+    body["computed_at"] = json!(job.finished_at);
+    Ok(Json(body))
+}
@@ -0,0 +1,96 @@
+//! FIXML position reports (`PosRpt`), as an OMS or clearing system exports
+//! them, read into tickers and market values. Only the few elements and
+//! attributes a position needs are looked at.
+
+use crate::xml::{self, Tag};
+
+// PosRpt attributes (tag numbers in the FIX spec)
+const ACCOUNT: &str = "Acct"; // 1
+const SETTLEMENT_PRICE: &str = "SetPx"; // 730
+// Quantity types preferred for the holding: end of day, then start of day
+const QUANTITY_TYPES: [&str; 2] = ["FIN", "SOD"];
+// Amount type carrying the position's settlement value
+const SETTLEMENT_VALUE: &str = "SETL";
+
+/// One position report, valued in the report's currency.
+pub struct FixmlPosition {
+    pub account: Option<String>,
+    pub ticker: String,
+    pub value: f64,
+}
+
+fn number(tag: &Tag, attribute: &str) -> Result<Option<f64>, String> {
+    match tag.get(attribute) {
+        None => Ok(None),
+        Some(v) => v
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|x| x.is_finite())
+            .map(Some)
+            .ok_or_else(|| format!("{}@{attribute} {v:?} is not a number", tag.name)),
+    }
+}
+
+// A PosRpt's elements, reduced to a position
+fn position(report: &Tag, children: &[Tag]) -> Result<FixmlPosition, String> {
+    let ticker = children
+        .iter()
+        .find(|t| t.name == "Instrmt")
+        .and_then(|t| t.get("Sym").or_else(|| t.get("ID")))
+        .ok_or("no Instrmt with a Sym")?
+        .trim()
+        .to_string();
+    let account = report.get(ACCOUNT).map(str::to_string);
+    if let Some(amount) = children.iter().find(|t| t.name == "Amt" && t.get("Typ") == Some(SETTLEMENT_VALUE)) {
+        let value = number(amount, "Amt")?.ok_or("Amt of type SETL without a value")?;
+        return Ok(FixmlPosition { account, ticker, value });
+    }
+    let quantities: Vec<&Tag> = children.iter().filter(|t| t.name == "Qty").collect();
+    let quantity = QUANTITY_TYPES
+        .iter()
+        .find_map(|typ| quantities.iter().find(|t| t.get("Typ") == Some(typ)))
+        .or(quantities.first())
+        .ok_or("no Qty")?;
+    let net = number(quantity, "Long")?.unwrap_or(0.0) - number(quantity, "Short")?.unwrap_or(0.0);
+    let price = number(report, SETTLEMENT_PRICE)?.ok_or("no SetPx or SETL amount to value the position")?;
+    let multiplier = match children.iter().find(|t| t.name == "Instrmt") {
+        Some(instrument) => number(instrument, "Mult")?.unwrap_or(1.0),
+        None => 1.0,
+    };
+    Ok(FixmlPosition { account, ticker, value: net * price * multiplier })
+}
+
+/// Every `PosRpt` of a FIXML document (bare or in a `Batch`), in order,
+/// each valued from its `SETL` amount if it has one, else as net quantity
+/// (`Long` − `Short`, end-of-day if reported) × `SetPx` × the instrument's
+/// `Mult`. A report that cannot be read is an `Err` in its place.
+pub fn positions(xml: &str) -> Result<Vec<Result<FixmlPosition, String>>, String> {
+    let tags = xml::tags(xml)?;
+    let mut reports = Vec::new();
+    let mut i = 0;
+    while i < tags.len() {
+        let tag = &tags[i];
+        i += 1;
+        if tag.name != "PosRpt" || tag.closing {
+            continue;
+        }
+        if tag.self_closing {
+            reports.push(position(tag, &[]));
+            continue;
+        }
+        let start = i;
+        while i < tags.len() && !(tags[i].name == "PosRpt" && tags[i].closing) {
+            i += 1;
+        }
+        if i == tags.len() {
+            return Err("unterminated PosRpt".into());
+        }
+        reports.push(position(tag, &tags[start..i]));
+        i += 1;
+    }
+    if reports.is_empty() {
+        return Err("no PosRpt elements found".into());
+    }
+    Ok(reports)
+}
@@ -0,0 +1,161 @@
+use axum::{
+    extract::{MatchedPath, Path, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    sync::Mutex,
+};
+
+use crate::{admin::Admin, error::ApiError, tenant::Tenant, AppState};
+
+/// A gate on an experimental method (`method.<name>`) or endpoint
+/// (`endpoint.<route>`, e.g. `endpoint./api/compare_models`). Anything
+/// without a flag is generally available; a flagged feature is open to
+/// everyone when `enabled`, otherwise only to the listed pilot tenants.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Flag {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub tenants: BTreeSet<String>,
+}
+
+#[derive(Default)]
+pub struct FlagStore {
+    flags: Mutex<BTreeMap<String, Flag>>,
+}
+
+impl FlagStore {
+    /// Reads `FEATURE_FLAGS`: `;`-separated `name=tenant,tenant` entries,
+    /// where `*` enables the flag for everyone and an empty list for no one.
+    pub fn from_env() -> Self {
+        Self::parse(&env::var("FEATURE_FLAGS").unwrap_or_default())
+    }
+
+    fn parse(spec: &str) -> Self {
+        let store = FlagStore::default();
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, tenants) = entry.split_once('=').unwrap_or((entry, ""));
+            let mut flag = Flag::default();
+            for tenant in tenants.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                match tenant {
+                    "*" => flag.enabled = true,
+                    t => {
+                        flag.tenants.insert(t.to_string());
+                    }
+                }
+            }
+            store.set(name.trim(), flag);
+        }
+        store
+    }
+
+    pub fn all(&self) -> BTreeMap<String, Flag> {
+        self.flags.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, name: &str, flag: Flag) {
+        self.flags.lock().unwrap().insert(name.to_string(), flag);
+    }
+
+    fn remove(&self, name: &str) -> Option<Flag> {
+        self.flags.lock().unwrap().remove(name)
+    }
+
+    pub fn allows(&self, name: &str, tenant: &str) -> bool {
+        self.flags
+            .lock()
+            .unwrap()
+            .get(name)
+            .is_none_or(|f| f.enabled || f.tenants.contains(tenant))
+    }
+
+    /// Rejects methods still in pilot for tenants outside it.
+    pub fn check_method(&self, method: &str, tenant: &str) -> Result<(), ApiError> {
+        if self.allows(&format!("method.{method}"), tenant) {
+            Ok(())
+        } else {
+            Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                format!("method {method} is not enabled for tenant {tenant}"),
+            ))
+        }
+    }
+}
+
+/// Middleware turning away callers from endpoints that are flagged off for
+/// their tenant
+pub async fn gate_endpoints(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    path: MatchedPath,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !state.flags.allows(&format!("endpoint.{}", path.as_str()), &tenant) {
+        return ApiError::new(
+            StatusCode::FORBIDDEN,
+            format!("{} is not enabled for tenant {tenant}", path.as_str()),
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+/// All feature flags
+pub async fn list_flags_handler(_: Admin, State(state): State<AppState>) -> Json<BTreeMap<String, Flag>> {
+    Json(state.flags.all())
+}
+
+/// Create or replace a flag
+pub async fn put_flag_handler(
+    _: Admin,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(flag): Json<Flag>,
+) -> Json<Flag> {
+    println!("🚩 Flag {} → enabled={}, tenants={:?}", name, flag.enabled, flag.tenants);
+    state.flags.set(&name, flag.clone());
+    Json(flag)
+}
+
+/// Remove a flag, making the feature generally available
+pub async fn delete_flag_handler(
+    _: Admin,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .flags
+        .remove(&name)
+        .map(|_| StatusCode::NO_CONTENT)
+        .ok_or_else(|| ApiError::not_found(format!("flag {name} not found")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flagged_features_are_open_to_pilot_tenants_only() {
+        let flags = FlagStore::parse("method.garch=acme, beta ; endpoint./api/compare_models=*; method.evt=");
+        assert!(flags.check_method("garch", "acme").is_ok());
+        assert!(flags.check_method("garch", "beta").is_ok());
+        assert_eq!(flags.check_method("garch", "other").err().unwrap().status, StatusCode::FORBIDDEN);
+        assert!(flags.check_method("evt", "acme").is_err());
+        assert!(flags.allows("endpoint./api/compare_models", "other"));
+        // Unflagged features are generally available
+        assert!(flags.check_method("historical", "other").is_ok());
+
+        flags.set("method.evt", Flag { enabled: false, tenants: BTreeSet::from(["other".to_string()]) });
+        assert!(flags.check_method("evt", "other").is_ok());
+        assert!(flags.remove("method.garch").is_some());
+        assert!(flags.check_method("garch", "other").is_ok());
+    }
+}
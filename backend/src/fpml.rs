@@ -0,0 +1,180 @@
+//! A minimal subset of FpML confirmations: equity options (`equityOption`)
+//! and FX forwards (`fxSingleLeg`, or `fxForward`), mapped into linear
+//! positions the engine can risk. A forward is its exposure to the currency
+//! pair; an option its Black–Scholes delta-equivalent in the underlyer, at
+//! the underlyer's stored close and realised volatility.
+
+use chrono::NaiveDate;
+use statrs::distribution::{ContinuousCDF, Normal};
+
+use crate::{
+    portfolios::Position,
+    reduce::Summation,
+    storage::PriceStore,
+    xml::{self, Element},
+};
+
+const TRADING_DAYS: f64 = 252.0;
+// Most recent returns behind an option's volatility
+const VOLATILITY_WINDOW: usize = 250;
+
+/// A trade as read, from the perspective of the party holding the book.
+pub enum Trade {
+    // Exposure to `pair` (currency1 then currency2), signed: positive when
+    // the book receives currency1; in currency2 at the contract rate
+    FxForward { pair: String, value: f64 },
+    EquityOption { underlyer: String, call: bool, long: bool, strike: f64, quantity: f64, expiry: NaiveDate },
+}
+
+fn text<'a>(element: &'a Element, path: &[&str]) -> Result<&'a str, String> {
+    element.path(path).map(|e| e.text.trim()).filter(|t| !t.is_empty()).ok_or_else(|| format!("no {}", path.join("/")))
+}
+
+fn number(element: &Element, path: &[&str]) -> Result<f64, String> {
+    let value = text(element, path)?;
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|x| x.is_finite())
+        .ok_or_else(|| format!("{} {value:?} is not a number", path.join("/")))
+}
+
+fn reference<'a>(element: &'a Element, name: &str) -> Option<&'a str> {
+    element.child(name).and_then(|e| e.attribute("href"))
+}
+
+fn fx_forward(leg: &Element, party: &str) -> Result<Trade, String> {
+    let currency1 = text(leg, &["exchangeRate", "quotedCurrencyPair", "currency1"])?;
+    let currency2 = text(leg, &["exchangeRate", "quotedCurrencyPair", "currency2"])?;
+    let rate = number(leg, &["exchangeRate", "rate"])?;
+    let exchanged = ["exchangedCurrency1", "exchangedCurrency2"]
+        .iter()
+        .filter_map(|name| leg.child(name))
+        .find(|e| text(e, &["paymentAmount", "currency"]).ok() == Some(currency1))
+        .ok_or_else(|| format!("no exchanged currency in {currency1}"))?;
+    let amount = number(exchanged, &["paymentAmount", "amount"])?;
+    let sign = if reference(exchanged, "receiverPartyReference") == Some(party) {
+        1.0
+    } else if reference(exchanged, "payerPartyReference") == Some(party) {
+        -1.0
+    } else {
+        return Err(format!("party {party} neither pays nor receives {currency1}"));
+    };
+    Ok(Trade::FxForward { pair: format!("{currency1}{currency2}"), value: sign * amount * rate })
+}
+
+fn equity_option(option: &Element, party: &str) -> Result<Trade, String> {
+    let long = if reference(option, "buyerPartyReference") == Some(party) {
+        true
+    } else if reference(option, "sellerPartyReference") == Some(party) {
+        false
+    } else {
+        return Err(format!("party {party} neither buys nor sells the option"));
+    };
+    let call = match text(option, &["optionType"])? {
+        "Call" => true,
+        "Put" => false,
+        other => return Err(format!("optionType {other:?} is neither Call nor Put")),
+    };
+    let underlyer = option
+        .child("underlyer")
+        .and_then(|u| u.find("instrumentId"))
+        .map(|e| e.text.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .ok_or("no underlyer instrumentId")?;
+    let expiry = option.child("equityExercise").and_then(|e| e.find("expirationDate")).ok_or("no expirationDate")?;
+    let expiry = expiry.find("unadjustedDate").map(|e| e.text.trim()).ok_or("no expirationDate unadjustedDate")?;
+    let expiry = NaiveDate::parse_from_str(expiry, "%Y-%m-%d").map_err(|_| format!("expiry {expiry:?} is not a date"))?;
+    let entitlement = match option.child("optionEntitlement") {
+        Some(_) => number(option, &["optionEntitlement"])?,
+        None => 1.0,
+    };
+    Ok(Trade::EquityOption {
+        underlyer,
+        call,
+        long,
+        strike: number(option, &["strike", "strikePrice"])?,
+        quantity: number(option, &["numberOfOptions"])? * entitlement,
+        expiry,
+    })
+}
+
+/// Every `trade` of an FpML document, in order, as seen by `party` (an id
+/// of the document's `party` elements; by default the first). A trade that
+/// cannot be read, or is of another product, is an `Err` in its place.
+pub fn trades(xml: &str, party: Option<&str>) -> Result<Vec<Result<Trade, String>>, String> {
+    let root = xml::parse(xml)?;
+    let mut parties = Vec::new();
+    root.find_all("party", &mut parties);
+    let party = match party {
+        Some(party) => party.to_string(),
+        None => parties.iter().find_map(|p| p.attribute("id")).ok_or("no party to read the trades as")?.to_string(),
+    };
+    let mut trades = Vec::new();
+    root.find_all("trade", &mut trades);
+    if trades.is_empty() {
+        return Err("no trade elements found".into());
+    }
+    Ok(trades
+        .into_iter()
+        .map(|trade| {
+            if let Some(option) = trade.child("equityOption") {
+                equity_option(option, &party)
+            } else if let Some(leg) = trade.child("fxSingleLeg").or_else(|| trade.child("fxForward")) {
+                fx_forward(leg, &party)
+            } else {
+                Err("unsupported product; only equityOption and fxSingleLeg are read".into())
+            }
+        })
+        .collect())
+}
+
+/// Black–Scholes delta at zero rates and dividends; at zero volatility the
+/// option is its intrinsic step.
+pub fn delta(call: bool, spot: f64, strike: f64, volatility: f64, years: f64) -> f64 {
+    let d1 = if volatility > 0.0 {
+        ((spot / strike).ln() + 0.5 * volatility * volatility * years) / (volatility * years.sqrt())
+    } else if spot >= strike {
+        f64::INFINITY
+    } else {
+        f64::NEG_INFINITY
+    };
+    let n = Normal::standard().cdf(d1);
+    if call {
+        n
+    } else {
+        n - 1.0
+    }
+}
+
+// Latest close and annualised volatility of the underlyer's stored history
+async fn market(store: &dyn PriceStore, ticker: &str) -> Result<(f64, f64), String> {
+    let bars = store.range(ticker, None, None).await?;
+    let bars = &bars[bars.len().saturating_sub(VOLATILITY_WINDOW + 1)..];
+    if bars.len() < 3 {
+        return Err(format!("no stored price history for {ticker} to delta-map the option"));
+    }
+    let returns: Vec<f64> = bars.windows(2).map(|w| w[1].1 / w[0].1 - 1.0).collect();
+    let (_, std) = Summation::Sequential.mean_std(&returns);
+    Ok((bars[bars.len() - 1].1, std * TRADING_DAYS.sqrt()))
+}
+
+impl Trade {
+    /// The linear position the trade is risked as, as of `today`.
+    pub async fn position(&self, store: &dyn PriceStore, today: NaiveDate) -> Result<Position, String> {
+        match self {
+            Trade::FxForward { pair, value } => Ok(Position { ticker: pair.clone(), value: *value, against: None }),
+            Trade::EquityOption { underlyer, call, long, strike, quantity, expiry } => {
+                let days = (*expiry - today).num_days();
+                if days <= 0 {
+                    return Err(format!("option on {underlyer} expired on {expiry}"));
+                }
+                let ticker = underlyer.to_uppercase();
+                let (spot, volatility) = market(store, &ticker).await?;
+                let delta = delta(*call, spot, *strike, volatility, days as f64 / 365.0);
+                let sign = if *long { 1.0 } else { -1.0 };
+                Ok(Position { ticker, value: sign * delta * quantity * spot, against: None })
+            }
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    cleaning,
+    error::ApiError,
+    storage::{Bar, RangeQuery},
+    validation::{cross_field, Valid},
+    AppState,
+};
+
+// One dated contract; its prices are stored under `symbol` like any ticker
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Contract {
+    pub symbol: String,
+    pub expiry: NaiveDate,
+}
+
+// When positions move to the next contract
+#[derive(Clone, Serialize, Deserialize, Validate)]
+pub struct RollRule {
+    // Roll this many calendar days before expiry
+    #[serde(default = "default_roll_days")]
+    #[validate(range(max = 90, message = "days_before_expiry must be at most 90"))]
+    pub days_before_expiry: u32,
+}
+
+fn default_roll_days() -> u32 {
+    5
+}
+
+impl Default for RollRule {
+    fn default() -> Self {
+        RollRule { days_before_expiry: default_roll_days() }
+    }
+}
+
+/// A futures instrument: contract economics plus the dated contracts that
+/// are chained into its continuous series.
+#[derive(Clone, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "distinct_contracts"))]
+pub struct FuturesSpec {
+    #[serde(default)]
+    pub root: String,
+    // Currency value of a one-point move per contract
+    #[validate(range(exclusive_min = 0.0, message = "multiplier must be positive"))]
+    pub multiplier: f64,
+    // Initial margin per contract
+    #[serde(default)]
+    #[validate(range(min = 0.0, message = "margin must not be negative"))]
+    pub margin: f64,
+    #[serde(default)]
+    #[validate(nested)]
+    pub roll: RollRule,
+    #[validate(length(min = 1, message = "at least one contract is required"))]
+    pub contracts: Vec<Contract>,
+}
+
+fn distinct_contracts(spec: &FuturesSpec) -> Result<(), ValidationError> {
+    let mut symbols: Vec<String> = spec.contracts.iter().map(|c| c.symbol.trim().to_uppercase()).collect();
+    if symbols.iter().any(String::is_empty) {
+        return Err(cross_field("contracts", "contract symbols must not be blank".into()));
+    }
+    symbols.sort();
+    symbols.dedup();
+    if symbols.len() != spec.contracts.len() {
+        return Err(cross_field("contracts", "contract symbols must be unique".into()));
+    }
+    Ok(())
+}
+
+impl FuturesSpec {
+    /// Contract held on `date`: the first whose roll date is still ahead.
+    fn active(&self, date: NaiveDate) -> Option<&Contract> {
+        let lead = Duration::days(i64::from(self.roll.days_before_expiry));
+        self.contracts.iter().find(|c| date < c.expiry - lead)
+    }
+}
+
+// Switch from one contract to the next
+#[derive(Serialize)]
+pub struct Roll {
+    pub date: String,
+    pub from: String,
+    pub to: String,
+}
+
+// Continuous series chained from the individual contracts
+#[derive(Serialize)]
+pub struct Continuous {
+    pub root: String,
+    // Ratio back-adjusted closes: the latest equals the front contract's
+    // price, earlier ones are scaled so no roll gap shows up as a return
+    pub closes: Vec<Bar>,
+    pub rolls: Vec<Roll>,
+    // Days skipped because the held contract had no price on both days
+    pub gaps: usize,
+}
+
+/// Chains contract prices into one return series. Each day's return is
+/// measured on the contract held that day, including on roll days (the new
+/// contract's move from the previous close), so the price difference
+/// between contracts never counts as P&L.
+pub fn continuous(spec: &FuturesSpec, prices: &HashMap<String, BTreeMap<String, f64>>) -> Continuous {
+    let mut dates: Vec<&String> = prices.values().flat_map(|p| p.keys()).collect();
+    dates.sort();
+    dates.dedup();
+
+    let mut returns: Vec<(String, f64)> = Vec::new();
+    let mut rolls = Vec::new();
+    let mut gaps = 0;
+    let mut held: Option<&Contract> = None;
+    for pair in dates.windows(2) {
+        let (prev, date) = (pair[0], pair[1]);
+        let Some(day) = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok() else {
+            continue;
+        };
+        let Some(contract) = spec.active(day) else {
+            continue;
+        };
+        if let Some(old) = held.filter(|old| old.symbol != contract.symbol) {
+            rolls.push(Roll { date: date.clone(), from: old.symbol.clone(), to: contract.symbol.clone() });
+        }
+        held = Some(contract);
+        let series = prices.get(&contract.symbol);
+        match series.and_then(|s| Some((s.get(prev)?, s.get(date)?))) {
+            Some((p0, p1)) => returns.push((date.clone(), p1 / p0 - 1.0)),
+            None => gaps += 1,
+        }
+    }
+
+    // Anchor on the held contract's close on the last return date
+    let last = returns.last().zip(held).and_then(|((date, _), c)| prices.get(&c.symbol)?.get(date).copied());
+    let mut closes = Vec::with_capacity(returns.len() + 1);
+    if let (Some(mut level), Some((first_date, _))) = (last, returns.first()) {
+        // The close before the first return is dated like that return's previous day
+        let first_prev = dates.iter().position(|d| *d == first_date).map(|i| dates[i - 1].clone());
+        for (date, r) in returns.iter().rev() {
+            closes.push((date.clone(), level));
+            level /= 1.0 + r;
+        }
+        closes.extend(first_prev.map(|d| (d, level)));
+        closes.reverse();
+    }
+    Continuous { root: spec.root.clone(), closes, rolls, gaps }
+}
+
+/// Registered futures roots
+#[derive(Default)]
+pub struct FuturesStore {
+    specs: Mutex<HashMap<String, FuturesSpec>>,
+}
+
+impl FuturesStore {
+    pub fn all(&self) -> Vec<FuturesSpec> {
+        let mut specs: Vec<FuturesSpec> = self.specs.lock().unwrap().values().cloned().collect();
+        specs.sort_by(|a, b| a.root.cmp(&b.root));
+        specs
+    }
+
+    pub fn get(&self, root: &str) -> Option<FuturesSpec> {
+        self.specs.lock().unwrap().get(root).cloned()
+    }
+
+    pub fn restore(&self, spec: FuturesSpec) {
+        self.specs.lock().unwrap().insert(spec.root.clone(), spec);
+    }
+
+    fn remove(&self, root: &str) -> bool {
+        self.specs.lock().unwrap().remove(root).is_some()
+    }
+}
+
+/// Continuous series of a root from its contracts' stored prices.
+pub async fn load_continuous(
+    state: &AppState,
+    spec: &FuturesSpec,
+    start: Option<&str>,
+    end: Option<&str>,
+) -> Result<Continuous, ApiError> {
+    let mut prices = HashMap::new();
+    for contract in &spec.contracts {
+        let bars = state
+            .prices
+            .range(&contract.symbol, start, end)
+            .await
+            .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        prices.insert(contract.symbol.clone(), cleaning::closes_by_date(&bars));
+    }
+    Ok(continuous(spec, &prices))
+}
+
+/// Registered futures roots
+pub async fn list_futures_handler(State(state): State<AppState>) -> Json<Vec<FuturesSpec>> {
+    Json(state.futures.all())
+}
+
+/// Register or replace a futures root and its contracts
+pub async fn put_futures_handler(
+    State(state): State<AppState>,
+    Path(root): Path<String>,
+    Valid(mut spec): Valid<FuturesSpec>,
+) -> Json<FuturesSpec> {
+    spec.root = root.trim().to_uppercase();
+    for contract in &mut spec.contracts {
+        contract.symbol = contract.symbol.trim().to_uppercase();
+    }
+    spec.contracts.sort_by_key(|c| c.expiry);
+    state.futures.restore(spec.clone());
+    Json(spec)
+}
+
+/// Remove a futures root
+pub async fn delete_futures_handler(State(state): State<AppState>, Path(root): Path<String>) -> Result<StatusCode, ApiError> {
+    let root = root.to_uppercase();
+    if state.futures.remove(&root) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::not_found(format!("futures root {root} not found")))
+    }
+}
+
+/// Continuous back-adjusted series of a root with its roll dates
+pub async fn continuous_handler(
+    State(state): State<AppState>,
+    Path(root): Path<String>,
+    Query(q): Query<RangeQuery>,
+) -> Result<Json<Continuous>, ApiError> {
+    let root = root.to_uppercase();
+    let spec = state.futures.get(&root).ok_or_else(|| ApiError::not_found(format!("futures root {root} not found")))?;
+    load_continuous(&state, &spec, q.start.as_deref(), q.end.as_deref()).await.map(Json)
+}
@@ -0,0 +1,180 @@
+use serde::Serialize;
+
+use crate::var::{parametric_z, MethodParams};
+
+// Cap on α + β: at 1 the variance has no long-run level to revert to
+const MAX_PERSISTENCE: f64 = 0.9999;
+// Starting point of the fit, typical of daily equity returns
+const START_ALPHA: f64 = 0.05;
+const START_BETA: f64 = 0.90;
+const MAX_ITERATIONS: usize = 2_000;
+// Nelder–Mead stops once the simplex's log-likelihoods agree this closely
+const TOLERANCE: f64 = 1e-10;
+
+/// GARCH(1,1) fitted by Gaussian maximum likelihood,
+/// σ²ₜ = ω + α ε²ₜ₋₁ + β σ²ₜ₋₁ with εₜ = rₜ − μ, and its next-day forecast.
+#[derive(Clone, Copy, Serialize)]
+pub struct Garch {
+    pub omega: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    pub mean: f64,
+    pub persistence: f64,
+    // √(ω / (1 − α − β)), the level the forecast reverts to
+    pub long_run_volatility: f64,
+    pub forecast_volatility: f64,
+    pub log_likelihood: f64,
+    pub iterations: usize,
+}
+
+impl Garch {
+    /// Conditional volatilities of `returns` under the fitted model, one per
+    /// day from the information before it, and the next day's forecast.
+    pub fn volatilities(&self, returns: &[f64], params: &MethodParams) -> (Vec<f64>, f64) {
+        let (_, std) = params.summation().mean_std(returns);
+        let residuals: Vec<f64> = returns.iter().map(|r| r - self.mean).collect();
+        let (path, forecast) = variances(&residuals, std * std, (self.omega, self.alpha, self.beta));
+        (path.into_iter().map(f64::sqrt).collect(), forecast.sqrt())
+    }
+
+    /// Normal VaR and ES of the next day around the sample mean, at the
+    /// forecast volatility.
+    pub fn var_es(&self, confidence: f64) -> (f64, f64) {
+        let z = parametric_z(confidence);
+        let density = (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt();
+        let std = self.forecast_volatility;
+        (-(self.mean - z * std), -(self.mean - std * density / (1.0 - confidence)))
+    }
+}
+
+// Unconstrained coordinates → (ω, α, β): ω = eˣ⁰, α + β = cap·logistic(x₁),
+// α its logistic(x₂) share, so every point is a stationary, positive model
+fn parameters(x: &[f64]) -> (f64, f64, f64) {
+    let logistic = |v: f64| 1.0 / (1.0 + (-v).exp());
+    let persistence = MAX_PERSISTENCE * logistic(x[1]);
+    let alpha = persistence * logistic(x[2]);
+    (x[0].exp(), alpha, persistence - alpha)
+}
+
+fn logit(p: f64) -> f64 {
+    (p / (1.0 - p)).ln()
+}
+
+/// Conditional variances of `residuals` under (ω, α, β), the recursion
+/// started at their sample variance, with the forecast for the day after.
+fn variances(residuals: &[f64], variance: f64, (omega, alpha, beta): (f64, f64, f64)) -> (Vec<f64>, f64) {
+    let mut path = Vec::with_capacity(residuals.len());
+    let mut current = variance;
+    for e in residuals {
+        path.push(current);
+        current = omega + alpha * e * e + beta * current;
+    }
+    (path, current)
+}
+
+fn log_likelihood(residuals: &[f64], path: &[f64]) -> f64 {
+    let ln_2pi = (2.0 * std::f64::consts::PI).ln();
+    -0.5 * residuals.iter().zip(path).map(|(e, s2)| ln_2pi + s2.ln() + e * e / s2).sum::<f64>()
+}
+
+/// Minimises `f` from `start` by the Nelder–Mead simplex method; returns
+/// the best point and the iterations used.
+fn nelder_mead(f: impl Fn(&[f64]) -> f64, start: &[f64], step: f64) -> (Vec<f64>, usize) {
+    let n = start.len();
+    let mut simplex: Vec<(Vec<f64>, f64)> = (0..=n)
+        .map(|i| {
+            let mut x = start.to_vec();
+            if i > 0 {
+                x[i - 1] += step;
+            }
+            let fx = f(&x);
+            (x, fx)
+        })
+        .collect();
+    let point = |a: &[f64], b: &[f64], t: f64| -> Vec<f64> { a.iter().zip(b).map(|(a, b)| a + t * (b - a)).collect() };
+    for iteration in 0..MAX_ITERATIONS {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        if (simplex[n].1 - simplex[0].1).abs() <= TOLERANCE * (1.0 + simplex[0].1.abs()) {
+            return (simplex.swap_remove(0).0, iteration);
+        }
+        let centroid: Vec<f64> =
+            (0..n).map(|j| simplex[..n].iter().map(|(x, _)| x[j]).sum::<f64>() / n as f64).collect();
+        let worst = simplex[n].clone();
+        let reflected = point(&centroid, &worst.0, -1.0);
+        let fr = f(&reflected);
+        if fr < simplex[0].1 {
+            let expanded = point(&centroid, &worst.0, -2.0);
+            let fe = f(&expanded);
+            simplex[n] = if fe < fr { (expanded, fe) } else { (reflected, fr) };
+        } else if fr < simplex[n - 1].1 {
+            simplex[n] = (reflected, fr);
+        } else {
+            let contracted = point(&centroid, &worst.0, 0.5);
+            let fc = f(&contracted);
+            if fc < worst.1 {
+                simplex[n] = (contracted, fc);
+            } else {
+                // Shrink towards the best point
+                let best = simplex[0].0.clone();
+                for (x, fx) in simplex.iter_mut().skip(1) {
+                    *x = point(&best, x, 0.5);
+                    *fx = f(x);
+                }
+            }
+        }
+    }
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    (simplex.swap_remove(0).0, MAX_ITERATIONS)
+}
+
+/// Fits GARCH(1,1) to chronological `returns` by maximising the Gaussian
+/// log-likelihood of the demeaned series. A series without variation has
+/// nothing to fit and forecasts zero volatility.
+pub fn fit(returns: &[f64], params: &MethodParams) -> Garch {
+    let (mean, std) = params.summation().mean_std(returns);
+    let variance = std * std;
+    if variance == 0.0 {
+        return Garch {
+            omega: 0.0,
+            alpha: 0.0,
+            beta: 0.0,
+            mean,
+            persistence: 0.0,
+            long_run_volatility: 0.0,
+            forecast_volatility: 0.0,
+            log_likelihood: f64::NAN,
+            iterations: 0,
+        };
+    }
+    let residuals: Vec<f64> = returns.iter().map(|r| r - mean).collect();
+    let negative_likelihood = |x: &[f64]| {
+        let (path, _) = variances(&residuals, variance, parameters(x));
+        let ll = log_likelihood(&residuals, &path);
+        if ll.is_finite() {
+            -ll
+        } else {
+            f64::INFINITY
+        }
+    };
+    // Start with the unconditional variance matching the sample's
+    let persistence = START_ALPHA + START_BETA;
+    let start = [
+        (variance * (1.0 - persistence)).ln(),
+        logit(persistence / MAX_PERSISTENCE),
+        logit(START_ALPHA / persistence),
+    ];
+    let (best, iterations) = nelder_mead(negative_likelihood, &start, 0.5);
+    let (omega, alpha, beta) = parameters(&best);
+    let (path, forecast) = variances(&residuals, variance, (omega, alpha, beta));
+    Garch {
+        omega,
+        alpha,
+        beta,
+        mean,
+        persistence: alpha + beta,
+        long_run_volatility: (omega / (1.0 - alpha - beta)).sqrt(),
+        forecast_volatility: forecast.sqrt(),
+        log_likelihood: log_likelihood(&residuals, &path),
+        iterations,
+    }
+}
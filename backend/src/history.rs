@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backfill::{self, Backfill},
+    storage::Bar,
+};
+
+/// What to do when a series is shorter than its method's minimum history.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryPolicy {
+    // Refuse the computation
+    Reject,
+    // Compute on the short sample and say so
+    Warn,
+    // Backfill from a proxy; series that can't be backfilled are refused
+    Proxy,
+}
+
+impl HistoryPolicy {
+    /// The explicit policy, else `proxy` when a proxy was named and `warn` otherwise.
+    pub fn resolve(policy: Option<HistoryPolicy>, has_proxy: bool) -> HistoryPolicy {
+        match policy {
+            Some(policy) => policy,
+            None if has_proxy => HistoryPolicy::Proxy,
+            None => HistoryPolicy::Warn,
+        }
+    }
+}
+
+/// Returns each method needs before its estimate is trusted: a year of
+/// daily data for plain historical simulation (about a dozen tail points at
+/// 95%), less where age weighting or a fitted distribution does the work.
+pub fn min_history(method: &str) -> usize {
+    match method {
+        // A fitted tail shape needs more data than a fitted volatility
+        "weighted_historical" | "parametric_t" | "cornish_fisher" => 100,
+        "parametric" | "ewma" | "montecarlo" => 60,
+        _ => 250,
+    }
+}
+
+pub fn short_message(label: &str, observations: usize, min_history: usize) -> String {
+    format!("{label} has only {observations} returns, fewer than the {min_history} required")
+}
+
+/// Applies `policy` to a short client-supplied series (no ticker, so no proxy).
+pub fn check_series(policy: HistoryPolicy, observations: usize, min_history: usize) -> Result<Option<String>, String> {
+    if observations >= min_history {
+        return Ok(None);
+    }
+    let message = short_message("the series", observations, min_history);
+    match policy {
+        HistoryPolicy::Warn => Ok(Some(format!("{message}; the estimate rests on a short sample"))),
+        HistoryPolicy::Reject => Err(message),
+        HistoryPolicy::Proxy => Err(format!("{message}; proxy backfill needs a ticker (use /api/fetch_returns or /api/portfolio_var)")),
+    }
+}
+
+/// Applies `policy` to a ticker's price history: long series pass through,
+/// short ones are backfilled from `proxy` (proxy policy), used with a warning
+/// (warn) or refused (reject, or proxy without usable proxy data).
+pub fn enforce(
+    policy: HistoryPolicy,
+    ticker: &str,
+    bars: Vec<Bar>,
+    proxy: Option<(&str, &[Bar])>,
+    min_history: usize,
+    warnings: &mut Vec<String>,
+) -> Result<(Vec<Bar>, Option<Backfill>), String> {
+    let own = backfill::own_observations(&bars);
+    if own >= min_history {
+        return Ok((bars, None));
+    }
+    let message = short_message(ticker, own, min_history);
+    match (policy, proxy) {
+        (HistoryPolicy::Reject, _) => Err(message),
+        (HistoryPolicy::Warn, _) => {
+            warnings.push(format!("{message}; its VaR rests on a short sample. Name a proxy to backfill it"));
+            Ok((bars, None))
+        }
+        (HistoryPolicy::Proxy, None) => Err(format!("{message} and no proxy was named for it")),
+        (HistoryPolicy::Proxy, Some((proxy, proxy_bars))) => match backfill::splice(ticker, &bars, proxy, proxy_bars) {
+            Some((extended, b)) => {
+                warnings.push(format!(
+                    "{} has only {} returns of its own; {} earlier returns ({} to {}) are proxied by {}",
+                    b.ticker, b.own_observations, b.backfilled, b.from, b.to, b.proxy
+                ));
+                Ok((extended, Some(b)))
+            }
+            None => Err(format!("{message} and proxy {proxy} has no history before it to backfill from")),
+        },
+    }
+}
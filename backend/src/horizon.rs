@@ -0,0 +1,356 @@
+//! Multi-day VaR. By default the one-day figure is scaled by √h, which
+//! assumes i.i.d. returns; `simulated` scaling instead draws h-day paths
+//! day by day from the method's own daily model, volatility dynamics
+//! included, and compounds them, Π(1 + rₜ) − 1, or for log returns sums them.
+
+use axum::{extract::State, Json};
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use rand_distr::{Distribution, StandardNormal, StudentT};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    budget, cancel, defaults,
+    error::{ApiError, VarError},
+    garch,
+    profiles::Profiled,
+    replay,
+    scenarios::CHUNK_PATHS,
+    tenant::Tenant,
+    units::ReturnType,
+    validation::cross_field,
+    var::{
+        self, empirical_var_es, ewma_lambda, ewma_volatilities, ewma_volatility, historical_weights,
+        simulation_seed, student_t_dof, MethodParams, VarRequest, VolatilityModel,
+    },
+    version, AppState,
+};
+
+const DEFAULT_MAX_HORIZON: u32 = 30;
+
+/// How a one-day VaR becomes an h-day one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HorizonScaling {
+    #[default]
+    SqrtTime,
+    Simulated,
+}
+
+impl HorizonScaling {
+    pub fn is_sqrt_time(&self) -> bool {
+        *self == HorizonScaling::SqrtTime
+    }
+}
+
+/// Whether `method` has a daily model to simulate paths from.
+pub fn simulable(method: &str) -> bool {
+    method != "cornish_fisher"
+}
+
+/// Checks that `req` can be taken out to `horizon_days` days as it asks.
+pub fn check(req: &VarRequest, horizon_days: u32) -> Result<(), ValidationError> {
+    if req.horizon_scaling == HorizonScaling::SqrtTime || horizon_days <= 1 {
+        return Ok(());
+    }
+    if !simulable(&req.method) {
+        return Err(cross_field("horizon_scaling", format!("{} has no daily model to simulate", req.method)));
+    }
+    if req.target_se.is_some() || req.importance.is_some() {
+        let message = "simulated horizons run a fixed budget of n_sims paths; drop target_se and importance";
+        return Err(cross_field("horizon_scaling", message.into()));
+    }
+    if req.params.deterministic && req.params.seed.is_none() {
+        return Err(cross_field("seed", "deterministic simulated horizons need a seed".into()));
+    }
+    Ok(())
+}
+
+// Daily shocks zₜ: standard normal, unit-variance Student-t, or days of a
+// (standardised) sample drawn with replacement, by weight if given
+enum Shocks {
+    Normal,
+    StudentT { dist: StudentT<f64>, scale: f64 },
+    Resample { days: Vec<f64>, cumulative: Option<Vec<f64>> },
+}
+
+impl Shocks {
+    fn draw(&self, rng: &mut dyn RngCore) -> f64 {
+        match self {
+            Shocks::Normal => StandardNormal.sample(rng),
+            Shocks::StudentT { dist, scale } => scale * dist.sample(rng),
+            Shocks::Resample { days, cumulative: None } => days[rng.gen_range(0..days.len())],
+            Shocks::Resample { days, cumulative: Some(cumulative) } => {
+                let u = rng.gen::<f64>() * cumulative[cumulative.len() - 1];
+                days[cumulative.partition_point(|c| *c <= u).min(days.len() - 1)]
+            }
+        }
+    }
+}
+
+// Volatility σₜ of each day, updated from that day's residual εₜ = rₜ − μ
+#[derive(Clone, Copy)]
+enum Volatility {
+    Constant,
+    Garch { omega: f64, alpha: f64, beta: f64 },
+    Ewma { lambda: f64 },
+}
+
+/// A method's daily return process: rₜ = μ + σₜzₜ, starting from tomorrow's
+/// volatility forecast.
+pub struct DailyModel {
+    mean: f64,
+    volatility: f64,
+    dynamics: Volatility,
+    shocks: Shocks,
+}
+
+impl DailyModel {
+    /// The daily model behind `method`, fitted to `returns` (oldest first):
+    /// historical methods resample days (filtered historical its
+    /// standardised residuals, rescaled along an EWMA or GARCH path), the
+    /// parametric ones draw their distribution, and `ewma` and `garch` carry
+    /// their volatility recursion forward.
+    pub fn fit(method: &str, returns: &[f64], params: &MethodParams) -> Result<Self, VarError> {
+        let sum = params.summation();
+        let resample = |days: Vec<f64>, cumulative| Shocks::Resample { days, cumulative };
+        let model = match method {
+            "historical" | "bootstrap" => DailyModel {
+                mean: 0.0,
+                volatility: 1.0,
+                dynamics: Volatility::Constant,
+                shocks: resample(returns.to_vec(), None),
+            },
+            "weighted_historical" => {
+                let cumulative = historical_weights(returns.len(), params)
+                    .iter()
+                    .scan(0.0, |acc, w| {
+                        *acc += w;
+                        Some(*acc)
+                    })
+                    .collect();
+                DailyModel {
+                    mean: 0.0,
+                    volatility: 1.0,
+                    dynamics: Volatility::Constant,
+                    shocks: resample(returns.to_vec(), Some(cumulative)),
+                }
+            }
+            "filtered_historical" => {
+                let (mean, dynamics, (path, forecast)) = match params.volatility_model.unwrap_or_default() {
+                    VolatilityModel::Ewma => {
+                        (0.0, Volatility::Ewma { lambda: ewma_lambda(params) }, ewma_volatilities(returns, params))
+                    }
+                    VolatilityModel::Garch => {
+                        let fit = garch::fit(returns, params);
+                        let dynamics = Volatility::Garch { omega: fit.omega, alpha: fit.alpha, beta: fit.beta };
+                        (fit.mean, dynamics, fit.volatilities(returns, params))
+                    }
+                };
+                let residuals = returns.iter().zip(&path).map(|(r, s)| if *s > 0.0 { (r - mean) / s } else { 0.0 });
+                DailyModel { mean, volatility: forecast, dynamics, shocks: resample(residuals.collect(), None) }
+            }
+            "parametric" | "montecarlo" => {
+                let (mean, std) = sum.mean_std(returns);
+                DailyModel { mean, volatility: std, dynamics: Volatility::Constant, shocks: Shocks::Normal }
+            }
+            "parametric_t" => {
+                let (mean, std) = sum.mean_std(returns);
+                let dof = student_t_dof(returns, params);
+                let dist = StudentT::new(dof)
+                    .map_err(|e| VarError::Numerical(format!("Student-t with {dof} degrees of freedom: {e}")))?;
+                let shocks = Shocks::StudentT { dist, scale: ((dof - 2.0) / dof).sqrt() };
+                DailyModel { mean, volatility: std, dynamics: Volatility::Constant, shocks }
+            }
+            "ewma" => DailyModel {
+                mean: 0.0,
+                volatility: ewma_volatility(returns, params),
+                dynamics: Volatility::Ewma { lambda: ewma_lambda(params) },
+                shocks: Shocks::Normal,
+            },
+            "garch" => {
+                let fit = garch::fit(returns, params);
+                DailyModel {
+                    mean: fit.mean,
+                    volatility: fit.forecast_volatility,
+                    dynamics: Volatility::Garch { omega: fit.omega, alpha: fit.alpha, beta: fit.beta },
+                    shocks: Shocks::Normal,
+                }
+            }
+            _ => {
+                return Err(VarError::InvalidInput(format!(
+                    "{method} has no daily model to simulate; use horizon_scaling sqrt_time"
+                )))
+            }
+        };
+        Ok(model)
+    }
+
+    /// Compounded returns of `paths` paths at each of the ascending
+    /// `horizons` (days), one vector per horizon, in `return_type`'s terms. Paths are drawn in chunks
+    /// across the rayon pool, chunk i from ChaCha stream i of the seed, so
+    /// the result does not depend on the thread count.
+    pub fn simulate(
+        &self,
+        horizons: &[u32],
+        paths: usize,
+        return_type: ReturnType,
+        params: &MethodParams,
+    ) -> Result<Vec<Vec<f64>>, VarError> {
+        let seed = simulation_seed(params.seed);
+        let longest = horizons.last().copied().unwrap_or(0);
+        let chunks: Vec<Vec<Vec<f64>>> = (0..paths.div_ceil(CHUNK_PATHS))
+            .into_par_iter()
+            .map(|chunk| {
+                let mut out = vec![Vec::new(); horizons.len()];
+                if params.cancel.is_cancelled() {
+                    return out;
+                }
+                let mut rng = ChaCha12Rng::seed_from_u64(seed);
+                rng.set_stream(chunk as u64);
+                for _ in 0..CHUNK_PATHS.min(paths - chunk * CHUNK_PATHS) {
+                    let (mut total, mut sigma, mut next) = (0.0, self.volatility, 0);
+                    for day in 1..=longest {
+                        let residual = sigma * self.shocks.draw(&mut rng);
+                        total = return_type.compound(total, self.mean + residual);
+                        sigma = match self.dynamics {
+                            Volatility::Constant => sigma,
+                            Volatility::Garch { omega, alpha, beta } => {
+                                (omega + alpha * residual * residual + beta * sigma * sigma).sqrt()
+                            }
+                            Volatility::Ewma { lambda } => {
+                                let r = self.mean + residual;
+                                (lambda * sigma * sigma + (1.0 - lambda) * r * r).sqrt()
+                            }
+                        };
+                        if horizons[next] == day {
+                            out[next].push(total);
+                            next += 1;
+                        }
+                    }
+                }
+                out
+            })
+            .collect();
+        params.cancel.check()?;
+        let mut by_horizon = vec![Vec::with_capacity(paths); horizons.len()];
+        for chunk in chunks {
+            for (all, part) in by_horizon.iter_mut().zip(chunk) {
+                all.extend(part);
+            }
+        }
+        Ok(by_horizon)
+    }
+}
+
+// Payload for /api/var_term_structure: a compute_var request, whose
+// horizon_days is ignored, and the longest horizon wanted
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "check_term_structure"))]
+pub struct TermStructureRequest {
+    #[serde(flatten)]
+    #[validate(nested)]
+    request: VarRequest,
+    #[serde(default = "default_max_horizon")]
+    #[validate(range(min = 1, max = 250, message = "max_horizon_days must be between 1 and 250"))]
+    max_horizon_days: u32,
+}
+
+fn default_max_horizon() -> u32 {
+    DEFAULT_MAX_HORIZON
+}
+
+fn check_term_structure(req: &TermStructureRequest) -> Result<(), ValidationError> {
+    check(&req.request, req.max_horizon_days)?;
+    if req.request.horizon_scaling == HorizonScaling::SqrtTime {
+        return Ok(());
+    }
+    // Every path's compounded return at every horizon is held at once
+    let (paths, days) = (defaults::mc_paths(&req.request), req.max_horizon_days as usize);
+    let fixed = 4 * req.request.returns.len();
+    let advice = format!(
+        "lower n_sims to at most {} or max_horizon_days to at most {}, or use horizon_scaling sqrt_time",
+        budget::fits(fixed, days),
+        budget::fits(fixed, paths)
+    );
+    budget::check(fixed + paths * days, "max_horizon_days", advice)
+}
+
+/// VaR and ES at every horizon from 1 to `req.horizon_days` days: the
+/// one-day figures scaled by √h, or quantiles of the same simulated paths
+/// read off at each day.
+pub fn term_structure(req: &VarRequest) -> Result<Value, VarError> {
+    let (resolved, parameters) = defaults::resolve(req);
+    let req = &resolved;
+    let one_day = var::evaluate(&VarRequest { horizon_days: 1, confidences: Vec::new(), verify: false, ..req.clone() })?;
+    // √h scales losses in the request's own return terms
+    let figures = if req.return_type.is_simple() { &one_day } else { &one_day["log_returns"] };
+    let (var, es) = (figures["var"].as_f64().unwrap_or_default(), figures["es"].as_f64().unwrap_or_default());
+    let loss = |x: f64| req.return_type.simple_loss(x);
+    let horizons: Vec<u32> = (1..=req.horizon_days).collect();
+    let paths = req.params.mc_paths();
+    let points: Vec<Value> = if req.simulates_horizon() {
+        let returns = var::prepare(req)?.returns;
+        let model = DailyModel::fit(&req.method, &returns, &req.params)?;
+        let sims = model.simulate(&horizons, paths, req.return_type, &req.params)?;
+        horizons
+            .iter()
+            .zip(sims)
+            .map(|(h, mut sims)| {
+                let (var, es) = empirical_var_es(&mut sims, req.confidence, req.params.summation());
+                json!({ "horizon_days": h, "var": loss(var), "es": loss(es) })
+            })
+            .collect()
+    } else {
+        horizons
+            .iter()
+            .map(|h| {
+                let scale = f64::from(*h).sqrt();
+                json!({ "horizon_days": h, "var": loss(var * scale), "es": loss(es * scale) })
+            })
+            .collect()
+    };
+
+    let mut body = json!({
+        "method": req.method,
+        "confidence": req.confidence,
+        "horizon_scaling": req.horizon_scaling,
+        "max_horizon_days": req.horizon_days,
+        "return_type": req.return_type,
+        "one_day": { "var": one_day["var"], "es": one_day["es"] },
+        "term_structure": points,
+        "parameters": parameters,
+        "units": one_day["units"],
+        "engine": version::current(),
+    });
+    if req.simulates_horizon() {
+        body["paths"] = json!(paths);
+    }
+    if req.is_simulated() {
+        body["seed"] = json!(req.params.seed);
+    }
+    if let Some(warnings) = one_day.get("warnings") {
+        body["warnings"] = warnings.clone();
+    }
+    Ok(body)
+}
+
+/// VaR term structure from one day out to max_horizon_days
+pub async fn term_structure_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Profiled(req): Profiled<TermStructureRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let TermStructureRequest { mut request, max_horizon_days } = req;
+    state.flags.check_method(&request.method, &tenant)?;
+    request.horizon_days = max_horizon_days;
+    replay::pin_seed(&mut request);
+    let cancel = request.params.cancel.clone();
+    let body = cancel::spawn_blocking(&cancel, move || term_structure(&request))
+        .await
+        .map_err(|_| ApiError::aborted("term structure"))??;
+    Ok(Json(body))
+}
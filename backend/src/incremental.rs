@@ -0,0 +1,166 @@
+use axum::{extract::State, Json};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use validator::{Validate, ValidationError};
+
+use crate::{
+    costs::TransactionCosts,
+    error::ApiError,
+    portfolios::{self, Position},
+    report::aligned_returns,
+    tenant::Tenant,
+    validation::{self, cross_field, Valid},
+    var::{compute_var_es, MethodParams},
+    version, AppState,
+};
+
+// Payload for /api/incremental_var: the current book, inline or saved, and
+// the trade proposed on top of it
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "check_incremental"))]
+pub struct IncrementalRequest {
+    #[serde(default)]
+    positions: Option<Vec<Position>>,
+    // Id of a saved portfolio of the caller's
+    #[serde(default)]
+    portfolio: Option<String>,
+    // Positions the trade adds (negative values sell or short)
+    #[validate(length(min = 1, message = "trade must not be empty"))]
+    trade: Vec<Position>,
+    // Ticker → aligned return series, oldest first, for every ticker of the
+    // book and trade; loaded like /api/fetch_returns when absent
+    #[serde(default)]
+    returns: Option<BTreeMap<String, Vec<f64>>>,
+    #[serde(default = "default_method")]
+    #[validate(custom(function = "validation::known_method"))]
+    method: String,
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+    // Most recent aligned returns to use (default: all)
+    #[serde(default)]
+    #[validate(range(min = 2, message = "window must be at least 2"))]
+    window: Option<usize>,
+    #[serde(default)]
+    #[validate(nested)]
+    params: MethodParams,
+    // Commission and spread on the trade's value (`turnover` is ignored),
+    // paid the day it is made
+    #[serde(default)]
+    #[validate(nested)]
+    costs: Option<TransactionCosts>,
+}
+
+fn default_method() -> String {
+    "historical".into()
+}
+
+fn check_incremental(req: &IncrementalRequest) -> Result<(), ValidationError> {
+    if req.positions.is_some() == req.portfolio.is_some() {
+        return Err(cross_field("positions", "send either positions or portfolio".into()));
+    }
+    let Some(returns) = &req.returns else { return Ok(()) };
+    let n = returns.values().next().map_or(0, Vec::len);
+    if n < 2 || returns.values().any(|r| r.len() != n) {
+        return Err(cross_field("returns", "series must all have the same length, at least 2".into()));
+    }
+    if returns.values().any(|r| validation::finite(r).is_err()) {
+        return Err(cross_field("returns", "returns must be finite".into()));
+    }
+    Ok(())
+}
+
+/// Pre-trade check: the book's VaR with and without a proposed trade, on
+/// the same aligned history. The incremental VaR is the difference, negative
+/// when the trade hedges; the trade's P&L on the current book's VaR day is
+/// reported as the first-order estimate a desk can get without re-running
+/// the book, good for trades small against it.
+pub async fn incremental_var_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Valid(req): Valid<IncrementalRequest>,
+) -> Result<Json<Value>, ApiError> {
+    state.flags.check_method(&req.method, &tenant)?;
+    let raw = match (&req.positions, &req.portfolio) {
+        (Some(positions), _) => positions.clone(),
+        (None, Some(id)) => state.portfolios.get(&tenant, id)?.positions,
+        (None, None) => return Err(ApiError::bad_request("send either positions or portfolio")),
+    };
+    let current = portfolios::merge_positions(&raw).map_err(ApiError::bad_request)?;
+    let trade = portfolios::merge_positions(&req.trade).map_err(ApiError::bad_request)?;
+    let combined: Vec<Position> = current.iter().chain(&trade).cloned().collect();
+    let after = portfolios::merge_positions(&combined).map_err(ApiError::bad_request)?;
+
+    let mut tickers: Vec<String> = Vec::new();
+    for t in after.iter().flat_map(|p| p.tickers()) {
+        if !tickers.iter().any(|u| u == t) {
+            tickers.push(t.to_string());
+        }
+    }
+    let (dates, columns) = match &req.returns {
+        Some(returns) => {
+            let series: BTreeMap<String, &Vec<f64>> =
+                returns.iter().map(|(t, r)| (t.trim().to_uppercase(), r)).collect();
+            let missing: Vec<&str> = tickers.iter().filter(|t| !series.contains_key(*t)).map(String::as_str).collect();
+            if !missing.is_empty() {
+                return Err(ApiError::bad_request(format!("no returns sent for {}", missing.join(", "))));
+            }
+            (None, tickers.iter().map(|t| series[t].clone()).collect::<Vec<_>>())
+        }
+        None => {
+            let (dates, columns) = aligned_returns(&state, &tenant, &tickers).await?;
+            (Some(dates), columns)
+        }
+    };
+    let total = columns[0].len();
+    let first = req.window.map_or(0, |w| total.saturating_sub(w));
+    let n = total - first;
+    if n < 2 {
+        return Err(ApiError::bad_request(format!(
+            "only {total} returns are common to every ticker; need at least 2"
+        )));
+    }
+    req.params.check_weights(n).map_err(|e| ApiError::bad_request(e.message.unwrap_or_default()))?;
+
+    // Daily P&L of a set of positions over the window
+    let column = |ticker: &str| tickers.iter().position(|t| t == ticker).unwrap_or_default();
+    let pnl = |positions: &[Position]| -> Vec<f64> {
+        (first..total)
+            .map(|t| positions.iter().map(|p| p.value * p.instrument_return(|ticker| columns[column(ticker)][t])).sum())
+            .collect()
+    };
+    let (before_pnl, mut trade_pnl, mut after_pnl) = (pnl(&current), pnl(&trade), pnl(&after));
+    // The cost comes off every day's P&L: whichever day follows the trade pays it
+    let cost = req.costs.map_or(0.0, |c| trade.iter().map(|p| c.charge(p.value)).sum());
+    for p in trade_pnl.iter_mut().chain(after_pnl.iter_mut()) {
+        *p -= cost;
+    }
+    let measure = |pnl: &[f64]| compute_var_es(&req.method, &mut pnl.to_vec(), req.confidence, &req.params);
+    let (var_before, es_before) = measure(&before_pnl)?;
+    let (var_after, es_after) = measure(&after_pnl)?;
+    let (trade_var, trade_es) = measure(&trade_pnl)?;
+
+    // The current book's VaR order-statistic day
+    let mut order: Vec<usize> = (0..n).collect();
+    let idx = ((1.0 - req.confidence) * n as f64).floor() as usize;
+    let var_day = *order.select_nth_unstable_by(idx, |a, b| before_pnl[*a].total_cmp(&before_pnl[*b])).1;
+
+    let mut body = json!({
+        "method": req.method,
+        "confidence": req.confidence,
+        "observations": n,
+        "before": { "positions": current, "var": var_before, "es": es_before },
+        "after": { "positions": after, "var": var_after, "es": es_after },
+        "trade": { "positions": trade, "standalone_var": trade_var, "standalone_es": trade_es, "cost": cost },
+        "incremental_var": var_after - var_before,
+        "incremental_es": es_after - es_before,
+        "first_order_estimate": -trade_pnl[var_day],
+        "hedges": var_after < var_before,
+    });
+    if let Some(dates) = dates {
+        body["start"] = json!(dates[first]);
+        body["end"] = json!(dates[total - 1]);
+    }
+    body["engine"] = json!(version::current());
+    Ok(Json(body))
+}
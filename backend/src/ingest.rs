@@ -0,0 +1,494 @@
+//! Streaming ingestion: position updates and price ticks consumed from Kafka
+//! topics, applied to the saved portfolios and the price store, and every
+//! book they touch re-risked from stored history. Enabled by `KAFKA_BROKERS`
+//! plus a positions and/or prices topic; without a consumer group, offsets
+//! are kept in memory and a restart resumes from `KAFKA_START_OFFSET`.
+//! Built with the `streaming` feature only: the client in `kafka` has no
+//! TLS or SASL, so it suits trusted networks.
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{hash_map, BTreeMap, HashMap},
+    env,
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::{
+    admin::Admin,
+    error::ApiError,
+    kafka::{self, Connection, KafkaResult, Metadata, Record},
+    portfolios::{self, Portfolio, Position},
+    publish::Event,
+    report::{book_tickers, build_report, position_returns, stored_returns},
+    storage::Bar,
+    tenant::{Tenant, DEFAULT_TENANT},
+    version::Engine,
+    AppState,
+};
+
+// Longest a fetch waits for new records before the next leader is polled
+const FETCH_WAIT: Duration = Duration::from_millis(500);
+// Pause before reconnecting after a broker or protocol error
+const RETRY: Duration = Duration::from_secs(5);
+
+/// Where to consume from, read from the environment.
+pub struct StreamConfig {
+    brokers: Vec<String>,
+    positions_topic: Option<String>,
+    prices_topic: Option<String>,
+    // `kafka::LATEST` or `kafka::EARLIEST`
+    start: i64,
+    client_id: String,
+    confidence: f64,
+}
+
+impl StreamConfig {
+    /// Enabled when `KAFKA_BROKERS` (comma-separated host:port) and at least
+    /// one of `KAFKA_POSITIONS_TOPIC` / `KAFKA_PRICES_TOPIC` are set.
+    /// `KAFKA_START_OFFSET` (`latest`, the default, or `earliest`) is where
+    /// partitions are first read from, `KAFKA_CLIENT_ID` (default `riskvar`)
+    /// names the client, and `STREAM_CONFIDENCE` (default 0.95) sets the
+    /// confidence books are re-risked at.
+    pub fn from_env() -> Option<Self> {
+        let brokers: Vec<String> = env::var("KAFKA_BROKERS")
+            .ok()?
+            .split(',')
+            .map(str::trim)
+            .filter(|b| !b.is_empty())
+            .map(str::to_string)
+            .collect();
+        let topic = |name| env::var(name).ok().map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+        let (positions_topic, prices_topic) = (topic("KAFKA_POSITIONS_TOPIC"), topic("KAFKA_PRICES_TOPIC"));
+        if brokers.is_empty() || (positions_topic.is_none() && prices_topic.is_none()) {
+            eprintln!("⚠️ KAFKA_BROKERS is set but no broker or topic to consume; streaming disabled");
+            return None;
+        }
+        let start = match env::var("KAFKA_START_OFFSET").as_deref() {
+            Ok("earliest") => kafka::EARLIEST,
+            Ok("latest") | Err(_) => kafka::LATEST,
+            Ok(other) => {
+                eprintln!("⚠️ Unknown KAFKA_START_OFFSET {other:?}; reading from the latest offset");
+                kafka::LATEST
+            }
+        };
+        let confidence = env::var("STREAM_CONFIDENCE")
+            .ok()
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|c| *c > 0.0 && *c < 1.0)
+            .unwrap_or(0.95);
+        Some(StreamConfig {
+            brokers,
+            positions_topic,
+            prices_topic,
+            start,
+            client_id: env::var("KAFKA_CLIENT_ID").unwrap_or_else(|_| "riskvar".into()),
+            confidence,
+        })
+    }
+
+    fn topics(&self) -> Vec<String> {
+        self.positions_topic.iter().chain(&self.prices_topic).cloned().collect()
+    }
+}
+
+// Consumer progress, as reported by /api/admin/stream
+#[derive(Clone, Default, Serialize)]
+struct Status {
+    enabled: bool,
+    brokers: Vec<String>,
+    topics: Vec<String>,
+    // "topic/partition" → next offset to read
+    offsets: BTreeMap<String, i64>,
+    applied: u64,
+    rejected: u64,
+    recomputed: u64,
+    last_message_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    last_error_at: Option<DateTime<Utc>>,
+}
+
+/// A book's risk as of its last streamed change.
+#[derive(Clone, Serialize)]
+pub struct LiveRisk {
+    pub portfolio: String,
+    #[serde(skip)]
+    pub tenant: String,
+    pub version: u64,
+    pub as_of: Option<String>,
+    pub method: &'static str,
+    pub confidence: f64,
+    pub observations: usize,
+    pub gross_value: f64,
+    pub net_value: f64,
+    pub var: f64,
+    pub es: f64,
+    // P&L of the latest day of history, and the VaR the book went into that
+    // day with (from the previous day's snapshot), which it breached if the
+    // loss exceeds it
+    pub pnl: f64,
+    pub previous_var: Option<f64>,
+    pub breach: bool,
+    // What prompted the recomputation: `positions` or `prices`
+    pub trigger: &'static str,
+    pub computed_at: DateTime<Utc>,
+    pub engine: Engine,
+}
+
+/// Consumer status and the books' latest streamed risk.
+#[derive(Default)]
+pub struct Streaming {
+    status: Mutex<Status>,
+    live: Mutex<HashMap<String, LiveRisk>>,
+}
+
+impl Streaming {
+    fn error(&self, message: String) {
+        let mut status = self.status.lock().unwrap();
+        status.last_error = Some(message);
+        status.last_error_at = Some(Utc::now());
+    }
+
+    /// Latest streamed risk of a portfolio.
+    pub fn live(&self, id: &str) -> Option<LiveRisk> {
+        self.live.lock().unwrap().get(id).cloned()
+    }
+}
+
+fn default_tenant() -> String {
+    DEFAULT_TENANT.into()
+}
+
+// A positions-topic message: a book's positions replaced, or one holding
+// set (a value of 0 drops it)
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PositionUpdate {
+    Replace {
+        #[serde(default = "default_tenant")]
+        tenant: String,
+        portfolio: String,
+        positions: Vec<Position>,
+    },
+    Set {
+        #[serde(default = "default_tenant")]
+        tenant: String,
+        portfolio: String,
+        #[serde(flatten)]
+        position: Position,
+    },
+}
+
+// A prices-topic message: the close of `ts`'s bar, which a later tick with
+// the same `ts` overwrites. The ticker may be the record key instead.
+#[derive(Deserialize)]
+struct PriceTick {
+    #[serde(default)]
+    ticker: Option<String>,
+    ts: String,
+    close: f64,
+}
+
+// What one poll changed: books edited, and bars per ticker (last tick per
+// timestamp wins)
+#[derive(Default)]
+struct Changes {
+    books: BTreeMap<String, &'static str>,
+    bars: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
+fn apply_position(state: &AppState, update: PositionUpdate) -> Result<Portfolio, String> {
+    let message = |e: ApiError| e.message;
+    match update {
+        PositionUpdate::Replace { tenant, portfolio, positions } => {
+            let positions = portfolios::merge_positions(&positions)?;
+            state.portfolios.apply(&tenant, &portfolio, |book| book.positions = positions).map_err(message)
+        }
+        PositionUpdate::Set { tenant, portfolio, position } => {
+            let position = portfolios::merge_positions(&[position])?.remove(0);
+            state
+                .portfolios
+                .apply(&tenant, &portfolio, |book| {
+                    let held = book.positions.iter().position(|q| q.ticker == position.ticker && q.against == position.against);
+                    match (held, position.value == 0.0) {
+                        (Some(i), true) => {
+                            book.positions.remove(i);
+                        }
+                        (Some(i), false) => book.positions[i].value = position.value,
+                        (None, true) => {}
+                        (None, false) => book.positions.push(position),
+                    }
+                })
+                .map_err(message)
+        }
+    }
+}
+
+fn apply_tick(record: &Record, changes: &mut Changes) -> Result<(), String> {
+    let value = record.value.as_deref().ok_or("empty message")?;
+    let tick: PriceTick = serde_json::from_slice(value).map_err(|e| format!("invalid price tick: {e}"))?;
+    let key = record.key.as_deref().map(String::from_utf8_lossy);
+    let ticker = tick.ticker.or(key.map(|k| k.into_owned())).unwrap_or_default().trim().to_uppercase();
+    if ticker.is_empty() {
+        return Err("price tick has no ticker (field or record key)".into());
+    }
+    if tick.ts.trim().is_empty() || !(tick.close.is_finite() && tick.close > 0.0) {
+        return Err(format!("price tick for {ticker} needs a ts and a positive close"));
+    }
+    changes.bars.entry(ticker).or_default().insert(tick.ts.trim().to_string(), tick.close);
+    Ok(())
+}
+
+fn handle(state: &AppState, config: &StreamConfig, topic: &str, record: &Record, changes: &mut Changes) -> Result<(), String> {
+    if config.prices_topic.as_deref() == Some(topic) {
+        return apply_tick(record, changes);
+    }
+    let value = record.value.as_deref().ok_or("empty message")?;
+    let update: PositionUpdate =
+        serde_json::from_slice(value).map_err(|e| format!("invalid position update: {e}"))?;
+    let book = apply_position(state, update)?;
+    changes.books.insert(book.id, "positions");
+    Ok(())
+}
+
+// Historical VaR/ES of a book over its stored history
+async fn recompute(state: &AppState, book: Portfolio, confidence: f64, trigger: &'static str) -> Result<LiveRisk, String> {
+    if book.positions.is_empty() {
+        return Err(format!("portfolio {} has no positions", book.id));
+    }
+    let tickers = book_tickers(&book);
+    let (dates, ticker_returns) = stored_returns(state, &tickers).await.map_err(|e| e.message)?;
+    if dates.len() < 2 {
+        return Err(format!("portfolio {} has too little overlapping stored history", book.id));
+    }
+    let returns = position_returns(&book, &tickers, &ticker_returns);
+    let pnl = book.positions.iter().zip(&returns).map(|(p, r)| p.value * r[r.len() - 1]).sum();
+    let tenant = book.tenant.clone();
+    // A window as long as the history skips the report's backtest section
+    let window = dates.len();
+    let report = tokio::task::spawn_blocking(move || build_report(&book, &dates, &returns, confidence, window))
+        .await
+        .map_err(|_| "recomputation failed".to_string())?
+        .map_err(|e| e.to_string())?;
+    Ok(LiveRisk {
+        portfolio: report.portfolio,
+        tenant,
+        version: report.version,
+        as_of: report.as_of,
+        method: report.method,
+        confidence,
+        observations: report.observations,
+        gross_value: report.gross_value,
+        net_value: report.net_value,
+        var: report.var,
+        es: report.es,
+        pnl,
+        previous_var: None,
+        breach: false,
+        trigger,
+        computed_at: Utc::now(),
+        engine: report.engine,
+    })
+}
+
+// Stores the poll's bars and re-risks every book they or the position
+// updates touched
+async fn commit(state: &AppState, config: &StreamConfig, mut changes: Changes) {
+    for (ticker, bars) in changes.bars {
+        let bars: Vec<Bar> = bars.into_iter().collect();
+        match state.prices.insert(&ticker, &bars).await {
+            Ok(_) => state.rolling.ingest(&*state.prices, &ticker, &bars).await,
+            Err(e) => {
+                state.stream.error(format!("cannot store ticks for {ticker}: {e}"));
+                continue;
+            }
+        }
+        for book in state.portfolios.holding(&ticker) {
+            changes.books.entry(book.id).or_insert("prices");
+        }
+    }
+    for (id, trigger) in changes.books {
+        let Some(book) = state.portfolios.all().into_iter().find(|p| p.id == id && p.deleted_at.is_none()) else {
+            continue;
+        };
+        match recompute(state, book, config.confidence, trigger).await {
+            Ok(mut risk) => {
+                let previous = state.stream.live(&id);
+                risk.previous_var = match &previous {
+                    Some(p) if p.as_of == risk.as_of => p.previous_var,
+                    Some(p) => Some(p.var),
+                    None => None,
+                };
+                risk.breach = risk.previous_var.is_some_and(|var| -risk.pnl > var);
+                if let Some(events) = &state.events {
+                    for event in Event::from_snapshot(&risk, previous.as_ref(), events.var_jump) {
+                        events.publish(&id, event);
+                    }
+                }
+                state.stream.live.lock().unwrap().insert(id, risk);
+                state.stream.status.lock().unwrap().recomputed += 1;
+            }
+            Err(e) => {
+                state.stream.live.lock().unwrap().remove(&id);
+                state.stream.error(e);
+            }
+        }
+    }
+}
+
+// Partitions by the address of their leader. A leader missing from the
+// broker list fails the session, which retries with fresh metadata.
+fn by_leader(metadata: Metadata) -> KafkaResult<BTreeMap<String, Vec<(String, i32)>>> {
+    let mut leaders: BTreeMap<String, Vec<(String, i32)>> = BTreeMap::new();
+    for (topic, partition, leader) in metadata.partitions {
+        let address = metadata.brokers.get(&leader).ok_or_else(|| format!("leader {leader} is not a known broker"))?;
+        leaders.entry(address.clone()).or_default().push((topic, partition));
+    }
+    Ok(leaders)
+}
+
+// One session: leaders looked up, then fetched from until an error calls
+// for a fresh metadata lookup. Offsets are kept by partition, not by
+// leader, so a session after a leader change resumes where this one stopped.
+async fn consume(state: &AppState, config: &StreamConfig, offsets: &mut HashMap<(String, i32), i64>) -> KafkaResult<()> {
+    let metadata = kafka::bootstrap(&config.brokers, &config.client_id).await?.metadata(&config.topics()).await?;
+    if metadata.partitions.is_empty() {
+        return Err("no partition of the topics has a leader".into());
+    }
+    let mut leaders = Vec::new();
+    for (address, partitions) in by_leader(metadata)? {
+        let mut connection = Connection::connect(&address, &config.client_id).await?;
+        for (topic, partition) in &partitions {
+            if let hash_map::Entry::Vacant(slot) = offsets.entry((topic.clone(), *partition)) {
+                slot.insert(connection.offset(topic, *partition, config.start).await?);
+            }
+        }
+        leaders.push((connection, partitions));
+    }
+
+    loop {
+        let mut changes = Changes::default();
+        let (mut applied, mut rejected) = (0, 0);
+        for (connection, partitions) in &mut leaders {
+            let from: Vec<(String, i32, i64)> =
+                partitions.iter().map(|(t, p)| (t.clone(), *p, offsets[&(t.clone(), *p)])).collect();
+            for fetched in connection.fetch(&from, FETCH_WAIT).await? {
+                let key = (fetched.topic.clone(), fetched.partition);
+                match fetched.error {
+                    0 => {}
+                    kafka::OFFSET_OUT_OF_RANGE => {
+                        let offset = connection.offset(&fetched.topic, fetched.partition, config.start).await?;
+                        state.stream.error(format!(
+                            "{}/{} offset {} is out of range; skipped to {offset}",
+                            key.0, key.1, offsets[&key]
+                        ));
+                        offsets.insert(key, offset);
+                        continue;
+                    }
+                    e => return Err(format!("fetching {}/{} failed (error {e})", key.0, key.1)),
+                }
+                let next = offsets[&key];
+                for record in fetched.records.iter().filter(|r| r.offset >= next) {
+                    match handle(state, config, &fetched.topic, record, &mut changes) {
+                        Ok(()) => applied += 1,
+                        Err(e) => {
+                            rejected += 1;
+                            eprintln!("⚠️ Rejected {}/{}@{}: {e}", key.0, key.1, record.offset);
+                            state.stream.error(format!("{}/{}@{}: {e}", key.0, key.1, record.offset));
+                        }
+                    }
+                    offsets.insert(key.clone(), record.offset + 1);
+                }
+            }
+        }
+        if applied + rejected > 0 {
+            let mut status = state.stream.status.lock().unwrap();
+            status.applied += applied;
+            status.rejected += rejected;
+            status.last_message_at = Some(Utc::now());
+            status.offsets = offsets.iter().map(|((t, p), o)| (format!("{t}/{p}"), *o)).collect();
+        }
+        commit(state, config, changes).await;
+    }
+}
+
+/// Consumes the configured topics for the life of the process,
+/// reconnecting after failures.
+pub async fn run(state: AppState, config: StreamConfig) {
+    let topics = config.topics();
+    println!("📡 Consuming {} from {}", topics.join(", "), config.brokers.join(","));
+    {
+        let mut status = state.stream.status.lock().unwrap();
+        status.enabled = true;
+        status.brokers = config.brokers.clone();
+        status.topics = topics;
+    }
+    let mut offsets = HashMap::new();
+    loop {
+        if let Err(e) = consume(&state, &config, &mut offsets).await {
+            eprintln!("⚠️ Stream consumer: {e}; reconnecting in {}s", RETRY.as_secs());
+            state.stream.error(e);
+            tokio::time::sleep(RETRY).await;
+        }
+    }
+}
+
+/// Consumer status: topics, offsets, message counts and the last error
+pub async fn stream_status_handler(_: Admin, State(state): State<AppState>) -> Json<Value> {
+    let mut status = json!(state.stream.status.lock().unwrap().clone());
+    status["publishing"] = state.events.as_ref().map_or(Value::Null, |events| events.status());
+    Json(status)
+}
+
+/// A portfolio's risk as of its last streamed position or price change
+pub async fn live_risk_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    state.portfolios.get(&tenant, &id)?;
+    let risk = state
+        .stream
+        .live(&id)
+        .filter(|r| r.tenant == tenant)
+        .ok_or_else(|| ApiError::not_found(format!("no streamed risk for portfolio {id} yet")))?;
+    Ok(Json(json!(risk)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(brokers: &[(i32, &str)], partitions: &[(&str, i32, i32)]) -> Metadata {
+        Metadata {
+            brokers: brokers.iter().map(|(id, address)| (*id, address.to_string())).collect(),
+            partitions: partitions.iter().map(|(t, p, leader)| (t.to_string(), *p, *leader)).collect(),
+        }
+    }
+
+    #[test]
+    fn partitions_follow_their_leader_across_sessions() {
+        let brokers = [(1, "kafka-1:9092"), (2, "kafka-2:9092")];
+        let before = by_leader(metadata(&brokers, &[("prices", 0, 1), ("prices", 1, 2), ("positions", 0, 1)])).unwrap();
+        assert_eq!(before["kafka-1:9092"], [("prices".to_string(), 0), ("positions".to_string(), 0)]);
+        assert_eq!(before["kafka-2:9092"], [("prices".to_string(), 1)]);
+
+        // Broker 1 goes away and broker 2 takes over its partitions
+        let moved = metadata(&brokers[1..], &[("prices", 0, 2), ("prices", 1, 2), ("positions", 0, 2)]);
+        let after = by_leader(moved).unwrap();
+        assert_eq!(after.len(), 1);
+        assert_eq!(after["kafka-2:9092"].len(), 3);
+    }
+
+    #[test]
+    fn an_unlisted_leader_fails_the_session() {
+        let stale = metadata(&[(1, "kafka-1:9092")], &[("prices", 0, 1), ("prices", 1, 3)]);
+        assert_eq!(by_leader(stale).unwrap_err(), "leader 3 is not a known broker");
+    }
+}
@@ -0,0 +1,723 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::Validate;
+use std::{
+    collections::{HashMap, VecDeque},
+    env, fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    admin::Admin,
+    audit::{self, AuditLog},
+    error::ApiError,
+    replay,
+    tenant::{Tenant, DEFAULT_TENANT},
+    profiles::{self, Profiled},
+    validation,
+    var::{evaluate_with, Checkpoints, McCheckpoint, VarRequest},
+    AppState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+// Interactive jobs are dispatched ahead of batch jobs, and batch jobs may
+// only occupy part of the worker pool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    #[default]
+    Interactive,
+    Batch,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub status: JobStatus,
+    #[serde(default = "default_tenant")]
+    pub tenant: String,
+    #[serde(default)]
+    pub priority: Priority,
+    pub request: VarRequest,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+    pub rerun_of: Option<String>,
+    pub retention_hours: i64,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Job {
+    // What admins see of a job in the scheduler, without its data
+    fn summary(&self) -> Value {
+        json!({
+            "id": self.id,
+            "tenant": self.tenant,
+            "status": self.status,
+            "priority": self.priority,
+            "method": self.request.method,
+            "observations": self.request.returns.len(),
+            "created_at": self.created_at,
+        })
+    }
+}
+
+fn default_tenant() -> String {
+    DEFAULT_TENANT.to_string()
+}
+
+// Payload to submit a job: a VaR request plus scheduling options
+#[derive(Deserialize, Validate)]
+pub struct SubmitJob {
+    #[serde(flatten)]
+    #[validate(nested)]
+    request: VarRequest,
+    #[serde(default)]
+    #[validate(range(min = 1, message = "retention_hours must be at least 1"))]
+    retention_hours: Option<i64>,
+    #[serde(default)]
+    priority: Priority,
+}
+
+// Queued job ids per priority class plus running counts
+#[derive(Default)]
+struct Scheduler {
+    interactive: VecDeque<(String, String)>,
+    batch: VecDeque<(String, String)>,
+    running: usize,
+    running_batch: usize,
+    per_tenant: HashMap<String, usize>,
+}
+
+impl Scheduler {
+    // Tenants come from a request header, so idle ones are dropped from the
+    // running counts rather than kept at zero
+    fn release(&mut self, tenant: &str, priority: Priority) {
+        self.running -= 1;
+        if priority == Priority::Batch {
+            self.running_batch -= 1;
+        }
+        if let Some(n) = self.per_tenant.get_mut(tenant) {
+            *n -= 1;
+            if *n == 0 {
+                self.per_tenant.remove(tenant);
+            }
+        }
+    }
+}
+
+/// In-memory job table; finished jobs are also written to `dir` so results
+/// survive restarts until they expire.
+pub struct JobStore {
+    jobs: Mutex<HashMap<String, Job>>,
+    sched: Mutex<Scheduler>,
+    dir: PathBuf,
+    retention_hours: i64,
+    workers: usize,
+    batch_workers: usize,
+    tenant_limit: usize,
+    // Monte Carlo batches between checkpoints of a running job
+    checkpoint_every: usize,
+    #[cfg(feature = "redis")]
+    remote: Option<Arc<crate::queue::RedisQueue>>,
+    // Where finished jobs are announced
+    #[cfg(feature = "streaming")]
+    events: Option<Arc<crate::publish::Publisher>>,
+    audit: Arc<AuditLog>,
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+impl JobStore {
+    /// Reads `JOBS_DIR` (default `jobs`), `JOB_RETENTION_HOURS` (default 24),
+    /// `JOB_WORKERS` (default: available cores), `JOB_BATCH_WORKERS` (default
+    /// half the workers), `JOB_TENANT_LIMIT` (default 2) and
+    /// `JOB_CHECKPOINT_BATCHES` (default 10), and reloads any persisted jobs.
+    pub fn from_env(audit: Arc<AuditLog>) -> Self {
+        let dir = PathBuf::from(env::var("JOBS_DIR").unwrap_or_else(|_| "jobs".into()));
+        let retention_hours = env_or("JOB_RETENTION_HOURS", 24);
+        let cores = std::thread::available_parallelism().map_or(2, |n| n.get());
+        let workers = env_or("JOB_WORKERS", cores).max(1);
+        let batch_workers = env_or("JOB_BATCH_WORKERS", workers / 2).clamp(1, workers);
+        let tenant_limit = env_or("JOB_TENANT_LIMIT", 2).max(1);
+        let checkpoint_every = env_or("JOB_CHECKPOINT_BATCHES", 10).max(1);
+
+        if let Err(e) = fs::create_dir_all(dir.join("checkpoints")) {
+            eprintln!("⚠️ Cannot create jobs dir {}: {}", dir.display(), e);
+        }
+        let mut jobs = HashMap::new();
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let job = fs::read(entry.path())
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<Job>(&bytes).ok());
+            if let Some(job) = job {
+                jobs.insert(job.id.clone(), job);
+            }
+        }
+        println!("🗂️ Loaded {} persisted jobs from {}", jobs.len(), dir.display());
+
+        JobStore {
+            jobs: Mutex::new(jobs),
+            sched: Mutex::new(Scheduler::default()),
+            dir,
+            retention_hours,
+            workers,
+            batch_workers,
+            tenant_limit,
+            checkpoint_every,
+            #[cfg(feature = "redis")]
+            remote: crate::queue::RedisQueue::from_env().map(Arc::new),
+            #[cfg(feature = "streaming")]
+            events: None,
+            audit,
+        }
+    }
+
+    /// Announces finished and cancelled jobs through `events`.
+    #[cfg(feature = "streaming")]
+    pub fn publishing(self, events: Option<Arc<crate::publish::Publisher>>) -> Self {
+        JobStore { events, ..self }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+
+    /// The job if `tenant` submitted it; another tenant's job is reported
+    /// missing rather than forbidden, so its id gives nothing away.
+    pub fn owned(&self, id: &str, tenant: &str) -> Result<Job, ApiError> {
+        self.get(id)
+            .filter(|job| job.tenant == tenant)
+            .ok_or_else(|| ApiError::not_found(format!("job {id} not found")))
+    }
+
+    pub fn all(&self) -> Vec<Job> {
+        self.jobs.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Inserts a job from a snapshot, persisting it if it had finished.
+    pub fn restore(&self, job: Job) {
+        if job.finished_at.is_some() {
+            self.persist(&job);
+        }
+        self.insert(job);
+    }
+
+    fn insert(&self, job: Job) {
+        self.jobs.lock().unwrap().insert(job.id.clone(), job);
+    }
+
+    fn update(&self, id: &str, f: impl FnOnce(&mut Job)) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(id)?;
+        f(job);
+        Some(job.clone())
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    fn persist(&self, job: &Job) {
+        let written = serde_json::to_vec(job)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| fs::write(self.path(&job.id), bytes).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            eprintln!("⚠️ Failed to persist job {}: {}", job.id, e);
+        }
+    }
+
+    fn checkpoint_path(&self, id: &str) -> PathBuf {
+        self.dir.join("checkpoints").join(format!("{id}.json"))
+    }
+
+    fn load_checkpoint(&self, id: &str) -> Option<McCheckpoint> {
+        let bytes = fs::read(self.checkpoint_path(id)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// Writes a checkpoint through a temporary file, so a crash mid-write
+    /// leaves the previous one intact.
+    fn save_checkpoint(&self, id: &str, checkpoint: &McCheckpoint) {
+        let path = self.checkpoint_path(id);
+        let tmp = path.with_extension("tmp");
+        let written = serde_json::to_vec(checkpoint)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| fs::write(&tmp, bytes).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&tmp, &path).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            eprintln!("⚠️ Failed to checkpoint job {}: {}", id, e);
+        }
+    }
+
+    /// Running jobs, then queued ones in the order they will be dispatched,
+    /// with the scheduler's occupancy.
+    fn active(&self) -> Value {
+        let (queued, running, running_batch) = {
+            let sched = self.sched.lock().unwrap();
+            let queued: Vec<String> = sched.interactive.iter().chain(&sched.batch).map(|(id, _)| id.clone()).collect();
+            (queued, sched.running, sched.running_batch)
+        };
+        let jobs = self.jobs.lock().unwrap();
+        let mut active: Vec<&Job> = jobs.values().filter(|j| j.status == JobStatus::Running).collect();
+        active.sort_by_key(|j| j.created_at);
+        json!({
+            "workers": self.workers,
+            "batch_workers": self.batch_workers,
+            "busy_workers": running,
+            "busy_batch_workers": running_batch,
+            "running": active.iter().map(|j| j.summary()).collect::<Vec<_>>(),
+            "queued": queued.iter().filter_map(|id| jobs.get(id)).map(Job::summary).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Cancels a queued or running job on behalf of `by` (`tenant` or
+    /// `admin`). A queued job leaves the queue; a running one is signalled
+    /// through its request's token and stops at the simulation's next
+    /// checkpoint of work, and whatever it returns is discarded. Jobs on
+    /// remote workers run on there, but their outcome is discarded too.
+    /// The returned event is for the request's audit record.
+    fn cancel(&self, id: &str, by: &str) -> Result<(Job, audit::Event), ApiError> {
+        {
+            let mut sched = self.sched.lock().unwrap();
+            sched.interactive.retain(|(queued, _)| queued != id);
+            sched.batch.retain(|(queued, _)| queued != id);
+        }
+        let (job, was) = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let job = jobs.get_mut(id).ok_or_else(|| ApiError::not_found(format!("job {id} not found")))?;
+            let was = job.status;
+            if !matches!(was, JobStatus::Queued | JobStatus::Running) {
+                return Err(ApiError::new(StatusCode::CONFLICT, format!("job {id} is no longer queued or running")));
+            }
+            let now = Utc::now();
+            // The running computation holds a clone of this request
+            job.request.params.cancel.cancel();
+            job.status = JobStatus::Cancelled;
+            job.finished_at = Some(now);
+            job.expires_at = Some(now + Duration::hours(job.retention_hours));
+            (job.clone(), was)
+        };
+        println!("🛑 Job {} ({}) cancelled while {:?}", job.id, job.tenant, was);
+        self.persist(&job);
+        #[cfg(feature = "streaming")]
+        if let Some(events) = &self.events {
+            events.publish(&job.id, crate::publish::Event::job(&job));
+        }
+        let data = json!({ "method": job.request.method, "was": was, "by": by });
+        let event = audit::Event::new("job.cancelled", &job.id, data);
+        let event = audit::Event { tenant: Some(job.tenant.clone()), ..event };
+        Ok((job, event))
+    }
+
+    /// Drops expired jobs from memory and disk, returning how many were removed.
+    pub fn collect_garbage(&self) -> usize {
+        let now = Utc::now();
+        let expired: Vec<String> = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let ids: Vec<String> = jobs
+                .values()
+                .filter(|j| j.expires_at.is_some_and(|t| t <= now))
+                .map(|j| j.id.clone())
+                .collect();
+            for id in &ids {
+                jobs.remove(id);
+            }
+            ids
+        };
+        for id in &expired {
+            let _ = fs::remove_file(self.path(id));
+        }
+        expired.len()
+    }
+}
+
+// Everything needed to create a job
+struct NewJob {
+    request: VarRequest,
+    tenant: String,
+    priority: Priority,
+    retention_hours: Option<i64>,
+    rerun_of: Option<String>,
+}
+
+/// Registers a job and queues it for dispatch.
+fn submit(store: &Arc<JobStore>, new: NewJob) -> Job {
+    let job = Job {
+        id: format!("{:016x}", rand::random::<u64>()),
+        status: JobStatus::Queued,
+        tenant: new.tenant,
+        priority: new.priority,
+        request: new.request,
+        result: None,
+        error: None,
+        rerun_of: new.rerun_of,
+        retention_hours: new.retention_hours.unwrap_or(store.retention_hours),
+        created_at: Utc::now(),
+        finished_at: None,
+        expires_at: None,
+    };
+    // Persisted while pending too, so a restart can pick it back up
+    store.persist(&job);
+    store.insert(job.clone());
+    enqueue(store, &job);
+    dispatch(store);
+    job
+}
+
+fn enqueue(store: &JobStore, job: &Job) {
+    let mut sched = store.sched.lock().unwrap();
+    let entry = (job.id.clone(), job.tenant.clone());
+    match job.priority {
+        Priority::Interactive => sched.interactive.push_back(entry),
+        Priority::Batch => sched.batch.push_back(entry),
+    }
+}
+
+/// Requeues jobs a previous process left queued or running, oldest first;
+/// Monte Carlo jobs continue from their last checkpoint.
+pub fn resume_unfinished(store: &Arc<JobStore>) {
+    let mut pending: Vec<Job> = store
+        .all()
+        .into_iter()
+        .filter(|j| matches!(j.status, JobStatus::Queued | JobStatus::Running))
+        .collect();
+    if pending.is_empty() {
+        return;
+    }
+    pending.sort_by_key(|j| j.created_at);
+    for job in &pending {
+        store.update(&job.id, |j| j.status = JobStatus::Queued);
+        enqueue(store, job);
+    }
+    println!("♻️ Resuming {} unfinished jobs", pending.len());
+    dispatch(store);
+}
+
+/// Starts queued jobs while worker slots are free: interactive first, then
+/// batch, skipping tenants already at their concurrency cap.
+fn dispatch(store: &Arc<JobStore>) {
+    let mut sched = store.sched.lock().unwrap();
+    while sched.running < store.workers {
+        let tenant_free = |s: &Scheduler, tenant: &str| s.per_tenant.get(tenant).copied().unwrap_or(0) < store.tenant_limit;
+
+        let next = match sched.interactive.iter().position(|(_, t)| tenant_free(&sched, t)) {
+            Some(pos) => sched.interactive.remove(pos).map(|e| (e, Priority::Interactive)),
+            None if sched.running_batch < store.batch_workers => sched
+                .batch
+                .iter()
+                .position(|(_, t)| tenant_free(&sched, t))
+                .and_then(|pos| sched.batch.remove(pos))
+                .map(|e| (e, Priority::Batch)),
+            None => None,
+        };
+        let Some(((id, tenant), priority)) = next else {
+            break;
+        };
+
+        sched.running += 1;
+        if priority == Priority::Batch {
+            sched.running_batch += 1;
+        }
+        *sched.per_tenant.entry(tenant.clone()).or_default() += 1;
+
+        #[cfg(feature = "redis")]
+        if let Some(queue) = store.remote.clone() {
+            let store = store.clone();
+            tokio::spawn(async move {
+                if let Some(job) = start(&store, &id) {
+                    let outcome = queue.execute(&job).await;
+                    finish(&store, &id, outcome);
+                }
+                release(&store, &tenant, priority);
+                dispatch(&store);
+            });
+            continue;
+        }
+
+        let store = store.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Some(job) = start(&store, &id) {
+                let resume = store.load_checkpoint(&id);
+                if let Some(c) = &resume {
+                    println!("♻️ Job {} resumes after {} paths", id, c.paths);
+                }
+                let save = |c: &McCheckpoint| store.save_checkpoint(&id, c);
+                let checkpoints = Checkpoints { every: store.checkpoint_every, resume, save: &save };
+                let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    evaluate_with(&job.request, Some(checkpoints))
+                }))
+                .map_err(|_| "computation panicked".to_string())
+                .and_then(|result| result.map_err(|e| e.to_string()));
+                finish(&store, &id, outcome);
+            }
+            release(&store, &tenant, priority);
+            dispatch(&store);
+        });
+    }
+}
+
+/// Frees the worker slot held by a finished job.
+fn release(store: &JobStore, tenant: &str, priority: Priority) {
+    store.sched.lock().unwrap().release(tenant, priority);
+}
+
+/// Marks a queued job as running and returns it; `None` if it was
+/// cancelled after leaving the queue.
+fn start(store: &JobStore, id: &str) -> Option<Job> {
+    let mut jobs = store.jobs.lock().unwrap();
+    let job = jobs.get_mut(id).filter(|j| j.status == JobStatus::Queued)?;
+    job.status = JobStatus::Running;
+    Some(job.clone())
+}
+
+/// Records a job's outcome and persists it, unless it was cancelled.
+fn finish(store: &JobStore, id: &str, outcome: Result<Value, String>) {
+    let mut cancelled = false;
+    let finished = store.update(id, |j| {
+        if j.status == JobStatus::Cancelled {
+            cancelled = true;
+            return;
+        }
+        let now = Utc::now();
+        match outcome {
+            Ok(result) => {
+                j.status = JobStatus::Done;
+                j.result = Some(result);
+            }
+            Err(e) => {
+                j.status = JobStatus::Failed;
+                j.error = Some(e);
+            }
+        }
+        j.finished_at = Some(now);
+        j.expires_at = Some(now + Duration::hours(j.retention_hours));
+    });
+    if cancelled {
+        println!("🛑 Job {} was cancelled; its outcome is discarded", id);
+        let _ = fs::remove_file(store.checkpoint_path(id));
+        return;
+    }
+    if let Some(job) = finished {
+        println!("✅ Job {} ({}, {:?}) finished: {:?}", job.id, job.tenant, job.priority, job.status);
+        store.persist(&job);
+        #[cfg(feature = "streaming")]
+        if let Some(events) = &store.events {
+            events.publish(&job.id, crate::publish::Event::job(&job));
+        }
+        let data = match (&job.result, &job.error) {
+            (Some(result), _) => audit::computation(&job.request, result),
+            (None, error) => json!({ "method": job.request.method, "error": error }),
+        };
+        store.audit.record(&job.tenant, "job.finished", &job.id, data);
+        let _ = fs::remove_file(store.checkpoint_path(id));
+    }
+}
+
+/// Periodically purges expired jobs; interval from `JOB_GC_INTERVAL_SECS` (default 300).
+pub fn spawn_gc(store: Arc<JobStore>) {
+    let secs = env::var("JOB_GC_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(300);
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(secs));
+        loop {
+            tick.tick().await;
+            let removed = store.collect_garbage();
+            if removed > 0 {
+                println!("🧹 Removed {} expired jobs", removed);
+            }
+        }
+    });
+}
+
+/// Submit a VaR computation as a background job
+pub async fn submit_job_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Profiled(mut payload): Profiled<SubmitJob>,
+) -> Result<(StatusCode, Json<Job>), ApiError> {
+    state.flags.check_method(&payload.request.method, &tenant)?;
+    // Jobs are replayable, so simulations always run with a recorded seed
+    replay::pin_seed(&mut payload.request);
+    let job = submit(
+        &state.jobs,
+        NewJob {
+            request: payload.request,
+            tenant,
+            priority: payload.priority,
+            retention_hours: payload.retention_hours,
+            rerun_of: None,
+        },
+    );
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Status and result of one of the caller's jobs
+pub async fn get_job_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, ApiError> {
+    state.jobs.owned(&id, &tenant).map(Json)
+}
+
+/// Re-run one of the caller's stored jobs, overriding any request fields
+/// present in the body
+pub async fn rerun_job_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+    Json(overrides): Json<Value>,
+) -> Result<(StatusCode, Json<Job>), ApiError> {
+    let original = state.jobs.owned(&id, &tenant)?;
+    let SubmitJob { request, retention_hours, priority } = rerun_spec(&original, &overrides)?;
+    validation::check(&request)?;
+    state.flags.check_method(&request.method, &original.tenant)?;
+
+    let job = submit(
+        &state.jobs,
+        NewJob { request, tenant: original.tenant, priority, retention_hours, rerun_of: Some(id) },
+    );
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// The original job's request and scheduling options with `overrides`
+/// applied. Unless the overrides choose a seed, a simulation reruns with the
+/// seed the original ran with (from its result for jobs submitted before
+/// seeds were pinned), so the rerun reproduces it.
+fn rerun_spec(original: &Job, overrides: &Value) -> Result<SubmitJob, ApiError> {
+    let mut spec = serde_json::to_value(&original.request).expect("serialising request");
+    spec["retention_hours"] = original.retention_hours.into();
+    spec["priority"] = serde_json::to_value(original.priority).expect("serialising priority");
+    if let Some(seed) = original.result.as_ref().map(|r| &r["seed"]).filter(|s| s.is_u64()) {
+        if spec["seed"].is_null() {
+            spec["seed"] = seed.clone();
+        }
+    }
+    if let Some(fields) = overrides.as_object() {
+        for (k, v) in fields {
+            spec[k] = v.clone();
+        }
+    }
+    profiles::expand(&mut spec)?;
+    let mut job: SubmitJob =
+        serde_json::from_value(spec).map_err(|e| ApiError::bad_request(format!("invalid overrides: {e}")))?;
+    replay::pin_seed(&mut job.request);
+    Ok(job)
+}
+
+/// Running and queued jobs of every tenant
+pub async fn list_active_jobs_handler(_: Admin, State(state): State<AppState>) -> Json<Value> {
+    Json(state.jobs.active())
+}
+
+/// Cancel one of the caller's queued or running jobs
+pub async fn cancel_job_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+) -> Result<(Extension<audit::Event>, Json<Job>), ApiError> {
+    state.jobs.owned(&id, &tenant)?;
+    let (job, event) = state.jobs.cancel(&id, "tenant")?;
+    Ok((Extension(event), Json(job)))
+}
+
+/// Cancel any tenant's queued or running job
+pub async fn admin_cancel_job_handler(
+    _: Admin,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<(Extension<audit::Event>, Json<Job>), ApiError> {
+    let (job, event) = state.jobs.cancel(&id, "admin")?;
+    Ok((Extension(event), Json(job)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::var::evaluate;
+
+    fn done(request: Value) -> Job {
+        let request: VarRequest = serde_json::from_value(request).unwrap();
+        Job {
+            id: "job".into(),
+            status: JobStatus::Done,
+            tenant: DEFAULT_TENANT.into(),
+            priority: Priority::Interactive,
+            result: Some(evaluate(&request).unwrap()),
+            request,
+            error: None,
+            rerun_of: None,
+            retention_hours: 24,
+            created_at: Utc::now(),
+            finished_at: Some(Utc::now()),
+            expires_at: None,
+        }
+    }
+
+    fn returns() -> Vec<f64> {
+        (0..250).map(|i| 0.01 * ((i * 37 % 11) as f64 - 5.0)).collect()
+    }
+
+    #[test]
+    fn a_monte_carlo_rerun_reproduces_the_original() {
+        let original = done(json!({ "method": "montecarlo", "returns": returns(), "confidence": 0.99, "seed": 7 }));
+        let rerun = rerun_spec(&original, &json!({})).unwrap();
+        assert_eq!(rerun.request.params.seed, Some(7));
+        assert_eq!(evaluate(&rerun.request).unwrap(), original.result.unwrap());
+    }
+
+    #[test]
+    fn a_rerun_takes_the_seed_the_original_reported() {
+        // Jobs stored before seeds were pinned only carry it in their result
+        let mut original = done(json!({ "method": "montecarlo", "returns": returns(), "confidence": 0.99, "seed": 7 }));
+        original.request.params.seed = None;
+        let rerun = rerun_spec(&original, &json!({ "confidence": 0.95 })).unwrap();
+        assert_eq!(rerun.request.params.seed, Some(7));
+
+        let reseeded = rerun_spec(&original, &json!({ "seed": 8 })).unwrap();
+        assert_eq!(reseeded.request.params.seed, Some(8));
+    }
+
+    #[test]
+    fn idle_tenants_leave_the_scheduler() {
+        let mut sched = Scheduler { running: 3, running_batch: 1, ..Default::default() };
+        sched.per_tenant.insert("a".into(), 2);
+        sched.per_tenant.insert("b".into(), 1);
+        sched.release("b", Priority::Batch);
+        sched.release("a", Priority::Interactive);
+        assert_eq!(sched.per_tenant, HashMap::from([("a".to_string(), 1)]));
+        sched.release("a", Priority::Interactive);
+        assert!(sched.per_tenant.is_empty());
+        assert_eq!((sched.running, sched.running_batch), (0, 0));
+    }
+
+    #[test]
+    fn a_rerun_switched_to_a_simulation_is_pinned() {
+        let original = done(json!({ "method": "historical", "returns": returns(), "confidence": 0.99 }));
+        let rerun = rerun_spec(&original, &json!({ "method": "bootstrap" })).unwrap();
+        assert!(rerun.request.params.seed.is_some());
+        assert!(rerun.request.is_deterministic());
+    }
+}
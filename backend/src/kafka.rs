@@ -0,0 +1,509 @@
+//! A minimal Kafka client over the broker wire protocol, enough to read
+//! topics partition by partition without a consumer group and to publish
+//! to them. It speaks fixed, non-flexible API versions every broker from
+//! 1.0 through 4.x accepts (Metadata v4, ListOffsets v1, Fetch v4, Produce
+//! v3), reads v2 record batches uncompressed or gzip and writes them
+//! uncompressed. No TLS or SASL.
+
+use flate2::read::GzDecoder;
+use std::{collections::HashMap, io::Read, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+const PRODUCE: i16 = 0;
+const FETCH: i16 = 1;
+const LIST_OFFSETS: i16 = 2;
+const METADATA: i16 = 3;
+
+// Error codes the consumer acts on
+pub const OFFSET_OUT_OF_RANGE: i16 = 1;
+const LEADER_NOT_AVAILABLE: i16 = 5;
+
+/// `ListOffsets` timestamps for the log end and start.
+pub const LATEST: i64 = -1;
+pub const EARLIEST: i64 = -2;
+
+// Round trip allowance on top of a fetch's own wait
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// Bytes fetched per partition per request
+const PARTITION_MAX_BYTES: i32 = 1024 * 1024;
+const FETCH_MAX_BYTES: i32 = 16 * 1024 * 1024;
+const MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+// Batch attribute bits
+const COMPRESSION_MASK: i16 = 0x07;
+const GZIP: i16 = 1;
+const CONTROL_BATCH: i16 = 0x20;
+
+pub type KafkaResult<T> = Result<T, String>;
+
+/// A record to produce: key and value.
+pub type KeyedRecord<'a> = (&'a [u8], &'a [u8]);
+
+// Request body, big-endian as on the wire
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn i8(&mut self, v: i8) -> &mut Self {
+        self.0.extend(v.to_be_bytes());
+        self
+    }
+
+    fn i16(&mut self, v: i16) -> &mut Self {
+        self.0.extend(v.to_be_bytes());
+        self
+    }
+
+    fn i32(&mut self, v: i32) -> &mut Self {
+        self.0.extend(v.to_be_bytes());
+        self
+    }
+
+    fn i64(&mut self, v: i64) -> &mut Self {
+        self.0.extend(v.to_be_bytes());
+        self
+    }
+
+    fn string(&mut self, v: &str) -> &mut Self {
+        self.i16(v.len() as i16);
+        self.0.extend(v.as_bytes());
+        self
+    }
+
+    fn array(&mut self, len: usize) -> &mut Self {
+        self.i32(len as i32)
+    }
+
+    // Zig-zag varint of the record format
+    fn varint(&mut self, v: i64) -> &mut Self {
+        let mut n = ((v << 1) ^ (v >> 63)) as u64;
+        while n >= 0x80 {
+            self.0.push((n as u8) | 0x80);
+            n >>= 7;
+        }
+        self.0.push(n as u8);
+        self
+    }
+
+    fn varint_bytes(&mut self, v: Option<&[u8]>) -> &mut Self {
+        match v {
+            None => self.varint(-1),
+            Some(bytes) => {
+                self.varint(bytes.len() as i64);
+                self.0.extend(bytes);
+                self
+            }
+        }
+    }
+}
+
+// Response reader; every read fails cleanly on a short buffer
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> KafkaResult<&'a [u8]> {
+        if self.buf.len() < n {
+            return Err("truncated response".into());
+        }
+        let (head, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(head)
+    }
+
+    fn i8(&mut self) -> KafkaResult<i8> {
+        Ok(self.take(1)?[0] as i8)
+    }
+
+    fn i16(&mut self) -> KafkaResult<i16> {
+        Ok(i16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> KafkaResult<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> KafkaResult<i64> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    // A nullable string reads as empty
+    fn string(&mut self) -> KafkaResult<String> {
+        let len = self.i16()?;
+        if len < 0 {
+            return Ok(String::new());
+        }
+        let bytes = self.take(len as usize)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "string is not UTF-8".into())
+    }
+
+    fn bytes(&mut self) -> KafkaResult<Option<&'a [u8]>> {
+        let len = self.i32()?;
+        if len < 0 {
+            return Ok(None);
+        }
+        self.take(len as usize).map(Some)
+    }
+
+    fn array<T>(&mut self, mut item: impl FnMut(&mut Self) -> KafkaResult<T>) -> KafkaResult<Vec<T>> {
+        let len = self.i32()?;
+        (0..len.max(0)).map(|_| item(self)).collect()
+    }
+
+    // Zig-zag varint of the record format
+    fn varint(&mut self) -> KafkaResult<i64> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+            }
+        }
+        Err("varint too long".into())
+    }
+
+    fn varint_bytes(&mut self) -> KafkaResult<Option<&'a [u8]>> {
+        let len = self.varint()?;
+        if len < 0 {
+            return Ok(None);
+        }
+        self.take(len as usize).map(Some)
+    }
+}
+
+/// One consumed record.
+pub struct Record {
+    pub offset: i64,
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+}
+
+/// Where each partition of the requested topics lives.
+pub struct Metadata {
+    // Node id → host:port
+    pub brokers: HashMap<i32, String>,
+    // (topic, partition, leader node); partitions without a leader are left out
+    pub partitions: Vec<(String, i32, i32)>,
+}
+
+/// A partition's share of a fetch response.
+pub struct Fetched {
+    pub topic: String,
+    pub partition: i32,
+    pub error: i16,
+    pub records: Vec<Record>,
+}
+
+/// A connection to one broker.
+pub struct Connection {
+    stream: TcpStream,
+    client_id: String,
+    correlation: i32,
+}
+
+impl Connection {
+    pub async fn connect(address: &str, client_id: &str) -> KafkaResult<Self> {
+        let stream = timeout(REQUEST_TIMEOUT, TcpStream::connect(address))
+            .await
+            .map_err(|_| format!("connecting to {address} timed out"))?
+            .map_err(|e| format!("cannot connect to {address}: {e}"))?;
+        Ok(Connection { stream, client_id: client_id.to_string(), correlation: 0 })
+    }
+
+    // Sends a request (header v1) and returns the response body after its
+    // correlation id
+    async fn call(&mut self, api_key: i16, version: i16, body: &[u8]) -> KafkaResult<Vec<u8>> {
+        self.correlation = self.correlation.wrapping_add(1);
+        let mut header = Encoder::default();
+        header.i16(api_key).i16(version).i32(self.correlation).string(&self.client_id);
+        let size = (header.0.len() + body.len()) as i32;
+        let mut frame = size.to_be_bytes().to_vec();
+        frame.extend(header.0);
+        frame.extend(body);
+        let exchange = async {
+            self.stream.write_all(&frame).await?;
+            let size = self.stream.read_i32().await?.max(0) as usize;
+            if size > MAX_RESPONSE_BYTES {
+                return Err(std::io::Error::other(format!("{size}-byte response is too large")));
+            }
+            let mut response = vec![0; size];
+            self.stream.read_exact(&mut response).await?;
+            Ok(response)
+        };
+        let response = timeout(REQUEST_TIMEOUT, exchange)
+            .await
+            .map_err(|_| "broker did not answer in time".to_string())?
+            .map_err(|e| format!("broker connection failed: {e}"))?;
+        let mut decoder = Decoder { buf: &response };
+        if decoder.i32()? != self.correlation {
+            return Err("response does not match its request".into());
+        }
+        Ok(decoder.buf.to_vec())
+    }
+
+    /// Brokers and partition leaders of `topics`. An unknown topic is an
+    /// error: topics are never auto-created.
+    pub async fn metadata(&mut self, topics: &[String]) -> KafkaResult<Metadata> {
+        let mut body = Encoder::default();
+        body.array(topics.len());
+        for topic in topics {
+            body.string(topic);
+        }
+        body.i8(0); // allow_auto_topic_creation
+        let response = self.call(METADATA, 4, &body.0).await?;
+        let mut d = Decoder { buf: &response };
+        d.i32()?; // throttle_time_ms
+        let brokers = d.array(|d| {
+            let id = d.i32()?;
+            let host = d.string()?;
+            let port = d.i32()?;
+            d.string()?; // rack
+            Ok((id, format!("{host}:{port}")))
+        })?;
+        d.string()?; // cluster_id
+        d.i32()?; // controller_id
+        let mut partitions = Vec::new();
+        for _ in 0..d.i32()?.max(0) {
+            let error = d.i16()?;
+            let topic = d.string()?;
+            d.i8()?; // is_internal
+            if error != 0 && error != LEADER_NOT_AVAILABLE {
+                return Err(format!("topic {topic} is unavailable (error {error})"));
+            }
+            for _ in 0..d.i32()?.max(0) {
+                let error = d.i16()?;
+                let partition = d.i32()?;
+                let leader = d.i32()?;
+                d.array(|d| d.i32())?; // replicas
+                d.array(|d| d.i32())?; // isr
+                if error == 0 && leader >= 0 {
+                    partitions.push((topic.clone(), partition, leader));
+                }
+            }
+        }
+        Ok(Metadata { brokers: brokers.into_iter().collect(), partitions })
+    }
+
+    /// A partition's offset at `time`: `LATEST`, `EARLIEST` or a timestamp
+    /// in milliseconds.
+    pub async fn offset(&mut self, topic: &str, partition: i32, time: i64) -> KafkaResult<i64> {
+        let mut body = Encoder::default();
+        body.i32(-1).array(1).string(topic).array(1).i32(partition).i64(time);
+        let response = self.call(LIST_OFFSETS, 1, &body.0).await?;
+        let mut d = Decoder { buf: &response };
+        for _ in 0..d.i32()?.max(0) {
+            d.string()?;
+            for _ in 0..d.i32()?.max(0) {
+                let index = d.i32()?;
+                let error = d.i16()?;
+                d.i64()?; // timestamp
+                let offset = d.i64()?;
+                if index == partition {
+                    return match error {
+                        0 => Ok(offset),
+                        e => Err(format!("cannot list offsets of {topic}/{partition} (error {e})")),
+                    };
+                }
+            }
+        }
+        Err(format!("no offset returned for {topic}/{partition}"))
+    }
+
+    /// Appends `records` (key, value) to a partition this broker leads,
+    /// acknowledged by the leader, returning the first record's offset.
+    pub async fn produce(&mut self, topic: &str, partition: i32, records: &[KeyedRecord<'_>]) -> KafkaResult<i64> {
+        let batch = encode_batch(records, chrono::Utc::now().timestamp_millis());
+        let mut body = Encoder::default();
+        body.i16(-1); // transactional_id
+        body.i16(1).i32(REQUEST_TIMEOUT.as_millis() as i32); // acks, timeout_ms
+        body.array(1).string(topic).array(1).i32(partition).i32(batch.len() as i32);
+        body.0.extend(batch);
+        let response = self.call(PRODUCE, 3, &body.0).await?;
+        let mut d = Decoder { buf: &response };
+        for _ in 0..d.i32()?.max(0) {
+            d.string()?;
+            for _ in 0..d.i32()?.max(0) {
+                let index = d.i32()?;
+                let error = d.i16()?;
+                let offset = d.i64()?;
+                d.i64()?; // log_append_time_ms
+                if index == partition {
+                    return match error {
+                        0 => Ok(offset),
+                        e => Err(format!("producing to {topic}/{partition} failed (error {e})")),
+                    };
+                }
+            }
+        }
+        Err(format!("no produce acknowledgement for {topic}/{partition}"))
+    }
+
+    /// Records from each `(topic, partition, offset)` onwards, waiting up to
+    /// `max_wait` for any to arrive.
+    pub async fn fetch(&mut self, from: &[(String, i32, i64)], max_wait: Duration) -> KafkaResult<Vec<Fetched>> {
+        let mut by_topic: Vec<(&str, Vec<(i32, i64)>)> = Vec::new();
+        for (topic, partition, offset) in from {
+            match by_topic.iter_mut().find(|(t, _)| t == topic) {
+                Some((_, partitions)) => partitions.push((*partition, *offset)),
+                None => by_topic.push((topic, vec![(*partition, *offset)])),
+            }
+        }
+        let mut body = Encoder::default();
+        body.i32(-1).i32(max_wait.as_millis() as i32).i32(1).i32(FETCH_MAX_BYTES);
+        body.i8(0); // read_uncommitted
+        body.array(by_topic.len());
+        for (topic, partitions) in &by_topic {
+            body.string(topic).array(partitions.len());
+            for (partition, offset) in partitions {
+                body.i32(*partition).i64(*offset).i32(PARTITION_MAX_BYTES);
+            }
+        }
+        let response = self.call(FETCH, 4, &body.0).await?;
+        let mut d = Decoder { buf: &response };
+        d.i32()?; // throttle_time_ms
+        let mut fetched = Vec::new();
+        for _ in 0..d.i32()?.max(0) {
+            let topic = d.string()?;
+            for _ in 0..d.i32()?.max(0) {
+                let partition = d.i32()?;
+                let error = d.i16()?;
+                d.i64()?; // high_watermark
+                d.i64()?; // last_stable_offset
+                d.array(|d| Ok((d.i64()?, d.i64()?)))?; // aborted_transactions
+                let data = d.bytes()?.unwrap_or_default();
+                let records = if error == 0 { batches(data)? } else { Vec::new() };
+                fetched.push(Fetched { topic: topic.clone(), partition, error, records });
+            }
+        }
+        Ok(fetched)
+    }
+}
+
+/// Connects to the first reachable of `brokers` (host:port).
+pub async fn bootstrap(brokers: &[String], client_id: &str) -> KafkaResult<Connection> {
+    let mut errors = Vec::new();
+    for broker in brokers {
+        match Connection::connect(broker, client_id).await {
+            Ok(connection) => return Ok(connection),
+            Err(e) => errors.push(e),
+        }
+    }
+    Err(errors.join("; "))
+}
+
+// CRC-32C (Castagnoli), the checksum of v2 record batches
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x82f6_3b78 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// An uncompressed v2 batch of records stamped `timestamp` (ms), offsets
+// assigned by the broker
+fn encode_batch(records: &[KeyedRecord<'_>], timestamp: i64) -> Vec<u8> {
+    let mut body = Encoder::default();
+    body.i16(0); // attributes
+    body.i32(records.len() as i32 - 1).i64(timestamp).i64(timestamp);
+    body.i64(-1).i16(-1).i32(-1); // producer id, epoch, base sequence
+    body.array(records.len());
+    for (delta, (key, value)) in records.iter().enumerate() {
+        let mut record = Encoder::default();
+        record.i8(0).varint(0).varint(delta as i64);
+        record.varint_bytes(Some(key)).varint_bytes(Some(value)).varint(0);
+        body.varint(record.0.len() as i64);
+        body.0.extend(record.0);
+    }
+    let mut batch = Encoder::default();
+    batch.i64(0).i32(4 + 1 + 4 + body.0.len() as i32);
+    batch.i32(-1).i8(2).i32(crc32c(&body.0) as i32);
+    batch.0.extend(body.0);
+    batch.0
+}
+
+/// The partition of `key` among `partitions`, as the Java client's default
+/// partitioner picks it (murmur2), so keyed events land where other
+/// producers would put them.
+pub fn partition_for(key: &[u8], partitions: usize) -> i32 {
+    const M: u32 = 0x5bd1_e995;
+    let mut h: u32 = 0x9747_b28c ^ key.len() as u32;
+    let chunks = key.chunks_exact(4);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let mut k = u32::from_le_bytes(chunk.try_into().unwrap()).wrapping_mul(M);
+        k ^= k >> 24;
+        h = h.wrapping_mul(M) ^ k.wrapping_mul(M);
+    }
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate().rev() {
+            h ^= u32::from(*byte) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    ((h & 0x7fff_ffff) as usize % partitions.max(1)) as i32
+}
+
+// Records of the v2 batches in a fetched record set. A trailing batch cut
+// short by the fetch size is left for the next fetch; control batches
+// (transaction markers) carry no data.
+fn batches(mut data: &[u8]) -> KafkaResult<Vec<Record>> {
+    let mut records = Vec::new();
+    while data.len() >= 12 {
+        let base_offset = i64::from_be_bytes(data[..8].try_into().unwrap());
+        let length = i32::from_be_bytes(data[8..12].try_into().unwrap()).max(0) as usize;
+        if data.len() < 12 + length {
+            break;
+        }
+        let mut d = Decoder { buf: &data[12..12 + length] };
+        data = &data[12 + length..];
+        d.i32()?; // partition_leader_epoch
+        let magic = d.i8()?;
+        if magic != 2 {
+            return Err(format!("message format v{magic} is not supported; records must be v2 (Kafka 0.11+)"));
+        }
+        d.i32()?; // crc
+        let attributes = d.i16()?;
+        d.take(4 + 8 + 8 + 8 + 2 + 4)?; // last_offset_delta .. base_sequence
+        let count = d.i32()?;
+        if attributes & CONTROL_BATCH != 0 {
+            continue;
+        }
+        let inflated;
+        let mut body = match attributes & COMPRESSION_MASK {
+            0 => d,
+            GZIP => {
+                let mut buf = Vec::new();
+                GzDecoder::new(d.buf).read_to_end(&mut buf).map_err(|e| format!("corrupt gzip batch: {e}"))?;
+                inflated = buf;
+                Decoder { buf: &inflated }
+            }
+            codec => return Err(format!("compression codec {codec} is not supported; use none or gzip")),
+        };
+        for _ in 0..count.max(0) {
+            body.varint()?; // length
+            body.i8()?; // attributes
+            body.varint()?; // timestamp_delta
+            let offset_delta = body.varint()?;
+            let key = body.varint_bytes()?.map(<[u8]>::to_vec);
+            let value = body.varint_bytes()?.map(<[u8]>::to_vec);
+            for _ in 0..body.varint()?.max(0) {
+                body.varint_bytes()?;
+                body.varint_bytes()?;
+            }
+            records.push(Record { offset: base_offset + offset_delta, key, value });
+        }
+    }
+    Ok(records)
+}
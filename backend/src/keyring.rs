@@ -0,0 +1,122 @@
+//! Master keys for envelope encryption. Every stored secret is encrypted
+//! under its own random data key; only that data key is encrypted
+//! ("wrapped") under a master key, as a KMS would. Rotating the master key
+//! therefore re-wraps small data keys and never touches the secrets.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, env};
+
+pub const KEY_LEN: usize = 32;
+
+/// A data key encrypted under the master key `key_id`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WrappedKey {
+    pub key_id: String,
+    // Base64 of nonce ‖ ciphertext ‖ tag
+    pub ciphertext: String,
+}
+
+/// KMS-style key management: data keys are generated locally and wrapped
+/// under a named master key that never leaves the provider.
+pub trait KeyManager: Send + Sync {
+    /// Master key new data keys are wrapped under.
+    fn active_key(&self) -> String;
+    /// Master keys that can still unwrap, active one included.
+    fn key_ids(&self) -> Vec<String>;
+    fn wrap(&self, data_key: &[u8]) -> Result<WrappedKey, String>;
+    fn unwrap(&self, wrapped: &WrappedKey) -> Result<Vec<u8>, String>;
+}
+
+/// Random bytes from the OS generator.
+pub fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    SystemRandom::new().fill(&mut bytes).expect("OS random number generator failed");
+    bytes
+}
+
+/// AES-256-GCM over `plaintext`, bound to `aad`, as nonce ‖ ciphertext ‖ tag.
+pub fn seal(key: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).map_err(|_| "invalid AES-256 key")?);
+    let nonce = random::<NONCE_LEN>();
+    let mut sealed = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad), &mut sealed)
+        .map_err(|_| "encryption failed")?;
+    Ok([nonce.as_slice(), &sealed].concat())
+}
+
+/// Inverse of `seal`; fails on a wrong key, a wrong `aad` or tampering.
+pub fn open(key: &[u8], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, String> {
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).map_err(|_| "invalid AES-256 key")?);
+    if sealed.len() < NONCE_LEN {
+        return Err("ciphertext is truncated".into());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "invalid nonce")?;
+    let mut buffer = ciphertext.to_vec();
+    let plaintext = key.open_in_place(nonce, Aad::from(aad), &mut buffer).map_err(|_| "decryption failed")?;
+    Ok(plaintext.to_vec())
+}
+
+/// Master keys held in the process, from `MASTER_KEYS`: comma-separated
+/// `id:base64` pairs of 32-byte keys. `MASTER_KEY_ID` names the active one
+/// (default: the last listed), so rotating means appending a key, switching
+/// to it and re-wrapping; older keys stay listed until nothing uses them.
+pub struct LocalKeyring {
+    keys: BTreeMap<String, [u8; KEY_LEN]>,
+    active: String,
+}
+
+impl LocalKeyring {
+    pub fn from_env() -> Option<Result<Self, String>> {
+        let spec = env::var("MASTER_KEYS").ok()?;
+        Some(Self::parse(&spec, env::var("MASTER_KEY_ID").ok()))
+    }
+
+    fn parse(spec: &str, active: Option<String>) -> Result<Self, String> {
+        let mut keys = BTreeMap::new();
+        let mut last = None;
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (id, encoded) = entry.split_once(':').ok_or_else(|| format!("master key {entry:?} is not id:base64"))?;
+            let bytes = BASE64.decode(encoded).map_err(|e| format!("master key {id}: {e}"))?;
+            let key: [u8; KEY_LEN] =
+                bytes.try_into().map_err(|_| format!("master key {id} must be {KEY_LEN} bytes"))?;
+            keys.insert(id.to_string(), key);
+            last = Some(id.to_string());
+        }
+        let active = active.or(last).ok_or("MASTER_KEYS lists no keys")?;
+        if !keys.contains_key(&active) {
+            return Err(format!("active master key {active} is not in MASTER_KEYS"));
+        }
+        Ok(LocalKeyring { keys, active })
+    }
+
+    fn key(&self, id: &str) -> Result<&[u8; KEY_LEN], String> {
+        self.keys.get(id).ok_or_else(|| format!("master key {id} is not configured"))
+    }
+}
+
+impl KeyManager for LocalKeyring {
+    fn active_key(&self) -> String {
+        self.active.clone()
+    }
+
+    fn key_ids(&self) -> Vec<String> {
+        self.keys.keys().cloned().collect()
+    }
+
+    fn wrap(&self, data_key: &[u8]) -> Result<WrappedKey, String> {
+        // The key id is authenticated, so a wrapped key can't be relabelled
+        let sealed = seal(self.key(&self.active)?, self.active.as_bytes(), data_key)?;
+        Ok(WrappedKey { key_id: self.active.clone(), ciphertext: BASE64.encode(sealed) })
+    }
+
+    fn unwrap(&self, wrapped: &WrappedKey) -> Result<Vec<u8>, String> {
+        let sealed = BASE64.decode(&wrapped.ciphertext).map_err(|e| e.to_string())?;
+        open(self.key(&wrapped.key_id)?, wrapped.key_id.as_bytes(), &sealed)
+    }
+}
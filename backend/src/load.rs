@@ -0,0 +1,63 @@
+use axum::{
+    error_handling::HandleErrorLayer,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    BoxError, Json,
+};
+use serde_json::json;
+use std::{env, future::Ready, sync::Arc};
+use tokio::sync::Semaphore;
+use tower::{
+    layer::util::{Identity, Stack},
+    limit::GlobalConcurrencyLimitLayer,
+    load_shed::LoadShedLayer,
+    ServiceBuilder,
+};
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// In-flight request budgets shared by every endpoint of a class: cheap
+/// price fetches and lookups, and CPU-bound simulations.
+#[derive(Clone)]
+pub struct Limits {
+    pub fetch: Arc<Semaphore>,
+    pub compute: Arc<Semaphore>,
+}
+
+impl Limits {
+    /// Reads `FETCH_CONCURRENCY` (default 64) and `COMPUTE_CONCURRENCY`
+    /// (default: available cores).
+    pub fn from_env() -> Self {
+        let cores = std::thread::available_parallelism().map_or(2, |n| n.get());
+        Limits {
+            fetch: Arc::new(Semaphore::new(env_or("FETCH_CONCURRENCY", 64))),
+            compute: Arc::new(Semaphore::new(env_or("COMPUTE_CONCURRENCY", cores))),
+        }
+    }
+}
+
+type Shed = ServiceBuilder<
+    Stack<GlobalConcurrencyLimitLayer, Stack<LoadShedLayer, Stack<HandleErrorLayer<fn(BoxError) -> Ready<Response>, ()>, Identity>>>,
+>;
+
+/// Caps a route at the budget's concurrency and sheds anything beyond it
+/// with 503 + `Retry-After` instead of queueing it.
+pub fn shed(budget: &Arc<Semaphore>) -> Shed {
+    ServiceBuilder::new()
+        .layer(HandleErrorLayer::new(overloaded as fn(BoxError) -> Ready<Response>))
+        .layer(LoadShedLayer::new())
+        .layer(GlobalConcurrencyLimitLayer::with_semaphore(budget.clone()))
+}
+
+fn overloaded(err: BoxError) -> Ready<Response> {
+    let retry_after = env_or("RETRY_AFTER_SECS", 1u64);
+    let response = if err.is::<tower::load_shed::error::Overloaded>() {
+        let body = json!({ "error": "server is at capacity for this endpoint; retry shortly" });
+        (StatusCode::SERVICE_UNAVAILABLE, [(header::RETRY_AFTER, retry_after.to_string())], Json(body)).into_response()
+    } else {
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": err.to_string() }))).into_response()
+    };
+    std::future::ready(response)
+}
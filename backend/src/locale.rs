@@ -0,0 +1,211 @@
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{error::ApiError, tenant::Tenant, AppState};
+
+/// Presentation locale for generated reports and CSV files. Computation is
+/// unaffected; only labels, number and date rendering change.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Fr,
+    Ja,
+}
+
+// Report labels by key, in en / de / fr / ja order
+const LABELS: &[(&str, [&str; 4])] = &[
+    ("risk_report", ["Risk report", "Risikobericht", "Rapport de risque", "リスクレポート"]),
+    ("portfolio", ["Portfolio", "Portfolio", "Portefeuille", "ポートフォリオ"]),
+    ("portfolio_id", ["Portfolio id", "Portfolio-ID", "Identifiant du portefeuille", "ポートフォリオID"]),
+    ("as_of", ["As of", "Stand", "Au", "基準日"]),
+    ("generated", ["Generated", "Erstellt", "Généré le", "作成日時"]),
+    ("method", ["Method", "Methode", "Méthode", "手法"]),
+    ("engine", ["Engine", "Engine", "Moteur", "エンジン"]),
+    ("methodology", ["methodology", "Methodik", "méthodologie", "方法論"]),
+    ("observations", ["Observations", "Beobachtungen", "Observations", "観測数"]),
+    ("confidence", ["Confidence", "Konfidenzniveau", "Niveau de confiance", "信頼水準"]),
+    ("gross_value", ["Gross value", "Bruttowert", "Valeur brute", "グロス額"]),
+    ("net_value", ["Net value", "Nettowert", "Valeur nette", "ネット額"]),
+    ("var", ["VaR", "VaR", "VaR", "VaR"]),
+    ("es", ["Expected shortfall", "Expected Shortfall", "Expected shortfall", "期待ショートフォール"]),
+    ("summary", ["Summary", "Übersicht", "Synthèse", "概要"]),
+    ("positions", ["Positions", "Positionen", "Positions", "ポジション"]),
+    ("ticker", ["Ticker", "Ticker", "Code", "銘柄"]),
+    ("value", ["Value", "Wert", "Valeur", "評価額"]),
+    ("weight", ["Weight", "Gewicht", "Poids", "ウェイト"]),
+    ("vol", ["Daily vol", "Tagesvolatilität", "Volatilité quotidienne", "日次ボラティリティ"]),
+    ("standalone_var", ["Standalone VaR", "Einzel-VaR", "VaR individuelle", "単体VaR"]),
+    ("component_var", ["Component VaR", "Komponenten-VaR", "VaR par composante", "コンポーネントVaR"]),
+    ("pct_of_var", ["% of VaR", "% des VaR", "% de la VaR", "VaR比率"]),
+    ("backtest", ["Backtest", "Backtest", "Backtest", "バックテスト"]),
+    ("date", ["Date", "Datum", "Date", "日付"]),
+    ("pnl", ["P&L", "GuV", "P&L", "損益"]),
+    ("portfolio_pnl", ["Portfolio P&L", "Portfolio-GuV", "P&L du portefeuille", "ポートフォリオ損益"]),
+    ("breach", ["Breach", "Überschreitung", "Dépassement", "超過"]),
+    ("breaches", ["Breaches", "Überschreitungen", "Dépassements", "超過回数"]),
+    ("expected", ["Expected", "Erwartet", "Attendu", "期待値"]),
+    ("window", ["Window", "Fenster", "Fenêtre", "ウィンドウ"]),
+    ("days", ["days", "Tage", "jours", "日"]),
+    ("no_backtest", [
+        "Not enough history for the backtest window",
+        "Zu wenig Historie für das Backtest-Fenster",
+        "Historique insuffisant pour la fenêtre de backtest",
+        "バックテスト期間に対して履歴が不足しています",
+    ]),
+    ("scenarios", ["Scenarios", "Szenarien", "Scénarios", "シナリオ"]),
+    ("worst_scenarios", ["Worst scenarios", "Schlechteste Szenarien", "Pires scénarios", "最悪シナリオ"]),
+    ("disclaimer", [
+        "Figures are historical simulations and do not predict future losses.",
+        "Die Zahlen beruhen auf historischer Simulation und sind keine Prognose künftiger Verluste.",
+        "Les chiffres sont des simulations historiques et ne prédisent pas les pertes futures.",
+        "数値はヒストリカル・シミュレーションによるもので、将来の損失を予測するものではありません。",
+    ]),
+];
+
+impl Locale {
+    /// Accepts language codes with or without a region (`de`, `de-CH`, `ja_JP`).
+    pub fn parse(tag: &str) -> Option<Locale> {
+        match tag.split(['-', '_']).next()?.to_lowercase().as_str() {
+            "en" => Some(Locale::En),
+            "de" => Some(Locale::De),
+            "fr" => Some(Locale::Fr),
+            "ja" => Some(Locale::Ja),
+            _ => None,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Translated report label; unknown keys come back unchanged.
+    pub fn label(self, key: &str) -> &str {
+        LABELS
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map_or(key, |(_, labels)| labels[self.index()])
+    }
+
+    /// Field separator for CSV files; `;` where the decimal separator is a comma.
+    pub fn csv_delimiter(self) -> u8 {
+        match self {
+            Locale::De | Locale::Fr => b';',
+            Locale::En | Locale::Ja => b',',
+        }
+    }
+
+    fn separators(self) -> (char, &'static str) {
+        match self {
+            Locale::En | Locale::Ja => ('.', ","),
+            Locale::De => (',', "."),
+            Locale::Fr => (',', "\u{202f}"),
+        }
+    }
+
+    /// Number with `decimals` places and the locale's grouping and decimal separators.
+    pub fn number(self, value: f64, decimals: usize) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+        let (decimal, group) = self.separators();
+        let formatted = format!("{:.*}", decimals, value.abs());
+        let (int, frac) = formatted.split_once('.').unwrap_or((&formatted, ""));
+        let mut grouped = String::new();
+        for (i, digit) in int.chars().enumerate() {
+            if i > 0 && (int.len() - i) % 3 == 0 {
+                grouped.push_str(group);
+            }
+            grouped.push(digit);
+        }
+        let sign = if value < 0.0 && formatted.chars().any(|c| c.is_ascii_digit() && c != '0') { "-" } else { "" };
+        match frac {
+            "" => format!("{sign}{grouped}"),
+            frac => format!("{sign}{grouped}{decimal}{frac}"),
+        }
+    }
+
+    /// Number with the locale's decimal separator and no grouping, for data files.
+    pub fn plain(self, value: f64, decimals: usize) -> String {
+        let (decimal, _) = self.separators();
+        format!("{:.*}", decimals, value).replace('.', &decimal.to_string())
+    }
+
+    /// Fraction as a percentage, e.g. 0.0123 → `1.23 %` in de/fr.
+    pub fn percent(self, value: f64) -> String {
+        match self {
+            Locale::En | Locale::Ja => format!("{}%", self.number(value * 100.0, 2)),
+            Locale::De | Locale::Fr => format!("{}\u{a0}%", self.number(value * 100.0, 2)),
+        }
+    }
+
+    /// ISO date (`YYYY-MM-DD`, optionally followed by a time) in the
+    /// locale's order; anything else is returned as is.
+    pub fn date(self, iso: &str) -> String {
+        let Some(date) = iso.get(..10).and_then(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()) else {
+            return iso.to_string();
+        };
+        let pattern = match self {
+            Locale::En => "%Y-%m-%d",
+            Locale::De => "%d.%m.%Y",
+            Locale::Fr => "%d/%m/%Y",
+            Locale::Ja => "%Y/%m/%d",
+        };
+        date.format(pattern).to_string()
+    }
+}
+
+/// Per-tenant default locale
+#[derive(Default)]
+pub struct LocaleStore {
+    tenants: Mutex<HashMap<String, Locale>>,
+}
+
+impl LocaleStore {
+    pub fn all(&self) -> HashMap<String, Locale> {
+        self.tenants.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, tenant: &str, locale: Locale) {
+        self.tenants.lock().unwrap().insert(tenant.to_string(), locale);
+    }
+
+    pub fn get(&self, tenant: &str) -> Locale {
+        self.tenants.lock().unwrap().get(tenant).copied().unwrap_or_default()
+    }
+
+    /// The request's `locale` if given, else the tenant's default.
+    pub fn resolve(&self, tenant: &str, requested: Option<&str>) -> Result<Locale, ApiError> {
+        match requested {
+            Some(tag) => Locale::parse(tag)
+                .ok_or_else(|| ApiError::bad_request(format!("unsupported locale {tag}; use en, de, fr or ja"))),
+            None => Ok(self.get(tenant)),
+        }
+    }
+}
+
+// Payload to set a tenant's locale
+#[derive(Deserialize)]
+pub struct LocaleSetting {
+    locale: String,
+}
+
+/// The caller's default locale
+pub async fn get_locale_handler(State(state): State<AppState>, Tenant(tenant): Tenant) -> Json<Value> {
+    Json(json!({ "locale": state.locales.get(&tenant) }))
+}
+
+/// Set the caller's default locale
+pub async fn put_locale_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Json(body): Json<LocaleSetting>,
+) -> Result<Json<Value>, ApiError> {
+    let locale = state.locales.resolve(&tenant, Some(&body.locale))?;
+    state.locales.set(&tenant, locale);
+    Ok(Json(json!({ "locale": locale })))
+}
@@ -0,0 +1,98 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+use crate::validation::cross_field;
+
+// Look-back when neither `start` nor `days` is given
+const DEFAULT_DAYS: u32 = 365;
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Interval {
+    #[default]
+    #[serde(rename = "1d")]
+    Daily,
+    #[serde(rename = "1wk")]
+    Weekly,
+    #[serde(rename = "1mo")]
+    Monthly,
+}
+
+impl Interval {
+    /// Yahoo's name for the bar size
+    pub fn code(self) -> &'static str {
+        match self {
+            Interval::Daily => "1d",
+            Interval::Weekly => "1wk",
+            Interval::Monthly => "1mo",
+        }
+    }
+
+    /// Alpha Vantage's function for the bar size and the key of its series
+    pub fn alpha_vantage(self) -> (&'static str, &'static str) {
+        match self {
+            Interval::Daily => ("TIME_SERIES_DAILY", "Time Series (Daily)"),
+            Interval::Weekly => ("TIME_SERIES_WEEKLY", "Weekly Time Series"),
+            Interval::Monthly => ("TIME_SERIES_MONTHLY", "Monthly Time Series"),
+        }
+    }
+
+    /// Polygon's timespan for the bar size
+    pub fn polygon(self) -> &'static str {
+        match self {
+            Interval::Daily => "day",
+            Interval::Weekly => "week",
+            Interval::Monthly => "month",
+        }
+    }
+}
+
+// Span and bar size of the history fetched for a ticker
+#[derive(Clone, Default, Deserialize, Validate)]
+#[validate(schema(function = "check_span"))]
+pub struct Lookback {
+    // First and last day (inclusive); `end` defaults to today
+    #[serde(default)]
+    pub start: Option<NaiveDate>,
+    #[serde(default)]
+    pub end: Option<NaiveDate>,
+    // Calendar days back from `end` (default 365); not combined with `start`
+    #[serde(default)]
+    #[validate(range(min = 1, max = 36500, message = "days must be between 1 and 36500"))]
+    pub days: Option<u32>,
+    #[serde(default)]
+    pub interval: Interval,
+}
+
+fn check_span(lookback: &Lookback) -> Result<(), ValidationError> {
+    if lookback.start.is_some() && lookback.days.is_some() {
+        return Err(cross_field("days", "send either start or days, not both".into()));
+    }
+    if let (Some(start), Some(end)) = (lookback.start, lookback.end) {
+        if start > end {
+            return Err(cross_field("start", format!("start {start} is after end {end}")));
+        }
+    }
+    Ok(())
+}
+
+impl Lookback {
+    /// Whether this is the default window (the last 365 days of daily bars),
+    /// which is what the price cache and store hold per ticker.
+    pub fn is_default(&self) -> bool {
+        self.start.is_none() && self.end.is_none() && self.days.is_none() && self.interval == Interval::Daily
+    }
+
+    /// Start and end instants, `end` at the close of its day.
+    pub fn span(&self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let end = match self.end {
+            Some(day) => day.and_time(NaiveTime::MIN).and_utc() + Duration::days(1) - Duration::seconds(1),
+            None => now,
+        };
+        let start = match self.start {
+            Some(day) => day.and_time(NaiveTime::MIN).and_utc(),
+            None => end - Duration::days(i64::from(self.days.unwrap_or(DEFAULT_DAYS))),
+        };
+        (start, end)
+    }
+}
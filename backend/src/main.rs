@@ -1,21 +1,125 @@
-use axum::{routing::post, Json, Router};
+use axum::{
+    extract::{DefaultBodyLimit, State},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
 use tower_http::cors::CorsLayer;
 use tokio::net::TcpListener;
-use std::{env, net::SocketAddr};
-use serde_json::{json, Value};
-use chrono::{Utc, Duration, TimeZone};
+use std::{env, net::SocketAddr, sync::Arc};
+use serde_json::json;
+use chrono::Utc;
 use dotenv::dotenv;
 
+mod admin;
+mod allocation;
+mod arrow;
+mod artifacts;
+mod audit;
+mod backfill;
+mod backtest;
+mod budget;
+mod cache;
+mod cancel;
+mod cleaning;
+mod costs;
+mod covariance;
+mod credentials;
+mod defaults;
+mod depeg;
+mod error;
+mod estimate;
+mod explain;
+mod fixml;
+mod fpml;
+mod flags;
+mod futures;
+mod garch;
+mod history;
+mod horizon;
+mod incremental;
+#[cfg(feature = "streaming")]
+mod ingest;
+mod jobs;
+#[cfg(feature = "streaming")]
+mod kafka;
+mod keyring;
+mod load;
+mod locale;
+mod lookback;
+mod margin;
+mod max_loss;
+mod measures;
+mod multipart;
+mod nested;
+mod perps;
+mod portfolio_var;
+mod panics;
+mod portfolios;
+mod profiles;
+mod providers;
+#[cfg(feature = "streaming")]
+mod publish;
+#[cfg(feature = "redis")]
+mod queue;
+mod reduce;
+mod replay;
+mod report;
+mod rolling;
+mod sanity;
+mod scenarios;
+mod scoring;
+mod steps;
+mod storage;
+mod stream;
+mod summary;
+mod templates;
+mod tenant;
+mod trash;
+mod units;
+mod upload;
+mod validation;
 mod var;
-use var::{VarRequest, compute_var};
+mod verify;
+mod version;
+mod watchlists;
+mod xml;
+use error::ApiError;
+use jobs::JobStore;
+use lookback::{Interval, Lookback};
+use profiles::Profiled;
+use tenant::Tenant;
+use units::ReturnType;
+use validation::Valid;
+use validator::Validate;
+use var::{VarRequest, evaluate};
 
 use serde::{Deserialize, Serialize};
-use reqwest;
 
 // Payload to fetch returns
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 struct FetchRequest {
+    #[validate(length(min = 1, max = 32, message = "ticker must be 1 to 32 characters"))]
     ticker: String,
+    // Stream the response body in chunks instead of buffering it
+    #[serde(default)]
+    stream: bool,
+    #[serde(flatten)]
+    #[validate(nested)]
+    cleaning: cleaning::CleanOptions,
+    // History span and bar size (default: the last 365 days of daily closes)
+    #[serde(flatten)]
+    #[validate(nested)]
+    lookback: Lookback,
+    // Backfills the series with this proxy's moves when it is shorter than min_history
+    #[serde(default)]
+    proxy: Option<String>,
+    // Returns required before the series counts as short (default: historical's minimum)
+    #[serde(default)]
+    min_history: Option<usize>,
+    #[serde(default)]
+    history_policy: Option<history::HistoryPolicy>,
 }
 
 // One row of preview
@@ -30,143 +134,360 @@ struct PreviewRow {
 #[derive(Serialize)]
 struct FetchResponse {
     returns: Vec<f64>,
+    return_type: ReturnType,
     preview: Vec<PreviewRow>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backfill: Option<backfill::Backfill>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+// Shared handler state
+#[derive(Clone)]
+struct AppState {
+    jobs: Arc<JobStore>,
+    cache: Arc<dyn cache::Cache>,
+    prices: Arc<dyn storage::PriceStore>,
+    rolling: Arc<rolling::RollingIndex>,
+    watchlists: Arc<watchlists::WatchlistStore>,
+    portfolios: Arc<portfolios::PortfolioStore>,
+    templates: Arc<templates::TemplateStore>,
+    locales: Arc<locale::LocaleStore>,
+    flags: Arc<flags::FlagStore>,
+    futures: Arc<futures::FuturesStore>,
+    #[cfg(feature = "streaming")]
+    stream: Arc<ingest::Streaming>,
+    #[cfg(feature = "streaming")]
+    events: Option<Arc<publish::Publisher>>,
+    artifacts: Arc<artifacts::Artifacts>,
+    audit: Arc<audit::AuditLog>,
+    credentials: Arc<credentials::CredentialStore>,
+    // Provider fetches in flight, by price cache key
+    fetches: Arc<cache::Flights>,
 }
 
 #[tokio::main]
 async fn main() {
     // Load .env
     dotenv().ok();
+    let _reporting = panics::init_reporting();
+    var::init_simulation_pool();
+
+    // `backend --worker` consumes jobs from the shared queue instead of serving HTTP
+    if env::args().any(|a| a == "--worker") {
+        #[cfg(feature = "redis")]
+        match queue::RedisQueue::from_env() {
+            Some(queue) => return queue::run_worker(queue).await,
+            None => eprintln!("❌ Worker mode requires REDIS_URL"),
+        }
+        #[cfg(not(feature = "redis"))]
+        eprintln!("❌ Worker mode requires building with --features redis");
+        std::process::exit(1);
+    }
+
+    let audit = Arc::new(audit::AuditLog::from_env());
+    let jobs = JobStore::from_env(audit.clone());
+    #[cfg(feature = "streaming")]
+    let events = publish::Publisher::from_env();
+    #[cfg(feature = "streaming")]
+    let jobs = jobs.publishing(events.clone());
+    let state = AppState {
+        jobs: Arc::new(jobs),
+        cache: cache::from_env().await.into(),
+        prices: storage::from_env().await.into(),
+        rolling: Arc::new(rolling::RollingIndex::from_env()),
+        watchlists: Arc::new(watchlists::WatchlistStore::default()),
+        portfolios: Arc::new(portfolios::PortfolioStore::default()),
+        templates: Arc::new(templates::TemplateStore::default()),
+        locales: Arc::new(locale::LocaleStore::default()),
+        flags: Arc::new(flags::FlagStore::from_env()),
+        futures: Arc::new(futures::FuturesStore::default()),
+        #[cfg(feature = "streaming")]
+        stream: Arc::new(ingest::Streaming::default()),
+        #[cfg(feature = "streaming")]
+        events,
+        artifacts: Arc::new(artifacts::Artifacts::from_env()),
+        audit,
+        credentials: Arc::new(credentials::CredentialStore::from_env()),
+        fetches: Arc::new(cache::Flights::default()),
+    };
+    jobs::spawn_gc(state.jobs.clone());
+    jobs::resume_unfinished(&state.jobs);
+    trash::spawn_purge(state.clone());
+    artifacts::spawn_purge(state.artifacts.clone());
+    #[cfg(feature = "streaming")]
+    if let Some(config) = ingest::StreamConfig::from_env() {
+        tokio::spawn(ingest::run(state.clone(), config));
+    }
+    #[cfg(not(feature = "streaming"))]
+    if env::var("KAFKA_BROKERS").is_ok() || env::var("EVENTS_TOPIC").is_ok() {
+        eprintln!("⚠️ KAFKA_BROKERS / EVENTS_TOPIC are ignored; streaming requires building with --features streaming");
+    }
 
+    // Fetches and simulations draw on separate budgets; excess load gets 503
+    let limits = load::Limits::from_env();
+    let (fetch, compute) = (load::shed(&limits.fetch), load::shed(&limits.compute));
     let app = Router::new()
-        .route("/api/fetch_returns", post(fetch_returns_handler))
-        .route("/api/compute_var",    post(var_handler))
-        .layer(CorsLayer::very_permissive());
+        .route("/api/version",        get(version::version_handler))
+        .route("/api/fetch_returns", post(fetch_returns_handler).layer(fetch.clone()))
+        .route("/api/upload_returns", post(upload::upload_returns_handler).layer(DefaultBodyLimit::max(64 * 1024 * 1024)))
+        .route("/api/returns/:ticker/cleaning", get(cleaning::cleaning_report_handler).layer(fetch.clone()))
+        .route("/api/compute_var",    post(var_handler).layer(compute.clone()))
+        .route("/api/var_term_structure", post(horizon::term_structure_handler).layer(compute.clone()))
+        .route("/api/estimate_cost",  post(estimate::estimate_cost_handler))
+        .route("/api/profiles",       get(profiles::list_profiles_handler))
+        .route("/api/portfolio_var",  post(portfolio_var::portfolio_var_handler).layer(compute.clone()))
+        .route("/api/compute_portfolio_var", post(allocation::compute_portfolio_var_handler).layer(compute.clone()))
+        .route("/api/incremental_var", post(incremental::incremental_var_handler).layer(compute.clone()))
+        .route("/api/max_loss",     post(max_loss::max_loss_handler).layer(compute.clone()))
+        .route("/api/risk_measures", post(measures::risk_measures_handler).layer(compute.clone()))
+        .route("/api/nested_simulation", post(nested::nested_simulation_handler).layer(compute.clone()))
+        .route("/api/simulate", post(scenarios::simulate_handler).layer(compute.clone()))
+        .route("/api/scenario_set", post(scenarios::scenario_set_handler).layer(compute.clone()))
+        .route("/api/aggregate_pnl", post(scenarios::aggregate_pnl_handler).layer(compute.clone()))
+        .route("/api/depeg_scenarios", get(depeg::list_scenarios_handler))
+        .route("/api/replay/bundle",  post(replay::bundle_handler).layer(compute.clone()))
+        .route("/api/replay/verify",  post(replay::verify_handler).layer(compute.clone()))
+        .route("/api/backtest",       post(backtest::backtest_handler).layer(compute.clone()))
+        .route("/api/compare_models", post(backtest::compare_models_handler).layer(compute.clone()))
+        .route("/api/prices/:ticker", get(storage::get_prices_handler)
+                                      .post(storage::put_prices_handler)
+                                      .layer((DefaultBodyLimit::max(512 * 1024 * 1024), fetch.clone())))
+        .route("/api/futures",        get(futures::list_futures_handler))
+        .route("/api/futures/:root",  put(futures::put_futures_handler).delete(futures::delete_futures_handler))
+        .route("/api/futures/:root/continuous", get(futures::continuous_handler).layer(fetch.clone()))
+        .route("/api/stats/:ticker",  get(rolling::stats_handler).layer(fetch.clone()))
+        .route("/api/risk_rank",      post(rolling::rank_series_handler).layer(compute.clone()))
+        .route("/api/risk_rank/:ticker", get(rolling::rank_ticker_handler).layer(fetch.clone()))
+        .route("/api/rolling_var/:ticker", get(rolling::rolling_var_handler).layer(compute.clone()))
+        .route("/api/watchlists",     get(watchlists::list_watchlists_handler).post(watchlists::create_watchlist_handler))
+        .route("/api/watchlists/:id", get(watchlists::get_watchlist_handler)
+                                      .put(watchlists::update_watchlist_handler)
+                                      .delete(watchlists::delete_watchlist_handler))
+        .route("/api/watchlists/:id/restore", post(watchlists::restore_watchlist_handler))
+        .route("/api/watchlists/:id/risk", get(watchlists::watchlist_risk_handler).layer(compute.clone()))
+        .route("/api/portfolios",     get(portfolios::list_portfolios_handler).post(portfolios::create_portfolio_handler))
+        .route("/api/portfolios/:id", get(portfolios::get_portfolio_handler)
+                                      .put(portfolios::update_portfolio_handler)
+                                      .delete(portfolios::delete_portfolio_handler))
+        .route("/api/portfolios/import", post(portfolios::import_portfolios_handler))
+        .route("/api/portfolios/:id/report", get(report::portfolio_report_handler).layer(compute))
+        .route("/api/portfolios/:id/restore", post(portfolios::restore_portfolio_handler))
+        .route("/api/report_template", get(templates::get_template_handler)
+                                      .put(templates::put_template_handler)
+                                      .delete(templates::delete_template_handler))
+        .route("/api/settings/locale", get(locale::get_locale_handler).put(locale::put_locale_handler))
+        .route("/api/credentials",    get(credentials::list_credentials_handler))
+        .route("/api/credentials/:name", put(credentials::put_credential_handler).delete(credentials::delete_credential_handler))
+        .route("/api/jobs",           post(jobs::submit_job_handler))
+        .route("/api/jobs/:id",       get(jobs::get_job_handler))
+        .route("/api/jobs/:id/rerun", post(jobs::rerun_job_handler))
+        .route("/api/jobs/:id/cancel", post(jobs::cancel_job_handler))
+        .route("/api/jobs/:id/bundle", get(replay::job_bundle_handler))
+        .route("/api/explain/:id",    get(explain::explain_handler))
+        .route("/api/artifacts/*key", get(artifacts::download_handler))
+        .route("/api/admin/export",   get(admin::export_handler))
+        .route("/api/admin/import",   post(admin::import_handler).layer(DefaultBodyLimit::max(512 * 1024 * 1024)))
+        .route("/api/admin/credentials/rotate", post(credentials::rotate_credentials_handler))
+        .route("/api/admin/audit",    get(audit::list_audit_handler))
+        .route("/api/admin/audit/verify", get(audit::verify_audit_handler))
+        .route("/api/admin/cache",    get(cache::list_cache_handler).delete(cache::evict_cache_handler))
+        .route("/api/admin/jobs",     get(jobs::list_active_jobs_handler))
+        .route("/api/admin/jobs/:id/cancel", post(jobs::admin_cancel_job_handler))
+        .route("/api/admin/flags",    get(flags::list_flags_handler))
+        .route("/api/admin/flags/:name", put(flags::put_flag_handler).delete(flags::delete_flag_handler));
+    #[cfg(feature = "streaming")]
+    let app = app
+        .route("/api/portfolios/:id/live_risk", get(ingest::live_risk_handler))
+        .route("/api/admin/stream",   get(ingest::stream_status_handler));
+    let app = app
+        .route_layer(middleware::from_fn_with_state(state.clone(), flags::gate_endpoints))
+        .layer(panics::layer())
+        .layer(middleware::from_fn_with_state(state.clone(), audit::record_requests))
+        .layer(CorsLayer::very_permissive())
+        .with_state(state);
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 8000));
     println!("🚀 Backend running on http://{}", addr);
 
-    let listener = TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app.into_make_service())
-        .await
-        .unwrap();
-}
-
-/// VaR endpoint
-async fn var_handler(Json(payload): Json<VarRequest>) -> Json<serde_json::Value> {
-    let result = compute_var(&payload.method, &mut payload.returns.clone(), payload.confidence);
-    Json(json!({ "var": result }))
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("❌ Cannot listen on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = axum::serve(listener, app.into_make_service()).await {
+        eprintln!("❌ Server stopped: {}", e);
+        std::process::exit(1);
+    }
 }
 
-/// Fetch returns, Yahoo → Alpha Vantage fallback
-async fn fetch_returns_handler(Json(payload): Json<FetchRequest>) -> Json<FetchResponse> {
-    let ticker = payload.ticker.to_uppercase();
-    let now = Utc::now();
-    let (start_ts, end_ts) = ((now - Duration::days(365)).timestamp(), now.timestamp());
-
-    // 1) Try Yahoo JSON API
-    let yahoo_url = format!(
-        "https://query2.finance.yahoo.com/v8/finance/chart/{ticker}?\
-         period1={start}&period2={end}&interval=1d&includePrePost=false&events=history",
-        ticker=&ticker, start=start_ts, end=end_ts
-    );
-    println!("🔗 Trying Yahoo: {}", yahoo_url);
-
-    let mut data: Vec<(String, f64)> = Vec::new();
-    let fall_back = match reqwest::get(&yahoo_url).await {
-        Ok(resp) if resp.status().is_success() => {
-            let body: Value = resp.json().await.unwrap_or_default();
-            if body["chart"]["error"].is_null() {
-                let result = &body["chart"]["result"][0];
-                // **clone** the arrays into owned Vec<Value>
-                let timestamps: Vec<Value> = result["timestamp"]
-                    .as_array().cloned().unwrap_or_default();
-                let closes: Vec<Value> = result["indicators"]["adjclose"][0]["adjclose"]
-                    .as_array().cloned().unwrap_or_default();
-
-                for (ts_val, price_val) in timestamps.iter().zip(closes.iter()) {
-                    if let (Some(ts), Some(p)) = (ts_val.as_i64(), price_val.as_f64()) {
-                        let date = Utc.timestamp_opt(ts, 0).single().unwrap()
-                            .format("%Y-%m-%d").to_string();
-                        data.push((date, p));
-                    }
-                }
-                println!("🔢 Yahoo returned {} points", data.len());
-                false
-            } else {
-                println!("⚠️ Yahoo JSON error");
-                true
+/// VaR endpoint; deterministic results are served from the cache. The
+/// audit middleware records the result as `var.computed`.
+async fn var_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Profiled(mut payload): Profiled<VarRequest>,
+) -> Result<(Extension<audit::Event>, Json<serde_json::Value>), ApiError> {
+    state.flags.check_method(&payload.method, &tenant)?;
+    let (body, cached) = if !payload.is_deterministic() {
+        // Unseeded simulations draw a fresh seed, reported so the run can be replayed
+        replay::pin_seed(&mut payload);
+        (evaluate_blocking(payload.clone()).await?, false)
+    } else {
+        let key = cache::key_for("var", &payload);
+        match cache::get_json(&*state.cache, &key).await {
+            Some(body) => (body, true),
+            None => {
+                let body = evaluate_blocking(payload.clone()).await?;
+                cache::set_json(&*state.cache, &key, &body).await;
+                (body, false)
             }
         }
-        Ok(r) => {
-            println!("❌ Yahoo HTTP {}", r.status());
-            true
-        }
-        Err(e) => {
-            eprintln!("❌ Yahoo request failed: {}", e);
-            true
-        }
     };
+    let mut record = audit::computation(&payload, &body);
+    record["cached"] = json!(cached);
+    Ok((Extension(audit::Event::new("var.computed", "compute_var", record)), Json(body)))
+}
 
+/// `evaluate` off the async workers: large simulations run for seconds, and
+/// stop early if the client goes away.
+async fn evaluate_blocking(payload: VarRequest) -> Result<serde_json::Value, ApiError> {
+    let cancel = payload.params.cancel.clone();
+    cancel::spawn_blocking(&cancel, move || evaluate(&payload))
+        .await
+        .map_err(|_| ApiError::aborted("VaR computation"))?
+        .map_err(ApiError::from)
+}
 
-    // 2) Fallback to Alpha Vantage if needed
-    if fall_back {
-        let key = env::var("ALPHA_VANTAGE_KEY")
-            .expect("ALPHA_VANTAGE_KEY not set in .env");
-        let av_url = format!(
-            "https://www.alphavantage.co/query?function=TIME_SERIES_DAILY\
-             &symbol={ticker}&outputsize=compact&apikey={key}&datatype=json",
-            ticker=&ticker, key=&key
-        );
-        println!("🔗 Fallback to Alpha Vantage (daily): {}", av_url);
-
-        let resp = reqwest::get(&av_url).await.unwrap();
-        let body: Value = resp.json().await.unwrap_or_default();
-        println!("🔄 Alpha Vantage raw JSON:\n{}", body);
-
-        // handle rate-limit notes or errors
-        if let Some(note) = body.get("Note").or_else(|| body.get("Information")).or_else(|| body.get("Error Message")) {
-            eprintln!("⚠️ Alpha Vantage returned an error/note: {}", note);
-        } else if let Some(ts_map) = body.get("Time Series (Daily)").and_then(|v| v.as_object()) {
-            // parse the time‐series map using the "4. close" field
-            let mut vec: Vec<_> = ts_map.iter().map(|(date, obj)| {
-                let close = obj["4. close"].as_str()
-                    .unwrap_or("0")
-                    .parse::<f64>()
-                    .unwrap_or(0.0);
-                (date.clone(), close)
-            }).collect();
-            vec.sort_by_key(|(d, _)| d.clone());
-            data = vec;
-            println!("🔢 Alpha Vantage returned {} points", data.len());
-        } else {
-            eprintln!("❌ Unexpected Alpha Vantage JSON structure");
+/// Daily closes for a ticker over the last year: price cache first, then the
+/// providers, with freshly fetched series written through to the price store
+async fn load_prices(state: &AppState, tenant: &str, ticker: &str) -> Vec<(String, f64)> {
+    load_prices_over(state, tenant, ticker, &Lookback::default()).await
+}
+
+/// `load_prices` over any span and bar size. Only daily bars are written
+/// through to the price store, which holds daily history. Providers are
+/// called with `tenant`'s keys.
+async fn load_prices_over(state: &AppState, tenant: &str, ticker: &str, lookback: &Lookback) -> Vec<(String, f64)> {
+    let cache_key = if lookback.is_default() {
+        format!("prices:{ticker}")
+    } else {
+        let (start, end) = lookback.span(Utc::now());
+        format!("prices:{ticker}:{}:{}:{}", lookback.interval.code(), start.date_naive(), end.date_naive())
+    };
+    let cached = || async {
+        let data: Vec<(String, f64)> = cache::get_json(&*state.cache, &cache_key).await?;
+        println!("⚡ Price cache hit for {} ({} bars)", ticker, data.len());
+        Some(data)
+    };
+    if let Some(data) = cached().await {
+        return data;
+    }
+    // Concurrent misses wait for the first one's fetch rather than repeat it
+    let _flight = state.fetches.lock(&cache_key).await;
+    if let Some(data) = cached().await {
+        return data;
+    }
+    let data = fetch_prices(state, tenant, ticker, lookback).await;
+    let ttl = cache::price_ttl();
+    // Empty lookups are remembered briefly so they don't hit the providers on every call
+    if data.is_empty() && !ttl.misses.is_zero() {
+        cache::set_json_for(&*state.cache, &cache_key, &data, ttl.misses).await;
+    }
+    if !data.is_empty() {
+        cache::set_json_for(&*state.cache, &cache_key, &data, ttl.prices).await;
+        if lookback.interval == Interval::Daily {
+            match state.prices.insert(ticker, &data).await {
+                Ok(_) => state.rolling.ingest(&*state.prices, ticker, &data).await,
+                Err(e) => eprintln!("⚠️ Failed to store prices for {}: {}", ticker, e),
+            }
         }
     }
+    data
+}
 
+/// Fetch returns, served from the price cache when possible
+async fn fetch_returns_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Valid(payload): Valid<FetchRequest>,
+) -> Result<Response, ApiError> {
+    let ticker = payload.ticker.to_uppercase();
+    let data = load_prices_over(&state, &tenant, &ticker, &payload.lookback).await;
+    if data.is_empty() {
+        return Err(ApiError::new(
+            axum::http::StatusCode::BAD_GATEWAY,
+            format!("no prices for {ticker} from any provider"),
+        ));
+    }
 
+    // Short histories (recent listings) borrow the proxy's earlier moves
+    let mut warnings = Vec::new();
+    let min_history = payload.min_history.unwrap_or_else(|| history::min_history("historical"));
+    let policy = history::HistoryPolicy::resolve(payload.history_policy, payload.proxy.is_some());
+    let proxy = payload.proxy.as_deref().map(|p| p.trim().to_uppercase());
+    let proxy_bars = match &proxy {
+        Some(proxy) if backfill::own_observations(&data) < min_history => {
+            load_prices_over(&state, &tenant, proxy, &payload.lookback).await
+        }
+        _ => Vec::new(),
+    };
+    let proxy = proxy.as_deref().map(|p| (p, proxy_bars.as_slice()));
+    let (data, backfilled) = history::enforce(policy, &ticker, data, proxy, min_history, &mut warnings)
+        .map_err(|e| ApiError::new(axum::http::StatusCode::UNPROCESSABLE_ENTITY, e))?;
 
-    // 3) Compute returns
-    let mut returns = Vec::new();
-    for window in data.windows(2) {
-        let p0 = window[0].1;
-        let p1 = window[1].1;
-        returns.push((p1 - p0) / p0);
-    }
-    println!("🔢 Computed {} returns", returns.len());
+    // 3) Clean prices; returns are drawn from the rows below
+    let rows = cleaning::clean(&data, &payload.cleaning);
+    println!("🔢 Computed {} returns", rows.iter().filter(|row| row.ret.is_some()).count());
 
     // 4) Build last-5 preview
-    let mut preview = Vec::new();
-    for i in (1..data.len()).rev().take(5) {
-        let (ref date, price) = &data[i];
-        let prev_price = data[i - 1].1;
-        preview.push(PreviewRow {
-            date: date.clone(),
-            ret: (price - prev_price) / prev_price,
-        });
-    }
+    let mut preview: Vec<PreviewRow> = rows
+        .iter()
+        .rev()
+        .filter_map(|row| Some(PreviewRow { date: row.date.clone(), ret: row.ret? }))
+        .take(5)
+        .collect();
     preview.reverse();
     println!("🔢 Preview rows: {:?}", preview);
 
-    Json(FetchResponse { returns, preview })
+    if payload.stream {
+        let mut trailer = serde_json::Map::new();
+        trailer.insert("preview".into(), json!(preview));
+        trailer.insert("return_type".into(), json!(payload.cleaning.return_type));
+        if let Some(info) = &backfilled {
+            trailer.insert("backfill".into(), json!(info));
+        }
+        if !warnings.is_empty() {
+            trailer.insert("warnings".into(), json!(warnings));
+        }
+        // Serialised from the rows as the client reads, never collected
+        let returns = rows.into_iter().filter_map(|row| row.ret);
+        return Ok(stream::json_array_stream("returns", returns, trailer));
+    }
+    let returns = cleaning::returns(&rows);
+    let return_type = payload.cleaning.return_type;
+    Ok(Json(FetchResponse { returns, return_type, preview, backfill: backfilled, warnings }).into_response())
+}
+
+/// Fetch closes over the look-back from the providers in `PRICE_PROVIDERS`
+/// order, each failure falling back to the next
+async fn fetch_prices(state: &AppState, tenant: &str, ticker: &str, lookback: &Lookback) -> Vec<(String, f64)> {
+    for &provider in providers::chain() {
+        let key = match provider.credential() {
+            None => None,
+            Some((name, var)) => match state.credentials.provider_key(tenant, name, var) {
+                Some(key) => Some(key),
+                None => {
+                    eprintln!("⚠️ Skipping {}: no {} credential stored and {} not set", provider.name(), name, var);
+                    continue;
+                }
+            },
+        };
+        if let Some(data) = provider.fetch(ticker, lookback, key.as_deref()).await {
+            return data;
+        }
+    }
+    Vec::new()
 }
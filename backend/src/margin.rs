@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::var::mean_std;
+
+/// Margin account backing a leveraged book.
+#[derive(Clone, Serialize, Deserialize, Validate)]
+pub struct Account {
+    // Account equity (net liquidation value) in the book's currency
+    #[validate(range(exclusive_min = 0.0, message = "equity must be positive"))]
+    pub equity: f64,
+    // Equity below which the account is called; defaults to the book's
+    // futures initial margin, else 0
+    #[serde(default)]
+    #[validate(range(min = 0.0, message = "maintenance_margin must not be negative"))]
+    pub maintenance_margin: Option<f64>,
+    #[serde(default = "default_horizon")]
+    #[validate(range(min = 1, max = 250, message = "horizon_days must be between 1 and 250"))]
+    pub horizon_days: u32,
+}
+
+fn default_horizon() -> u32 {
+    1
+}
+
+// Risk measured against equity rather than notional
+#[derive(Serialize)]
+pub struct LeverageRisk {
+    pub equity: f64,
+    pub gross_leverage: f64,
+    pub net_leverage: f64,
+    pub horizon_days: u32,
+    // Horizon VaR (√h-scaled) and its share of equity
+    pub var: f64,
+    pub var_fraction_of_equity: f64,
+    pub maintenance_margin: f64,
+    // Equity above maintenance: the loss the account can absorb
+    pub cushion: f64,
+    // Share of historical h-day paths whose running loss reached the
+    // cushion on any day (None when the history is shorter than h)
+    pub margin_call_probability: Option<f64>,
+    pub paths: usize,
+    // Uniform adverse move of gross exposure that exhausts the cushion
+    pub distance_to_liquidation: f64,
+    // The cushion in horizon P&L standard deviations (None for a flat P&L)
+    pub distance_in_sigmas: Option<f64>,
+}
+
+/// Scores a book's daily P&L history against its margin account. A margin
+/// call is path-dependent: it is triggered by the running loss on any day of
+/// the horizon, not just by the loss at its end, so every overlapping h-day
+/// window of history is replayed from today's equity.
+pub fn assess(account: &Account, pnl: &[f64], var: f64, gross: f64, net: f64, default_maintenance: f64) -> LeverageRisk {
+    let h = account.horizon_days as usize;
+    let maintenance = account.maintenance_margin.unwrap_or(default_maintenance);
+    let cushion = account.equity - maintenance;
+    let var = var * (h as f64).sqrt();
+
+    let paths = (pnl.len() + 1).saturating_sub(h);
+    let called = (0..paths)
+        .filter(|&start| {
+            let mut total = 0.0;
+            pnl[start..start + h].iter().any(|p| {
+                total += p;
+                total <= -cushion
+            })
+        })
+        .count();
+    let margin_call_probability = match (cushion <= 0.0, paths) {
+        (true, _) => Some(1.0),
+        (false, 0) => None,
+        (false, _) => Some(called as f64 / paths as f64),
+    };
+    let sigma = mean_std(pnl).1 * (h as f64).sqrt();
+
+    LeverageRisk {
+        equity: account.equity,
+        gross_leverage: gross / account.equity,
+        net_leverage: net / account.equity,
+        horizon_days: account.horizon_days,
+        var,
+        var_fraction_of_equity: var / account.equity,
+        maintenance_margin: maintenance,
+        cushion,
+        margin_call_probability,
+        paths,
+        distance_to_liquidation: (cushion / gross).max(0.0),
+        distance_in_sigmas: (sigma > 0.0).then(|| cushion.max(0.0) / sigma),
+    }
+}
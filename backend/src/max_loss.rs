@@ -0,0 +1,185 @@
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use statrs::distribution::{ChiSquared, ContinuousCDF};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    covariance::{covariance_matrix, portfolio_variance},
+    error::ApiError,
+    reduce::Summation,
+    validation::{self, cross_field, Valid},
+    var::parametric_z,
+    version,
+};
+
+/// Bounded set of factor shocks the worst case is sought over.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShockSet {
+    // Shocks within Mahalanobis distance `radius` of no move,
+    // sᵀΣ⁻¹s ≤ radius²; by default the radius holding `confidence` of a
+    // normal distribution with covariance Σ
+    Ellipsoid {
+        #[serde(default)]
+        radius: Option<f64>,
+        #[serde(default = "default_confidence")]
+        confidence: f64,
+    },
+    // Each factor moving at most `bounds[i]` either way, by default
+    // `sigmas` of its own volatility
+    Box {
+        #[serde(default)]
+        bounds: Option<Vec<f64>>,
+        #[serde(default = "default_sigmas")]
+        sigmas: f64,
+    },
+}
+
+impl Default for ShockSet {
+    fn default() -> Self {
+        ShockSet::Ellipsoid { radius: None, confidence: default_confidence() }
+    }
+}
+
+fn default_confidence() -> f64 {
+    0.99
+}
+
+fn default_sigmas() -> f64 {
+    3.0
+}
+
+// Payload for /api/max_loss
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "check_max_loss"))]
+pub struct MaxLossRequest {
+    // P&L per unit return of each factor (a position's market value, or a
+    // sensitivity); the loss is linear in the shocks
+    #[validate(length(min = 1, message = "exposures must not be empty"), custom(function = "validation::finite"))]
+    exposures: Vec<f64>,
+    // Labels of the factors, in the same order
+    #[serde(default)]
+    factors: Option<Vec<String>>,
+    // Covariance of the factor returns, or the aligned return series (one
+    // per factor, oldest first) to estimate it from
+    #[serde(default)]
+    covariance: Option<Vec<Vec<f64>>>,
+    #[serde(default)]
+    returns: Option<Vec<Vec<f64>>>,
+    #[serde(default)]
+    shock_set: ShockSet,
+}
+
+fn check_max_loss(req: &MaxLossRequest) -> Result<(), ValidationError> {
+    let k = req.exposures.len();
+    if req.factors.as_ref().is_some_and(|f| f.len() != k) {
+        return Err(cross_field("factors", format!("expected {k} factors (one per exposure)")));
+    }
+    match (&req.covariance, &req.returns) {
+        (Some(cov), None) => {
+            if cov.len() != k || cov.iter().any(|row| row.len() != k) {
+                return Err(cross_field("covariance", format!("covariance must be {k}×{k}")));
+            }
+            if cov.iter().any(|row| validation::finite(row).is_err()) {
+                return Err(cross_field("covariance", "covariance must be finite".into()));
+            }
+            let asymmetric = (0..k).any(|i| (0..i).any(|j| (cov[i][j] - cov[j][i]).abs() > 1e-12 * (1.0 + cov[i][j].abs())));
+            if asymmetric || (0..k).any(|i| cov[i][i] < 0.0) {
+                return Err(cross_field("covariance", "covariance must be symmetric with non-negative variances".into()));
+            }
+        }
+        (None, Some(returns)) => {
+            if returns.len() != k {
+                return Err(cross_field("returns", format!("expected {k} series (one per exposure)")));
+            }
+            let n = returns[0].len();
+            if n < 2 || returns.iter().any(|r| r.len() != n) {
+                return Err(cross_field("returns", "series must all have the same length, at least 2".into()));
+            }
+            if returns.iter().any(|r| validation::finite(r).is_err()) {
+                return Err(cross_field("returns", "returns must be finite".into()));
+            }
+        }
+        _ => return Err(cross_field("covariance", "send either covariance or returns".into())),
+    }
+    match &req.shock_set {
+        ShockSet::Ellipsoid { radius: Some(r), .. } if !(*r > 0.0 && r.is_finite()) => {
+            Err(cross_field("shock_set", "radius must be positive".into()))
+        }
+        ShockSet::Ellipsoid { confidence, .. } if !(*confidence > 0.0 && *confidence < 1.0) => {
+            Err(cross_field("shock_set", "confidence must be in (0, 1)".into()))
+        }
+        ShockSet::Box { bounds: Some(b), .. } if b.len() != k || b.iter().any(|b| !(*b >= 0.0 && b.is_finite())) => {
+            Err(cross_field("shock_set", format!("bounds must be {k} non-negative values (one per exposure)")))
+        }
+        ShockSet::Box { sigmas, .. } if !(*sigmas > 0.0 && sigmas.is_finite()) => {
+            Err(cross_field("shock_set", "sigmas must be positive".into()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Worst loss of a linear book over a bounded set of factor shocks, with
+/// the shock that causes it. Over the ellipsoid sᵀΣ⁻¹s ≤ ρ² the loss −eᵀs is
+/// largest at s* = −ρΣe/√(eᵀΣe), a loss of ρ√(eᵀΣe); over the box
+/// |sᵢ| ≤ bᵢ each factor moves its full bound against its exposure, a loss
+/// of Σ|eᵢ|bᵢ. Unlike VaR the answer is a scenario, and as the maximum over
+/// a fixed set it is a coherent risk measure. The normal VaR at the
+/// ellipsoid's confidence is reported for comparison: the ellipsoid holds
+/// that much probability in k dimensions, so its worst case is the larger.
+pub async fn max_loss_handler(Valid(req): Valid<MaxLossRequest>) -> Result<Json<Value>, ApiError> {
+    let k = req.exposures.len();
+    let cov = match (&req.covariance, &req.returns) {
+        (Some(cov), _) => cov.clone(),
+        (None, Some(returns)) => covariance_matrix(returns, Summation::Sequential),
+        (None, None) => unreachable!("validated"),
+    };
+    let variance = portfolio_variance(&req.exposures, &cov);
+    if variance < 0.0 {
+        return Err(ApiError::bad_request("covariance is not positive semi-definite (eᵀΣe < 0)"));
+    }
+    let volatility = variance.sqrt();
+
+    let mut body = match &req.shock_set {
+        ShockSet::Ellipsoid { radius, confidence } => {
+            let chi2 = ChiSquared::new(k as f64).expect("k ≥ 1");
+            // A given radius reports the probability its ellipsoid holds
+            let (radius, confidence) = match radius {
+                Some(r) => (*r, chi2.cdf(r * r)),
+                None => (chi2.inverse_cdf(*confidence).sqrt(), *confidence),
+            };
+            let shock: Vec<f64> = if volatility > 0.0 {
+                // Σe, row by row
+                let sigma_e = cov.iter().map(|row| row.iter().zip(&req.exposures).map(|(c, e)| c * e).sum::<f64>());
+                sigma_e.map(|v| -radius * v / volatility).collect()
+            } else {
+                vec![0.0; k]
+            };
+            json!({
+                "shock_set": "ellipsoid",
+                "radius": radius,
+                "confidence": confidence,
+                "max_loss": radius * volatility,
+                "worst_shock": shock,
+                "parametric_var": parametric_z(confidence) * volatility,
+            })
+        }
+        ShockSet::Box { bounds, sigmas } => {
+            let bounds = bounds.clone().unwrap_or_else(|| (0..k).map(|i| sigmas * cov[i][i].sqrt()).collect());
+            let shock: Vec<f64> = req.exposures.iter().zip(&bounds).map(|(e, b)| -e.signum() * b).collect();
+            json!({
+                "shock_set": "box",
+                "bounds": bounds,
+                "max_loss": req.exposures.iter().zip(&bounds).map(|(e, b)| e.abs() * b).sum::<f64>(),
+                "worst_shock": shock,
+            })
+        }
+    };
+    body["portfolio_volatility"] = json!(volatility);
+    if let Some(factors) = &req.factors {
+        body["factors"] = json!(factors);
+    }
+    body["engine"] = json!(version::current());
+    Ok(Json(body))
+}
@@ -0,0 +1,194 @@
+use std::borrow::Cow;
+
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    error::{ApiError, VarError},
+    validation::{self, cross_field, Valid},
+    var::{compute_var_es, mean_std, MethodParams},
+    version,
+};
+
+// Golden-section steps of the EVaR minimisation, and the span searched
+// either side of 1/σ, in e-folds
+const EVAR_STEPS: usize = 200;
+const EVAR_SPAN: f64 = 12.0;
+
+/// Measures `evaluate` computes.
+pub const MEASURES: &[&str] = &["var", "es", "evar", "spectral"];
+
+/// Weight function φ over tail probabilities p ∈ [0, 1] (p = 0 the worst
+/// outcome) of a spectral risk measure ∫ φ(p) q(p) dp. The measure is
+/// coherent when φ is non-negative, non-increasing and integrates to one.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Spectrum {
+    // φ(p) = γe^(−γp) / (1 − e^(−γ)): constant absolute risk aversion γ
+    Exponential { gamma: f64 },
+    // φ(p) = γp^(γ−1), 0 < γ ≤ 1: γ = 1 is the mean loss
+    Power { gamma: f64 },
+    // Step function: `weights[i]` on the i-th of equal slices of [0, 1],
+    // worst slice first, normalised to integrate to one
+    Piecewise { weights: Vec<f64> },
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        Spectrum::Exponential { gamma: 10.0 }
+    }
+}
+
+impl Spectrum {
+    pub fn check(&self) -> Result<(), String> {
+        match self {
+            Spectrum::Exponential { gamma } if !(*gamma > 0.0 && gamma.is_finite()) => {
+                Err("exponential gamma must be positive".into())
+            }
+            Spectrum::Power { gamma } if !(*gamma > 0.0 && *gamma <= 1.0) => Err("power gamma must be in (0, 1]".into()),
+            Spectrum::Piecewise { weights } if weights.is_empty() => Err("piecewise weights must not be empty".into()),
+            Spectrum::Piecewise { weights } if weights.iter().any(|w| !w.is_finite()) => {
+                Err("piecewise weights must be finite".into())
+            }
+            Spectrum::Piecewise { weights } if weights.iter().sum::<f64>() <= 0.0 => {
+                Err("piecewise weights must have a positive sum".into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// ∫₀ᵖ φ, the weight the measure puts on the worst p of outcomes.
+    fn cumulative(&self, p: f64) -> f64 {
+        match self {
+            Spectrum::Exponential { gamma } => (1.0 - (-gamma * p).exp()) / (1.0 - (-gamma).exp()),
+            Spectrum::Power { gamma } => p.powf(*gamma),
+            Spectrum::Piecewise { weights } => {
+                let k = weights.len();
+                let total: f64 = weights.iter().sum();
+                let full = ((p * k as f64).floor() as usize).min(k);
+                let partial = if full < k { weights[full] * (p * k as f64 - full as f64) } else { 0.0 };
+                (weights[..full].iter().sum::<f64>() + partial) / total
+            }
+        }
+    }
+
+    // φ non-negative and non-increasing: worse outcomes never weigh less
+    fn coherent(&self) -> bool {
+        match self {
+            Spectrum::Exponential { .. } | Spectrum::Power { .. } => true,
+            Spectrum::Piecewise { weights } => {
+                weights.iter().all(|w| *w >= 0.0) && weights.windows(2).all(|pair| pair[0] >= pair[1])
+            }
+        }
+    }
+}
+
+// Payload for /api/risk_measures
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "check_measures"))]
+pub struct MeasuresRequest {
+    #[validate(length(min = 2, message = "need at least 2 returns"), custom(function = "validation::finite"))]
+    returns: Vec<f64>,
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+    #[serde(default)]
+    spectrum: Spectrum,
+}
+
+fn check_measures(req: &MeasuresRequest) -> Result<(), ValidationError> {
+    req.spectrum.check().map_err(|e| cross_field("spectrum", e))
+}
+
+/// Empirical spectral risk measure of `returns`: the i-th worst of n returns
+/// takes the spectrum's weight on [(i − 1)/n, i/n], as a positive loss.
+pub fn spectral(returns: &[f64], spectrum: &Spectrum) -> f64 {
+    let mut sorted = returns.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let n = sorted.len() as f64;
+    -sorted
+        .iter()
+        .enumerate()
+        .map(|(i, r)| r * (spectrum.cumulative((i + 1) as f64 / n) - spectrum.cumulative(i as f64 / n)))
+        .sum::<f64>()
+}
+
+// ln((1/n) Σ e^(z·Lᵢ)) without overflow
+fn log_mgf(losses: &[f64], z: f64) -> f64 {
+    let top = losses.iter().map(|l| z * l).fold(f64::NEG_INFINITY, f64::max);
+    top + (losses.iter().map(|l| (z * l - top).exp()).sum::<f64>() / losses.len() as f64).ln()
+}
+
+/// Entropic VaR of the empirical loss distribution (Ahmadi-Javid):
+/// inf over z > 0 of (ln M(z) − ln α) / z, with M the moment generating
+/// function of the loss and α = 1 − confidence. The tightest bound on VaR
+/// Chernoff's inequality gives, above ES, and coherent. Returns the value
+/// and the minimising z.
+pub fn entropic_var(returns: &[f64], confidence: f64) -> (f64, f64) {
+    let losses: Vec<f64> = returns.iter().map(|r| -r).collect();
+    let ln_alpha = (1.0 - confidence).ln();
+    let (_, std) = mean_std(&losses);
+    let bound = |u: f64| {
+        let z = u.exp();
+        (log_mgf(&losses, z) - ln_alpha) / z
+    };
+    // The objective is quasi-convex in z; search ln z around 1/σ
+    let centre = if std > 0.0 { -std.ln() } else { 0.0 };
+    let (mut lo, mut hi) = (centre - EVAR_SPAN, centre + EVAR_SPAN);
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    for _ in 0..EVAR_STEPS {
+        let (a, b) = (hi - ratio * (hi - lo), lo + ratio * (hi - lo));
+        if bound(a) < bound(b) {
+            hi = b;
+        } else {
+            lo = a;
+        }
+    }
+    let u = 0.5 * (lo + hi);
+    (bound(u), u.exp())
+}
+
+pub fn known_measures(measures: &[String]) -> Result<(), ValidationError> {
+    match measures.iter().find(|m| !MEASURES.contains(&m.as_str())) {
+        None => Ok(()),
+        Some(m) => {
+            let mut err = ValidationError::new("unknown_measure");
+            err.message = Some(Cow::Owned(format!("unknown measure {m}; expected one of {}", MEASURES.join(", "))));
+            Err(err)
+        }
+    }
+}
+
+/// One of `MEASURES` of `returns`, as a positive loss.
+pub fn evaluate(measure: &str, returns: &[f64], confidence: f64, spectrum: &Spectrum) -> Result<f64, VarError> {
+    Ok(match measure {
+        "var" => compute_var_es("historical", &mut returns.to_vec(), confidence, &MethodParams::default())?.0,
+        "es" => compute_var_es("historical", &mut returns.to_vec(), confidence, &MethodParams::default())?.1,
+        "evar" => entropic_var(returns, confidence).0,
+        "spectral" => spectral(returns, spectrum),
+        _ => return Err(VarError::InvalidInput(format!("unknown measure {measure}"))),
+    })
+}
+
+/// The sample's risk under several measures side by side: VaR (not
+/// coherent: it can reward splitting a book), ES, entropic VaR and a
+/// spectral measure of the caller's risk aversion. Coherent measures order
+/// as ES ≤ EVaR at a common confidence, with VaR below ES.
+pub async fn risk_measures_handler(Valid(req): Valid<MeasuresRequest>) -> Result<Json<Value>, ApiError> {
+    let (var, es) = compute_var_es("historical", &mut req.returns.clone(), req.confidence, &MethodParams::default())?;
+    let (evar, z) = entropic_var(&req.returns, req.confidence);
+    Ok(Json(json!({
+        "confidence": req.confidence,
+        "observations": req.returns.len(),
+        "var": var,
+        "es": es,
+        "evar": { "value": evar, "z": z },
+        "spectral": {
+            "spectrum": req.spectrum,
+            "value": spectral(&req.returns, &req.spectrum),
+            "coherent": req.spectrum.coherent(),
+        },
+        "engine": version::current(),
+    })))
+}
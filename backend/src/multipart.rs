@@ -0,0 +1,87 @@
+//! Just enough `multipart/form-data` for file uploads: the body split on its
+//! boundary into parts, each with its form field name, file name, content
+//! type and raw bytes. No nested multiparts or transfer encodings.
+
+/// One field of a form: a text field, or a file when `filename` is set.
+pub struct Part {
+    pub name: Option<String>,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl Part {
+    /// The part's contents as text, without a UTF-8 byte-order mark.
+    pub fn text(&self) -> Result<&str, String> {
+        let body = self.body.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&self.body);
+        std::str::from_utf8(body).map_err(|_| format!("{} is not UTF-8 text", self.label()))
+    }
+
+    pub fn label(&self) -> String {
+        match (&self.filename, &self.name) {
+            (Some(file), _) => format!("file {file:?}"),
+            (None, Some(name)) => format!("field {name:?}"),
+            (None, None) => "unnamed part".to_string(),
+        }
+    }
+}
+
+/// The boundary of a `multipart/form-data` content type, `None` for any other.
+pub fn boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = content_type.split_once(';').unwrap_or((content_type, ""));
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    parameter(params, "boundary").filter(|b| !b.is_empty())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+// Value of `name=value` or `name="value"` among `;`-separated parameters
+fn parameter(params: &str, name: &str) -> Option<String> {
+    params.split(';').find_map(|p| {
+        let (key, value) = p.split_once('=')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Splits a body into its parts. Text before the first boundary and after
+/// the closing one is ignored, as the format requires.
+pub fn parts(body: &[u8], boundary: &str) -> Result<Vec<Part>, String> {
+    let delimiter = format!("--{boundary}").into_bytes();
+    let start = find(body, &delimiter).ok_or("no multipart boundary in the body")?;
+    let mut rest = &body[start + delimiter.len()..];
+    // Every later boundary starts a new line
+    let next = [b"\r\n".as_slice(), &delimiter].concat();
+    let mut parts = Vec::new();
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        rest = rest.strip_prefix(b"\r\n").ok_or("malformed multipart boundary line")?;
+        let end = find(rest, &next).ok_or("multipart body ends before its closing boundary")?;
+        let (headers, content) = match find(&rest[..end], b"\r\n\r\n") {
+            Some(blank) => (&rest[..blank], &rest[blank + 4..end]),
+            // A part with no headers starts with the blank line
+            None if rest.starts_with(b"\r\n") => (&rest[..0], &rest[2..end]),
+            None => return Err("multipart part without a blank line after its headers".into()),
+        };
+        let headers = std::str::from_utf8(headers).map_err(|_| "multipart headers are not UTF-8")?;
+        let mut part = Part { name: None, filename: None, content_type: None, body: content.to_vec() };
+        for line in headers.split("\r\n") {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            if key.trim().eq_ignore_ascii_case("content-disposition") {
+                part.name = parameter(value, "name");
+                part.filename = parameter(value, "filename");
+            } else if key.trim().eq_ignore_ascii_case("content-type") {
+                part.content_type = Some(value.trim().to_string());
+            }
+        }
+        parts.push(part);
+        rest = &rest[end + next.len()..];
+    }
+}
@@ -0,0 +1,579 @@
+use axum::Json;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    budget,
+    cancel,
+    costs::TransactionCosts,
+    error::{ApiError, VarError},
+    validation::{self, cross_field, Valid},
+    var::{ewma_volatility, weighted_var_es, MethodParams},
+    version,
+};
+
+// Upper bound on inner draws per request (outer paths × days × inner paths)
+const MAX_INNER_DRAWS: usize = 50_000_000;
+// Annualises daily volatility for vol_target
+const TRADING_DAYS: f64 = 252.0;
+
+/// Trading rule re-applied every simulated day: it sees the path so far and
+/// resizes the exposure (1 = the book as it stands).
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Rule {
+    // Flatten for the rest of the horizon once the loss from the start
+    // reaches `level` (a fraction of the starting value)
+    StopLoss { level: f64 },
+    // Flatten once the gain from the start reaches `level`, locking it in
+    TakeProfit { level: f64 },
+    // Size the exposure so the next day's VaR, estimated by an inner
+    // simulation from the path's last `lookback` returns, is `target`
+    // (a fraction of current value), up to `max_leverage`
+    VarTarget {
+        target: f64,
+        #[serde(default = "default_lookback")]
+        lookback: usize,
+        #[serde(default = "default_max_leverage")]
+        max_leverage: f64,
+    },
+    // Scale the exposure to an annualised volatility of `target`, the
+    // volatility an EWMA estimate with decay `lambda` carried along the
+    // path, up to `max_leverage`
+    VolTarget {
+        target: f64,
+        #[serde(default = "default_vol_lambda")]
+        lambda: f64,
+        #[serde(default = "default_max_leverage")]
+        max_leverage: f64,
+    },
+}
+
+fn default_lookback() -> usize {
+    20
+}
+
+fn default_max_leverage() -> f64 {
+    1.0
+}
+
+fn default_vol_lambda() -> f64 {
+    0.94
+}
+
+impl Rule {
+    fn check(&self) -> Result<(), String> {
+        match *self {
+            Rule::StopLoss { level } if !(level > 0.0 && level < 1.0) => Err("stop_loss level must be in (0, 1)".into()),
+            Rule::TakeProfit { level } if !(level > 0.0 && level.is_finite()) => {
+                Err("take_profit level must be positive".into())
+            }
+            Rule::VarTarget { target, .. } if !(target > 0.0 && target.is_finite()) => {
+                Err("var_target target must be positive".into())
+            }
+            Rule::VarTarget { lookback, .. } if lookback < 2 => Err("var_target lookback must be at least 2".into()),
+            Rule::VarTarget { max_leverage, .. } if !(max_leverage > 0.0 && max_leverage.is_finite()) => {
+                Err("var_target max_leverage must be positive".into())
+            }
+            Rule::VolTarget { target, .. } if !(target > 0.0 && target.is_finite()) => {
+                Err("vol_target target must be positive".into())
+            }
+            Rule::VolTarget { lambda, .. } if !(lambda > 0.0 && lambda < 1.0) => {
+                Err("vol_target lambda must be in (0, 1)".into())
+            }
+            Rule::VolTarget { max_leverage, .. } if !(max_leverage > 0.0 && max_leverage.is_finite()) => {
+                Err("vol_target max_leverage must be positive".into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Rule::StopLoss { .. } => "stop_loss",
+            Rule::TakeProfit { .. } => "take_profit",
+            Rule::VarTarget { .. } => "var_target",
+            Rule::VolTarget { .. } => "vol_target",
+        }
+    }
+}
+
+// A position of the book with its own exit levels
+#[derive(Serialize, Deserialize, Validate)]
+pub struct PathPosition {
+    #[serde(default)]
+    ticker: Option<String>,
+    // Market value today; negative for shorts
+    value: f64,
+    // Daily return history, aligned with the other positions', oldest first
+    #[validate(length(min = 2, message = "need at least 2 returns"), custom(function = "validation::finite"))]
+    returns: Vec<f64>,
+    // Close the position at the first close where its loss (gain) reaches
+    // this fraction of its value today
+    #[serde(default)]
+    #[validate(range(exclusive_min = 0.0, message = "stop_loss must be positive"))]
+    stop_loss: Option<f64>,
+    #[serde(default)]
+    #[validate(range(exclusive_min = 0.0, message = "take_profit must be positive"))]
+    take_profit: Option<f64>,
+}
+
+// Payload for /api/nested_simulation: a single return series or positions
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "check_nested"))]
+pub struct NestedRequest {
+    // Daily return history of the book the market scenarios are resampled from
+    #[serde(default)]
+    #[validate(length(min = 2, message = "need at least 2 returns"), custom(function = "validation::finite"))]
+    returns: Option<Vec<f64>>,
+    #[serde(default)]
+    #[validate(length(min = 1, message = "positions must not be empty"), nested)]
+    positions: Option<Vec<PathPosition>>,
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+    #[serde(default = "default_horizon")]
+    #[validate(range(min = 1, max = 250, message = "horizon_days must be between 1 and 250"))]
+    horizon_days: usize,
+    // Book-level rules, applied in order each day, each to the exposure the
+    // previous one left
+    #[serde(default)]
+    rules: Vec<Rule>,
+    // Outer market scenarios, resampled in blocks of `block` consecutive
+    // days so volatility regimes survive into the paths
+    #[serde(default = "default_outer")]
+    #[validate(range(min = 100, max = 100_000, message = "outer_paths must be between 100 and 100000"))]
+    outer_paths: usize,
+    #[serde(default = "default_block")]
+    #[validate(range(min = 1, message = "block must be at least 1"))]
+    block: usize,
+    // Inner draws per node for rules that estimate risk along the path
+    #[serde(default = "default_inner")]
+    #[validate(range(min = 10, max = 10_000, message = "inner_paths must be between 10 and 10000"))]
+    inner_paths: usize,
+    #[serde(default)]
+    seed: Option<u64>,
+    // Commission and spread charged on what the rules and exits trade along
+    // each path (`turnover` is ignored: the paths' own trades are charged)
+    #[serde(default)]
+    #[validate(nested)]
+    costs: Option<TransactionCosts>,
+}
+
+fn default_horizon() -> usize {
+    10
+}
+
+fn default_outer() -> usize {
+    1_000
+}
+
+fn default_block() -> usize {
+    5
+}
+
+fn default_inner() -> usize {
+    200
+}
+
+fn check_nested(req: &NestedRequest) -> Result<(), ValidationError> {
+    let n = match (&req.returns, &req.positions) {
+        (Some(returns), None) => returns.len(),
+        (None, Some(positions)) => {
+            let n = positions[0].returns.len();
+            if positions.iter().any(|p| p.returns.len() != n) {
+                return Err(cross_field("positions", "position returns must all have the same length".into()));
+            }
+            if positions.iter().any(|p| p.value == 0.0) {
+                return Err(cross_field("positions", "position values must not be zero".into()));
+            }
+            if positions.iter().map(|p| p.value).sum::<f64>() <= 0.0 {
+                return Err(cross_field("positions", "positions must have a positive total value".into()));
+            }
+            n
+        }
+        _ => return Err(cross_field("returns", "send either returns or positions".into())),
+    };
+    let levels = req.positions.iter().flatten().any(|p| p.stop_loss.is_some() || p.take_profit.is_some());
+    if req.rules.is_empty() && !levels {
+        return Err(cross_field("rules", "add rules or position stop_loss / take_profit levels".into()));
+    }
+    if req.block > n {
+        return Err(cross_field("block", format!("block must not exceed the {n} returns")));
+    }
+    if let Some(e) = req.rules.iter().find_map(|r| r.check().err()) {
+        return Err(cross_field("rules", e));
+    }
+    let nested = req.rules.iter().any(|r| matches!(r, Rule::VarTarget { .. }));
+    if nested && req.outer_paths * req.horizon_days * req.inner_paths > MAX_INNER_DRAWS {
+        return Err(cross_field(
+            "inner_paths",
+            format!("outer_paths × horizon_days × inner_paths must not exceed {MAX_INNER_DRAWS}"),
+        ));
+    }
+    // The book's history per position, then every outer path's outcome:
+    // its P&L, static P&L and exposure, and each rule's and position's exit
+    let legs = req.positions.as_ref().map_or(1, Vec::len);
+    let fixed = n * (legs + 1);
+    let per_path = 4 + legs + req.rules.len();
+    let advice = format!("lower outer_paths to at most {}", budget::fits(fixed, per_path));
+    budget::check(fixed + req.outer_paths * per_path, "outer_paths", advice)
+}
+
+// A position as simulated: its share of the book's value today, history
+// and exit levels
+struct Leg {
+    weight: f64,
+    returns: Vec<f64>,
+    stop_loss: Option<f64>,
+    take_profit: Option<f64>,
+}
+
+// How a position left the book
+#[derive(Clone, Copy, PartialEq)]
+enum Exit {
+    Open,
+    StopLoss,
+    TakeProfit,
+}
+
+fn legs(req: &NestedRequest) -> Vec<Leg> {
+    match &req.positions {
+        Some(positions) => {
+            let total: f64 = positions.iter().map(|p| p.value).sum();
+            positions
+                .iter()
+                .map(|p| Leg {
+                    weight: p.value / total,
+                    returns: p.returns.clone(),
+                    stop_loss: p.stop_loss,
+                    take_profit: p.take_profit,
+                })
+                .collect()
+        }
+        None => vec![Leg {
+            weight: 1.0,
+            returns: req.returns.clone().unwrap_or_default(),
+            stop_loss: None,
+            take_profit: None,
+        }],
+    }
+}
+
+// One outer scenario replayed under the rules
+struct PathOutcome {
+    pnl: f64,
+    static_pnl: f64,
+    mean_exposure: f64,
+    triggered: Vec<bool>,
+    exits: Vec<Exit>,
+    // Value traded resizing the exposure and closing positions, and what
+    // that cost
+    traded: f64,
+    cost: f64,
+}
+
+/// Empirical VaR of one day, from `draws` resamples of `recent`.
+fn inner_var(recent: &[f64], draws: usize, confidence: f64, rng: &mut StdRng) -> f64 {
+    let mut sims: Vec<f64> = (0..draws).map(|_| recent[rng.gen_range(0..recent.len())]).collect();
+    let idx = ((1.0 - confidence) * draws as f64).floor() as usize;
+    -*sims.select_nth_unstable_by(idx, |a, b| a.total_cmp(b)).1
+}
+
+/// EWMA variance of the book's history at the start of every path, for
+/// each vol_target rule (zero for the others).
+fn start_variances(rules: &[Rule], book: &[f64]) -> Vec<f64> {
+    rules
+        .iter()
+        .map(|rule| match *rule {
+            Rule::VolTarget { lambda, .. } => {
+                let params = MethodParams { lambda: Some(lambda), ..MethodParams::default() };
+                ewma_volatility(book, &params).powi(2)
+            }
+            _ => 0.0,
+        })
+        .collect()
+}
+
+/// Replays the historical days `scenario` (indices into the history) on the
+/// book. Values are fractions of the book's value today; a closed
+/// position's value is held as cash from its exit on. A change of exposure
+/// trades the change on the open positions and an exit trades the exposed
+/// position, each charged the request's costs in the path's P&L.
+fn replay(
+    req: &NestedRequest,
+    legs: &[Leg],
+    book: &[f64],
+    start: &[f64],
+    scenario: &[usize],
+    rng: &mut StdRng,
+) -> PathOutcome {
+    let mut history: Vec<f64> = book.to_vec();
+    let mut holdings: Vec<f64> = legs.iter().map(|l| l.weight).collect();
+    let mut exits = vec![Exit::Open; legs.len()];
+    let mut held: Vec<f64> = holdings.clone();
+    let mut cash = 0.0;
+    let mut stopped = vec![false; req.rules.len()];
+    let mut variances = start.to_vec();
+    let mut triggered = vec![false; req.rules.len()];
+    let mut exposure_sum = 0.0;
+    let (mut traded, mut previous) = (0.0, 1.0);
+    for &day in scenario {
+        let value = cash + holdings.iter().zip(&exits).filter(|(_, e)| **e == Exit::Open).map(|(h, _)| h).sum::<f64>();
+        let mut exposure = 1.0;
+        for (i, rule) in req.rules.iter().enumerate() {
+            exposure = match *rule {
+                Rule::StopLoss { level } | Rule::TakeProfit { level } => {
+                    let hit = match rule {
+                        Rule::StopLoss { .. } => value <= 1.0 - level,
+                        _ => value >= 1.0 + level,
+                    };
+                    stopped[i] |= hit;
+                    triggered[i] |= stopped[i];
+                    if stopped[i] {
+                        0.0
+                    } else {
+                        exposure
+                    }
+                }
+                Rule::VarTarget { target, lookback, max_leverage } => {
+                    let recent = &history[history.len().saturating_sub(lookback)..];
+                    let var = inner_var(recent, req.inner_paths, req.confidence, rng);
+                    let size = if var > 0.0 { (target / var).min(max_leverage) } else { max_leverage };
+                    triggered[i] |= size < max_leverage;
+                    exposure * size
+                }
+                Rule::VolTarget { target, max_leverage, .. } => {
+                    let vol = (variances[i] * TRADING_DAYS).sqrt();
+                    let size = if vol > 0.0 { (target / vol).min(max_leverage) } else { max_leverage };
+                    triggered[i] |= size < max_leverage;
+                    exposure * size
+                }
+            };
+        }
+        exposure_sum += exposure;
+        let open: f64 = holdings.iter().zip(&exits).filter(|(_, e)| **e == Exit::Open).map(|(h, _)| h.abs()).sum();
+        traded += (exposure - previous).abs() * open;
+        previous = exposure;
+        for (i, leg) in legs.iter().enumerate() {
+            if exits[i] != Exit::Open {
+                continue;
+            }
+            holdings[i] *= 1.0 + exposure * leg.returns[day];
+            // Gain or loss on the position, as a fraction of its size today
+            let pnl = (holdings[i] - leg.weight) / leg.weight.abs();
+            if leg.stop_loss.is_some_and(|level| pnl <= -level) {
+                exits[i] = Exit::StopLoss;
+            } else if leg.take_profit.is_some_and(|level| pnl >= level) {
+                exits[i] = Exit::TakeProfit;
+            }
+            if exits[i] != Exit::Open {
+                traded += exposure * holdings[i].abs();
+                cash += holdings[i];
+            }
+        }
+        for (h, leg) in held.iter_mut().zip(legs) {
+            *h *= 1.0 + leg.returns[day];
+        }
+        // The estimates follow the market, not the scaled book
+        for (v, rule) in variances.iter_mut().zip(&req.rules) {
+            if let Rule::VolTarget { lambda, .. } = *rule {
+                *v = lambda * *v + (1.0 - lambda) * book[day] * book[day];
+            }
+        }
+        history.push(book[day]);
+    }
+    let open: f64 = holdings.iter().zip(&exits).filter(|(_, e)| **e == Exit::Open).map(|(h, _)| h).sum();
+    let cost = req.costs.map_or(0.0, |c| c.charge(traded));
+    PathOutcome {
+        pnl: cash + open - cost - 1.0,
+        static_pnl: held.iter().sum::<f64>() - 1.0,
+        mean_exposure: exposure_sum / scenario.len() as f64,
+        triggered,
+        exits,
+        traded,
+        cost,
+    }
+}
+
+// VaR, ES and mean of horizon P&L as fractions of the book's value today,
+// and in money for a book of positions worth `value`
+fn summary(pnl: &[f64], confidence: f64, value: Option<f64>) -> Value {
+    let weights = vec![1.0 / pnl.len() as f64; pnl.len()];
+    let (var, es) = weighted_var_es(pnl, &weights, confidence);
+    let mut summary = json!({ "var": var, "es": es, "mean": pnl.iter().sum::<f64>() / pnl.len() as f64 });
+    if let Some(value) = value {
+        summary["var_amount"] = json!(var * value);
+        summary["es_amount"] = json!(es * value);
+    }
+    summary
+}
+
+/// Two-level simulation of a book run by path-dependent rules. Outer
+/// scenarios are block-bootstrapped horizon paths of the return history;
+/// on every day of every scenario the rules resize the exposure from what
+/// the path has shown so far, those that need a risk estimate running an
+/// inner simulation at that node, and positions whose stop-loss or
+/// take-profit level is crossed are closed at that day's close. The horizon
+/// P&L distribution then reflects the strategy, and is reported next to
+/// the same scenarios held statically.
+pub async fn nested_simulation_handler(Valid(req): Valid<NestedRequest>) -> Result<Json<Value>, ApiError> {
+    let seed = req.seed.unwrap_or_else(rand::random);
+    let params = MethodParams::default();
+    let cancel = params.cancel.clone();
+    let outcome = cancel::spawn_blocking(&cancel, move || {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let legs = legs(&req);
+        let n = legs[0].returns.len();
+        // The book's daily return at today's weights, for rules that look back
+        let book: Vec<f64> = (0..n).map(|d| legs.iter().map(|l| l.weight * l.returns[d]).sum()).collect();
+        let start = start_variances(&req.rules, &book);
+        let outcomes = (0..req.outer_paths)
+            .map(|_| {
+                params.cancel.check()?;
+                let mut scenario = Vec::with_capacity(req.horizon_days + req.block);
+                while scenario.len() < req.horizon_days {
+                    let start = rng.gen_range(0..=n - req.block);
+                    scenario.extend(start..start + req.block);
+                }
+                scenario.truncate(req.horizon_days);
+                Ok(replay(&req, &legs, &book, &start, &scenario, &mut rng))
+            })
+            .collect::<Result<Vec<PathOutcome>, VarError>>()?;
+        Ok::<_, VarError>((req, outcomes))
+    })
+    .await;
+    let (req, outcomes) = outcome.map_err(|_| ApiError::aborted("nested simulation"))??;
+
+    let paths = outcomes.len() as f64;
+    let share = |hit: &dyn Fn(&PathOutcome) -> bool| outcomes.iter().filter(|o| hit(o)).count() as f64 / paths;
+    let pnl: Vec<f64> = outcomes.iter().map(|o| o.pnl).collect();
+    let static_pnl: Vec<f64> = outcomes.iter().map(|o| o.static_pnl).collect();
+    let value = req.positions.as_ref().map(|p| p.iter().map(|p| p.value).sum::<f64>());
+    let rules: Vec<Value> = req
+        .rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| {
+            json!({
+                "type": rule.name(),
+                // Share of scenarios in which the rule cut exposure at least once
+                "triggered_fraction": share(&|o| o.triggered[i]),
+            })
+        })
+        .collect();
+    let nested = req.rules.iter().any(|r| matches!(r, Rule::VarTarget { .. }));
+    let (strategy, held) = (summary(&pnl, req.confidence, value), summary(&static_pnl, req.confidence, value));
+    // What the rules change: negative var / es means less risk
+    let effect: Value = ["var", "es", "mean"]
+        .iter()
+        .map(|k| (k.to_string(), json!(strategy[k].as_f64().unwrap_or(0.0) - held[k].as_f64().unwrap_or(0.0))))
+        .collect::<serde_json::Map<_, _>>()
+        .into();
+    let mut body = json!({
+        "confidence": req.confidence,
+        "horizon_days": req.horizon_days,
+        "outer_paths": req.outer_paths,
+        "inner_paths": if nested { req.inner_paths } else { 0 },
+        "inner_evaluations": if nested { req.outer_paths * req.horizon_days } else { 0 },
+        "seed": seed,
+        "strategy": strategy,
+        "static": held,
+        "effect": effect,
+        "mean_exposure": outcomes.iter().map(|o| o.mean_exposure).sum::<f64>() / paths,
+        "rules": rules,
+    });
+    if req.costs.is_some() {
+        // Mean over the scenarios, as fractions of the book's value today;
+        // the static book trades nothing
+        body["costs"] = json!({
+            "mean_traded": outcomes.iter().map(|o| o.traded).sum::<f64>() / paths,
+            "mean_cost": outcomes.iter().map(|o| o.cost).sum::<f64>() / paths,
+        });
+    }
+    if let (Some(positions), Some(value)) = (&req.positions, value) {
+        let positions: Vec<Value> = positions
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                json!({
+                    "ticker": p.ticker,
+                    "value": p.value,
+                    "weight": p.value / value,
+                    // Share of scenarios in which the position was closed at each level
+                    "stop_loss_fraction": share(&|o| o.exits[i] == Exit::StopLoss),
+                    "take_profit_fraction": share(&|o| o.exits[i] == Exit::TakeProfit),
+                })
+            })
+            .collect();
+        body["value"] = json!(value);
+        body["positions"] = json!(positions);
+    }
+    body["engine"] = json!(version::current());
+    Ok(Json(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Replays the days of `scenario` once, with and without costs of 10bp
+    // per trade and a 10bp spread: 15bp of each unit traded
+    fn replayed(mut request: Value, scenario: &[usize]) -> (PathOutcome, PathOutcome) {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut run = |request: &Value| {
+            let req: NestedRequest = serde_json::from_value(request.clone()).unwrap();
+            let legs = legs(&req);
+            let book: Vec<f64> =
+                (0..legs[0].returns.len()).map(|d| legs.iter().map(|l| l.weight * l.returns[d]).sum()).collect();
+            let start = start_variances(&req.rules, &book);
+            replay(&req, &legs, &book, &start, scenario, &mut rng)
+        };
+        let free = run(&request);
+        request["costs"] = json!({ "bps_per_trade": 10.0, "spread_bps": 10.0 });
+        (free, run(&request))
+    }
+
+    #[test]
+    fn flattening_the_book_pays_on_what_it_sells() {
+        let request = json!({
+            "returns": [-0.05, 0.01, 0.01],
+            "confidence": 0.95,
+            "rules": [{ "type": "stop_loss", "level": 0.03 }],
+        });
+        let (free, charged) = replayed(request, &[0, 1, 2]);
+        // Stopped out at the second close, selling the 0.95 left
+        assert!((charged.traded - 0.95).abs() < 1e-12, "{}", charged.traded);
+        assert!((charged.cost - 0.95 * 0.0015).abs() < 1e-12, "{}", charged.cost);
+        assert!((free.pnl - charged.pnl - charged.cost).abs() < 1e-12);
+        assert_eq!(free.cost, 0.0);
+    }
+
+    #[test]
+    fn a_position_exit_pays_on_its_value() {
+        let request = json!({
+            "positions": [
+                { "value": 100.0, "returns": [-0.05, 0.02], "stop_loss": 0.04 },
+                { "value": 100.0, "returns": [0.01, 0.01] },
+            ],
+            "confidence": 0.95,
+        });
+        let (free, charged) = replayed(request, &[0, 1]);
+        // Half the book, down 5%, closed at the first close; nothing else trades
+        assert!((charged.traded - 0.475).abs() < 1e-12, "{}", charged.traded);
+        assert!((free.pnl - charged.pnl - 0.475 * 0.0015).abs() < 1e-12);
+    }
+
+    #[test]
+    fn a_steady_exposure_trades_nothing() {
+        let request = json!({
+            "returns": [0.01, -0.01, 0.02, -0.02],
+            "confidence": 0.95,
+            "rules": [{ "type": "take_profit", "level": 0.5 }],
+        });
+        let (free, charged) = replayed(request, &[0, 1, 2, 3]);
+        assert_eq!(charged.traded, 0.0);
+        assert_eq!(free.pnl, charged.pnl);
+    }
+}
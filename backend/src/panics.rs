@@ -0,0 +1,56 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::any::Any;
+use tower_http::catch_panic::CatchPanicLayer;
+
+type PanicHandler = fn(Box<dyn Any + Send + 'static>) -> Response;
+
+/// Turns a handler panic into a JSON 500 carrying an error ID, so the
+/// caller gets an answer and the ID leads to the logged (and, with the
+/// `sentry` feature, reported) panic message.
+pub fn layer() -> CatchPanicLayer<PanicHandler> {
+    CatchPanicLayer::custom(handle_panic as PanicHandler)
+}
+
+fn handle_panic(payload: Box<dyn Any + Send + 'static>) -> Response {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "non-string panic payload".into());
+    let error_id = format!("{:016x}", rand::random::<u64>());
+    eprintln!("💥 Handler panicked [{}]: {}", error_id, message);
+    report(&error_id, &message);
+    let body = json!({ "error": "internal error", "error_id": error_id });
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+}
+
+#[cfg(feature = "sentry")]
+fn report(error_id: &str, message: &str) {
+    sentry::with_scope(
+        |scope| scope.set_tag("error_id", error_id),
+        || sentry::capture_message(message, sentry::Level::Fatal),
+    );
+}
+
+#[cfg(not(feature = "sentry"))]
+fn report(_error_id: &str, _message: &str) {}
+
+/// Starts the Sentry client when built with the `sentry` feature and
+/// `SENTRY_DSN` is set; keep the guard alive for the life of the process.
+#[cfg(feature = "sentry")]
+pub fn init_reporting() -> Option<sentry::ClientInitGuard> {
+    let dsn = std::env::var("SENTRY_DSN").ok()?;
+    let guard = sentry::init((dsn, sentry::ClientOptions { release: sentry::release_name!(), ..Default::default() }));
+    println!("🛰️ Reporting panics to Sentry");
+    Some(guard)
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn init_reporting() -> Option<()> {
+    None
+}
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+use validator::Validate;
+
+/// Funding terms of a perpetual swap position. Longs pay shorts `rate` of
+/// their notional every funding interval when it is positive.
+#[derive(Clone, Serialize, Deserialize, Validate)]
+pub struct Funding {
+    // Rate per interval (0.0001 = 1bp); omit to fetch the current rate
+    #[serde(default)]
+    #[validate(range(min = -0.05, max = 0.05, message = "rate must be between -5% and 5% per interval"))]
+    pub rate: Option<f64>,
+    // Exchange symbol to fetch the rate for (default: the ticker, e.g. BTCUSDT)
+    #[serde(default)]
+    pub symbol: Option<String>,
+    // Funding payments per day (3 on 8-hourly venues)
+    #[serde(default = "default_intervals")]
+    #[validate(range(min = 1, max = 24, message = "intervals_per_day must be between 1 and 24"))]
+    pub intervals_per_day: u32,
+}
+
+fn default_intervals() -> u32 {
+    3
+}
+
+// Funding actually applied to a ticker's simulated P&L
+#[derive(Serialize)]
+pub struct AppliedFunding {
+    pub ticker: String,
+    pub rate: f64,
+    pub source: &'static str,
+    pub intervals_per_day: u32,
+    // Return deducted from a long every day (credited to a short)
+    pub daily_drag: f64,
+}
+
+/// Current funding rate of `symbol` from the exchange's premium index
+/// (`FUNDING_URL`, default Binance USDⓈ-M futures).
+pub async fn fetch_rate(symbol: &str) -> Result<f64, String> {
+    let base = env::var("FUNDING_URL").unwrap_or_else(|_| "https://fapi.binance.com/fapi/v1/premiumIndex".into());
+    let url = format!("{base}?symbol={symbol}");
+    println!("🔗 Fetching funding rate: {}", url);
+    let resp = reqwest::get(&url).await.map_err(|e| format!("funding rate request for {symbol} failed: {e}"))?;
+    if !resp.status().is_success() {
+        return Err(format!("funding rate for {symbol} unavailable (HTTP {})", resp.status()));
+    }
+    let body: Value = resp.json().await.unwrap_or_default();
+    // Binance quotes the rate as a decimal string
+    body["lastFundingRate"]
+        .as_str()
+        .and_then(|r| r.parse().ok())
+        .or_else(|| body["lastFundingRate"].as_f64())
+        .ok_or_else(|| format!("no funding rate for {symbol} in the exchange response"))
+}
+
+/// Resolves a ticker's funding, fetching the rate when none was supplied.
+pub async fn resolve(ticker: &str, funding: &Funding) -> Result<AppliedFunding, String> {
+    let (rate, source) = match funding.rate {
+        Some(rate) => (rate, "supplied"),
+        None => (fetch_rate(funding.symbol.as_deref().unwrap_or(ticker)).await?, "fetched"),
+    };
+    Ok(AppliedFunding {
+        ticker: ticker.to_string(),
+        rate,
+        source,
+        intervals_per_day: funding.intervals_per_day,
+        daily_drag: rate * f64::from(funding.intervals_per_day),
+    })
+}
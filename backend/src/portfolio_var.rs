@@ -0,0 +1,291 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    backfill::{self, Backfill},
+    cleaning,
+    depeg::{self, DepegConfig},
+    history::{self, HistoryPolicy},
+    error::{ApiError, VarError},
+    futures,
+    margin::{self, Account},
+    perps::{self, AppliedFunding, Funding},
+    portfolios::{self, Position},
+    sanity,
+    tenant::Tenant,
+    validation::{cross_field, Valid},
+    var::{compute_var_es, MethodParams},
+    version, AppState,
+};
+
+// Payload for /api/portfolio_var: inline positions or a saved portfolio
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "one_source"))]
+pub struct PortfolioVarRequest {
+    #[serde(default)]
+    positions: Option<Vec<Position>>,
+    // Id of a saved portfolio of the caller's
+    #[serde(default)]
+    portfolio: Option<String>,
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+    // Inclusive bounds on the stored history used
+    #[serde(default)]
+    start: Option<String>,
+    #[serde(default)]
+    end: Option<String>,
+    // Most recent aligned returns to use (default: all)
+    #[serde(default)]
+    #[validate(range(min = 2, message = "window must be at least 2"))]
+    window: Option<usize>,
+    // Own returns a ticker needs before it counts as short-history
+    // (default: historical's minimum)
+    #[serde(default)]
+    min_history: Option<usize>,
+    #[serde(default)]
+    history_policy: Option<HistoryPolicy>,
+    // Ticker → proxy (e.g. a sector ETF) backfilling short histories
+    #[serde(default)]
+    proxies: BTreeMap<String, String>,
+    // Ticker → funding terms of perpetual swap positions
+    #[serde(default)]
+    #[validate(nested)]
+    perpetuals: BTreeMap<String, Funding>,
+    // Stablecoin / pegged holdings: adds the `depeg` section
+    #[serde(default)]
+    #[validate(nested)]
+    depeg: Option<DepegConfig>,
+    // Margin account of a leveraged book: adds the `leverage` section
+    #[serde(default)]
+    #[validate(nested)]
+    account: Option<Account>,
+}
+
+fn one_source(req: &PortfolioVarRequest) -> Result<(), ValidationError> {
+    match (&req.positions, &req.portfolio) {
+        (Some(p), None) if p.is_empty() => Err(cross_field("positions", "positions must not be empty".into())),
+        (Some(_), None) | (None, Some(_)) => Ok(()),
+        _ => Err(cross_field("positions", "send either positions or portfolio".into())),
+    }
+}
+
+/// Historical simulation of a multi-asset book on stored prices. Closes are
+/// aligned on the dates every ticker traded before returns are taken, so
+/// each day's return spans the same interval for every position; today's
+/// holdings are then revalued with every historical day's returns and the
+/// empirical quantile of the resulting P&L is the VaR.
+pub async fn portfolio_var_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Valid(req): Valid<PortfolioVarRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let raw = match (&req.positions, &req.portfolio) {
+        (Some(positions), _) => positions.clone(),
+        (None, Some(id)) => state.portfolios.get(&tenant, id)?.positions,
+        (None, None) => return Err(ApiError::bad_request("send either positions or portfolio")),
+    };
+    let positions = portfolios::merge_positions(&raw).map_err(ApiError::bad_request)?;
+    let gross_value: f64 = positions.iter().map(|p| p.value.abs()).sum();
+    if gross_value == 0.0 {
+        return Err(ApiError::bad_request("portfolio has no exposure"));
+    }
+
+    let proxies: BTreeMap<String, String> =
+        req.proxies.iter().map(|(t, p)| (t.trim().to_uppercase(), p.trim().to_uppercase())).collect();
+    let range = |ticker: String| {
+        let state = state.clone();
+        let (start, end) = (req.start.clone(), req.end.clone());
+        async move {
+            state
+                .prices
+                .range(&ticker, start.as_deref(), end.as_deref())
+                .await
+                .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))
+        }
+    };
+    let min_history = req.min_history.unwrap_or_else(|| history::min_history("historical"));
+    let policy = HistoryPolicy::resolve(req.history_policy, !proxies.is_empty());
+    // Spread legs are fetched and aligned like any other ticker
+    let mut tickers: Vec<&str> = Vec::new();
+    for t in positions.iter().flat_map(|p| p.tickers()) {
+        if !tickers.contains(&t) {
+            tickers.push(t);
+        }
+    }
+    let mut closes: BTreeMap<&str, BTreeMap<String, f64>> = BTreeMap::new();
+    let mut missing = Vec::new();
+    let mut rejected = Vec::new();
+    let mut backfilled: Vec<Backfill> = Vec::new();
+    let mut warnings = Vec::new();
+    // Registered futures roots are risked on their back-adjusted continuous
+    // series; the open contracts are sized from its latest close
+    let mut margined: Vec<Value> = Vec::new();
+    for &ticker in &tickers {
+        let bars = match state.futures.get(ticker) {
+            Some(spec) => {
+                let series = futures::load_continuous(&state, &spec, req.start.as_deref(), req.end.as_deref()).await?;
+                if let Some((_, price)) = series.closes.last() {
+                    let exposure: f64 = positions
+                        .iter()
+                        .map(|p| match &p.against {
+                            Some(leg) if leg.ticker == ticker => (p.value * leg.ratio).abs(),
+                            _ if p.ticker == ticker => p.value.abs(),
+                            _ => 0.0,
+                        })
+                        .sum();
+                    let contracts = exposure / (spec.multiplier * price);
+                    margined.push(json!({
+                        "root": ticker,
+                        "price": price,
+                        "multiplier": spec.multiplier,
+                        "contracts": contracts,
+                        "margin": contracts * spec.margin,
+                        "rolls": series.rolls.len(),
+                    }));
+                }
+                series.closes
+            }
+            None => range(ticker.to_string()).await?,
+        };
+        let proxy = proxies.get(ticker);
+        let proxy_bars = match proxy {
+            Some(proxy) if backfill::own_observations(&bars) < min_history => range(proxy.clone()).await?,
+            _ => Vec::new(),
+        };
+        let proxy = proxy.map(|p| (p.as_str(), proxy_bars.as_slice()));
+        let bars = match history::enforce(policy, ticker, bars, proxy, min_history, &mut warnings) {
+            Ok((bars, info)) => {
+                backfilled.extend(info);
+                bars
+            }
+            Err(e) => {
+                rejected.push(e);
+                Vec::new()
+            }
+        };
+        let series = cleaning::closes_by_date(&bars);
+        if series.len() < 2 {
+            missing.push(ticker);
+        }
+        closes.insert(ticker, series);
+    }
+    if !rejected.is_empty() {
+        return Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, rejected.join("; ")));
+    }
+    if !missing.is_empty() {
+        return Err(ApiError::not_found(format!(
+            "no stored price history for {}; load it with POST /api/prices/:ticker or /api/fetch_returns",
+            missing.join(", ")
+        )));
+    }
+
+    let mut common: BTreeSet<&String> = closes[tickers[0]].keys().collect();
+    for series in closes.values() {
+        common.retain(|d| series.contains_key(*d));
+    }
+    let dates: Vec<&String> = common.into_iter().collect();
+    let mut first = 1;
+    if let Some(window) = req.window {
+        first = dates.len().saturating_sub(window).max(1);
+    }
+    if dates.len().saturating_sub(first) < 2 {
+        return Err(ApiError::bad_request(format!(
+            "only {} dates are common to every ticker; need at least 3",
+            dates.len()
+        )));
+    }
+
+    // Perpetuals pay (or earn) funding every day on top of the price move
+    let mut funding: Vec<AppliedFunding> = Vec::new();
+    for (ticker, terms) in &req.perpetuals {
+        let ticker = ticker.trim().to_uppercase();
+        if tickers.contains(&ticker.as_str()) {
+            funding.push(perps::resolve(&ticker, terms).await.map_err(|e| ApiError::new(StatusCode::BAD_GATEWAY, e))?);
+        } else {
+            warnings.push(format!("perpetual {ticker} is not in the portfolio; its funding was ignored"));
+        }
+    }
+    let drag = |ticker: &str| funding.iter().find(|f| f.ticker == ticker).map_or(0.0, |f| f.daily_drag);
+
+    // Each position's P&L per day; a spread nets its legs into one series
+    let position_pnl: Vec<Vec<f64>> = positions
+        .iter()
+        .map(|p| {
+            (first..dates.len())
+                .map(|t| {
+                    let ret =
+                        |ticker: &str| closes[ticker][dates[t]] / closes[ticker][dates[t - 1]] - 1.0 - drag(ticker);
+                    p.value * p.instrument_return(ret)
+                })
+                .collect()
+        })
+        .collect();
+    let n = dates.len() - first;
+    let pnl: Vec<f64> = (0..n).map(|t| position_pnl.iter().map(|pp| pp[t]).sum()).collect();
+    let params = MethodParams::default();
+    let (var, es) = compute_var_es("historical", &mut pnl.clone(), req.confidence, &params)?;
+
+    // Components are each position's loss on the VaR order-statistic day,
+    // so they sum to the portfolio VaR
+    let mut order: Vec<usize> = (0..n).collect();
+    let idx = ((1.0 - req.confidence) * n as f64).floor() as usize;
+    let var_day = *order.select_nth_unstable_by(idx, |a, b| pnl[*a].total_cmp(&pnl[*b])).1;
+    let contributions = positions
+        .iter()
+        .zip(&position_pnl)
+        .map(|(p, pp)| {
+            Ok(json!({
+                "instrument": p.label(),
+                "value": p.value,
+                "standalone_var": compute_var_es("historical", &mut pp.clone(), req.confidence, &params)?.0,
+                "component_var": -pp[var_day],
+            }))
+        })
+        .collect::<Result<Vec<Value>, VarError>>()?;
+
+    let net_value: f64 = positions.iter().map(|p| p.value).sum();
+    let initial_margin: f64 = margined.iter().filter_map(|f| f["margin"].as_f64()).sum();
+    let depeg = req.depeg.as_ref().map(|config| {
+        for ticker in config.assets.keys().map(|t| t.trim().to_uppercase()) {
+            if !positions.iter().any(|p| p.tickers().contains(&ticker.as_str())) {
+                warnings.push(format!("pegged asset {ticker} is not in the portfolio"));
+            }
+        }
+        depeg::assess(config, &positions, &pnl, req.confidence)
+    });
+    let leverage = req
+        .account
+        .as_ref()
+        .map(|account| margin::assess(account, &pnl, var, gross_value, net_value, initial_margin));
+
+    let excluded: BTreeMap<&str, usize> =
+        closes.iter().map(|(ticker, series)| (*ticker, series.len() - dates.len())).collect();
+    Ok(Json(json!({
+        "method": "historical",
+        "confidence": req.confidence,
+        "observations": n,
+        "start": dates[first - 1],
+        "end": dates[dates.len() - 1],
+        "positions": positions,
+        "gross_value": gross_value,
+        "net_value": net_value,
+        "var": var,
+        "es": es,
+        "var_fraction": var / gross_value,
+        "quantile_pnl": -var,
+        "contributions": contributions,
+        "excluded_dates": excluded,
+        "backfilled": backfilled,
+        "futures": margined,
+        "funding": funding,
+        "initial_margin": initial_margin,
+        "leverage": leverage,
+        "depeg": depeg,
+        "warnings": warnings,
+        "sanity": sanity::check(var / gross_value, Some(es / gross_value)),
+        "engine": version::current(),
+    })))
+}
@@ -0,0 +1,655 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{error::ApiError, fixml, fpml, tenant::Tenant, trash, xml, AppState};
+
+// One holding: market value of the exposure (negative for shorts). With
+// `against` it is a spread, long `ticker` and short `ratio` times the value
+// in the other leg, risked as a single instrument.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Position {
+    pub ticker: String,
+    pub value: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub against: Option<SpreadLeg>,
+}
+
+// Short leg of a spread
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpreadLeg {
+    pub ticker: String,
+    #[serde(default = "default_ratio")]
+    pub ratio: f64,
+}
+
+fn default_ratio() -> f64 {
+    1.0
+}
+
+impl Position {
+    /// Every ticker whose prices the position needs.
+    pub fn tickers(&self) -> Vec<&str> {
+        let mut tickers = vec![self.ticker.as_str()];
+        tickers.extend(self.against.as_ref().map(|leg| leg.ticker.as_str()));
+        tickers
+    }
+
+    /// `A`, or `A/B` (`A/1.5×B` with a ratio) for a spread.
+    pub fn label(&self) -> String {
+        match &self.against {
+            None => self.ticker.clone(),
+            Some(leg) if leg.ratio == 1.0 => format!("{}/{}", self.ticker, leg.ticker),
+            Some(leg) => format!("{}/{}×{}", self.ticker, leg.ratio, leg.ticker),
+        }
+    }
+
+    /// Return per unit of value from the legs' returns: r_long - ratio · r_short.
+    pub fn instrument_return(&self, ret: impl Fn(&str) -> f64) -> f64 {
+        match &self.against {
+            None => ret(&self.ticker),
+            Some(leg) => ret(&self.ticker) - leg.ratio * ret(&leg.ticker),
+        }
+    }
+}
+
+/// A saved book. `version` starts at 1 and is bumped by every change;
+/// writers must present the version they read (`If-Match` or `version`).
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Portfolio {
+    pub id: String,
+    pub tenant: String,
+    pub name: String,
+    pub positions: Vec<Position>,
+    pub version: u64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purge_at: Option<DateTime<Utc>>,
+}
+
+// Payload to create or replace a portfolio
+#[derive(Deserialize)]
+pub struct PortfolioBody {
+    name: String,
+    positions: Vec<Position>,
+    // Version the client last read; alternative to If-Match
+    #[serde(default)]
+    version: Option<u64>,
+}
+
+impl PortfolioBody {
+    /// Upper-cases tickers and merges repeated ones.
+    fn normalized_positions(&self) -> Result<Vec<Position>, String> {
+        if self.name.trim().is_empty() {
+            return Err("name must not be blank".into());
+        }
+        merge_positions(&self.positions)
+    }
+}
+
+/// Upper-cased tickers with repeated instruments (same ticker, or same
+/// spread legs and ratio) merged into a single position.
+pub fn merge_positions(raw: &[Position]) -> Result<Vec<Position>, String> {
+    let mut positions: Vec<Position> = Vec::new();
+    for p in raw {
+        let ticker = p.ticker.trim().to_uppercase();
+        if ticker.is_empty() {
+            return Err("tickers must not be blank".into());
+        }
+        if !p.value.is_finite() {
+            return Err(format!("value for {ticker} must be a finite number"));
+        }
+        let against = match &p.against {
+            None => None,
+            Some(leg) => {
+                let leg_ticker = leg.ticker.trim().to_uppercase();
+                if leg_ticker.is_empty() || leg_ticker == ticker {
+                    return Err(format!("spread on {ticker} needs a different short-leg ticker"));
+                }
+                if !(leg.ratio.is_finite() && leg.ratio > 0.0) {
+                    return Err(format!("spread ratio for {ticker}/{leg_ticker} must be positive"));
+                }
+                Some(SpreadLeg { ticker: leg_ticker, ratio: leg.ratio })
+            }
+        };
+        match positions.iter_mut().find(|q| q.ticker == ticker && q.against == against) {
+            Some(q) => q.value += p.value,
+            None => positions.push(Position { ticker, value: p.value, against }),
+        }
+    }
+    Ok(positions)
+}
+
+#[derive(Default)]
+pub struct PortfolioStore {
+    books: Mutex<HashMap<String, Portfolio>>,
+}
+
+fn conflict(id: &str, expected: u64, current: u64) -> ApiError {
+    ApiError::new(
+        StatusCode::CONFLICT,
+        format!("portfolio {id} was modified concurrently: expected version {expected}, current version {current}"),
+    )
+}
+
+impl PortfolioStore {
+    pub fn all(&self) -> Vec<Portfolio> {
+        self.books.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn restore(&self, book: Portfolio) {
+        self.books.lock().unwrap().insert(book.id.clone(), book);
+    }
+
+    /// Drops portfolios whose trash retention has run out by `now`.
+    pub fn purge(&self, now: DateTime<Utc>) -> usize {
+        let mut books = self.books.lock().unwrap();
+        let before = books.len();
+        books.retain(|_, p| p.purge_at.is_none_or(|at| at > now));
+        before - books.len()
+    }
+
+    pub fn get(&self, tenant: &str, id: &str) -> Result<Portfolio, ApiError> {
+        self.books
+            .lock()
+            .unwrap()
+            .get(id)
+            .filter(|p| p.tenant == tenant && p.deleted_at.is_none())
+            .cloned()
+            .ok_or_else(|| ApiError::not_found(format!("portfolio {id} not found")))
+    }
+
+    /// Applies `f` if the stored version still equals `expected`, bumping the
+    /// version; the check and the write happen under one lock.
+    fn update(
+        &self,
+        tenant: &str,
+        id: &str,
+        deleted: bool,
+        expected: u64,
+        f: impl FnOnce(&mut Portfolio),
+    ) -> Result<Portfolio, ApiError> {
+        let mut books = self.books.lock().unwrap();
+        let book = books
+            .get_mut(id)
+            .filter(|p| p.tenant == tenant && p.deleted_at.is_some() == deleted)
+            .ok_or_else(|| ApiError::not_found(format!("portfolio {id} not found")))?;
+        if book.version != expected {
+            return Err(conflict(id, expected, book.version));
+        }
+        f(book);
+        book.version += 1;
+        book.updated_at = Utc::now();
+        Ok(book.clone())
+    }
+
+    /// Applies `f` whatever the stored version, bumping it: for feeds that
+    /// own the book (see `ingest`) rather than clients editing what they read.
+    #[cfg(feature = "streaming")]
+    pub fn apply(&self, tenant: &str, id: &str, f: impl FnOnce(&mut Portfolio)) -> Result<Portfolio, ApiError> {
+        let mut books = self.books.lock().unwrap();
+        let book = books
+            .get_mut(id)
+            .filter(|p| p.tenant == tenant && p.deleted_at.is_none())
+            .ok_or_else(|| ApiError::not_found(format!("portfolio {id} not found")))?;
+        f(book);
+        book.version += 1;
+        book.updated_at = Utc::now();
+        Ok(book.clone())
+    }
+
+    /// Live portfolios, of every tenant, holding `ticker` (in either leg).
+    #[cfg(feature = "streaming")]
+    pub fn holding(&self, ticker: &str) -> Vec<Portfolio> {
+        let books = self.books.lock().unwrap();
+        books
+            .values()
+            .filter(|p| p.deleted_at.is_none() && p.positions.iter().any(|q| q.tickers().contains(&ticker)))
+            .cloned()
+            .collect()
+    }
+}
+
+fn etag(version: u64) -> HeaderValue {
+    HeaderValue::from_str(&format!("\"{version}\"")).unwrap()
+}
+
+/// Portfolio JSON with its version as a strong ETag
+fn with_etag(status: StatusCode, book: Portfolio) -> Response {
+    (status, [(header::ETAG, etag(book.version))], Json(book)).into_response()
+}
+
+/// Version the client based its write on, from `If-Match` or the body.
+fn precondition(headers: &HeaderMap, body: Option<u64>) -> Result<u64, ApiError> {
+    let header = headers.get(header::IF_MATCH).and_then(|v| v.to_str().ok());
+    let from_header = match header {
+        Some(v) => Some(
+            v.trim()
+                .trim_start_matches("W/")
+                .trim_matches('"')
+                .parse::<u64>()
+                .map_err(|_| ApiError::bad_request(format!("invalid If-Match: {v}")))?,
+        ),
+        None => None,
+    };
+    match (from_header, body) {
+        (Some(h), Some(b)) if h != b => Err(ApiError::bad_request("If-Match and body version disagree")),
+        (Some(v), _) | (None, Some(v)) => Ok(v),
+        (None, None) => Err(ApiError::new(
+            StatusCode::PRECONDITION_REQUIRED,
+            "send the version you are updating (If-Match header or `version` field)",
+        )),
+    }
+}
+
+// Query for listing portfolios
+#[derive(Deserialize)]
+pub struct ListQuery {
+    // List the trash instead of live portfolios
+    #[serde(default)]
+    deleted: bool,
+}
+
+/// Portfolios owned by the caller's tenant
+pub async fn list_portfolios_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Query(q): Query<ListQuery>,
+) -> Json<Vec<Portfolio>> {
+    let mut books: Vec<Portfolio> = state
+        .portfolios
+        .all()
+        .into_iter()
+        .filter(|p| p.tenant == tenant && p.deleted_at.is_some() == q.deleted)
+        .collect();
+    books.sort_by_key(|p| p.created_at);
+    Json(books)
+}
+
+/// Create a portfolio
+pub async fn create_portfolio_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Json(body): Json<PortfolioBody>,
+) -> Result<Response, ApiError> {
+    let now = Utc::now();
+    let book = Portfolio {
+        id: format!("{:016x}", rand::random::<u64>()),
+        tenant,
+        positions: body.normalized_positions().map_err(ApiError::bad_request)?,
+        name: body.name,
+        version: 1,
+        created_at: now,
+        updated_at: now,
+        deleted_at: None,
+        purge_at: None,
+    };
+    state.portfolios.restore(book.clone());
+    Ok(with_etag(StatusCode::CREATED, book))
+}
+
+/// Fetch one portfolio
+pub async fn get_portfolio_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let book = state.portfolios.get(&tenant, &id)?;
+    Ok(with_etag(StatusCode::OK, book))
+}
+
+/// Replace a portfolio's name and positions; 409 if someone else saved first
+pub async fn update_portfolio_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<PortfolioBody>,
+) -> Result<Response, ApiError> {
+    let expected = precondition(&headers, body.version)?;
+    let positions = body.normalized_positions().map_err(ApiError::bad_request)?;
+    let book = state.portfolios.update(&tenant, &id, false, expected, |p| {
+        p.name = body.name;
+        p.positions = positions;
+    })?;
+    Ok(with_etag(StatusCode::OK, book))
+}
+
+// Optional version guard for deletes and restores
+#[derive(Deserialize)]
+pub struct VersionQuery {
+    #[serde(default)]
+    version: Option<u64>,
+}
+
+/// Move a portfolio to the trash; it stays restorable until purged
+pub async fn delete_portfolio_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Query(q): Query<VersionQuery>,
+) -> Result<StatusCode, ApiError> {
+    let expected = precondition(&headers, q.version)?;
+    state.portfolios.update(&tenant, &id, false, expected, |p| {
+        let now = Utc::now();
+        p.deleted_at = Some(now);
+        p.purge_at = Some(trash::purge_at(now));
+    })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Take a portfolio back out of the trash
+pub async fn restore_portfolio_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Query(q): Query<VersionQuery>,
+) -> Result<Response, ApiError> {
+    let expected = precondition(&headers, q.version)?;
+    let book = state.portfolios.update(&tenant, &id, true, expected, |p| {
+        p.deleted_at = None;
+        p.purge_at = None;
+    })?;
+    Ok(with_etag(StatusCode::OK, book))
+}
+
+// Options for bulk import
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    // Validate and report without saving anything
+    #[serde(default)]
+    dry_run: bool,
+    // Portfolio for FIXML reports without an account (default `fixml`) and
+    // for FpML trades (default `fpml`)
+    #[serde(default)]
+    portfolio: Option<String>,
+    // FpML party id whose side of the trades the portfolio holds (default:
+    // the document's first party)
+    #[serde(default)]
+    party: Option<String>,
+}
+
+// Outcome for one input row (a CSV line, or one portfolio of a JSON array)
+#[derive(Serialize)]
+struct ImportRow {
+    row: usize,
+    portfolio: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// CSV line as read, before validation
+#[derive(Deserialize)]
+struct CsvRow {
+    portfolio: String,
+    ticker: String,
+    value: String,
+    // Optional spread columns: short-leg ticker and hedge ratio
+    #[serde(default)]
+    against: Option<String>,
+    #[serde(default)]
+    ratio: Option<f64>,
+}
+
+// A parsed portfolio and the indices of the report rows it came from
+type Parsed = Vec<(PortfolioBody, Vec<usize>)>;
+
+/// Groups `portfolio,ticker,value` lines (plus optional `against,ratio`
+/// spread columns) into portfolio bodies (in order of first appearance),
+/// reporting every line; lines are numbered from 2, after the header.
+fn parse_csv(body: &[u8]) -> Result<(Parsed, Vec<ImportRow>), ApiError> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(body);
+    let headers = reader
+        .headers()
+        .map_err(|e| ApiError::bad_request(format!("invalid CSV header: {e}")))?
+        .iter()
+        .map(str::to_lowercase)
+        .collect();
+    reader.set_headers(headers);
+
+    let mut books: Parsed = Vec::new();
+    let mut rows = Vec::new();
+    for (i, record) in reader.deserialize::<CsvRow>().enumerate() {
+        let row = i + 2;
+        let line = match record {
+            Ok(line) => line,
+            Err(e) => {
+                rows.push(ImportRow { row, portfolio: String::new(), error: Some(e.to_string()) });
+                continue;
+            }
+        };
+        let group = match books.iter().position(|(b, _)| b.name == line.portfolio) {
+            Some(g) => g,
+            None => {
+                let book = PortfolioBody { name: line.portfolio.clone(), positions: Vec::new(), version: None };
+                books.push((book, Vec::new()));
+                books.len() - 1
+            }
+        };
+        let (book, lines) = &mut books[group];
+        lines.push(rows.len());
+        let error = match line.value.parse::<f64>() {
+            Ok(value) => {
+                let against = line
+                    .against
+                    .filter(|t| !t.trim().is_empty())
+                    .map(|ticker| SpreadLeg { ticker, ratio: line.ratio.unwrap_or_else(default_ratio) });
+                book.positions.push(Position { ticker: line.ticker, value, against });
+                None
+            }
+            Err(_) => Some(format!("value {:?} is not a number", line.value)),
+        };
+        rows.push(ImportRow { row, portfolio: line.portfolio, error });
+    }
+    Ok((books, rows))
+}
+
+/// Groups positions read from a document, each with the portfolio it goes
+/// to, into portfolio bodies; rows are numbered from 1 in document order.
+fn group_positions(positions: Vec<Result<(String, Position), String>>) -> (Parsed, Vec<ImportRow>) {
+    let mut books: Parsed = Vec::new();
+    let mut rows = Vec::new();
+    for (i, position) in positions.into_iter().enumerate() {
+        let row = i + 1;
+        let (name, position) = match position {
+            Ok(position) => position,
+            Err(e) => {
+                rows.push(ImportRow { row, portfolio: String::new(), error: Some(e) });
+                continue;
+            }
+        };
+        let group = match books.iter().position(|(b, _)| b.name == name) {
+            Some(g) => g,
+            None => {
+                books.push((PortfolioBody { name: name.clone(), positions: Vec::new(), version: None }, Vec::new()));
+                books.len() - 1
+            }
+        };
+        let (book, lines) = &mut books[group];
+        lines.push(rows.len());
+        book.positions.push(position);
+        rows.push(ImportRow { row, portfolio: name, error: None });
+    }
+    (books, rows)
+}
+
+/// FIXML position reports, one portfolio per account (`Acct`) or
+/// `default_name` for reports without one.
+fn parse_fixml(xml: &str, default_name: &str) -> Result<(Parsed, Vec<ImportRow>), ApiError> {
+    let reports = fixml::positions(xml).map_err(|e| ApiError::bad_request(format!("invalid FIXML: {e}")))?;
+    let positions = reports
+        .into_iter()
+        .map(|report| {
+            report.map(|r| {
+                let name = r.account.unwrap_or_else(|| default_name.to_string());
+                (name, Position { ticker: r.ticker, value: r.value, against: None })
+            })
+        })
+        .collect();
+    Ok(group_positions(positions))
+}
+
+/// FpML trades as `party` holds them, mapped to positions in one portfolio.
+async fn parse_fpml(
+    state: &AppState,
+    xml: &str,
+    name: &str,
+    party: Option<&str>,
+) -> Result<(Parsed, Vec<ImportRow>), ApiError> {
+    let trades = fpml::trades(xml, party).map_err(|e| ApiError::bad_request(format!("invalid FpML: {e}")))?;
+    let today = Utc::now().date_naive();
+    let mut positions = Vec::new();
+    for trade in trades {
+        let position = match trade {
+            Ok(trade) => trade.position(&*state.prices, today).await,
+            Err(e) => Err(e),
+        };
+        positions.push(position.map(|p| (name.to_string(), p)));
+    }
+    Ok(group_positions(positions))
+}
+
+/// Import many portfolios from a JSON array of portfolio bodies, a CSV of
+/// `portfolio,ticker,value` lines, or XML (`text/xml` or `application/xml`):
+/// FIXML position reports or FpML trades. Every row is validated and
+/// reported; a portfolio with any invalid row is skipped as a whole.
+pub async fn import_portfolios_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Query(q): Query<ImportQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Value>, ApiError> {
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let is_xml = ["text/xml", "application/xml", "application/fixml+xml"].iter().any(|t| content_type.starts_with(t));
+    let (books, mut rows) = if content_type.starts_with("text/csv") {
+        parse_csv(&body)?
+    } else if is_xml {
+        let document = std::str::from_utf8(&body).map_err(|_| ApiError::bad_request("XML must be UTF-8"))?;
+        let root = xml::tags(document).ok().and_then(|tags| tags.into_iter().next()).map(|t| t.name);
+        if matches!(root.as_deref(), Some("FIXML" | "Batch" | "PosRpt")) {
+            parse_fixml(document, q.portfolio.as_deref().unwrap_or("fixml"))?
+        } else {
+            parse_fpml(&state, document, q.portfolio.as_deref().unwrap_or("fpml"), q.party.as_deref()).await?
+        }
+    } else {
+        let books: Vec<PortfolioBody> =
+            serde_json::from_slice(&body).map_err(|e| ApiError::bad_request(format!("invalid JSON: {e}")))?;
+        let rows = books
+            .iter()
+            .enumerate()
+            .map(|(i, b)| ImportRow { row: i + 1, portfolio: b.name.clone(), error: None })
+            .collect();
+        (books.into_iter().enumerate().map(|(i, b)| (b, vec![i])).collect(), rows)
+    };
+
+    let mut created = Vec::new();
+    for (book, lines) in books {
+        let failed = lines.iter().any(|&i| rows[i].error.is_some());
+        let error = match book.normalized_positions() {
+            Ok(positions) if !failed => Ok(positions),
+            Ok(_) => Err("skipped: another row of this portfolio is invalid".to_string()),
+            Err(e) => Err(e),
+        };
+        let positions = match error {
+            Ok(positions) => positions,
+            Err(e) => {
+                for &i in &lines {
+                    rows[i].error.get_or_insert_with(|| e.clone());
+                }
+                continue;
+            }
+        };
+        if q.dry_run {
+            continue;
+        }
+        let now = Utc::now();
+        let saved = Portfolio {
+            id: format!("{:016x}", rand::random::<u64>()),
+            tenant: tenant.clone(),
+            name: book.name,
+            positions,
+            version: 1,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            purge_at: None,
+        };
+        created.push(json!({ "id": saved.id, "name": saved.name }));
+        state.portfolios.restore(saved);
+    }
+
+    let rejected = rows.iter().filter(|r| r.error.is_some()).count();
+    println!("📥 Portfolio import: {} created, {} of {} rows rejected", created.len(), rejected, rows.len());
+    Ok(Json(json!({
+        "dry_run": q.dry_run,
+        "created": created,
+        "rows": rows.len(),
+        "rejected_rows": rejected,
+        "report": rows,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> PortfolioStore {
+        let store = PortfolioStore::default();
+        let now = Utc::now();
+        store.restore(Portfolio {
+            id: "book".into(),
+            tenant: "acme".into(),
+            name: "Core".into(),
+            positions: vec![Position { ticker: "AAPL".into(), value: 1000.0, against: None }],
+            version: 1,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            purge_at: None,
+        });
+        store
+    }
+
+    fn if_match(tag: &str) -> HeaderMap {
+        HeaderMap::from_iter([(header::IF_MATCH, HeaderValue::from_str(tag).unwrap())])
+    }
+
+    #[test]
+    fn a_stale_if_match_is_a_conflict() {
+        let store = store();
+        let expected = precondition(&if_match("\"1\""), None).unwrap();
+        let book = store.update("acme", "book", false, expected, |p| p.name = "Growth".into()).unwrap();
+        assert_eq!(book.version, 2);
+
+        // A second writer that also read version 1 loses
+        let stale = precondition(&if_match("\"1\""), None).unwrap();
+        let err = store.update("acme", "book", false, stale, |p| p.name = "Value".into()).err().unwrap();
+        assert_eq!(err.status, StatusCode::CONFLICT);
+        assert_eq!(store.get("acme", "book").unwrap().name, "Growth");
+
+        let weak = precondition(&if_match("W/\"2\""), None).unwrap();
+        assert!(store.update("acme", "book", false, weak, |p| p.name = "Value".into()).is_ok());
+    }
+
+    #[test]
+    fn writes_must_name_one_version() {
+        assert_eq!(precondition(&HeaderMap::new(), Some(3)).unwrap(), 3);
+        assert_eq!(precondition(&HeaderMap::new(), None).err().unwrap().status, StatusCode::PRECONDITION_REQUIRED);
+        assert_eq!(precondition(&if_match("\"2\""), Some(3)).err().unwrap().status, StatusCode::BAD_REQUEST);
+        assert_eq!(precondition(&if_match("latest"), None).err().unwrap().status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,120 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, Request},
+    http::StatusCode,
+    Json,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use validator::Validate;
+
+use crate::{error::ApiError, validation};
+
+/// Named regulatory / house setup that pins confidence, horizon and method.
+#[derive(Serialize)]
+pub struct Profile {
+    pub name: &'static str,
+    pub confidence: f64,
+    pub horizon_days: u32,
+    pub method: &'static str,
+    pub description: &'static str,
+}
+
+pub const PROFILES: &[Profile] = &[
+    Profile {
+        name: "basel_99_10d",
+        confidence: 0.99,
+        horizon_days: 10,
+        method: "historical",
+        description: "Basel market-risk VaR: 99% one-tailed, 10-day horizon",
+    },
+    Profile {
+        name: "ucits_99_20d",
+        confidence: 0.99,
+        horizon_days: 20,
+        method: "historical",
+        description: "UCITS absolute VaR: 99% one-tailed, 20-day (one month) horizon",
+    },
+    Profile {
+        name: "daily_95",
+        confidence: 0.95,
+        horizon_days: 1,
+        method: "historical",
+        description: "Internal daily monitoring: 95%, 1-day horizon",
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Profile> {
+    PROFILES.iter().find(|p| p.name == name)
+}
+
+/// Fills in the settings of the body's `profile`. Settings the caller also
+/// sent must agree with the profile, so a profile can't be half-overridden.
+pub fn expand(body: &mut Value) -> Result<(), ApiError> {
+    let Some(fields) = body.as_object_mut() else {
+        return Ok(());
+    };
+    let Some(name) = fields.get("profile").filter(|p| !p.is_null()) else {
+        return Ok(());
+    };
+    let mut errors: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let Some(profile) = name.as_str().and_then(find) else {
+        let name = name.as_str().map_or_else(|| name.to_string(), str::to_string);
+        let known: Vec<&str> = PROFILES.iter().map(|p| p.name).collect();
+        errors.insert("profile".into(), vec![format!("unknown profile {name}; expected one of {}", known.join(", "))]);
+        return Err(ApiError::invalid(errors));
+    };
+    let settings = [
+        ("confidence", json!(profile.confidence)),
+        ("horizon_days", json!(profile.horizon_days)),
+        ("method", json!(profile.method)),
+    ];
+    for (key, value) in settings {
+        match fields.get(key) {
+            None | Some(Value::Null) => {
+                fields.insert(key.into(), value);
+            }
+            Some(sent) if *sent == value || sent.as_f64().is_some_and(|s| Some(s) == value.as_f64()) => {}
+            Some(_) => {
+                errors
+                    .entry(key.into())
+                    .or_default()
+                    .push(format!("{key} is fixed at {value} by profile {}", profile.name));
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::invalid(errors))
+    }
+}
+
+/// JSON body with its `profile` expanded, then deserialised and validated.
+pub struct Profiled<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Profiled<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(mut body) = Json::<Value>::from_request(req, state)
+            .await
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+        expand(&mut body)?;
+        let value: T =
+            serde_json::from_value(body).map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+        validation::check(&value)?;
+        Ok(Profiled(value))
+    }
+}
+
+/// Available parameter profiles
+pub async fn list_profiles_handler() -> Json<&'static [Profile]> {
+    Json(PROFILES)
+}
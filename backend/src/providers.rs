@@ -0,0 +1,223 @@
+//! Price providers and the order they are tried in. Each turns its API's
+//! response into `(date, close)` pairs, oldest first; one that fails hands
+//! over to the next in `PRICE_PROVIDERS`.
+
+use chrono::{TimeZone, Utc};
+use serde_json::Value;
+use std::{env, sync::OnceLock};
+
+use crate::lookback::Lookback;
+
+const DEFAULT_CHAIN: &[Provider] = &[Provider::Yahoo, Provider::AlphaVantage, Provider::Polygon];
+// Bars Polygon returns a page at most
+const POLYGON_LIMIT: usize = 50_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Provider {
+    Yahoo,
+    AlphaVantage,
+    Polygon,
+}
+
+impl Provider {
+    fn parse(name: &str) -> Option<Provider> {
+        match name.trim().to_lowercase().as_str() {
+            "yahoo" => Some(Provider::Yahoo),
+            "alpha_vantage" | "alphavantage" => Some(Provider::AlphaVantage),
+            "polygon" => Some(Provider::Polygon),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::Yahoo => "Yahoo",
+            Provider::AlphaVantage => "Alpha Vantage",
+            Provider::Polygon => "Polygon",
+        }
+    }
+
+    /// Stored credential and environment variable holding the provider's
+    /// API key, for those that need one.
+    pub fn credential(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Provider::Yahoo => None,
+            Provider::AlphaVantage => Some(("alpha_vantage", "ALPHA_VANTAGE_KEY")),
+            Provider::Polygon => Some(("polygon", "POLYGON_API_KEY")),
+        }
+    }
+
+    /// Closes of `ticker` over the look-back, `None` when the provider
+    /// failed and the next should be tried.
+    pub async fn fetch(self, ticker: &str, lookback: &Lookback, key: Option<&str>) -> Option<Vec<(String, f64)>> {
+        match (self, key) {
+            (Provider::Yahoo, _) => yahoo(ticker, lookback).await,
+            (Provider::AlphaVantage, Some(key)) => alpha_vantage(ticker, lookback, key).await,
+            (Provider::Polygon, Some(key)) => polygon(ticker, lookback, key).await,
+            (_, None) => None,
+        }
+    }
+}
+
+/// Providers in the order they are tried: `PRICE_PROVIDERS`, e.g.
+/// `polygon,yahoo` (default `yahoo,alpha_vantage,polygon`). Providers needing
+/// a key are skipped while none is stored or set.
+pub fn chain() -> &'static [Provider] {
+    static CHAIN: OnceLock<Vec<Provider>> = OnceLock::new();
+    CHAIN.get_or_init(|| {
+        let Ok(names) = env::var("PRICE_PROVIDERS") else {
+            return DEFAULT_CHAIN.to_vec();
+        };
+        let mut chain = Vec::new();
+        for name in names.split(',').filter(|n| !n.trim().is_empty()) {
+            match Provider::parse(name) {
+                Some(provider) if !chain.contains(&provider) => chain.push(provider),
+                Some(_) => {}
+                None => eprintln!("⚠️ Ignoring unknown price provider {:?} in PRICE_PROVIDERS", name.trim()),
+            }
+        }
+        if chain.is_empty() {
+            eprintln!("⚠️ PRICE_PROVIDERS names no known provider, using yahoo,alpha_vantage,polygon");
+            return DEFAULT_CHAIN.to_vec();
+        }
+        let names: Vec<&str> = chain.iter().map(|p| p.name()).collect();
+        println!("📡 Price providers: {}", names.join(" → "));
+        chain
+    })
+}
+
+async fn yahoo(ticker: &str, lookback: &Lookback) -> Option<Vec<(String, f64)>> {
+    let (start, end) = lookback.span(Utc::now());
+    let yahoo_url = format!(
+        "https://query2.finance.yahoo.com/v8/finance/chart/{ticker}?\
+         period1={start}&period2={end}&interval={interval}&includePrePost=false&events=history",
+        ticker=ticker, start=start.timestamp(), end=end.timestamp(), interval=lookback.interval.code()
+    );
+    println!("🔗 Trying Yahoo: {}", yahoo_url);
+
+    match reqwest::get(&yahoo_url).await {
+        Ok(resp) if resp.status().is_success() => {
+            let body: Value = resp.json().await.unwrap_or_default();
+            if !body["chart"]["error"].is_null() {
+                println!("⚠️ Yahoo JSON error");
+                return None;
+            }
+            let result = &body["chart"]["result"][0];
+            // **clone** the arrays into owned Vec<Value>
+            let timestamps: Vec<Value> = result["timestamp"]
+                .as_array().cloned().unwrap_or_default();
+            let closes: Vec<Value> = result["indicators"]["adjclose"][0]["adjclose"]
+                .as_array().cloned().unwrap_or_default();
+
+            let mut data = Vec::new();
+            for (ts_val, price_val) in timestamps.iter().zip(closes.iter()) {
+                let stamp = ts_val.as_i64().and_then(|ts| Utc.timestamp_opt(ts, 0).single());
+                if let (Some(stamp), Some(p)) = (stamp, price_val.as_f64()) {
+                    data.push((stamp.format("%Y-%m-%d").to_string(), p));
+                }
+            }
+            println!("🔢 Yahoo returned {} points", data.len());
+            Some(data)
+        }
+        Ok(r) => {
+            println!("❌ Yahoo HTTP {}", r.status());
+            None
+        }
+        Err(e) => {
+            eprintln!("❌ Yahoo request failed: {}", e);
+            None
+        }
+    }
+}
+
+async fn alpha_vantage(ticker: &str, lookback: &Lookback, key: &str) -> Option<Vec<(String, f64)>> {
+    let (start, end) = lookback.span(Utc::now());
+    // compact is the last 100 bars; longer or older spans need the full history
+    let (function, series_key) = lookback.interval.alpha_vantage();
+    let size = if lookback.is_default() { "compact" } else { "full" };
+    let av_url = format!(
+        "https://www.alphavantage.co/query?function={function}\
+         &symbol={ticker}&outputsize={size}&apikey={key}&datatype=json",
+        function=function, ticker=ticker, size=size, key=key
+    );
+    println!("🔗 Trying Alpha Vantage ({}): {}", lookback.interval.code(), av_url.replace(key, "***"));
+
+    let body: Value = match reqwest::get(&av_url).await {
+        Ok(resp) => resp.json().await.unwrap_or_default(),
+        Err(e) => {
+            // reqwest errors carry the URL, key included
+            eprintln!("❌ Alpha Vantage request failed: {}", e.without_url());
+            return None;
+        }
+    };
+    println!("🔄 Alpha Vantage raw JSON:\n{}", body);
+
+    // handle rate-limit notes or errors
+    if let Some(note) = body.get("Note").or_else(|| body.get("Information")).or_else(|| body.get("Error Message")) {
+        eprintln!("⚠️ Alpha Vantage returned an error/note: {}", note);
+        None
+    } else if let Some(ts_map) = body.get(series_key).and_then(|v| v.as_object()) {
+        // parse the time‐series map using the "4. close" field
+        let mut vec: Vec<_> = ts_map.iter().map(|(date, obj)| {
+            let close = obj["4. close"].as_str()
+                .unwrap_or("0")
+                .parse::<f64>()
+                .unwrap_or(0.0);
+            (date.clone(), close)
+        }).collect();
+        vec.sort_by_key(|(d, _)| d.clone());
+        let (first, last) = (start.format("%Y-%m-%d").to_string(), end.format("%Y-%m-%d").to_string());
+        vec.retain(|(d, _)| *d >= first && *d <= last);
+        println!("🔢 Alpha Vantage returned {} points", vec.len());
+        Some(vec)
+    } else {
+        eprintln!("❌ Unexpected Alpha Vantage JSON structure");
+        None
+    }
+}
+
+/// Split-adjusted (not dividend-adjusted) aggregates from Polygon.io. Bars
+/// are stamped with their start in Unix milliseconds; pages beyond the first
+/// are followed through `next_url`, which needs the key appended again.
+async fn polygon(ticker: &str, lookback: &Lookback, key: &str) -> Option<Vec<(String, f64)>> {
+    let (start, end) = lookback.span(Utc::now());
+    let mut url = format!(
+        "https://api.polygon.io/v2/aggs/ticker/{ticker}/range/1/{timespan}/{from}/{to}\
+         ?adjusted=true&sort=asc&limit={POLYGON_LIMIT}",
+        timespan = lookback.interval.polygon(),
+        from = start.format("%Y-%m-%d"),
+        to = end.format("%Y-%m-%d"),
+    );
+    println!("🔗 Trying Polygon ({}): {}", lookback.interval.code(), url);
+
+    let mut data = Vec::new();
+    loop {
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let body: Value = match reqwest::get(format!("{url}{separator}apiKey={key}")).await {
+            Ok(resp) => resp.json().await.unwrap_or_default(),
+            Err(e) => {
+                // reqwest errors carry the URL, key included
+                eprintln!("❌ Polygon request failed: {}", e.without_url());
+                return None;
+            }
+        };
+        // OK, or DELAYED on plans without real-time data
+        if !matches!(body["status"].as_str(), Some("OK" | "DELAYED")) {
+            let reason = body["error"].as_str().or(body["message"].as_str()).unwrap_or("unexpected response");
+            eprintln!("⚠️ Polygon returned {}: {}", body["status"], reason);
+            return None;
+        }
+        for bar in body["results"].as_array().into_iter().flatten() {
+            let stamp = bar["t"].as_i64().and_then(|ms| Utc.timestamp_millis_opt(ms).single());
+            if let (Some(stamp), Some(close)) = (stamp, bar["c"].as_f64()) {
+                data.push((stamp.format("%Y-%m-%d").to_string(), close));
+            }
+        }
+        match body["next_url"].as_str() {
+            Some(next) => url = next.to_string(),
+            None => break,
+        }
+    }
+    println!("🔢 Polygon returned {} points", data.len());
+    Some(data)
+}
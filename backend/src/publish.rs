@@ -0,0 +1,375 @@
+//! Outbound risk events: streamed book snapshots, VaR breaches and alerts,
+//! and finished jobs, published as JSON to a Kafka topic or NATS subject so
+//! downstream systems get the numbers without polling. Publishing never
+//! blocks the computation: events queue in memory and are dropped, and
+//! counted, when the queue is full or the broker keeps failing.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    env,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::mpsc,
+};
+
+use crate::{
+    ingest::LiveRisk,
+    jobs::{Job, JobStatus},
+    kafka::{self, Connection, KafkaResult, KeyedRecord},
+};
+
+// Events held while the broker is slow or down
+const QUEUE: usize = 10_000;
+// Events sent per Kafka produce request
+const BATCH: usize = 500;
+const RETRY: Duration = Duration::from_secs(5);
+const NATS_PORT: u16 = 4222;
+
+/// Something downstream systems want to hear about, keyed by the portfolio
+/// or job it concerns.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    // A book re-risked after a streamed change
+    Snapshot {
+        tenant: String,
+        #[serde(flatten)]
+        risk: LiveRisk,
+    },
+    // The latest day's loss exceeded the VaR the book went into it with
+    Breach {
+        tenant: String,
+        portfolio: String,
+        date: Option<String>,
+        pnl: f64,
+        var: f64,
+        confidence: f64,
+    },
+    // The VaR moved by more than `EVENTS_VAR_JUMP` (a fraction) between
+    // two snapshots
+    Alert {
+        tenant: String,
+        portfolio: String,
+        kind: &'static str,
+        var: f64,
+        previous_var: f64,
+        change: f64,
+    },
+    Job {
+        tenant: String,
+        id: String,
+        status: JobStatus,
+        method: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        var: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        es: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        finished_at: Option<DateTime<Utc>>,
+    },
+}
+
+impl Event {
+    pub fn job(job: &Job) -> Self {
+        let field = |name: &str| job.result.as_ref().and_then(|r| r[name].as_f64());
+        Event::Job {
+            tenant: job.tenant.clone(),
+            id: job.id.clone(),
+            status: job.status,
+            method: job.request.method.clone(),
+            var: field("var"),
+            es: field("es"),
+            error: job.error.clone(),
+            finished_at: job.finished_at,
+        }
+    }
+
+    /// Events a new snapshot of a book raises against its previous one.
+    pub fn from_snapshot(risk: &LiveRisk, previous: Option<&LiveRisk>, var_jump: f64) -> Vec<Self> {
+        let mut events = Vec::new();
+        if let Some(previous) = previous {
+            let already_breached = previous.as_of == risk.as_of && previous.breach;
+            if risk.breach && !already_breached {
+                events.push(Event::Breach {
+                    tenant: risk.tenant.clone(),
+                    portfolio: risk.portfolio.clone(),
+                    date: risk.as_of.clone(),
+                    pnl: risk.pnl,
+                    var: risk.previous_var.unwrap_or_default(),
+                    confidence: risk.confidence,
+                });
+            }
+            let change = risk.var / previous.var - 1.0;
+            if previous.var > 0.0 && change.abs() > var_jump {
+                events.push(Event::Alert {
+                    tenant: risk.tenant.clone(),
+                    portfolio: risk.portfolio.clone(),
+                    kind: "var_jump",
+                    var: risk.var,
+                    previous_var: previous.var,
+                    change,
+                });
+            }
+        }
+        events.push(Event::Snapshot { tenant: risk.tenant.clone(), risk: risk.clone() });
+        events
+    }
+}
+
+// Where events go
+enum Sink {
+    Kafka { brokers: Vec<String>, topic: String, client_id: String },
+    Nats { address: String, subject: String, credentials: Value },
+}
+
+// Publishing progress, as reported by /api/admin/stream
+#[derive(Clone, Default, Serialize)]
+struct Status {
+    destination: String,
+    published: u64,
+    dropped: u64,
+    last_error: Option<String>,
+    last_error_at: Option<DateTime<Utc>>,
+}
+
+/// Queues events for the background publisher.
+pub struct Publisher {
+    queue: mpsc::Sender<(String, Vec<u8>)>,
+    status: Arc<Mutex<Status>>,
+    /// Relative VaR change between snapshots that raises an alert.
+    pub var_jump: f64,
+}
+
+fn record_error(status: &Mutex<Status>, message: String) {
+    eprintln!("⚠️ Event publishing: {message}");
+    let mut status = status.lock().unwrap();
+    status.last_error = Some(message);
+    status.last_error_at = Some(Utc::now());
+}
+
+impl Publisher {
+    /// Enabled when `EVENTS_TOPIC` is set: published to that NATS subject if
+    /// `NATS_URL` (`nats://[user:password@|token@]host[:port]`) is set, else
+    /// to that Kafka topic on `KAFKA_BROKERS`. `EVENTS_VAR_JUMP` (default
+    /// 0.25) is the relative VaR change that raises an alert. Must be called
+    /// within the runtime.
+    pub fn from_env() -> Option<Arc<Self>> {
+        let topic = env::var("EVENTS_TOPIC").ok().map(|t| t.trim().to_string()).filter(|t| !t.is_empty())?;
+        let sink = if let Ok(url) = env::var("NATS_URL") {
+            let Some((address, credentials)) = nats_address(&url) else {
+                eprintln!("⚠️ Invalid NATS_URL {url:?}; events are not published");
+                return None;
+            };
+            Sink::Nats { address, subject: topic, credentials }
+        } else if let Ok(brokers) = env::var("KAFKA_BROKERS") {
+            let brokers = brokers.split(',').map(str::trim).filter(|b| !b.is_empty()).map(str::to_string).collect();
+            let client_id = env::var("KAFKA_CLIENT_ID").unwrap_or_else(|_| "riskvar".into());
+            Sink::Kafka { brokers, topic, client_id }
+        } else {
+            eprintln!("⚠️ EVENTS_TOPIC is set but neither NATS_URL nor KAFKA_BROKERS; events are not published");
+            return None;
+        };
+        let destination = match &sink {
+            Sink::Kafka { brokers, topic, .. } => format!("kafka://{}/{topic}", brokers.join(",")),
+            Sink::Nats { address, subject, .. } => format!("nats://{address}/{subject}"),
+        };
+        println!("📣 Publishing risk events to {destination}");
+        let var_jump = env::var("EVENTS_VAR_JUMP")
+            .ok()
+            .and_then(|s| s.parse::<f64>().ok())
+            .filter(|x| *x > 0.0)
+            .unwrap_or(0.25);
+        let status = Arc::new(Mutex::new(Status { destination, ..Status::default() }));
+        let (queue, events) = mpsc::channel(QUEUE);
+        tokio::spawn(run(sink, events, status.clone()));
+        Some(Arc::new(Publisher { queue, status, var_jump }))
+    }
+
+    /// Queues an event without waiting; dropped if the queue is full.
+    pub fn publish(&self, key: &str, event: Event) {
+        let mut payload = json!(event);
+        payload["emitted_at"] = json!(Utc::now());
+        let payload = serde_json::to_vec(&payload).expect("serialising JSON to memory");
+        if self.queue.try_send((key.to_string(), payload)).is_err() {
+            self.status.lock().unwrap().dropped += 1;
+        }
+    }
+
+    pub fn status(&self) -> Value {
+        json!(self.status.lock().unwrap().clone())
+    }
+}
+
+// host:port and CONNECT credentials of a nats:// URL
+fn nats_address(url: &str) -> Option<(String, Value)> {
+    let rest = url.trim().strip_prefix("nats://").unwrap_or(url.trim()).trim_end_matches('/');
+    let (auth, host) = match rest.rsplit_once('@') {
+        Some((auth, host)) => (Some(auth), host),
+        None => (None, rest),
+    };
+    if host.is_empty() {
+        return None;
+    }
+    let address = if host.contains(':') { host.to_string() } else { format!("{host}:{NATS_PORT}") };
+    let credentials = match auth.map(|a| a.split_once(':')) {
+        None => json!({}),
+        Some(Some((user, pass))) => json!({ "user": user, "pass": pass }),
+        Some(None) => json!({ "auth_token": auth }),
+    };
+    Some((address, credentials))
+}
+
+async fn run(sink: Sink, mut events: mpsc::Receiver<(String, Vec<u8>)>, status: Arc<Mutex<Status>>) {
+    match sink {
+        Sink::Kafka { brokers, topic, client_id } => {
+            let mut producer = KafkaProducer { brokers, topic, client_id, partitions: 0, leaders: BTreeMap::new() };
+            while let Some(first) = events.recv().await {
+                let mut batch = vec![first];
+                while batch.len() < BATCH {
+                    match events.try_recv() {
+                        Ok(event) => batch.push(event),
+                        Err(_) => break,
+                    }
+                }
+                // One retry after fresh metadata, then the batch is dropped
+                let mut outcome = producer.send(&batch).await;
+                if let Err(e) = outcome {
+                    record_error(&status, e);
+                    producer.reset();
+                    tokio::time::sleep(RETRY).await;
+                    outcome = producer.send(&batch).await;
+                }
+                let mut status_now = status.lock().unwrap();
+                match outcome {
+                    Ok(()) => status_now.published += batch.len() as u64,
+                    Err(e) => {
+                        status_now.dropped += batch.len() as u64;
+                        drop(status_now);
+                        record_error(&status, e);
+                        producer.reset();
+                    }
+                }
+            }
+        }
+        Sink::Nats { address, subject, credentials } => {
+            let mut pending: Option<Vec<u8>> = None;
+            loop {
+                match nats_session(&address, &subject, &credentials, &mut events, &mut pending, &status).await {
+                    Ok(()) => return,
+                    Err(e) => {
+                        record_error(&status, e);
+                        tokio::time::sleep(RETRY).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct KafkaProducer {
+    brokers: Vec<String>,
+    topic: String,
+    client_id: String,
+    partitions: usize,
+    // Partitions with a leader, which keys are spread over, and each one's
+    // leader address and connection, once open
+    leaders: BTreeMap<i32, (String, Option<Connection>)>,
+}
+
+impl KafkaProducer {
+    fn reset(&mut self) {
+        self.partitions = 0;
+        self.leaders.clear();
+    }
+
+    async fn send(&mut self, batch: &[(String, Vec<u8>)]) -> KafkaResult<()> {
+        if self.partitions == 0 {
+            let metadata =
+                kafka::bootstrap(&self.brokers, &self.client_id).await?.metadata(std::slice::from_ref(&self.topic)).await?;
+            if metadata.partitions.is_empty() {
+                return Err(format!("topic {} has no partition with a leader", self.topic));
+            }
+            for (_, partition, leader) in &metadata.partitions {
+                let address = metadata.brokers.get(leader).ok_or_else(|| format!("leader {leader} is not a known broker"))?;
+                self.leaders.insert(*partition, (address.clone(), None));
+            }
+            self.partitions = metadata.partitions.len();
+        }
+        let mut by_partition: BTreeMap<i32, Vec<KeyedRecord<'_>>> = BTreeMap::new();
+        for (key, payload) in batch {
+            let partition = kafka::partition_for(key.as_bytes(), self.partitions);
+            by_partition.entry(partition).or_default().push((key.as_bytes(), payload));
+        }
+        for (partition, records) in by_partition {
+            let (address, connection) =
+                self.leaders.get_mut(&partition).ok_or_else(|| format!("{}/{partition} has no leader", self.topic))?;
+            if connection.is_none() {
+                *connection = Some(Connection::connect(address, &self.client_id).await?);
+            }
+            connection.as_mut().unwrap().produce(&self.topic, partition, &records).await?;
+        }
+        Ok(())
+    }
+}
+
+// One NATS connection: CONNECT, then PUB each event, answering the server's
+// PINGs. An event that could not be written is kept in `pending` for the
+// next session.
+async fn nats_session(
+    address: &str,
+    subject: &str,
+    credentials: &Value,
+    events: &mut mpsc::Receiver<(String, Vec<u8>)>,
+    pending: &mut Option<Vec<u8>>,
+    status: &Mutex<Status>,
+) -> Result<(), String> {
+    let stream = TcpStream::connect(address).await.map_err(|e| format!("cannot connect to {address}: {e}"))?;
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let io = |e: std::io::Error| format!("NATS connection failed: {e}");
+    match lines.next_line().await.map_err(io)? {
+        Some(info) if info.starts_with("INFO") => {}
+        other => return Err(format!("unexpected NATS greeting {other:?}")),
+    }
+    let mut connect = json!({ "verbose": false, "pedantic": false, "name": "riskvar", "lang": "rust" });
+    if let (Some(connect), Some(credentials)) = (connect.as_object_mut(), credentials.as_object()) {
+        connect.extend(credentials.clone());
+    }
+    write.write_all(format!("CONNECT {connect}\r\nPING\r\n").as_bytes()).await.map_err(io)?;
+    // The PONG to our PING confirms the CONNECT was accepted
+    match lines.next_line().await.map_err(io)? {
+        Some(line) if line.starts_with("PONG") => {}
+        Some(line) if line.starts_with("-ERR") => return Err(format!("NATS refused the connection: {line}")),
+        other => return Err(format!("unexpected NATS reply {other:?}")),
+    }
+    loop {
+        if let Some(payload) = pending.as_ref() {
+            let mut message = format!("PUB {subject} {}\r\n", payload.len()).into_bytes();
+            message.extend(payload);
+            message.extend(b"\r\n");
+            write.write_all(&message).await.map_err(io)?;
+            *pending = None;
+            status.lock().unwrap().published += 1;
+        }
+        tokio::select! {
+            event = events.recv() => match event {
+                Some((_, payload)) => *pending = Some(payload),
+                None => return Ok(()),
+            },
+            line = lines.next_line() => match line.map_err(io)? {
+                Some(line) if line.starts_with("PING") => write.write_all(b"PONG\r\n").await.map_err(io)?,
+                Some(line) if line.starts_with("-ERR") => return Err(format!("NATS error: {line}")),
+                Some(_) => {}
+                None => return Err("NATS server closed the connection".into()),
+            },
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Redis work queue for splitting the service into an API node and worker
+//! nodes. The API node pushes queued jobs onto a list per priority class and
+//! waits on a per-job reply list; workers pop jobs (interactive first), run
+//! them and push the outcome back.
+
+use redis::AsyncCommands;
+use serde_json::{json, Value};
+use std::env;
+
+use crate::{
+    jobs::{Job, Priority},
+    var::evaluate,
+};
+
+const INTERACTIVE_KEY: &str = "riskvar:jobs:interactive";
+const BATCH_KEY: &str = "riskvar:jobs:batch";
+
+fn reply_key(id: &str) -> String {
+    format!("riskvar:results:{id}")
+}
+
+pub struct RedisQueue {
+    client: redis::Client,
+    timeout_secs: f64,
+}
+
+impl RedisQueue {
+    /// Enabled when `REDIS_URL` is set; `JOB_REMOTE_TIMEOUT_SECS` (default 3600)
+    /// bounds how long the API node waits for a worker.
+    pub fn from_env() -> Option<Self> {
+        let url = env::var("REDIS_URL").ok()?;
+        let client = redis::Client::open(url.as_str())
+            .map_err(|e| eprintln!("⚠️ Invalid REDIS_URL: {}", e))
+            .ok()?;
+        let timeout_secs = env::var("JOB_REMOTE_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(3600.0);
+        println!("📮 Routing jobs to workers via {}", url);
+        Some(RedisQueue { client, timeout_secs })
+    }
+
+    /// Hands a job to a worker and waits for its result.
+    pub async fn execute(&self, job: &Job) -> Result<Value, String> {
+        let mut conn = self
+            .client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(|e| format!("redis connection failed: {e}"))?;
+        let key = match job.priority {
+            Priority::Interactive => INTERACTIVE_KEY,
+            Priority::Batch => BATCH_KEY,
+        };
+        let payload = serde_json::to_string(job).map_err(|e| e.to_string())?;
+        conn.rpush::<_, _, ()>(key, payload)
+            .await
+            .map_err(|e| format!("redis push failed: {e}"))?;
+
+        let reply: Option<(String, String)> = conn
+            .blpop(reply_key(&job.id), self.timeout_secs)
+            .await
+            .map_err(|e| format!("redis pop failed: {e}"))?;
+        let (_, reply) = reply.ok_or("timed out waiting for a worker")?;
+        let reply: Value = serde_json::from_str(&reply).map_err(|e| e.to_string())?;
+        match reply.get("error").and_then(Value::as_str) {
+            Some(err) => Err(err.to_string()),
+            None => Ok(reply["result"].clone()),
+        }
+    }
+}
+
+/// Worker loop: pops jobs and pushes their outcome to the job's reply list.
+pub async fn run_worker(queue: RedisQueue) {
+    println!("🛠️ Worker waiting for jobs");
+    loop {
+        let mut conn = match queue.client.get_multiplexed_async_connection().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                eprintln!("❌ Redis connection failed: {}", e);
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        loop {
+            let popped: Option<(String, String)> = match conn.blpop(&[INTERACTIVE_KEY, BATCH_KEY], 0.0).await {
+                Ok(popped) => popped,
+                Err(e) => {
+                    eprintln!("❌ Redis pop failed: {}", e);
+                    break;
+                }
+            };
+            let Some((_, payload)) = popped else { continue };
+            let job: Job = match serde_json::from_str(&payload) {
+                Ok(job) => job,
+                Err(e) => {
+                    eprintln!("⚠️ Dropping malformed job: {}", e);
+                    continue;
+                }
+            };
+
+            println!("🔧 Running job {} for {}", job.id, job.tenant);
+            let request = job.request.clone();
+            let outcome = tokio::task::spawn_blocking(move || evaluate(&request)).await;
+            let reply = match outcome {
+                Ok(Ok(result)) => json!({ "result": result }),
+                Ok(Err(e)) => json!({ "error": e.to_string() }),
+                Err(_) => json!({ "error": "computation panicked" }),
+            };
+            let key = reply_key(&job.id);
+            let pushed: redis::RedisResult<()> = redis::pipe()
+                .rpush(&key, reply.to_string())
+                .expire(&key, 3600)
+                .query_async(&mut conn)
+                .await;
+            if let Err(e) = pushed {
+                eprintln!("❌ Failed to publish result for {}: {}", job.id, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// Elements per chunk of a fixed-order sum. Part of the result's definition:
+// changing it changes the low bits of every deterministic figure.
+const CHUNK: usize = 1024;
+
+/// How the statistics kernels add up a series. `Sequential` is one
+/// compensated left-to-right pass. `FixedOrder` splits the series into chunks of
+/// `CHUNK` elements at fixed offsets, sums each with Neumaier compensation
+/// and combines the chunk sums pairwise in a fixed tree, so the result
+/// depends only on the input, never on how the work is split across
+/// threads, and carries far less rounding error than a plain loop.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Summation {
+    #[default]
+    Sequential,
+    FixedOrder,
+}
+
+impl Summation {
+    pub fn new(deterministic: bool) -> Self {
+        if deterministic {
+            Summation::FixedOrder
+        } else {
+            Summation::Sequential
+        }
+    }
+
+    pub fn sum(self, values: &[f64]) -> f64 {
+        match self {
+            Summation::Sequential => compensated(values),
+            Summation::FixedOrder => {
+                let chunks: Vec<(f64, f64)> = values.chunks(CHUNK).map(neumaier).collect();
+                let (sum, carry) = pairwise(&chunks);
+                sum + carry
+            }
+        }
+    }
+
+    /// Mean and population standard deviation by the corrected two-pass
+    /// algorithm: squared deviations from the compensated mean, less the
+    /// square of the deviations' own sum, which cancels what rounding the
+    /// mean left behind. Nothing subtracts large sums of squares, so a
+    /// series far from zero (prices, P&L in money) keeps its variance.
+    pub fn mean_std(self, values: &[f64]) -> (f64, f64) {
+        let n = values.len() as f64;
+        let mean = self.sum(values) / n;
+        let deviations: Vec<f64> = values.iter().map(|v| v - mean).collect();
+        let squares: Vec<f64> = deviations.iter().map(|d| d * d).collect();
+        let drift = self.sum(&deviations);
+        (mean, ((self.sum(&squares) - drift * drift / n) / n).max(0.0).sqrt())
+    }
+}
+
+// Neumaier's variant of Kahan summation, which also holds up when an
+// addend is larger than the running sum
+fn compensated(values: &[f64]) -> f64 {
+    let (sum, carry) = neumaier(values);
+    sum + carry
+}
+
+// Running sum and the rounding error it has shed
+fn neumaier(values: &[f64]) -> (f64, f64) {
+    values.iter().fold((0.0, 0.0), |acc, &v| add(acc, (v, 0.0)))
+}
+
+fn add((a, a_carry): (f64, f64), (b, b_carry): (f64, f64)) -> (f64, f64) {
+    let t = a + b;
+    let lost = if a.abs() >= b.abs() { (a - t) + b } else { (b - t) + a };
+    (t, a_carry + b_carry + lost)
+}
+
+// Chunk sums combined in a fixed binary tree, carries included
+fn pairwise(chunks: &[(f64, f64)]) -> (f64, f64) {
+    match chunks.len() {
+        0 => (0.0, 0.0),
+        1 => chunks[0],
+        n => add(pairwise(&chunks[..n / 2]), pairwise(&chunks[n / 2..])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Dyadic values (multiples of 1/4) around a large offset: every one is an
+    // exact f64, so exact references come from integer arithmetic in quarters
+    fn offset_series(n: usize, offset: f64) -> Vec<f64> {
+        let mut state: u64 = 12345;
+        (0..n)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                offset + ((state >> 33) % 41) as f64 * 0.25 - 5.0
+            })
+            .collect()
+    }
+
+    // Exact mean and population variance, as f64 only at the end
+    fn reference(values: &[f64]) -> (f64, f64) {
+        let quarters: Vec<i128> = values.iter().map(|v| (v * 4.0) as i128).collect();
+        let n = quarters.len() as i128;
+        let sum: i128 = quarters.iter().sum();
+        let sum_sq: i128 = quarters.iter().map(|q| q * q).sum();
+        // Var = (n Σq² − (Σq)²) / n² in quarters², over 16 for units
+        let var = (n * sum_sq - sum * sum) as f64 / (n * n) as f64 / 16.0;
+        (sum as f64 / n as f64 / 4.0, var)
+    }
+
+    fn relative(a: f64, b: f64) -> f64 {
+        (a - b).abs() / b.abs()
+    }
+
+    #[test]
+    fn compensated_sum_survives_cancellation() {
+        let values: Vec<f64> = (0..1000).flat_map(|_| [1e16, 1.0, -1e16]).collect();
+        assert_eq!(values.iter().sum::<f64>(), 0.0);
+        assert_eq!(Summation::Sequential.sum(&values), 1000.0);
+        assert_eq!(Summation::FixedOrder.sum(&values), 1000.0);
+    }
+
+    #[test]
+    fn sums_match_exact_reference() {
+        let values = offset_series(1_000_000, 1e6);
+        let exact = reference(&values).0 * values.len() as f64;
+        for summation in [Summation::Sequential, Summation::FixedOrder] {
+            assert!(relative(summation.sum(&values), exact) < 1e-15);
+        }
+    }
+
+    #[test]
+    fn variance_far_from_zero_matches_exact_reference() {
+        for offset in [0.0, 1e3, 1e6, 1e9] {
+            let values = offset_series(200_000, offset);
+            let (mean, var) = reference(&values);
+            for summation in [Summation::Sequential, Summation::FixedOrder] {
+                let (m, s) = summation.mean_std(&values);
+                assert!(relative(m, mean) < 1e-15, "mean at offset {offset}: {m} vs {mean}");
+                assert!(relative(s * s, var) < 1e-14, "variance at offset {offset}: {} vs {var}", s * s);
+            }
+        }
+    }
+}
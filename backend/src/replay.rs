@@ -0,0 +1,120 @@
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    cache,
+    error::ApiError,
+    jobs::JobStatus,
+    tenant::Tenant,
+    profiles::Profiled,
+    validation,
+    var::{evaluate, VarRequest},
+    version::{self, Engine},
+    AppState,
+};
+
+/// Everything needed to reproduce a computation: the full request (with the
+/// simulation seed pinned), a hash of the input series, the engine version
+/// and the result it produced.
+#[derive(Serialize, Deserialize)]
+pub struct Bundle {
+    pub engine: Engine,
+    pub created_at: DateTime<Utc>,
+    pub data_hash: String,
+    pub request: VarRequest,
+    pub result: Value,
+}
+
+impl Bundle {
+    fn new(request: VarRequest, result: Value) -> Self {
+        Bundle {
+            engine: version::current(),
+            created_at: Utc::now(),
+            data_hash: cache::digest(&request.returns),
+            request,
+            result,
+        }
+    }
+}
+
+/// Pins a seed on simulation requests that lack one, so they can be replayed.
+/// Seeds stay below 2^53 so JavaScript clients round-trip them exactly.
+pub fn pin_seed(request: &mut VarRequest) {
+    if !request.is_deterministic() {
+        request.params.seed = Some(rand::random::<u64>() >> 11);
+    }
+}
+
+fn download(bundle: Bundle) -> impl IntoResponse {
+    let filename = format!("riskvar-bundle-{}.json", &bundle.data_hash[..12]);
+    (
+        [(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{filename}\""))],
+        Json(bundle),
+    )
+}
+
+/// Run a VaR request and return it as a replay bundle
+pub async fn bundle_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Profiled(mut request): Profiled<VarRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    state.flags.check_method(&request.method, &tenant)?;
+    pin_seed(&mut request);
+    let result = tokio::task::spawn_blocking({
+        let request = request.clone();
+        move || evaluate(&request)
+    })
+    .await
+    .map_err(|_| ApiError::aborted("computation"))??;
+    Ok(download(Bundle::new(request, result)))
+}
+
+/// Replay bundle for one of the caller's finished jobs
+pub async fn job_bundle_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let job = state.jobs.owned(&id, &tenant)?;
+    match (job.status, job.result) {
+        (JobStatus::Done, Some(result)) => Ok(download(Bundle::new(job.request, result))),
+        _ => Err(ApiError::bad_request(format!("job {id} has no result to bundle"))),
+    }
+}
+
+/// Re-run a bundle and check the result matches the recorded one
+pub async fn verify_handler(Json(bundle): Json<Bundle>) -> Result<Json<Value>, ApiError> {
+    let data_hash_ok = cache::digest(&bundle.request.returns) == bundle.data_hash;
+    if !bundle.request.is_deterministic() {
+        return Err(ApiError::bad_request("bundle has no seed; simulation results cannot be replayed"));
+    }
+    validation::check(&bundle.request)?;
+    let request = bundle.request.clone();
+    let actual = tokio::task::spawn_blocking(move || evaluate(&request))
+        .await
+        .map_err(|_| ApiError::aborted("computation"))??;
+    // Results are compared on the numbers; the engine stamp is reported apart
+    let numbers = |v: &Value| {
+        let mut v = v.clone();
+        if let Some(fields) = v.as_object_mut() {
+            fields.remove("engine");
+        }
+        v
+    };
+    Ok(Json(json!({
+        "matches": data_hash_ok && numbers(&actual) == numbers(&bundle.result),
+        "data_hash_ok": data_hash_ok,
+        "engine": version::current(),
+        "bundle_engine": bundle.engine,
+        "expected": bundle.result,
+        "actual": actual,
+    })))
+}
@@ -0,0 +1,465 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use futures_util::future::join_all;
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use validator::Validate;
+
+use crate::{
+    artifacts::{Artifact, Delivery},
+    backtest::run_backtest,
+    cleaning::{self, CleanOptions},
+    error::{ApiError, VarError},
+    load_prices,
+    locale::Locale,
+    portfolios::Portfolio,
+    storage::Bar,
+    summary,
+    tenant::Tenant,
+    validation::ValidQuery,
+    var::{compute_var_es, mean_std, MethodParams},
+    version::{self, Engine},
+    AppState,
+};
+
+const WORST_SCENARIOS: usize = 10;
+
+// Query for a portfolio risk report
+#[derive(Deserialize, Validate)]
+pub struct ReportQuery {
+    #[serde(default = "default_confidence")]
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+    // Estimation window of the backtest section
+    #[serde(default = "default_window")]
+    #[validate(range(min = 2, message = "window must be at least 2"))]
+    window: usize,
+    // `json` (default), `html` (rendered through the tenant's template),
+    // `xlsx` or `csv` (positions table)
+    #[serde(default)]
+    format: Option<String>,
+    // Presentation locale (en, de, fr, ja); defaults to the tenant's
+    #[serde(default)]
+    locale: Option<String>,
+    // xlsx / csv sent inline or as a link to the stored file (default: by size)
+    #[serde(default)]
+    delivery: Delivery,
+}
+
+fn default_confidence() -> f64 {
+    0.95
+}
+
+fn default_window() -> usize {
+    100
+}
+
+#[derive(Serialize)]
+pub struct PositionRisk {
+    pub ticker: String,
+    pub value: f64,
+    pub weight: f64,
+    pub vol: f64,
+    pub standalone_var: f64,
+    // Loss of this position on the VaR scenario day; sums to the portfolio VaR
+    pub component_var: f64,
+    pub pct_of_var: f64,
+}
+
+#[derive(Serialize)]
+pub struct BacktestDay {
+    pub date: String,
+    pub var: f64,
+    pub pnl: f64,
+    pub breach: bool,
+}
+
+#[derive(Serialize)]
+pub struct BacktestSection {
+    pub window: usize,
+    pub observations: usize,
+    pub breaches: usize,
+    pub expected_breaches: f64,
+    pub days: Vec<BacktestDay>,
+}
+
+#[derive(Serialize)]
+pub struct Scenario {
+    pub date: String,
+    pub pnl: f64,
+    pub positions: BTreeMap<String, f64>,
+}
+
+/// Historical-simulation risk of a saved portfolio in money terms: every
+/// figure is a P&L of the current positions replayed over the common
+/// history of their returns.
+#[derive(Serialize)]
+pub struct RiskReport {
+    pub portfolio: String,
+    pub name: String,
+    pub version: u64,
+    pub as_of: Option<String>,
+    pub method: &'static str,
+    pub confidence: f64,
+    pub observations: usize,
+    pub gross_value: f64,
+    pub net_value: f64,
+    pub var: f64,
+    pub es: f64,
+    pub positions: Vec<PositionRisk>,
+    pub backtest: Option<BacktestSection>,
+    pub scenarios: Vec<Scenario>,
+    // The figures above in a few sentences
+    pub summary: String,
+    pub engine: Engine,
+}
+
+/// Daily returns of every ticker on the dates all of them have, oldest first.
+pub async fn aligned_returns(
+    state: &AppState,
+    tenant: &str,
+    tickers: &[String],
+) -> Result<(Vec<String>, Vec<Vec<f64>>), ApiError> {
+    let series = join_all(tickers.iter().map(|t| load_prices(state, tenant, t))).await;
+    align(tickers, series)
+}
+
+/// `aligned_returns` from the price store alone, without fetching: for
+/// books recomputed as prices stream in.
+#[cfg(feature = "streaming")]
+pub async fn stored_returns(
+    state: &AppState,
+    tickers: &[String],
+) -> Result<(Vec<String>, Vec<Vec<f64>>), ApiError> {
+    let series = join_all(tickers.iter().map(|t| state.prices.range(t, None, None))).await;
+    let series = series.into_iter().collect::<Result<Vec<_>, _>>().map_err(internal)?;
+    align(tickers, series)
+}
+
+fn align(tickers: &[String], series: Vec<Vec<Bar>>) -> Result<(Vec<String>, Vec<Vec<f64>>), ApiError> {
+    let mut by_date: Vec<BTreeMap<String, f64>> = Vec::new();
+    let mut missing = Vec::new();
+    for (ticker, bars) in tickers.iter().zip(series) {
+        let rows = cleaning::clean(&bars, &CleanOptions::default());
+        let returns: BTreeMap<String, f64> = rows.into_iter().filter_map(|r| Some((r.date, r.ret?))).collect();
+        if returns.is_empty() {
+            missing.push(ticker.as_str());
+        }
+        by_date.push(returns);
+    }
+    if !missing.is_empty() {
+        return Err(ApiError::not_found(format!("no price history for {}", missing.join(", "))));
+    }
+
+    let mut dates: BTreeSet<&String> = by_date[0].keys().collect();
+    for returns in &by_date[1..] {
+        dates.retain(|d| returns.contains_key(*d));
+    }
+    let dates: Vec<String> = dates.into_iter().cloned().collect();
+    let columns = by_date.iter().map(|returns| dates.iter().map(|d| returns[d]).collect()).collect();
+    Ok((dates, columns))
+}
+
+/// Every ticker the book's positions need, in first-seen order.
+pub fn book_tickers(book: &Portfolio) -> Vec<String> {
+    let mut tickers: Vec<String> = Vec::new();
+    for t in book.positions.iter().flat_map(|p| p.tickers()) {
+        if !tickers.iter().any(|u| u == t) {
+            tickers.push(t.to_string());
+        }
+    }
+    tickers
+}
+
+/// One return series per position from its tickers' aligned returns;
+/// spreads net their legs.
+pub fn position_returns(book: &Portfolio, tickers: &[String], ticker_returns: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = ticker_returns.first().map_or(0, Vec::len);
+    let column = |ticker: &str| tickers.iter().position(|t| t == ticker).unwrap_or_default();
+    book.positions
+        .iter()
+        .map(|p| (0..n).map(|t| p.instrument_return(|ticker| ticker_returns[column(ticker)][t])).collect())
+        .collect()
+}
+
+pub fn build_report(
+    book: &Portfolio,
+    dates: &[String],
+    returns: &[Vec<f64>],
+    confidence: f64,
+    window: usize,
+) -> Result<RiskReport, VarError> {
+    let n = dates.len();
+    let position_pnl: Vec<Vec<f64>> = book
+        .positions
+        .iter()
+        .zip(returns)
+        .map(|(p, r)| r.iter().map(|x| p.value * x).collect())
+        .collect();
+    let pnl: Vec<f64> = (0..n).map(|t| position_pnl.iter().map(|p| p[t]).sum()).collect();
+    let params = MethodParams::default();
+    let (var, es) = compute_var_es("historical", &mut pnl.clone(), confidence, &params)?;
+
+    // Day whose loss is the VaR order statistic
+    let mut order: Vec<usize> = (0..n).collect();
+    let idx = ((1.0 - confidence) * n as f64).floor() as usize;
+    let var_day = *order.select_nth_unstable_by(idx, |a, b| pnl[*a].total_cmp(&pnl[*b])).1;
+
+    let net_value: f64 = book.positions.iter().map(|p| p.value).sum();
+    let gross_value: f64 = book.positions.iter().map(|p| p.value.abs()).sum();
+    let positions = book
+        .positions
+        .iter()
+        .zip(&position_pnl)
+        .zip(returns)
+        .map(|((p, p_pnl), r)| {
+            let vol = mean_std(r).1;
+            let component_var = -p_pnl[var_day];
+            Ok(PositionRisk {
+                ticker: p.label(),
+                value: p.value,
+                weight: p.value / gross_value,
+                vol,
+                standalone_var: compute_var_es("historical", &mut p_pnl.clone(), confidence, &params)?.0,
+                component_var,
+                pct_of_var: component_var / var,
+            })
+        })
+        .collect::<Result<_, VarError>>()?;
+
+    let backtest = if n > window {
+        let bt = run_backtest("historical", &pnl, confidence, window, &params)?;
+        let breaches = bt.hits.iter().filter(|h| **h).count();
+        Some(BacktestSection {
+            window,
+            observations: bt.hits.len(),
+            breaches,
+            expected_breaches: bt.hits.len() as f64 * (1.0 - confidence),
+            days: (0..bt.hits.len())
+                .map(|i| BacktestDay {
+                    date: dates[window + i].clone(),
+                    var: bt.var[i],
+                    pnl: bt.realized[i],
+                    breach: bt.hits[i],
+                })
+                .collect(),
+        })
+    } else {
+        None
+    };
+
+    // Worst days, worst first: selected, then only those sorted
+    let worst = WORST_SCENARIOS.min(n);
+    let by_pnl = |a: &usize, b: &usize| pnl[*a].total_cmp(&pnl[*b]);
+    order.select_nth_unstable_by(worst - 1, by_pnl);
+    order[..worst].sort_by(by_pnl);
+    let scenarios = order[..worst]
+        .iter()
+        .map(|&t| Scenario {
+            date: dates[t].clone(),
+            pnl: pnl[t],
+            positions: book.positions.iter().zip(&position_pnl).map(|(p, pp)| (p.label(), pp[t])).collect(),
+        })
+        .collect();
+
+    let mut report = RiskReport {
+        portfolio: book.id.clone(),
+        name: book.name.clone(),
+        version: book.version,
+        as_of: dates.last().cloned(),
+        method: "historical",
+        confidence,
+        observations: n,
+        gross_value,
+        net_value,
+        var,
+        es,
+        positions,
+        backtest,
+        scenarios,
+        summary: String::new(),
+        engine: version::current(),
+    };
+    report.summary = summary::report_summary(&report);
+    Ok(report)
+}
+
+fn header_row(sheet: &mut Worksheet, keys: &[&str], locale: Locale, bold: &Format) -> Result<(), XlsxError> {
+    sheet.write_row_with_format(0, 0, keys.iter().map(|k| locale.label(k)), bold)?;
+    Ok(())
+}
+
+/// Workbook with Summary, Positions, Backtest and Scenarios tabs. Labels,
+/// sheet names and dates follow `locale`; numbers stay numeric so Excel
+/// renders them in the reader's own format.
+pub fn to_xlsx(report: &RiskReport, locale: Locale) -> Result<Vec<u8>, XlsxError> {
+    let bold = Format::new().set_bold();
+    let money = Format::new().set_num_format("#,##0.00");
+    let pct = Format::new().set_num_format("0.00%");
+    let mut workbook = Workbook::new();
+
+    let sheet = workbook.add_worksheet().set_name(locale.label("summary"))?;
+    let summary: [(&str, String); 6] = [
+        ("portfolio", report.name.clone()),
+        ("portfolio_id", report.portfolio.clone()),
+        ("as_of", report.as_of.as_deref().map(|d| locale.date(d)).unwrap_or_default()),
+        ("method", report.method.to_string()),
+        (
+            "engine",
+            format!("{} ({} {})", report.engine.version, locale.label("methodology"), report.engine.methodology),
+        ),
+        ("observations", report.observations.to_string()),
+    ];
+    for (row, (key, value)) in summary.iter().enumerate() {
+        sheet.write_string_with_format(row as u32, 0, locale.label(key), &bold)?;
+        sheet.write_string(row as u32, 1, value)?;
+    }
+    let figures = [
+        ("confidence", report.confidence, &pct),
+        ("gross_value", report.gross_value, &money),
+        ("net_value", report.net_value, &money),
+        ("var", report.var, &money),
+        ("es", report.es, &money),
+    ];
+    for (i, (key, value, format)) in figures.iter().enumerate() {
+        let row = (summary.len() + i) as u32;
+        sheet.write_string_with_format(row, 0, locale.label(key), &bold)?;
+        sheet.write_number_with_format(row, 1, *value, format)?;
+    }
+    sheet.write_string((summary.len() + figures.len() + 1) as u32, 0, locale.label("disclaimer"))?;
+    sheet.autofit();
+
+    let sheet = workbook.add_worksheet().set_name(locale.label("positions"))?;
+    let keys = ["ticker", "value", "weight", "vol", "standalone_var", "component_var", "pct_of_var"];
+    header_row(sheet, &keys, locale, &bold)?;
+    for (i, p) in report.positions.iter().enumerate() {
+        let row = i as u32 + 1;
+        sheet.write_string(row, 0, &p.ticker)?;
+        sheet.write_number_with_format(row, 1, p.value, &money)?;
+        sheet.write_number_with_format(row, 2, p.weight, &pct)?;
+        sheet.write_number_with_format(row, 3, p.vol, &pct)?;
+        sheet.write_number_with_format(row, 4, p.standalone_var, &money)?;
+        sheet.write_number_with_format(row, 5, p.component_var, &money)?;
+        sheet.write_number_with_format(row, 6, p.pct_of_var, &pct)?;
+    }
+    sheet.autofit();
+
+    let sheet = workbook.add_worksheet().set_name(locale.label("backtest"))?;
+    match &report.backtest {
+        Some(bt) => {
+            header_row(sheet, &["date", "var", "pnl", "breach"], locale, &bold)?;
+            for (i, day) in bt.days.iter().enumerate() {
+                let row = i as u32 + 1;
+                sheet.write_string(row, 0, locale.date(&day.date))?;
+                sheet.write_number_with_format(row, 1, day.var, &money)?;
+                sheet.write_number_with_format(row, 2, day.pnl, &money)?;
+                sheet.write_boolean(row, 3, day.breach)?;
+            }
+            let row = bt.days.len() as u32 + 2;
+            sheet.write_string_with_format(row, 0, locale.label("breaches"), &bold)?;
+            sheet.write_number(row, 1, bt.breaches as f64)?;
+            sheet.write_string_with_format(row + 1, 0, locale.label("expected"), &bold)?;
+            sheet.write_number(row + 1, 1, bt.expected_breaches)?;
+        }
+        None => {
+            sheet.write_string(0, 0, locale.label("no_backtest"))?;
+        }
+    }
+    sheet.autofit();
+
+    let sheet = workbook.add_worksheet().set_name(locale.label("scenarios"))?;
+    sheet.write_row_with_format(
+        0,
+        0,
+        [locale.label("date"), locale.label("portfolio_pnl")]
+            .into_iter()
+            .chain(report.positions.iter().map(|p| p.ticker.as_str())),
+        &bold,
+    )?;
+    for (i, s) in report.scenarios.iter().enumerate() {
+        let row = i as u32 + 1;
+        sheet.write_string(row, 0, locale.date(&s.date))?;
+        sheet.write_number_with_format(row, 1, s.pnl, &money)?;
+        for (j, p) in report.positions.iter().enumerate() {
+            sheet.write_number_with_format(row, j as u16 + 2, s.positions[&p.ticker], &money)?;
+        }
+    }
+    sheet.autofit();
+
+    workbook.save_to_buffer()
+}
+
+/// Per-position table as CSV, with the locale's labels, decimal separator and
+/// delimiter (`;` where the decimal separator is a comma).
+pub fn to_csv(report: &RiskReport, locale: Locale) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::WriterBuilder::new().delimiter(locale.csv_delimiter()).from_writer(Vec::new());
+    let keys = ["ticker", "value", "weight", "vol", "standalone_var", "component_var", "pct_of_var"];
+    writer.write_record(keys.iter().map(|k| locale.label(k)))?;
+    for p in &report.positions {
+        writer.write_record([
+            p.ticker.clone(),
+            locale.plain(p.value, 2),
+            locale.plain(p.weight, 6),
+            locale.plain(p.vol, 6),
+            locale.plain(p.standalone_var, 2),
+            locale.plain(p.component_var, 2),
+            locale.plain(p.pct_of_var, 6),
+        ])?;
+    }
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+fn internal(e: impl ToString) -> ApiError {
+    ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// Risk report for a saved portfolio, as JSON or an Excel workbook; large
+/// workbooks come back as a download link
+pub async fn portfolio_report_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+    ValidQuery(q): ValidQuery<ReportQuery>,
+) -> Result<Response, ApiError> {
+    let book = state.portfolios.get(&tenant, &id)?;
+    let locale = state.locales.resolve(&tenant, q.locale.as_deref())?;
+    if book.positions.is_empty() {
+        return Err(ApiError::bad_request(format!("portfolio {id} has no positions")));
+    }
+    let tickers = book_tickers(&book);
+    let (dates, ticker_returns) = aligned_returns(&state, &tenant, &tickers).await?;
+    let returns = position_returns(&book, &tickers, &ticker_returns);
+    if dates.len() < 2 {
+        return Err(ApiError::bad_request("not enough overlapping history across the positions"));
+    }
+    let report = build_report(&book, &dates, &returns, q.confidence, q.window.max(2))?;
+
+    match q.format.as_deref() {
+        None | Some("json") => Ok(Json(report).into_response()),
+        Some("html") => {
+            let html = state
+                .templates
+                .render(&tenant, &report, locale)
+                .map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, format!("template failed to render: {e}")))?;
+            Ok(Html(html).into_response())
+        }
+        Some("xlsx") => {
+            let bytes = to_xlsx(&report, locale).map_err(internal)?;
+            let filename = format!("risk-report-{}-{}.xlsx", report.portfolio, report.as_of.as_deref().unwrap_or(""));
+            let content_type = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+            state.artifacts.deliver(q.delivery, Artifact::new(filename, content_type, bytes), Vec::new()).await
+        }
+        Some("csv") => {
+            let bytes = to_csv(&report, locale).map_err(internal)?;
+            let filename = format!("risk-positions-{}-{}.csv", report.portfolio, report.as_of.as_deref().unwrap_or(""));
+            let artifact = Artifact::new(filename, "text/csv; charset=utf-8", bytes);
+            state.artifacts.deliver(q.delivery, artifact, Vec::new()).await
+        }
+        Some(other) => Err(ApiError::bad_request(format!("unknown format {other}; use json, html, xlsx or csv"))),
+    }
+}
@@ -0,0 +1,461 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Response,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::Validate;
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    sync::Mutex,
+};
+
+use crate::{
+    backtest::run_backtest,
+    cancel,
+    error::{ApiError, VarError},
+    load_prices,
+    storage::{Bar, PriceStore},
+    stream,
+    tenant::Tenant,
+    units::{self, Units},
+    validation::{self, Valid, ValidQuery},
+    var::{compute_var_es, MethodParams},
+    version::{self, Engine},
+    AppState,
+};
+
+/// Windowed return statistics for one ticker, updated bar by bar: mean and
+/// variance via Welford add/remove, quantiles from a sorted copy of the window.
+pub struct RollingStats {
+    window: usize,
+    last: Option<Bar>,
+    returns: VecDeque<f64>,
+    sorted: Vec<f64>,
+    mean: f64,
+    m2: f64,
+}
+
+#[derive(Serialize)]
+pub struct StatsSnapshot {
+    pub window: usize,
+    pub count: usize,
+    pub last_ts: Option<String>,
+    pub last_return: Option<f64>,
+    pub mean: f64,
+    pub std: f64,
+    pub confidence: f64,
+    pub var: Option<f64>,
+    pub engine: Engine,
+}
+
+impl RollingStats {
+    fn new(window: usize) -> Self {
+        RollingStats {
+            window,
+            last: None,
+            returns: VecDeque::with_capacity(window + 1),
+            sorted: Vec::with_capacity(window + 1),
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    /// Appends bars newer than the last one seen, skipping those without a
+    /// usable close. Returns false, leaving the state untouched, if any bar
+    /// is not strictly newer (a backfill).
+    fn extend(&mut self, bars: &[Bar]) -> bool {
+        let mut bars = bars.to_vec();
+        bars.sort_by(|a, b| a.0.cmp(&b.0));
+        if let (Some((last_ts, _)), Some((first_ts, _))) = (&self.last, bars.first()) {
+            if first_ts <= last_ts {
+                return false;
+            }
+        }
+        for (ts, close) in bars.into_iter().filter(|(_, close)| usable(*close)) {
+            if let Some((_, prev)) = self.last {
+                self.add((close - prev) / prev);
+            }
+            self.last = Some((ts, close));
+        }
+        true
+    }
+
+    fn add(&mut self, r: f64) {
+        self.returns.push_back(r);
+        let n = self.returns.len() as f64;
+        let delta = r - self.mean;
+        self.mean += delta / n;
+        self.m2 += delta * (r - self.mean);
+        let pos = self.sorted.partition_point(|x| *x < r);
+        self.sorted.insert(pos, r);
+
+        if self.returns.len() > self.window {
+            let old = self.returns.pop_front().unwrap();
+            self.remove(old);
+        }
+    }
+
+    fn remove(&mut self, r: f64) {
+        let n = self.returns.len() as f64;
+        let old_mean = self.mean;
+        self.mean -= (r - old_mean) / n;
+        self.m2 = (self.m2 - (r - old_mean) * (r - self.mean)).max(0.0);
+        let pos = self.sorted.partition_point(|x| *x < r);
+        self.sorted.remove(pos);
+    }
+
+    pub fn snapshot(&self, confidence: f64) -> StatsSnapshot {
+        let count = self.returns.len();
+        let std = if count > 0 { (self.m2 / count as f64).sqrt() } else { 0.0 };
+        let idx = ((1.0 - confidence) * count as f64).floor() as usize;
+        StatsSnapshot {
+            window: self.window,
+            count,
+            last_ts: self.last.as_ref().map(|(ts, _)| ts.clone()),
+            last_return: self.returns.back().copied(),
+            mean: self.mean,
+            std,
+            confidence,
+            var: self.sorted.get(idx).map(|q| -q),
+            engine: version::current(),
+        }
+    }
+}
+
+// A zero, negative or non-finite close has no return to or from it (and a
+// NaN would break the sorted window's order)
+fn usable(close: f64) -> bool {
+    close.is_finite() && close > 0.0
+}
+
+/// Returns between consecutive usable closes: a bar without one is left out
+/// and the return spans it. Each return is dated by the bar it ends on.
+fn bar_returns(bars: &mut Vec<Bar>) -> Vec<f64> {
+    bars.retain(|(_, close)| usable(*close));
+    bars.windows(2).map(|w| (w[1].1 - w[0].1) / w[0].1).collect()
+}
+
+/// Rolling statistics for every stored ticker, kept in step with the price store.
+pub struct RollingIndex {
+    window: usize,
+    stats: Mutex<HashMap<String, RollingStats>>,
+}
+
+impl RollingIndex {
+    /// Window length from `ROLLING_WINDOW` (default 250 observations).
+    pub fn from_env() -> Self {
+        let window = env::var("ROLLING_WINDOW")
+            .ok()
+            .and_then(|w| w.parse().ok())
+            .unwrap_or(250usize)
+            .max(1);
+        RollingIndex { window, stats: Mutex::new(HashMap::new()) }
+    }
+
+    /// Folds newly stored bars into the ticker's state, rebuilding from the
+    /// store when the bars are a backfill or the ticker has not been seen.
+    pub async fn ingest(&self, store: &dyn PriceStore, ticker: &str, bars: &[Bar]) {
+        let updated = match self.stats.lock().unwrap().get_mut(ticker) {
+            Some(stats) => stats.extend(bars),
+            None => false,
+        };
+        if updated {
+            return;
+        }
+        match store.range(ticker, None, None).await {
+            Ok(series) => {
+                let tail = &series[series.len().saturating_sub(self.window + 1)..];
+                let mut stats = RollingStats::new(self.window);
+                stats.extend(tail);
+                self.stats.lock().unwrap().insert(ticker.to_string(), stats);
+            }
+            Err(e) => eprintln!("⚠️ Cannot rebuild rolling stats for {}: {}", ticker, e),
+        }
+    }
+
+    pub fn snapshot(&self, ticker: &str, confidence: f64) -> Option<StatsSnapshot> {
+        self.stats.lock().unwrap().get(ticker).map(|s| s.snapshot(confidence))
+    }
+}
+
+// Query for rolling stats
+#[derive(Deserialize, Validate)]
+pub struct StatsQuery {
+    #[serde(default = "default_confidence")]
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+}
+
+fn default_confidence() -> f64 {
+    0.95
+}
+
+/// Rolling mean/std/historical VaR from precomputed state
+pub async fn stats_handler(
+    State(state): State<AppState>,
+    Path(ticker): Path<String>,
+    ValidQuery(q): ValidQuery<StatsQuery>,
+) -> Result<Json<StatsSnapshot>, ApiError> {
+    let ticker = ticker.to_uppercase();
+    if state.rolling.snapshot(&ticker, q.confidence).is_none() {
+        state.rolling.ingest(&*state.prices, &ticker, &[]).await;
+    }
+    state
+        .rolling
+        .snapshot(&ticker, q.confidence)
+        .filter(|s| s.count > 0)
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found(format!("no stored prices for {ticker}")))
+}
+
+/// Rolling (VaR, std) for every full window of `returns`, oldest first.
+pub fn rolling_history(returns: &[f64], window: usize, confidence: f64) -> Vec<(f64, f64)> {
+    let mut stats = RollingStats::new(window);
+    let mut history = Vec::new();
+    for &r in returns {
+        stats.add(r);
+        if stats.returns.len() == window {
+            let snap = stats.snapshot(confidence);
+            history.push((snap.var.unwrap_or(0.0), snap.std));
+        }
+    }
+    history
+}
+
+// Where the latest value sits within its own history
+#[derive(Serialize)]
+pub struct Rank {
+    pub current: f64,
+    pub percentile: f64,
+    pub min: f64,
+    pub median: f64,
+    pub max: f64,
+}
+
+fn rank(values: &[f64]) -> Option<Rank> {
+    let current = *values.last()?;
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let at_or_below = sorted.partition_point(|v| *v <= current);
+    Some(Rank {
+        current,
+        percentile: 100.0 * at_or_below as f64 / sorted.len() as f64,
+        min: sorted[0],
+        median: sorted[sorted.len() / 2],
+        max: sorted[sorted.len() - 1],
+    })
+}
+
+// Options for ranking current risk against its history
+#[derive(Deserialize, Validate)]
+pub struct RankQuery {
+    #[serde(default = "default_confidence")]
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+    #[serde(default = "default_rank_window")]
+    #[validate(range(min = 2, message = "window must be at least 2"))]
+    window: usize,
+    // Most recent rolling values to rank against (default: all)
+    #[serde(default)]
+    #[validate(range(min = 1, message = "lookback must be at least 1"))]
+    lookback: Option<usize>,
+}
+
+fn default_rank_window() -> usize {
+    60
+}
+
+// Ranking a client-supplied return series (e.g. portfolio P&L)
+#[derive(Deserialize, Validate)]
+pub struct RankSeries {
+    #[validate(custom(function = "validation::finite"))]
+    returns: Vec<f64>,
+    #[serde(default)]
+    units: Units,
+    #[serde(flatten)]
+    #[validate(nested)]
+    options: RankQuery,
+}
+
+fn rank_returns(returns: &[f64], q: &RankQuery) -> Result<Value, ApiError> {
+    let window = q.window.max(2);
+    let mut history = rolling_history(returns, window, q.confidence);
+    if let Some(lookback) = q.lookback {
+        history.drain(..history.len().saturating_sub(lookback));
+    }
+    let vars: Vec<f64> = history.iter().map(|(v, _)| *v).collect();
+    let vols: Vec<f64> = history.iter().map(|(_, s)| *s).collect();
+    let (Some(var), Some(vol)) = (rank(&vars), rank(&vols)) else {
+        return Err(ApiError::bad_request(format!(
+            "need at least {window} returns to rank, got {}",
+            returns.len()
+        )));
+    };
+    Ok(json!({
+        "window": window,
+        "confidence": q.confidence,
+        "history_points": history.len(),
+        "var": var,
+        "vol": vol,
+        "engine": version::current(),
+    }))
+}
+
+/// Percentile of a ticker's current rolling VaR/vol within its own history
+pub async fn rank_ticker_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(ticker): Path<String>,
+    ValidQuery(q): ValidQuery<RankQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let ticker = ticker.to_uppercase();
+    let mut bars = state
+        .prices
+        .range(&ticker, None, None)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    if bars.len() < 2 {
+        bars = load_prices(&state, &tenant, &ticker).await;
+    }
+    let returns = bar_returns(&mut bars);
+
+    let mut body = rank_returns(&returns, &q)?;
+    body["ticker"] = json!(ticker);
+    body["as_of"] = json!(bars.last().map(|(ts, _)| ts));
+    Ok(Json(body))
+}
+
+/// Percentile of the current rolling VaR/vol of an arbitrary return series
+pub async fn rank_series_handler(Valid(mut payload): Valid<RankSeries>) -> Result<Json<Value>, ApiError> {
+    let (units, warnings) = units::normalize(&mut payload.returns, payload.units);
+    let mut body = rank_returns(&payload.returns, &payload.options)?;
+    body["units"] = json!(units);
+    body["warnings"] = json!(warnings);
+    Ok(Json(body))
+}
+
+// Options for a ticker's rolling VaR series
+#[derive(Deserialize, Validate)]
+pub struct RollingVarQuery {
+    #[serde(default = "default_method")]
+    #[validate(custom(function = "validation::known_method"))]
+    method: String,
+    #[serde(default = "default_confidence")]
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+    #[serde(default = "default_var_window")]
+    #[validate(range(min = 2, message = "window must be at least 2"))]
+    window: usize,
+}
+
+fn default_method() -> String {
+    "historical".into()
+}
+
+fn default_var_window() -> usize {
+    250
+}
+
+/// A ticker's VaR through its whole stored history: for every day after the
+/// first `window` returns, the VaR and ES forecast from the `window` returns
+/// before it, dated and set against the return realised that day, then the
+/// forecast for the day after the last bar.
+pub async fn rolling_var_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(ticker): Path<String>,
+    ValidQuery(q): ValidQuery<RollingVarQuery>,
+) -> Result<Response, ApiError> {
+    state.flags.check_method(&q.method, &tenant)?;
+    let ticker = ticker.to_uppercase();
+    let mut bars = state
+        .prices
+        .range(&ticker, None, None)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    if bars.len() < 2 {
+        bars = load_prices(&state, &tenant, &ticker).await;
+    }
+    let returns = bar_returns(&mut bars);
+    if returns.len() < q.window {
+        return Err(ApiError::bad_request(format!(
+            "need at least window={} returns for {ticker}, got {}",
+            q.window,
+            returns.len()
+        )));
+    }
+
+    let params = MethodParams::default();
+    let cancel = params.cancel.clone();
+    let outcome = cancel::spawn_blocking(&cancel, move || {
+        let bt = run_backtest(&q.method, &returns, q.confidence, q.window, &params)?;
+        let mut last_window = returns[returns.len() - q.window..].to_vec();
+        let latest = compute_var_es(&q.method, &mut last_window, q.confidence, &params)?;
+        Ok::<_, VarError>((q, bt, latest))
+    })
+    .await;
+    let (q, bt, (var, es)) = outcome.map_err(|_| ApiError::aborted("rolling VaR"))??;
+
+    let trailer = stream::trailer([
+        ("ticker", json!(ticker)),
+        ("method", json!(q.method)),
+        ("confidence", json!(q.confidence)),
+        ("window", json!(q.window)),
+        ("points", json!(bt.var.len())),
+        ("breaches", json!(bt.hits.iter().filter(|h| **h).count())),
+        ("next", json!({ "after": bars.last().map(|(ts, _)| ts), "var": var, "es": es })),
+        ("engine", json!(version::current())),
+    ]);
+    // Returns are dated by the bar they end on; each point is serialised
+    // as the client reads the body
+    let series = (0..bt.var.len()).map(move |i| {
+        json!({
+            "date": bars[q.window + i + 1].0,
+            "var": bt.var[i],
+            "es": bt.es[i],
+            "realized": bt.realized[i],
+            "breach": bt.hits[i],
+        })
+    });
+    Ok(stream::json_array_stream("series", series, trailer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bars(closes: &[f64]) -> Vec<Bar> {
+        closes.iter().enumerate().map(|(i, close)| (format!("2024-01-{:02}", i + 1), *close)).collect()
+    }
+
+    #[test]
+    fn bars_without_a_usable_close_are_skipped() {
+        let closes = [100.0, 101.0, 0.0, 99.0, f64::NAN, 102.0, -5.0, 98.0, 103.0, 97.0, 104.0];
+        let mut stats = RollingStats::new(4);
+        assert!(stats.extend(&bars(&closes)));
+        let kept: Vec<f64> = closes.iter().copied().filter(|c| usable(*c)).collect();
+        let returns: Vec<f64> = kept.windows(2).map(|w| (w[1] - w[0]) / w[0]).collect();
+        let window = &returns[returns.len() - 4..];
+        assert_eq!(stats.returns.iter().copied().collect::<Vec<_>>(), window);
+        // The window slid over every skipped bar without losing its order
+        let mut sorted = window.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        assert_eq!(stats.sorted, sorted);
+        let snapshot = stats.snapshot(0.75);
+        assert_eq!(snapshot.var, Some(-sorted[1]));
+        assert!(snapshot.mean.is_finite() && snapshot.std.is_finite());
+    }
+
+    #[test]
+    fn stored_returns_span_a_bad_bar() {
+        let mut series = bars(&[100.0, 0.0, 110.0, 121.0]);
+        let returns = bar_returns(&mut series);
+        let dates: Vec<&str> = series.iter().map(|(ts, _)| ts.as_str()).collect();
+        assert_eq!(dates, ["2024-01-01", "2024-01-03", "2024-01-04"]);
+        assert!((returns[0] - 0.1).abs() < 1e-12 && (returns[1] - 0.1).abs() < 1e-12);
+    }
+}
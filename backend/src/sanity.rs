@@ -0,0 +1,83 @@
+use serde::Serialize;
+
+// Below this a daily VaR is implausible for anything but cash
+const MIN_VAR_FRACTION: f64 = 0.0001;
+
+/// Post-computation check that failed: `code` is stable for clients to
+/// match on, `value` is the offending figure.
+#[derive(Serialize)]
+pub struct SanityWarning {
+    pub code: &'static str,
+    pub message: String,
+    pub value: f64,
+}
+
+/// Order-of-magnitude checks on a result, with VaR and ES as fractions of
+/// the position's value. The numbers are still returned; the warnings say
+/// why they shouldn't be trusted as they stand.
+pub fn check(var_fraction: f64, es_fraction: Option<f64>) -> Vec<SanityWarning> {
+    let mut warnings = Vec::new();
+    if !var_fraction.is_finite() {
+        warnings.push(SanityWarning {
+            code: "var_not_finite",
+            message: "VaR is not a finite number; the input series is degenerate".into(),
+            value: var_fraction,
+        });
+        return warnings;
+    }
+    if var_fraction > 1.0 {
+        warnings.push(SanityWarning {
+            code: "var_exceeds_value",
+            message: format!(
+                "VaR is {:.0}% of the position's value, more than an unlevered position can lose; check the units",
+                var_fraction * 100.0
+            ),
+            value: var_fraction,
+        });
+    } else if var_fraction < MIN_VAR_FRACTION {
+        warnings.push(SanityWarning {
+            code: "var_below_1bp",
+            message: format!(
+                "VaR is {:.2}bp of the position's value, implausibly small for anything but cash; check for a flat or stale price series",
+                var_fraction * 10_000.0
+            ),
+            value: var_fraction,
+        });
+    }
+    if let Some(es) = es_fraction.filter(|es| *es < var_fraction * (1.0 - 1e-9)) {
+        warnings.push(SanityWarning {
+            code: "es_below_var",
+            message: "ES is below VaR although it averages the losses beyond it".into(),
+            value: es,
+        });
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::var::{evaluate, VarRequest};
+
+    fn codes(var: f64, es: Option<f64>) -> Vec<&'static str> {
+        check(var, es).iter().map(|w| w.code).collect()
+    }
+
+    #[test]
+    fn a_var_under_a_basis_point_is_flagged() {
+        assert_eq!(codes(0.00005, Some(0.00006)), ["var_below_1bp"]);
+        assert!(codes(0.0001, Some(0.00012)).is_empty());
+        assert!(codes(0.02, Some(0.03)).is_empty());
+        assert_eq!(codes(0.00005, Some(0.00004)), ["var_below_1bp", "es_below_var"]);
+    }
+
+    #[test]
+    fn a_near_flat_series_carries_the_warning() {
+        let returns: Vec<f64> = (0..100).map(|i| 1e-6 * ((i % 5) as f64 - 2.0)).collect();
+        let spec = serde_json::json!({ "method": "historical", "returns": returns, "confidence": 0.95 });
+        let request: VarRequest = serde_json::from_value(spec).unwrap();
+        let body = evaluate(&request).unwrap();
+        assert_eq!(body["sanity"][0]["code"], "var_below_1bp");
+        assert_eq!(body["sanity"][0]["value"], body["var"]);
+    }
+}
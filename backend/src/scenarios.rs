@@ -0,0 +1,883 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::Response,
+    Json,
+};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use rand_distr::{ChiSquared, Distribution, Normal as Gaussian, Poisson, StandardNormal};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use statrs::distribution::{ContinuousCDF, Normal as NormalCdf, StudentsT};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    arrow::{self, Column},
+    artifacts::{Artifact, Delivery},
+    budget,
+    cancel::{self, Cancel},
+    covariance::{cholesky, correlation_matrix, covariance_matrix},
+    error::{ApiError, VarError},
+    garch,
+    measures::{self, Spectrum},
+    reduce::Summation,
+    stream,
+    validation::{self, cross_field, Valid},
+    var::MethodParams,
+    version, AppState,
+};
+
+// Returns beyond this many standard deviations count as jumps when the
+// jump-diffusion's jump parameters are estimated
+const DEFAULT_JUMP_THRESHOLD: f64 = 3.0;
+// Most jumps per factor per day a jump-diffusion may be given: every jump
+// is a draw, so the intensity sets the work per path
+const MAX_JUMP_INTENSITY: f64 = 10.0;
+const DEFAULT_PATHS: usize = 10_000;
+// Cap on paths × factors of an exported scenario set
+const MAX_EXPORT_VALUES: usize = 10_000_000;
+// Bytes of a scenario value as JSON text, about, for deciding before the
+// set is drawn whether it is sent inline
+const JSON_VALUE_BYTES: usize = 24;
+// Paths per parallel work unit. Fixed, so a seed gives the same scenarios
+// whatever the number of threads.
+pub const CHUNK_PATHS: usize = 8_192;
+
+/// Source of one-day scenarios for a set of factors. Generators only draw;
+/// what is measured on the draws is up to the caller, so any risk measure
+/// runs over any generator.
+pub trait ScenarioGenerator: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn factors(&self) -> usize;
+
+    /// Fills `out` (one entry per factor) with one scenario's returns.
+    fn draw(&self, rng: &mut dyn RngCore, out: &mut [f64]);
+
+    /// `paths` scenarios, one row of factor returns each, checking `cancel`
+    /// every `CHUNK_PATHS` rows.
+    fn scenarios(&self, paths: usize, rng: &mut dyn RngCore, cancel: &Cancel) -> Result<Vec<Vec<f64>>, VarError> {
+        (0..paths)
+            .map(|path| {
+                if path % CHUNK_PATHS == 0 {
+                    cancel.check()?;
+                }
+                let mut row = vec![0.0; self.factors()];
+                self.draw(rng, &mut row);
+                Ok(row)
+            })
+            .collect()
+    }
+
+    /// P&L of a book with `weights` on the factors in each of `paths` scenarios.
+    fn portfolio(&self, weights: &[f64], paths: usize, rng: &mut dyn RngCore) -> Vec<f64> {
+        let mut row = vec![0.0; self.factors()];
+        (0..paths)
+            .map(|_| {
+                self.draw(rng, &mut row);
+                row.iter().zip(weights).map(|(r, w)| r * w).sum()
+            })
+            .collect()
+    }
+
+    /// `portfolio` across the rayon pool: the paths are split into fixed
+    /// chunks, chunk i drawn from ChaCha stream i of `seed`. Chunks not yet
+    /// started when `cancel` fires are skipped.
+    fn par_portfolio(&self, weights: &[f64], paths: usize, seed: u64, cancel: &Cancel) -> Result<Vec<f64>, VarError> {
+        let pnl = (0..paths.div_ceil(CHUNK_PATHS))
+            .into_par_iter()
+            .flat_map_iter(|chunk| {
+                if cancel.is_cancelled() {
+                    return Vec::new();
+                }
+                let mut rng = ChaCha12Rng::seed_from_u64(seed);
+                rng.set_stream(chunk as u64);
+                self.portfolio(weights, CHUNK_PATHS.min(paths - chunk * CHUNK_PATHS), &mut rng)
+            })
+            .collect();
+        cancel.check()?;
+        Ok(pnl)
+    }
+}
+
+// k correlated standard normals: L·z
+fn correlated(chol: &[Vec<f64>], rng: &mut dyn RngCore, out: &mut [f64]) {
+    let z: Vec<f64> = (0..chol.len()).map(|_| StandardNormal.sample(rng)).collect();
+    for (i, row) in chol.iter().enumerate() {
+        out[i] = row[..=i].iter().zip(&z).map(|(l, z)| l * z).sum();
+    }
+}
+
+/// Multivariate normal at the sample's means and covariance.
+pub struct Normal {
+    mean: Vec<f64>,
+    chol: Vec<Vec<f64>>,
+}
+
+impl Normal {
+    /// One factor: draws mean + std·z, as `rand_distr::Normal` does.
+    pub fn univariate(mean: f64, std: f64) -> Self {
+        Normal { mean: vec![mean], chol: vec![vec![std]] }
+    }
+
+    pub fn fit(columns: &[Vec<f64>], sum: Summation) -> Self {
+        let n = columns[0].len() as f64;
+        let mean = columns.iter().map(|c| sum.sum(c) / n).collect();
+        Normal { mean, chol: cholesky(&covariance_matrix(columns, sum)) }
+    }
+}
+
+impl ScenarioGenerator for Normal {
+    fn name(&self) -> &'static str {
+        "normal"
+    }
+
+    fn factors(&self) -> usize {
+        self.mean.len()
+    }
+
+    fn draw(&self, rng: &mut dyn RngCore, out: &mut [f64]) {
+        correlated(&self.chol, rng, out);
+        for (o, m) in out.iter_mut().zip(&self.mean) {
+            *o += m;
+        }
+    }
+}
+
+/// Historical days drawn with replacement, every factor from the same day.
+pub struct Bootstrap {
+    days: Vec<Vec<f64>>,
+}
+
+impl Bootstrap {
+    pub fn fit(columns: &[Vec<f64>]) -> Self {
+        Bootstrap { days: (0..columns[0].len()).map(|t| columns.iter().map(|c| c[t]).collect()).collect() }
+    }
+}
+
+impl ScenarioGenerator for Bootstrap {
+    fn name(&self) -> &'static str {
+        "bootstrap"
+    }
+
+    fn factors(&self) -> usize {
+        self.days[0].len()
+    }
+
+    fn draw(&self, rng: &mut dyn RngCore, out: &mut [f64]) {
+        out.copy_from_slice(&self.days[rng.gen_range(0..self.days.len())]);
+    }
+}
+
+/// Normal at each factor's GARCH(1,1) next-day volatility forecast, the
+/// factors correlated as their standardised residuals are.
+pub struct Garch {
+    mean: Vec<f64>,
+    volatility: Vec<f64>,
+    chol: Vec<Vec<f64>>,
+}
+
+impl Garch {
+    pub fn fit(columns: &[Vec<f64>], params: &MethodParams) -> Self {
+        let (mut mean, mut volatility, mut residuals) = (Vec::new(), Vec::new(), Vec::new());
+        for column in columns {
+            let fit = garch::fit(column, params);
+            let (path, forecast) = fit.volatilities(column, params);
+            let standardised = column.iter().zip(&path).map(|(r, s)| if *s > 0.0 { (r - fit.mean) / s } else { 0.0 });
+            residuals.push(standardised.collect());
+            mean.push(fit.mean);
+            volatility.push(forecast);
+        }
+        let correlation = correlation_matrix(&covariance_matrix(&residuals, params.summation()));
+        Garch { mean, volatility, chol: cholesky(&correlation) }
+    }
+}
+
+impl ScenarioGenerator for Garch {
+    fn name(&self) -> &'static str {
+        "garch"
+    }
+
+    fn factors(&self) -> usize {
+        self.mean.len()
+    }
+
+    fn draw(&self, rng: &mut dyn RngCore, out: &mut [f64]) {
+        correlated(&self.chol, rng, out);
+        for ((o, m), s) in out.iter_mut().zip(&self.mean).zip(&self.volatility) {
+            *o = m + s * *o;
+        }
+    }
+}
+
+// One factor's jumps: Poisson arrivals of normal sizes; no arrivals when
+// its intensity is zero
+struct Jumps {
+    arrivals: Option<Poisson<f64>>,
+    size: Gaussian<f64>,
+}
+
+impl Jumps {
+    fn new(intensity: f64, mean: f64, std: f64) -> Result<Self, VarError> {
+        let invalid = |e: &dyn std::fmt::Display| VarError::InvalidInput(format!("jump distribution: {e}"));
+        let arrivals = if intensity > 0.0 { Some(Poisson::new(intensity).map_err(|e| invalid(&e))?) } else { None };
+        Ok(Jumps { arrivals, size: Gaussian::new(mean, std).map_err(|e| invalid(&e))? })
+    }
+}
+
+/// Merton jump-diffusion: a correlated normal diffusion fitted to the days
+/// without jumps, plus each factor's own compound Poisson jumps.
+pub struct JumpDiffusion {
+    diffusion: Normal,
+    jumps: Vec<Jumps>,
+}
+
+impl JumpDiffusion {
+    /// Jumps are the returns beyond `threshold` standard deviations of
+    /// their factor; `intensity` (per day), `jump_mean` and `jump_std`
+    /// override what they imply for every factor.
+    pub fn fit(columns: &[Vec<f64>], spec: &JumpSpec, sum: Summation) -> Result<Self, VarError> {
+        let n = columns[0].len();
+        let threshold = spec.threshold.unwrap_or(DEFAULT_JUMP_THRESHOLD);
+        let flags: Vec<Vec<bool>> = columns
+            .iter()
+            .map(|c| {
+                let (mean, std) = sum.mean_std(c);
+                c.iter().map(|r| (r - mean).abs() > threshold * std).collect()
+            })
+            .collect();
+        let jumps = columns
+            .iter()
+            .zip(&flags)
+            .map(|(c, f)| {
+                let sizes: Vec<f64> = c.iter().zip(f).filter(|(_, j)| **j).map(|(r, _)| *r).collect();
+                let (mean, std) = if sizes.is_empty() { (0.0, 0.0) } else { sum.mean_std(&sizes) };
+                Jumps::new(
+                    spec.intensity.unwrap_or(sizes.len() as f64 / n as f64),
+                    spec.jump_mean.unwrap_or(mean),
+                    spec.jump_std.unwrap_or(std),
+                )
+            })
+            .collect::<Result<_, _>>()?;
+        let calm: Vec<usize> = (0..n).filter(|t| flags.iter().all(|f| !f[*t])).collect();
+        let calm_columns: Vec<Vec<f64>> = if calm.len() >= 2 {
+            columns.iter().map(|c| calm.iter().map(|t| c[*t]).collect()).collect()
+        } else {
+            columns.to_vec()
+        };
+        Ok(JumpDiffusion { diffusion: Normal::fit(&calm_columns, sum), jumps })
+    }
+}
+
+impl ScenarioGenerator for JumpDiffusion {
+    fn name(&self) -> &'static str {
+        "jump_diffusion"
+    }
+
+    fn factors(&self) -> usize {
+        self.jumps.len()
+    }
+
+    fn draw(&self, rng: &mut dyn RngCore, out: &mut [f64]) {
+        self.diffusion.draw(rng, out);
+        for (o, jump) in out.iter_mut().zip(&self.jumps) {
+            let Some(arrivals) = &jump.arrivals else { continue };
+            let count = arrivals.sample(rng) as usize;
+            *o += (0..count).map(|_| jump.size.sample(rng)).sum::<f64>();
+        }
+    }
+}
+
+/// Each factor's empirical distribution, joined by a Gaussian copula or,
+/// with `dof`, a Student-t copula whose joint tails move together.
+pub struct Copula {
+    sorted: Vec<Vec<f64>>,
+    chol: Vec<Vec<f64>>,
+    dof: Option<f64>,
+}
+
+impl Copula {
+    pub fn fit(columns: &[Vec<f64>], dof: Option<f64>, sum: Summation) -> Self {
+        let sorted = columns
+            .iter()
+            .map(|c| {
+                let mut s = c.clone();
+                s.sort_by(|a, b| a.total_cmp(b));
+                s
+            })
+            .collect();
+        let correlation = correlation_matrix(&covariance_matrix(columns, sum));
+        Copula { sorted, chol: cholesky(&correlation), dof }
+    }
+}
+
+impl ScenarioGenerator for Copula {
+    fn name(&self) -> &'static str {
+        "copula"
+    }
+
+    fn factors(&self) -> usize {
+        self.sorted.len()
+    }
+
+    fn draw(&self, rng: &mut dyn RngCore, out: &mut [f64]) {
+        correlated(&self.chol, rng, out);
+        let uniforms: Vec<f64> = match self.dof {
+            Some(dof) => {
+                let mix = (ChiSquared::new(dof).unwrap().sample(rng) / dof).sqrt();
+                let t = StudentsT::new(0.0, 1.0, dof).unwrap();
+                out.iter().map(|z| t.cdf(z / mix)).collect()
+            }
+            None => out.iter().map(|z| NormalCdf::standard().cdf(*z)).collect(),
+        };
+        for ((o, u), sorted) in out.iter_mut().zip(uniforms).zip(&self.sorted) {
+            *o = sorted[((u * sorted.len() as f64) as usize).min(sorted.len() - 1)];
+        }
+    }
+}
+
+// Jump-diffusion settings; unset parameters are estimated
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct JumpSpec {
+    #[serde(default)]
+    pub threshold: Option<f64>,
+    #[serde(default)]
+    pub intensity: Option<f64>,
+    #[serde(default)]
+    pub jump_mean: Option<f64>,
+    #[serde(default)]
+    pub jump_std: Option<f64>,
+}
+
+/// Which generator to build from the history, and its settings.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GeneratorSpec {
+    #[default]
+    Normal,
+    Bootstrap,
+    Garch,
+    JumpDiffusion(JumpSpec),
+    Copula {
+        #[serde(default)]
+        dof: Option<f64>,
+    },
+}
+
+impl GeneratorSpec {
+    fn check(&self) -> Result<(), String> {
+        match self {
+            GeneratorSpec::JumpDiffusion(spec) => {
+                let positive = |v: Option<f64>| v.is_none_or(|v| v > 0.0 && v.is_finite());
+                let non_negative = |v: Option<f64>| v.is_none_or(|v| v >= 0.0 && v.is_finite());
+                if !positive(spec.threshold) {
+                    Err("jump threshold must be positive".into())
+                } else if !non_negative(spec.intensity) || !non_negative(spec.jump_std) {
+                    Err("jump intensity and jump_std must not be negative".into())
+                } else if spec.intensity.is_some_and(|i| i > MAX_JUMP_INTENSITY) {
+                    Err(format!("jump intensity must not exceed {MAX_JUMP_INTENSITY} per day"))
+                } else if spec.jump_mean.is_some_and(|m| !m.is_finite()) {
+                    Err("jump_mean must be finite".into())
+                } else {
+                    Ok(())
+                }
+            }
+            GeneratorSpec::Copula { dof: Some(dof) } if !(*dof > 0.0 && dof.is_finite()) => {
+                Err("copula dof must be positive".into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Jump sizes one path draws, expected, beyond its `factors` returns.
+    /// An estimated intensity is a share of the history's days, at most one.
+    fn jump_draws(&self, factors: usize) -> usize {
+        match self {
+            GeneratorSpec::JumpDiffusion(spec) => (spec.intensity.unwrap_or(1.0) * factors as f64).ceil() as usize,
+            _ => 0,
+        }
+    }
+
+    /// The generator fitted to `columns`, one aligned return series per factor.
+    pub fn build(&self, columns: &[Vec<f64>], params: &MethodParams) -> Result<Box<dyn ScenarioGenerator>, VarError> {
+        let sum = params.summation();
+        Ok(match self {
+            GeneratorSpec::Normal => Box::new(Normal::fit(columns, sum)),
+            GeneratorSpec::Bootstrap => Box::new(Bootstrap::fit(columns)),
+            GeneratorSpec::Garch => Box::new(Garch::fit(columns, params)),
+            GeneratorSpec::JumpDiffusion(spec) => Box::new(JumpDiffusion::fit(columns, spec, sum)?),
+            GeneratorSpec::Copula { dof } => Box::new(Copula::fit(columns, *dof, sum)),
+        })
+    }
+}
+
+// Payload for /api/simulate
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "check_simulation"))]
+pub struct SimulationRequest {
+    // One aligned return series per factor, oldest first
+    #[validate(length(min = 1, message = "returns must not be empty"))]
+    returns: Vec<Vec<f64>>,
+    // Book weights on the factors (default: equal)
+    #[serde(default)]
+    weights: Option<Vec<f64>>,
+    #[serde(default)]
+    generator: GeneratorSpec,
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+    #[serde(default = "default_measures")]
+    #[validate(length(min = 1, message = "measures must not be empty"), custom(function = "measures::known_measures"))]
+    measures: Vec<String>,
+    // Weight function of the `spectral` measure
+    #[serde(default)]
+    spectrum: Spectrum,
+    #[serde(default = "default_paths")]
+    #[validate(range(min = 100, max = 1_000_000, message = "paths must be between 100 and 1000000"))]
+    paths: usize,
+    #[serde(default)]
+    seed: Option<u64>,
+}
+
+fn default_measures() -> Vec<String> {
+    vec!["var".into(), "es".into()]
+}
+
+fn default_paths() -> usize {
+    DEFAULT_PATHS
+}
+
+fn check_simulation(req: &SimulationRequest) -> Result<(), ValidationError> {
+    let k = req.returns.len();
+    let n = req.returns[0].len();
+    if n < 2 || req.returns.iter().any(|r| r.len() != n) {
+        return Err(cross_field("returns", "series must all have the same length, at least 2".into()));
+    }
+    if req.returns.iter().any(|r| validation::finite(r).is_err()) {
+        return Err(cross_field("returns", "returns must be finite".into()));
+    }
+    if let Some(weights) = &req.weights {
+        if weights.len() != k || validation::finite(weights).is_err() {
+            return Err(cross_field("weights", format!("expected {k} finite weights (one per factor)")));
+        }
+    }
+    req.generator.check().map_err(|e| cross_field("generator", e))?;
+    req.spectrum.check().map_err(|e| cross_field("spectrum", e))?;
+    // The history and its fit, then each path's P&L and a sorted copy, and
+    // its jump draws counted as values: the budget bounds the work too
+    let fixed = 2 * k * n + k * k;
+    let per_path = 2 + req.generator.jump_draws(k);
+    let advice = format!("lower paths to at most {}", budget::fits(fixed, per_path));
+    budget::check(fixed + req.paths * per_path, "paths", advice)
+}
+
+/// Risk of a factor book over scenarios from a chosen generator: the
+/// generator is fitted to the history, `paths` one-day scenarios drawn,
+/// the book's P&L taken in each, and every requested measure evaluated on
+/// that P&L.
+pub async fn simulate_handler(Valid(req): Valid<SimulationRequest>) -> Result<Json<Value>, ApiError> {
+    let seed = req.seed.unwrap_or_else(rand::random);
+    let params = MethodParams::default();
+    let cancel = params.cancel.clone();
+    let outcome = cancel::spawn_blocking(&cancel, move || {
+        let k = req.returns.len();
+        let weights = req.weights.clone().unwrap_or_else(|| vec![1.0 / k as f64; k]);
+        let generator = req.generator.build(&req.returns, &params)?;
+        let pnl = generator.par_portfolio(&weights, req.paths, seed, &params.cancel)?;
+        let results = req
+            .measures
+            .iter()
+            .map(|m| Ok((m.clone(), json!(measures::evaluate(m, &pnl, req.confidence, &req.spectrum)?))))
+            .collect::<Result<Map<String, Value>, VarError>>()?;
+        Ok::<_, VarError>((req, generator.name(), weights, results))
+    })
+    .await;
+    let (req, generator, weights, results) = outcome.map_err(|_| ApiError::aborted("simulation"))??;
+    Ok(Json(json!({
+        "generator": generator,
+        "factors": req.returns.len(),
+        "weights": weights,
+        "paths": req.paths,
+        "seed": seed,
+        "confidence": req.confidence,
+        "measures": results,
+        "engine": version::current(),
+    })))
+}
+
+// Payload for /api/scenario_set
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "check_scenario_set"))]
+pub struct ScenarioSetRequest {
+    // One aligned return series per factor, oldest first
+    #[validate(length(min = 1, message = "returns must not be empty"))]
+    returns: Vec<Vec<f64>>,
+    // Column labels of the factors, in the same order (default f0, f1, …)
+    #[serde(default)]
+    factors: Option<Vec<String>>,
+    #[serde(default)]
+    generator: GeneratorSpec,
+    #[serde(default = "default_paths")]
+    #[validate(range(min = 1, max = 1_000_000, message = "paths must be between 1 and 1000000"))]
+    paths: usize,
+    #[serde(default)]
+    seed: Option<u64>,
+    // `json` (default), `csv` or `arrow` (an Arrow IPC stream)
+    #[serde(default)]
+    format: Option<String>,
+    // Sent inline or as a link to the stored set (default: by size)
+    #[serde(default)]
+    delivery: Delivery,
+}
+
+fn check_scenario_set(req: &ScenarioSetRequest) -> Result<(), ValidationError> {
+    let k = req.returns.len();
+    let n = req.returns[0].len();
+    if n < 2 || req.returns.iter().any(|r| r.len() != n) {
+        return Err(cross_field("returns", "series must all have the same length, at least 2".into()));
+    }
+    if req.returns.iter().any(|r| validation::finite(r).is_err()) {
+        return Err(cross_field("returns", "returns must be finite".into()));
+    }
+    if req.factors.as_ref().is_some_and(|f| f.len() != k) {
+        return Err(cross_field("factors", format!("expected {k} factors (one per series)")));
+    }
+    if req.paths.saturating_mul(k) > MAX_EXPORT_VALUES {
+        return Err(cross_field("paths", format!("paths × factors must not exceed {MAX_EXPORT_VALUES}")));
+    }
+    // Every scenario is held, and again as the encoded body: about 64 bytes
+    // a value as JSON (through a serde_json tree), 24 as CSV, 8 as Arrow
+    let (encoded, instead) = match req.format.as_deref() {
+        None | Some("json") => (8, ", or export as arrow"),
+        Some("csv") => (3, ", or export as arrow"),
+        Some("arrow") => (1, ""),
+        _ => return Err(cross_field("format", "format must be json, csv or arrow".into())),
+    };
+    req.generator.check().map_err(|e| cross_field("generator", e))?;
+    let per_path = k * (1 + encoded) + req.generator.jump_draws(k);
+    let advice = format!("lower paths to at most {}{instead}", budget::fits(2 * k * n, per_path));
+    budget::check(2 * k * n + req.paths * per_path, "paths", advice)
+}
+
+fn internal(e: impl ToString) -> ApiError {
+    ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+fn scenarios_csv(factors: &[String], scenarios: &[Vec<f64>]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(std::iter::once("path").chain(factors.iter().map(String::as_str)))?;
+    for (path, row) in scenarios.iter().enumerate() {
+        writer.write_record(std::iter::once(path.to_string()).chain(row.iter().map(f64::to_string)))?;
+    }
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+/// `paths` scenarios of `generator` as a JSON body, each row drawn from
+/// `seed` as the client reads it: the rows `scenarios` gives for the seed.
+fn scenario_stream(
+    generator: Box<dyn ScenarioGenerator>,
+    paths: usize,
+    seed: u64,
+    trailer: Map<String, Value>,
+) -> Response {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let rows = (0..paths).map(move |_| {
+        let mut row = vec![0.0; generator.factors()];
+        generator.draw(&mut rng, &mut row);
+        row
+    });
+    stream::json_array_stream("scenarios", rows, trailer)
+}
+
+fn scenarios_arrow(factors: &[String], scenarios: &[Vec<f64>]) -> Vec<u8> {
+    let mut columns = vec![("path".to_string(), Column::Int64((0..scenarios.len() as i64).collect()))];
+    columns.extend(
+        factors
+            .iter()
+            .enumerate()
+            .map(|(j, f)| (f.clone(), Column::Float64(scenarios.iter().map(|row| row[j]).collect()))),
+    );
+    arrow::stream(&columns)
+}
+
+/// The generator's scenario set itself, for revaluing instruments the
+/// engine cannot price: one row per path of one-day factor returns, as
+/// JSON, CSV or an Arrow IPC stream, each row numbered by `path`. The seed
+/// (also sent as `x-scenario-seed`) regenerates the same set; per-path P&L
+/// computed from it comes back through `/api/aggregate_pnl`. Large sets
+/// are stored and answered with a download link (see `artifacts`); JSON
+/// sent inline is streamed, each row drawn as the client reads the body.
+pub async fn scenario_set_handler(
+    State(state): State<AppState>,
+    Valid(req): Valid<ScenarioSetRequest>,
+) -> Result<Response, ApiError> {
+    let seed = req.seed.unwrap_or_else(rand::random);
+    let json = matches!(req.format.as_deref(), None | Some("json"));
+    let streamed = json && !state.artifacts.offload(req.delivery, req.paths * req.returns.len() * JSON_VALUE_BYTES);
+    let params = MethodParams::default();
+    let cancel = params.cancel.clone();
+    let outcome = cancel::spawn_blocking(&cancel, move || {
+        let generator = req.generator.build(&req.returns, &params)?;
+        let scenarios = match streamed {
+            true => Vec::new(),
+            false => generator.scenarios(req.paths, &mut StdRng::seed_from_u64(seed), &params.cancel)?,
+        };
+        Ok::<_, VarError>((req, generator, scenarios))
+    })
+    .await;
+    let (req, generator, scenarios) = outcome.map_err(|_| ApiError::aborted("simulation"))??;
+    let factors = req.factors.clone().unwrap_or_else(|| (0..req.returns.len()).map(|j| format!("f{j}")).collect());
+    if streamed {
+        let trailer = stream::trailer([
+            ("generator", json!(generator.name())),
+            ("factors", json!(factors)),
+            ("paths", json!(req.paths)),
+            ("seed", json!(seed)),
+            ("engine", json!(version::current())),
+        ]);
+        return Ok(scenario_stream(generator, req.paths, seed, trailer));
+    }
+    let generator = generator.name();
+
+    let (content_type, extension, bytes) = match req.format.as_deref() {
+        None | Some("json") => {
+            let body = json!({
+                "generator": generator,
+                "factors": factors,
+                "paths": req.paths,
+                "seed": seed,
+                "scenarios": scenarios,
+                "engine": version::current(),
+            });
+            ("application/json", "json", serde_json::to_vec(&body).map_err(internal)?)
+        }
+        Some("csv") => ("text/csv; charset=utf-8", "csv", scenarios_csv(&factors, &scenarios).map_err(internal)?),
+        _ => ("application/vnd.apache.arrow.stream", "arrows", scenarios_arrow(&factors, &scenarios)),
+    };
+    let artifact = Artifact::new(format!("scenarios-{generator}-{seed}.{extension}"), content_type, bytes);
+    let headers = vec![(header::HeaderName::from_static("x-scenario-seed"), seed.to_string())];
+    state.artifacts.deliver(req.delivery, artifact, headers).await
+}
+
+// Payload for /api/aggregate_pnl
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "check_aggregate"))]
+pub struct AggregateRequest {
+    // Book P&L in each scenario, in path order
+    #[validate(length(min = 2, message = "need at least 2 paths"), custom(function = "validation::finite"))]
+    pnl: Vec<f64>,
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+    #[serde(default = "default_measures")]
+    #[validate(length(min = 1, message = "measures must not be empty"), custom(function = "measures::known_measures"))]
+    measures: Vec<String>,
+    #[serde(default)]
+    spectrum: Spectrum,
+    // Book value the P&L is on; adds the measures as fractions of it
+    #[serde(default)]
+    #[validate(range(exclusive_min = 0.0, message = "value must be positive"))]
+    value: Option<f64>,
+}
+
+fn check_aggregate(req: &AggregateRequest) -> Result<(), ValidationError> {
+    req.spectrum.check().map_err(|e| cross_field("spectrum", e))
+}
+
+// Paths beyond the VaR below which the tail is too thin to trust
+const MIN_TAIL_PATHS: usize = 10;
+// Coverage of the distribution-free interval around the VaR
+const VAR_INTERVAL_LEVEL: f64 = 0.95;
+const DIAGNOSTIC_QUANTILES: [f64; 5] = [0.01, 0.05, 0.5, 0.95, 0.99];
+
+/// Shape of an uploaded P&L distribution and how far its VaR can be trusted:
+/// moments, quantiles, and the order statistics bracketing the VaR with
+/// `VAR_INTERVAL_LEVEL` coverage whatever the distribution (the count of
+/// paths below the true quantile is Binomial(n, α)).
+fn diagnostics(pnl: &[f64], confidence: f64, warnings: &mut Vec<String>) -> Value {
+    let n = pnl.len();
+    let mut sorted = pnl.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let sum = Summation::Sequential;
+    let (mean, std) = sum.mean_std(pnl);
+    let (skewness, excess_kurtosis) = if std > 0.0 {
+        let moment = |k: i32| pnl.iter().map(|p| ((p - mean) / std).powi(k)).sum::<f64>() / n as f64;
+        (moment(3), moment(4) - 3.0)
+    } else {
+        (0.0, 0.0)
+    };
+    let at = |p: f64| sorted[((p * n as f64).floor() as usize).min(n - 1)];
+    let quantiles: Map<String, Value> = DIAGNOSTIC_QUANTILES.iter().map(|p| (p.to_string(), json!(at(*p)))).collect();
+
+    let alpha = 1.0 - confidence;
+    let z = NormalCdf::standard().inverse_cdf(0.5 + VAR_INTERVAL_LEVEL / 2.0);
+    let spread = z * (n as f64 * alpha * (1.0 - alpha)).sqrt();
+    let lo = (n as f64 * alpha - spread).floor().max(0.0) as usize;
+    let hi = ((n as f64 * alpha + spread).ceil() as usize).min(n - 1);
+    let tail_paths = (alpha * n as f64).floor() as usize;
+    if tail_paths < MIN_TAIL_PATHS {
+        warnings.push(format!(
+            "only {tail_paths} paths lie beyond the VaR at this confidence; the tail measures rest on too few \
+             scenarios to be reliable (at least {MIN_TAIL_PATHS} advised)"
+        ));
+    }
+    let distinct = sorted.windows(2).filter(|w| w[0] != w[1]).count() + 1;
+    if distinct * 2 < n {
+        warnings.push(format!("only {distinct} distinct P&L values in {n} paths; the quantiles are coarse"));
+    }
+    json!({
+        "std": std,
+        "skewness": skewness,
+        "excess_kurtosis": excess_kurtosis,
+        "min": sorted[0],
+        "max": sorted[n - 1],
+        "quantiles": quantiles,
+        "tail_paths": tail_paths,
+        "distinct_values": distinct,
+        "var_interval": { "level": VAR_INTERVAL_LEVEL, "lower": -sorted[hi], "upper": -sorted[lo] },
+    })
+}
+
+/// Risk measures of P&L distributions produced outside the engine, by the
+/// caller's own pricing or over a set from `/api/scenario_set`: the service
+/// as a pure risk-measure engine. Results are losses in the P&L's units,
+/// with diagnostics of the distribution they were read from.
+pub async fn aggregate_pnl_handler(Valid(req): Valid<AggregateRequest>) -> Result<Json<Value>, ApiError> {
+    let results = req
+        .measures
+        .iter()
+        .map(|m| Ok((m.clone(), json!(measures::evaluate(m, &req.pnl, req.confidence, &req.spectrum)?))))
+        .collect::<Result<Map<String, Value>, VarError>>()?;
+    let mut warnings = Vec::new();
+    let diagnostics = diagnostics(&req.pnl, req.confidence, &mut warnings);
+    let mut body = json!({
+        "paths": req.pnl.len(),
+        "confidence": req.confidence,
+        "mean": req.pnl.iter().sum::<f64>() / req.pnl.len() as f64,
+        "measures": results,
+        "diagnostics": diagnostics,
+    });
+    if let Some(value) = req.value {
+        let fractions: Map<String, Value> =
+            results.iter().map(|(m, v)| (m.clone(), json!(v.as_f64().unwrap_or(f64::NAN) / value))).collect();
+        body["fractions"] = json!(fractions);
+    }
+    if !warnings.is_empty() {
+        body["warnings"] = json!(warnings);
+    }
+    body["engine"] = json!(version::current());
+    Ok(Json(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    fn jumps(intensity: f64) -> GeneratorSpec {
+        GeneratorSpec::JumpDiffusion(JumpSpec { intensity: Some(intensity), ..JumpSpec::default() })
+    }
+
+    #[test]
+    fn jump_intensity_is_capped_per_day() {
+        assert!(jumps(MAX_JUMP_INTENSITY).check().is_ok());
+        assert!(jumps(1e9).check().is_err());
+        assert!(jumps(-1.0).check().is_err());
+        assert!(jumps(f64::NAN).check().is_err());
+    }
+
+    #[test]
+    fn jump_draws_count_against_the_budget() {
+        let history: Vec<f64> = (0..50).map(|i| 0.01 * ((i * 7 % 11) as f64 - 5.0)).collect();
+        let request = |generator: GeneratorSpec| SimulationRequest {
+            returns: vec![history.clone(), history.clone()],
+            weights: None,
+            generator,
+            confidence: 0.99,
+            measures: default_measures(),
+            spectrum: Spectrum::default(),
+            paths: 1_000_000,
+            seed: None,
+        };
+        assert_eq!(jumps(2.5).jump_draws(2), 5);
+        assert!(request(GeneratorSpec::Normal).validate().is_ok());
+        // Ten jumps a day on each of two factors over a million paths
+        let budget = budget::limit() / std::mem::size_of::<f64>();
+        assert_eq!(request(jumps(MAX_JUMP_INTENSITY)).validate().is_ok(), 22_000_000 <= budget);
+    }
+
+    #[test]
+    fn a_cancelled_scenario_set_stops() {
+        let generator = Normal::univariate(0.0, 0.01);
+        let cancel = Cancel::default();
+        let mut rng = StdRng::seed_from_u64(3);
+        assert_eq!(generator.scenarios(10, &mut rng, &cancel).unwrap().len(), 10);
+        cancel.cancel();
+        assert!(matches!(generator.scenarios(10, &mut rng, &cancel), Err(VarError::Cancelled)));
+    }
+
+    // Normal draws, counted
+    struct Counted(Normal, Arc<AtomicUsize>);
+
+    impl ScenarioGenerator for Counted {
+        fn name(&self) -> &'static str {
+            "counted"
+        }
+
+        fn factors(&self) -> usize {
+            self.0.factors()
+        }
+
+        fn draw(&self, rng: &mut dyn RngCore, out: &mut [f64]) {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            self.0.draw(rng, out);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_streamed_set_is_drawn_as_the_body_is_read() {
+        let paths = 5_000;
+        let drawn = Arc::new(AtomicUsize::new(0));
+        let generator = Counted(Normal::univariate(0.0, 0.01), drawn.clone());
+        let response = scenario_stream(Box::new(generator), paths, 5, Map::new());
+        assert_eq!(drawn.load(Ordering::SeqCst), 0);
+
+        let mut body = response.into_body().into_data_stream();
+        let mut document = Vec::new();
+        for _ in 0..2 {
+            document.extend(body.next().await.unwrap().unwrap());
+        }
+        let early = drawn.load(Ordering::SeqCst);
+        assert!(early > 0 && early < paths, "{early} of {paths} rows drawn for the first chunk");
+        while let Some(chunk) = body.next().await {
+            document.extend(chunk.unwrap());
+        }
+        assert_eq!(drawn.load(Ordering::SeqCst), paths);
+
+        // The same rows, to the digit, as drawing the set up front
+        let generator = Normal::univariate(0.0, 0.01);
+        let expected = generator.scenarios(paths, &mut StdRng::seed_from_u64(5), &Cancel::default()).unwrap();
+        let expected = format!("{{\"scenarios\":{}}}", serde_json::to_string(&expected).unwrap());
+        assert_eq!(String::from_utf8(document).unwrap(), expected);
+    }
+
+    #[test]
+    fn a_zero_intensity_draws_no_jumps() {
+        let history: Vec<f64> = (0..50).map(|i| 0.01 * ((i * 7 % 11) as f64 - 5.0)).collect();
+        let columns = vec![history];
+        let params = MethodParams::default();
+        let diffusion = GeneratorSpec::Normal.build(&columns, &params).unwrap();
+        let spec = JumpSpec { intensity: Some(0.0), threshold: Some(1e6), ..JumpSpec::default() };
+        let jumpless = GeneratorSpec::JumpDiffusion(spec).build(&columns, &params).unwrap();
+        let seed = 11;
+        let cancel = Cancel::default();
+        assert_eq!(
+            diffusion.par_portfolio(&[1.0], 1_000, seed, &cancel).unwrap(),
+            jumpless.par_portfolio(&[1.0], 1_000, seed, &cancel).unwrap()
+        );
+    }
+}
@@ -0,0 +1,54 @@
+//! Consistent scoring functions for risk forecasts. Losses are computed in
+//! return space: `v` is the VaR quantile (-VaR) and `e` the ES (-ES), both
+//! normally negative, and `alpha` is the tail probability 1 - confidence.
+
+use statrs::distribution::{ContinuousCDF, Normal};
+
+/// Quantile (pinball) loss of a VaR forecast; lower is better.
+pub fn pinball(y: f64, var: f64, alpha: f64) -> f64 {
+    let v = -var;
+    let hit = if y <= v { 1.0 } else { 0.0 };
+    (hit - alpha) * (v - y)
+}
+
+/// FZ0 loss of Patton, Ziegel & Chen (2019) for a joint (VaR, ES) forecast,
+/// from the Fissler–Ziegel class; lower is better. Requires ES > 0 (a loss).
+pub fn fz0(y: f64, var: f64, es: f64, alpha: f64) -> f64 {
+    let v = -var;
+    let e = (-es).min(-1e-12);
+    let hit = if y <= v { 1.0 } else { 0.0 };
+    -hit * (v - y) / (alpha * e) + v / e + (-e).ln() - 1.0
+}
+
+// Outcome of a Diebold–Mariano test on two loss series
+pub struct DieboldMariano {
+    pub mean_diff: f64,
+    pub stat: f64,
+    pub p_value: f64,
+}
+
+/// Diebold–Mariano test of equal predictive accuracy on the loss
+/// differential `a - b`, with a Newey–West (Bartlett) long-run variance using
+/// floor(n^(1/3)) lags. A negative statistic favours `a`; the p-value is
+/// two-sided against the standard normal.
+pub fn diebold_mariano(a: &[f64], b: &[f64]) -> Option<DieboldMariano> {
+    let d: Vec<f64> = a.iter().zip(b).map(|(x, y)| x - y).collect();
+    let n = d.len();
+    if n < 2 {
+        return None;
+    }
+    let mean = d.iter().sum::<f64>() / n as f64;
+    let autocov = |lag: usize| (lag..n).map(|t| (d[t] - mean) * (d[t - lag] - mean)).sum::<f64>() / n as f64;
+    let lags = (n as f64).cbrt().floor() as usize;
+    let lrv = autocov(0)
+        + 2.0
+            * (1..=lags)
+                .map(|l| (1.0 - l as f64 / (lags + 1) as f64) * autocov(l))
+                .sum::<f64>();
+    if lrv <= 0.0 {
+        return None;
+    }
+    let stat = mean / (lrv / n as f64).sqrt();
+    let p_value = 2.0 * (1.0 - Normal::new(0.0, 1.0).unwrap().cdf(stat.abs()));
+    Some(DieboldMariano { mean_diff: mean, stat, p_value })
+}
@@ -0,0 +1,262 @@
+//! Intermediate values of a `compute_var` run (`debug_steps: true`), so the
+//! arithmetic can be checked by hand: the sample's moments, the sorted tail
+//! and the order statistics around the VaR for the empirical methods, the
+//! quantile and its inputs for the parametric ones, the simulated quantile's
+//! neighbourhood for Monte Carlo, and the step from one day to the horizon.
+
+use serde_json::{json, Value};
+use statrs::distribution::{Continuous, ContinuousCDF, StudentsT};
+
+use crate::{
+    error::VarError,
+    horizon::HorizonScaling,
+    scenarios::{self, ScenarioGenerator},
+    var::{self, VarRequest},
+};
+
+// Worst observations listed from the tail; its sum and mean cover all of it
+const MAX_LISTED: usize = 100;
+// Order statistics shown either side of the VaR's
+const NEIGHBOURS: usize = 5;
+
+fn density(z: f64) -> f64 {
+    (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt()
+}
+
+/// Sum, mean and population standard deviation, each with its inputs.
+fn moments(req: &VarRequest, returns: &[f64]) -> Value {
+    let sum = req.params.summation();
+    let n = returns.len() as f64;
+    let total = sum.sum(returns);
+    let (mean, std_dev) = sum.mean_std(returns);
+    let squares: Vec<f64> = returns.iter().map(|r| (r - mean) * (r - mean)).collect();
+    let squared_deviations = sum.sum(&squares);
+    json!({
+        "n": returns.len(),
+        "sum": total,
+        "mean": mean,
+        "sum_squared_deviations": squared_deviations,
+        "variance": squared_deviations / n,
+        "std_dev": std_dev,
+        "formulas": ["μ = Σr / n", "σ² = Σ(r − μ)² / n"],
+    })
+}
+
+/// The ascending order statistics around the VaR's, the tail up to and
+/// including it, and the tail's sum and mean, the ES.
+fn order_statistics(req: &VarRequest, values: &[f64]) -> Value {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let n = sorted.len();
+    let k = var::quantile_index(n, req.confidence);
+    let tail = &sorted[..=k];
+    let tail_sum = req.params.summation().sum(tail);
+    let neighbourhood: Vec<Value> = (k.saturating_sub(NEIGHBOURS)..(k + NEIGHBOURS + 1).min(n))
+        .map(|i| json!({ "rank": i + 1, "value": sorted[i], "is_var": i == k }))
+        .collect();
+    json!({
+        "index": k,
+        "rank": k + 1,
+        "formula": format!("k = ⌊(1 − c)·n⌋ = ⌊{:.6} × {n}⌋ = {k}; VaR = −r₍ₖ₊₁₎", 1.0 - req.confidence),
+        "quantile_return": sorted[k],
+        "neighbourhood": neighbourhood,
+        "tail": &tail[..tail.len().min(MAX_LISTED)],
+        "tail_count": tail.len(),
+        "tail_sum": tail_sum,
+        "tail_mean": tail_sum / tail.len() as f64,
+        "es_formula": "ES = −(r₍₁₎ + … + r₍ₖ₊₁₎) / (k + 1)",
+    })
+}
+
+/// Age- or client-weighted observations sorted ascending with their running
+/// mass, around the one where it reaches 1 − c.
+fn weighted_statistics(req: &VarRequest, returns: &[f64]) -> Value {
+    let weights = var::historical_weights(returns.len(), &req.params);
+    let mut pairs: Vec<(f64, f64)> = returns.iter().copied().zip(weights).collect();
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let alpha = 1.0 - req.confidence;
+    let mut mass = 0.0;
+    let cumulative: Vec<f64> = pairs
+        .iter()
+        .map(|(_, w)| {
+            mass += w;
+            mass
+        })
+        .collect();
+    // First observation whose running mass reaches the tail probability
+    let k = cumulative.iter().position(|m| *m >= alpha).unwrap_or(pairs.len() - 1);
+    let neighbourhood: Vec<Value> = (k.saturating_sub(NEIGHBOURS)..(k + NEIGHBOURS + 1).min(pairs.len()))
+        .map(|i| {
+            json!({
+                "rank": i + 1,
+                "value": pairs[i].0,
+                "weight": pairs[i].1,
+                "cumulative_weight": cumulative[i],
+                "is_var": i == k,
+            })
+        })
+        .collect();
+    json!({
+        "tail_probability": alpha,
+        "rank": k + 1,
+        "quantile_return": pairs[k].0,
+        "neighbourhood": neighbourhood,
+        "formula": "VaR = −r₍ₖ₎ for the first k whose weights w₍₁₎ + … + w₍ₖ₎ reach 1 − c",
+        "es_formula": "ES = −(Σ w₍ᵢ₎ r₍ᵢ₎ over i < k + (1 − c − Σ w₍ᵢ₎ over i < k) · r₍ₖ₎) / (1 − c)",
+    })
+}
+
+/// The normal quantile z = Φ⁻¹(c), its density and the volatility it scales.
+fn normal(req: &VarRequest, mean: f64, volatility: f64) -> Value {
+    let z = var::parametric_z(req.confidence);
+    json!({
+        "z": z,
+        "density": density(z),
+        "mean": mean,
+        "volatility": volatility,
+        "var": -(mean - z * volatility),
+        "es": -(mean - volatility * density(z) / (1.0 - req.confidence)),
+        "formula": "VaR = −(μ − z·σ), z = Φ⁻¹(c)",
+        "es_formula": "ES = −(μ − σ·φ(z) / (1 − c))",
+    })
+}
+
+/// The method's own intermediate values on `returns`, the sample it saw.
+fn method_steps(req: &VarRequest, returns: &[f64], result: &Value) -> Result<Value, VarError> {
+    let (mean, std_dev) = req.params.summation().mean_std(returns);
+    Ok(match req.method.as_str() {
+        "historical" => order_statistics(req, returns),
+        "bootstrap" => {
+            let mut steps = order_statistics(req, returns);
+            steps["note"] = json!("order statistics of the sample itself; VaR and ES average them over resamples");
+            steps
+        }
+        "filtered_historical" => {
+            let filtered = var::filtered_returns(returns, &req.params);
+            let mut steps = order_statistics(req, &filtered.scenarios);
+            steps["forecast_volatility"] = json!(filtered.forecast_volatility);
+            steps["note"] = json!("order statistics of the filtered scenarios r̃ᵢ = rᵢ · σ̂ₜ₊₁ / σᵢ");
+            steps
+        }
+        "weighted_historical" => weighted_statistics(req, returns),
+        "parametric" => normal(req, mean, std_dev),
+        "ewma" => {
+            let mut steps = normal(req, 0.0, var::ewma_volatility(returns, &req.params));
+            steps["lambda"] = json!(var::ewma_lambda(&req.params));
+            steps["volatility_formula"] = json!("σ² = Σ wᵢ rᵢ², wᵢ = λ^age (1 − λ) / (1 − λⁿ)");
+            steps
+        }
+        "garch" => {
+            let fit = &result["garch"];
+            let mean = fit["mean"].as_f64().unwrap_or_default();
+            let mut steps = normal(req, mean, fit["forecast_volatility"].as_f64().unwrap_or_default());
+            steps["volatility_formula"] = json!("σ²ₜ₊₁ = ω + α ε²ₜ + β σ²ₜ");
+            steps
+        }
+        "parametric_t" => {
+            let dof = var::student_t_dof(returns, &req.params);
+            let t = StudentsT::new(0.0, 1.0, dof)
+                .map_err(|e| VarError::Numerical(format!("Student-t with {dof} degrees of freedom: {e}")))?;
+            let q = t.inverse_cdf(req.confidence);
+            let scale = std_dev * ((dof - 2.0) / dof).sqrt();
+            let tail = t.pdf(q) / (1.0 - req.confidence) * (dof + q * q) / (dof - 1.0);
+            json!({
+                "dof": dof,
+                "t_quantile": q,
+                "mean": mean,
+                "std_dev": std_dev,
+                "scale": scale,
+                "tail_factor": tail,
+                "var": -(mean - scale * q),
+                "es": -(mean - scale * tail),
+                "formula": "VaR = −(μ − t_ν(c)·s), s = σ·√((ν − 2)/ν)",
+                "es_formula": "ES = −(μ − s·g_ν(q)/(1 − c)·(ν + q²)/(ν − 1)), q = t_ν(c)",
+            })
+        }
+        "cornish_fisher" => {
+            let (s, k) = var::higher_moments(returns, &req.params);
+            let z = -var::parametric_z(req.confidence);
+            let adjusted = z + (z * z - 1.0) * s / 6.0 + (z.powi(3) - 3.0 * z) * k / 24.0
+                - (2.0 * z.powi(3) - 5.0 * z) * s * s / 36.0;
+            let tail_mean = -density(z) / (1.0 - req.confidence)
+                * (1.0 + z * s / 6.0 + (z * z - 1.0) * k / 24.0 - (2.0 * z * z - 1.0) * s * s / 36.0);
+            json!({
+                "z": z,
+                "skewness": s,
+                "excess_kurtosis": k,
+                "adjusted_z": adjusted,
+                "mean": mean,
+                "std_dev": std_dev,
+                "tail_mean": tail_mean,
+                "var": -(mean + std_dev * adjusted),
+                "es": -(mean + std_dev * tail_mean),
+                "formula": "z_cf = z + (z² − 1)S/6 + (z³ − 3z)K/24 − (2z³ − 5z)S²/36; VaR = −(μ + z_cf·σ)",
+            })
+        }
+        _ => montecarlo_steps(req, mean, std_dev)?,
+    })
+}
+
+/// A fixed-size, seeded Monte Carlo run is drawn again from its seed and
+/// read off as historical simulation is; adaptive and importance-sampled
+/// runs keep no full set of paths to show.
+fn montecarlo_steps(req: &VarRequest, mean: f64, std_dev: f64) -> Result<Value, VarError> {
+    let generator = json!({ "mean": mean, "std_dev": std_dev, "formula": "rⱼ = μ + σ·uⱼ, uⱼ ~ N(0, 1)" });
+    let seed = match req.params.seed {
+        Some(seed) if req.target_se.is_none() && req.importance.is_none() => seed,
+        _ => {
+            let note = "quantile neighbourhood only for seeded fixed-size runs";
+            return Ok(json!({ "generator": generator, "note": note }));
+        }
+    };
+    let paths = req.params.mc_paths();
+    let normal = scenarios::Normal::univariate(mean, std_dev);
+    let sims = normal.par_portfolio(&[1.0], paths, var::simulation_seed(Some(seed)), &req.params.cancel)?;
+    let mut steps = order_statistics(req, &sims);
+    steps["generator"] = generator;
+    steps["paths"] = json!(paths);
+    steps["seed"] = json!(seed);
+    Ok(steps)
+}
+
+/// From the one-day figures to the reported ones: the horizon, then log
+/// losses to fractions of value.
+fn horizon_steps(req: &VarRequest, one_day: (f64, f64), result: &Value) -> Value {
+    let h = req.horizon_days;
+    let mut steps = json!({ "one_day_var": one_day.0, "one_day_es": one_day.1, "horizon_days": h });
+    if h > 1 {
+        steps["scaling"] = json!(req.horizon_scaling);
+        match req.horizon_scaling {
+            HorizonScaling::SqrtTime => {
+                let factor = f64::from(h).sqrt();
+                steps["factor"] = json!(factor);
+                steps["horizon_var"] = json!(one_day.0 * factor);
+                steps["horizon_es"] = json!(one_day.1 * factor);
+                steps["formula"] = json!(format!("VaR_{h}d = VaR_1d · √{h}"));
+            }
+            HorizonScaling::Simulated => {
+                steps["paths"] = result["horizon"]["paths"].clone();
+                steps["formula"] = json!("h-day paths compounded day by day, Π(1 + rₜ) − 1, read off at 1 − c");
+            }
+        }
+    }
+    if !req.return_type.is_simple() {
+        steps["log_returns"] = result["log_returns"].clone();
+        steps["conversion"] = json!("loss as a fraction of value = 1 − e^(−L)");
+    }
+    steps["var"] = result["var"].clone();
+    steps["es"] = result["es"].clone();
+    steps
+}
+
+/// Every intermediate value of a run over `returns`, the sample as the
+/// method saw it (after lookback, units and costs).
+pub fn steps(req: &VarRequest, returns: &[f64], one_day: (f64, f64), result: &Value) -> Result<Value, VarError> {
+    Ok(json!({
+        "confidence": req.confidence,
+        "tail_probability": 1.0 - req.confidence,
+        "sample": moments(req, returns),
+        "method": method_steps(req, returns, result)?,
+        "horizon": horizon_steps(req, one_day, result),
+    }))
+}
@@ -0,0 +1,302 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    admin::Admin,
+    error::ApiError,
+    stream,
+    validation::{cross_field, Valid},
+    AppState,
+};
+
+/// One price observation: an ISO-8601 date (`2024-01-02`) or UTC timestamp
+/// (`2024-01-02T14:31:00Z`) and the close. Both forms sort chronologically.
+pub type Bar = (String, f64);
+
+pub type StoreResult<T> = Result<T, String>;
+
+/// Persistent price history per ticker.
+#[async_trait]
+pub trait PriceStore: Send + Sync {
+    /// Upserts bars, returning how many were written.
+    async fn insert(&self, ticker: &str, bars: &[Bar]) -> StoreResult<usize>;
+    /// Bars with `start <= ts <= end` in chronological order.
+    async fn range(&self, ticker: &str, start: Option<&str>, end: Option<&str>) -> StoreResult<Vec<Bar>>;
+    async fn tickers(&self) -> StoreResult<Vec<String>>;
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    bars: Mutex<HashMap<String, BTreeMap<String, f64>>>,
+}
+
+#[async_trait]
+impl PriceStore for MemoryStore {
+    async fn insert(&self, ticker: &str, bars: &[Bar]) -> StoreResult<usize> {
+        let mut all = self.bars.lock().unwrap();
+        let series = all.entry(ticker.to_string()).or_default();
+        for (ts, close) in bars {
+            series.insert(ts.clone(), *close);
+        }
+        Ok(bars.len())
+    }
+
+    async fn range(&self, ticker: &str, start: Option<&str>, end: Option<&str>) -> StoreResult<Vec<Bar>> {
+        let all = self.bars.lock().unwrap();
+        let Some(series) = all.get(ticker) else {
+            return Ok(Vec::new());
+        };
+        Ok(series
+            .iter()
+            .filter(|(ts, _)| start.is_none_or(|s| ts.as_str() >= s) && end.is_none_or(|e| ts.as_str() <= e))
+            .map(|(ts, close)| (ts.clone(), *close))
+            .collect())
+    }
+
+    async fn tickers(&self) -> StoreResult<Vec<String>> {
+        let mut tickers: Vec<String> = self.bars.lock().unwrap().keys().cloned().collect();
+        tickers.sort();
+        Ok(tickers)
+    }
+}
+
+/// TimescaleDB backend for long minute-bar histories: bars live in a
+/// hypertable keyed by (ticker, ts), inserts are batched through UNNEST and
+/// range reads hit the (ticker, ts) index.
+#[cfg(feature = "timescale")]
+pub struct TimescaleStore {
+    client: tokio_postgres::Client,
+}
+
+#[cfg(feature = "timescale")]
+mod timescale {
+    use super::*;
+    use chrono::{DateTime, NaiveDate, Utc};
+
+    const INSERT_BATCH: usize = 50_000;
+
+    fn parse_ts(ts: &str) -> StoreResult<DateTime<Utc>> {
+        if let Ok(date) = NaiveDate::parse_from_str(ts, "%Y-%m-%d") {
+            return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+        }
+        DateTime::parse_from_rfc3339(ts)
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(|e| format!("invalid timestamp {ts:?}: {e}"))
+    }
+
+    fn format_ts(ts: DateTime<Utc>) -> String {
+        if ts.time() == chrono::NaiveTime::MIN {
+            ts.format("%Y-%m-%d").to_string()
+        } else {
+            ts.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+        }
+    }
+
+    impl TimescaleStore {
+        pub async fn connect(url: &str) -> StoreResult<Self> {
+            let (client, connection) = tokio_postgres::connect(url, tokio_postgres::NoTls)
+                .await
+                .map_err(|e| e.to_string())?;
+            tokio::spawn(async move {
+                if let Err(e) = connection.await {
+                    eprintln!("❌ Price DB connection closed: {}", e);
+                }
+            });
+            client
+                .batch_execute(
+                    "CREATE TABLE IF NOT EXISTS price_bars (
+                        ticker TEXT NOT NULL,
+                        ts TIMESTAMPTZ NOT NULL,
+                        close DOUBLE PRECISION NOT NULL,
+                        PRIMARY KEY (ticker, ts)
+                     );
+                     SELECT create_hypertable('price_bars', 'ts', if_not_exists => TRUE);",
+                )
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(TimescaleStore { client })
+        }
+    }
+
+    #[async_trait]
+    impl PriceStore for TimescaleStore {
+        async fn insert(&self, ticker: &str, bars: &[Bar]) -> StoreResult<usize> {
+            for chunk in bars.chunks(INSERT_BATCH) {
+                let ts = chunk.iter().map(|(t, _)| parse_ts(t)).collect::<StoreResult<Vec<_>>>()?;
+                let closes: Vec<f64> = chunk.iter().map(|(_, c)| *c).collect();
+                self.client
+                    .execute(
+                        "INSERT INTO price_bars (ticker, ts, close)
+                         SELECT $1, * FROM UNNEST($2::timestamptz[], $3::float8[])
+                         ON CONFLICT (ticker, ts) DO UPDATE SET close = EXCLUDED.close",
+                        &[&ticker, &ts, &closes],
+                    )
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            Ok(bars.len())
+        }
+
+        async fn range(&self, ticker: &str, start: Option<&str>, end: Option<&str>) -> StoreResult<Vec<Bar>> {
+            let start = start.map(parse_ts).transpose()?;
+            let end = end.map(parse_ts).transpose()?;
+            let rows = self
+                .client
+                .query(
+                    "SELECT ts, close FROM price_bars
+                     WHERE ticker = $1
+                       AND ($2::timestamptz IS NULL OR ts >= $2)
+                       AND ($3::timestamptz IS NULL OR ts <= $3)
+                     ORDER BY ts",
+                    &[&ticker, &start, &end],
+                )
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(rows.iter().map(|r| (format_ts(r.get(0)), r.get(1))).collect())
+        }
+
+        async fn tickers(&self) -> StoreResult<Vec<String>> {
+            let rows = self
+                .client
+                .query("SELECT DISTINCT ticker FROM price_bars ORDER BY ticker", &[])
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(rows.iter().map(|r| r.get(0)).collect())
+        }
+    }
+}
+
+/// Builds the configured store: TimescaleDB when compiled with the
+/// `timescale` feature and `PRICE_DB_URL` is set, otherwise in-memory.
+pub async fn from_env() -> Box<dyn PriceStore> {
+    #[cfg(feature = "timescale")]
+    if let Ok(url) = std::env::var("PRICE_DB_URL") {
+        match TimescaleStore::connect(&url).await {
+            Ok(store) => {
+                println!("🗄️ Storing prices in TimescaleDB");
+                return Box::new(store);
+            }
+            Err(e) => eprintln!("⚠️ Price DB unavailable ({}), falling back to memory", e),
+        }
+    }
+
+    Box::new(MemoryStore::default())
+}
+
+// Wire format for one stored bar
+#[derive(Serialize, Deserialize)]
+pub struct BarRow {
+    ts: String,
+    close: f64,
+}
+
+// Optional window for stored price queries
+#[derive(Deserialize)]
+pub struct RangeQuery {
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+// Payload to bulk-load bars, in chronological order
+#[derive(Deserialize, Validate)]
+#[validate(schema(function = "check_bars"))]
+pub struct BulkBars {
+    bars: Vec<BarRow>,
+}
+
+// Every tenant's returns are taken from these closes: a zero or negative
+// one would turn into an infinite or meaningless return
+fn check_bars(payload: &BulkBars) -> Result<(), ValidationError> {
+    for (i, bar) in payload.bars.iter().enumerate() {
+        if !(bar.close.is_finite() && bar.close > 0.0) {
+            return Err(cross_field("bars", format!("bar {i} ({}): close must be positive, got {}", bar.ts, bar.close)));
+        }
+    }
+    if let Some(i) = payload.bars.windows(2).position(|w| w[1].ts <= w[0].ts) {
+        let ts = &payload.bars[i + 1].ts;
+        return Err(cross_field("bars", format!("bar {} ({ts}): timestamps must be unique and ascending", i + 1)));
+    }
+    Ok(())
+}
+
+/// Stored price history for a ticker, each bar serialised as the client
+/// reads the body
+pub async fn get_prices_handler(
+    State(state): State<AppState>,
+    Path(ticker): Path<String>,
+    Query(q): Query<RangeQuery>,
+) -> Result<Response, ApiError> {
+    let ticker = ticker.to_uppercase();
+    let bars = state
+        .prices
+        .range(&ticker, q.start.as_deref(), q.end.as_deref())
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let rows = bars.into_iter().map(|(ts, close)| BarRow { ts, close });
+    Ok(stream::json_array_stream("bars", rows, stream::trailer([("ticker", json!(ticker))])))
+}
+
+/// Bulk-load bars for a ticker (upserts on timestamp). The store is shared
+/// by every tenant, so loading is for admins.
+pub async fn put_prices_handler(
+    _: Admin,
+    State(state): State<AppState>,
+    Path(ticker): Path<String>,
+    Valid(payload): Valid<BulkBars>,
+) -> Result<Json<Value>, ApiError> {
+    let ticker = ticker.to_uppercase();
+    let bars: Vec<Bar> = payload.bars.into_iter().map(|b| (b.ts, b.close)).collect();
+    let inserted = state
+        .prices
+        .insert(&ticker, &bars)
+        .await
+        .map_err(ApiError::bad_request)?;
+    state.rolling.ingest(&*state.prices, &ticker, &bars).await;
+    println!("💾 Stored {} bars for {}", inserted, ticker);
+    Ok(Json(json!({ "ticker": ticker, "inserted": inserted })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bars(rows: &[(&str, f64)]) -> BulkBars {
+        BulkBars { bars: rows.iter().map(|(ts, close)| BarRow { ts: ts.to_string(), close: *close }).collect() }
+    }
+
+    #[test]
+    fn bulk_loads_need_positive_closes_in_order() {
+        assert!(bars(&[("2024-01-02", 10.0), ("2024-01-03", 10.5), ("2024-01-03T15:00:00Z", 10.6)]).validate().is_ok());
+        for close in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(bars(&[("2024-01-02", 10.0), ("2024-01-03", close)]).validate().is_err(), "{close}");
+        }
+        assert!(bars(&[("2024-01-02", 10.0), ("2024-01-02", 10.5)]).validate().is_err());
+        assert!(bars(&[("2024-01-03", 10.0), ("2024-01-02", 10.5)]).validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn memory_store_upserts_and_reads_ranges_in_order() {
+        let store = MemoryStore::default();
+        let first = [("2024-01-03".to_string(), 11.0), ("2024-01-02".to_string(), 10.0)];
+        assert_eq!(store.insert("AAPL", &first).await.unwrap(), 2);
+        store.insert("AAPL", &[("2024-01-03".to_string(), 12.0), ("2024-01-04".to_string(), 13.0)]).await.unwrap();
+        let all = store.range("AAPL", None, None).await.unwrap();
+        let expected = [("2024-01-02", 10.0), ("2024-01-03", 12.0), ("2024-01-04", 13.0)];
+        assert_eq!(all, expected.map(|(ts, close)| (ts.to_string(), close)));
+        let window = store.range("AAPL", Some("2024-01-03"), Some("2024-01-03")).await.unwrap();
+        assert_eq!(window, vec![("2024-01-03".to_string(), 12.0)]);
+        assert_eq!(store.tickers().await.unwrap(), vec!["AAPL".to_string()]);
+    }
+}
@@ -0,0 +1,104 @@
+use axum::{
+    body::{Body, Bytes},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use futures_util::stream;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::iter;
+
+// Elements serialised per body chunk
+const CHUNK: usize = 1024;
+
+/// Fields sent after a streamed array.
+pub fn trailer<const N: usize>(fields: [(&str, Value); N]) -> Map<String, Value> {
+    fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+}
+
+/// Streams `{"<key>":[...], <trailer fields>}` as a chunked body. Elements
+/// are drawn from `items` only as the body is read, `CHUNK` at a time, so
+/// neither they nor the JSON document need exist in full at any point.
+pub fn json_array_stream<I>(key: &'static str, items: I, trailer: Map<String, Value>) -> Response
+where
+    I: IntoIterator,
+    I::IntoIter: Send + 'static,
+    I::Item: Serialize,
+{
+    let mut items = items.into_iter();
+    let mut first = true;
+    let head = iter::once(format!("{{\"{key}\":[").into_bytes());
+    let chunks = iter::from_fn(move || {
+        let mut buf = Vec::new();
+        for item in items.by_ref().take(CHUNK) {
+            if !first {
+                buf.push(b',');
+            }
+            first = false;
+            serde_json::to_writer(&mut buf, &item).expect("serialising JSON to memory");
+        }
+        (!buf.is_empty()).then_some(buf)
+    });
+    let tail = iter::once({
+        let mut buf = b"]".to_vec();
+        for (k, v) in &trailer {
+            buf.push(b',');
+            serde_json::to_writer(&mut buf, k).expect("serialising JSON to memory");
+            buf.push(b':');
+            serde_json::to_writer(&mut buf, v).expect("serialising JSON to memory");
+        }
+        buf.push(b'}');
+        buf
+    });
+
+    let body = stream::iter(head.chain(chunks).chain(tail).map(|b| Ok::<_, std::io::Error>(Bytes::from(b))));
+    ([(header::CONTENT_TYPE, "application/json")], Body::from_stream(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use serde_json::json;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[tokio::test]
+    async fn elements_are_drawn_as_the_body_is_read() {
+        let n = 3 * CHUNK + 5;
+        let drawn = Arc::new(AtomicUsize::new(0));
+        let counter = drawn.clone();
+        let items = (0..n).inspect(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let mut trailer = Map::new();
+        trailer.insert("count".into(), json!(n));
+
+        let response = json_array_stream("items", items, trailer);
+        assert_eq!(drawn.load(Ordering::SeqCst), 0, "nothing is drawn before the body is read");
+
+        let mut body = response.into_body().into_data_stream();
+        let mut document = Vec::new();
+        document.extend(body.next().await.unwrap().unwrap());
+        assert_eq!(drawn.load(Ordering::SeqCst), 0, "the opening bracket needs no elements");
+        document.extend(body.next().await.unwrap().unwrap());
+        assert_eq!(drawn.load(Ordering::SeqCst), CHUNK, "one chunk is drawn per body chunk");
+        while let Some(chunk) = body.next().await {
+            document.extend(chunk.unwrap());
+        }
+        assert_eq!(drawn.load(Ordering::SeqCst), n);
+
+        let document: Value = serde_json::from_slice(&document).unwrap();
+        let expected: Vec<usize> = (0..n).collect();
+        assert_eq!(document, json!({ "items": expected, "count": n }));
+    }
+
+    #[tokio::test]
+    async fn an_empty_producer_gives_an_empty_array() {
+        let response = json_array_stream("items", iter::empty::<f64>(), Map::new());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"{\"items\":[]}");
+    }
+}
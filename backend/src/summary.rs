@@ -0,0 +1,220 @@
+//! A risk result in a few plain sentences ("A $100k position in AAPL has a
+//! 1-in-20 chance of losing more than $2,300 in a day..."), filled into
+//! fixed sentence templates from the numbers next to it. English only; the
+//! figures themselves stay in the response for anything that needs them.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use validator::Validate;
+
+use crate::{
+    horizon::HorizonScaling,
+    report::{PositionRisk, RiskReport},
+    var::VarRequest,
+};
+
+// What a `compute_var` summary calls the position and its money
+#[derive(Clone, Default, Serialize, Deserialize, Validate)]
+pub struct SummaryOptions {
+    // Name of the position, e.g. a ticker (default: "the position")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(length(min = 1, max = 64, message = "label must be 1 to 64 characters"))]
+    pub label: Option<String>,
+    // ISO currency code of `value`; USD, EUR, GBP and JPY get their symbol
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(length(equal = 3, message = "currency must be a three-letter code"))]
+    pub currency: Option<String>,
+}
+
+// 0.95 → "1-in-20", 0.99 → "1-in-100"
+fn odds(confidence: f64) -> String {
+    format!("1-in-{:.0}", 1.0 / (1.0 - confidence))
+}
+
+// 0.99 → "99%", 0.995 → "99.5%"
+fn level(confidence: f64) -> String {
+    format!("{}%", (confidence * 1e4).round() / 1e2)
+}
+
+fn pct(x: f64) -> String {
+    format!("{:.2}%", x * 100.0)
+}
+
+// Thousands separators on a whole number
+fn grouped(x: f64) -> String {
+    let digits = format!("{:.0}", x.abs());
+    let mut out = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+// Rounded to `figures` significant figures, and shortened from ten thousand
+// up: 2,300 / 23k / 1.2M / 3.4bn
+fn amount(x: f64, figures: i32) -> String {
+    let x = x.abs();
+    if x == 0.0 || !x.is_finite() {
+        return format!("{x:.0}");
+    }
+    let step = 10f64.powi(x.log10().floor() as i32 + 1 - figures);
+    let x = (x / step).round() * step;
+    let short = |x: f64, unit: &str| format!("{}{unit}", ((x * 10.0).round() / 10.0));
+    match x {
+        x if x >= 1e9 => short(x / 1e9, "bn"),
+        x if x >= 1e6 => short(x / 1e6, "M"),
+        x if x >= 1e4 => short(x / 1e3, "k"),
+        x if x >= 1.0 => grouped(x),
+        x => format!("{x}"),
+    }
+}
+
+// Money in the currency's symbol or code
+fn money(x: f64, figures: i32, currency: Option<&str>) -> String {
+    let amount = amount(x, figures);
+    match currency.map(str::to_uppercase).as_deref() {
+        None => amount,
+        Some("USD") => format!("${amount}"),
+        Some("EUR") => format!("€{amount}"),
+        Some("GBP") => format!("£{amount}"),
+        Some("JPY") => format!("¥{amount}"),
+        Some(code) => format!("{code} {amount}"),
+    }
+}
+
+fn period(horizon_days: u32) -> String {
+    match horizon_days {
+        1 => "in a day".to_string(),
+        h => format!("over {h} days"),
+    }
+}
+
+fn method_name(method: &str) -> &'static str {
+    match method {
+        "historical" => "historical simulation",
+        "weighted_historical" => "age-weighted historical simulation",
+        "filtered_historical" => "volatility-filtered historical simulation",
+        "bootstrap" => "a bootstrap of historical simulation",
+        "parametric" => "a normal distribution",
+        "parametric_t" => "a Student-t distribution",
+        "cornish_fisher" => "a skew- and tail-adjusted normal distribution",
+        "ewma" => "exponentially weighted volatility",
+        "garch" => "a GARCH(1,1) volatility forecast",
+        _ => "Monte Carlo simulation",
+    }
+}
+
+/// `compute_var`'s result in sentences: the chance of the VaR being
+/// exceeded, the average loss beyond it, what the figure rests on, and the
+/// VaR at any further confidence levels.
+pub fn var_summary(req: &VarRequest, options: &SummaryOptions, result: &Value) -> String {
+    let currency = options.currency.as_deref();
+    let detail = &result["var_detail"];
+    let loss = |fraction: &Value, amount: &Value| {
+        let fraction = fraction.as_f64().unwrap_or(f64::NAN);
+        match amount.as_f64() {
+            Some(amount) => format!("{} ({})", money(amount, 2, currency), pct(fraction)),
+            None => pct(fraction),
+        }
+    };
+    let subject = match (req.value, options.label.as_deref()) {
+        (Some(value), Some(label)) => format!("A {} position in {label}", money(value, 3, currency)),
+        (Some(value), None) => format!("A {} position", money(value, 3, currency)),
+        (None, Some(label)) => label.to_string(),
+        (None, None) => "The position".to_string(),
+    };
+    let of_value = if req.value.is_some() { "" } else { " of its value" };
+    let mut sentences = vec![
+        format!(
+            "{subject} has a {} chance of losing more than {}{of_value} {}.",
+            odds(req.confidence),
+            loss(&detail["loss_fraction"], &detail["loss_amount"]),
+            period(req.horizon_days)
+        ),
+        format!("When it does, the loss averages {}.", loss(&detail["es_fraction"], &detail["es_amount"])),
+    ];
+
+    let n = result["sample_size"].as_u64().unwrap_or_default();
+    let basis = match result["n_sims"].as_u64() {
+        Some(paths) => format!(
+            "{} fitted to the most recent {n} returns, over {} paths",
+            method_name(&req.method),
+            grouped(paths as f64)
+        ),
+        _ => format!("{} on the most recent {n} returns", method_name(&req.method)),
+    };
+    let scaling = match (req.horizon_days, req.horizon_scaling) {
+        (1, _) => "",
+        (_, HorizonScaling::SqrtTime) => ", scaled from one day by the square root of time",
+        (_, HorizonScaling::Simulated) => ", with the days simulated one by one",
+    };
+    sentences.push(format!("The figures come from {basis}{scaling}."));
+
+    for other in result["levels"].as_array().into_iter().flatten() {
+        let confidence = other["confidence"].as_f64().unwrap_or(f64::NAN);
+        let detail = &other["var_detail"];
+        sentences.push(format!(
+            "At {} confidence, a {} chance, the loss would exceed {}.",
+            level(confidence),
+            odds(confidence),
+            loss(&detail["loss_fraction"], &detail["loss_amount"])
+        ));
+    }
+    if let Some(warnings) = result["warnings"].as_array().filter(|w| !w.is_empty()) {
+        let count = match warnings.len() {
+            1 => "a warning".to_string(),
+            n => format!("{n} warnings"),
+        };
+        sentences.push(format!("Read them alongside {count} about the data or method."));
+    }
+    sentences.join(" ")
+}
+
+/// A portfolio risk report in sentences: the VaR and ES in money, the
+/// positions driving them, the backtest record and the worst day replayed.
+pub fn report_summary(report: &RiskReport) -> String {
+    let count = match report.positions.len() {
+        1 => "one position".to_string(),
+        n => format!("{n} positions"),
+    };
+    let mut sentences = vec![
+        format!(
+            "A portfolio of {} gross across {count} has a {} chance of losing more than {} {}, judged on {} days \
+             of history.",
+            amount(report.gross_value, 3),
+            odds(report.confidence),
+            amount(report.var, 2),
+            period(1),
+            report.observations
+        ),
+        format!("When it does, the loss averages {}.", amount(report.es, 2)),
+    ];
+
+    // The largest contribution to the VaR, and the largest offset to it
+    if report.positions.len() > 1 {
+        let by_share = |a: &&PositionRisk, b: &&PositionRisk| a.pct_of_var.total_cmp(&b.pct_of_var);
+        if let Some(p) = report.positions.iter().max_by(by_share).filter(|p| p.pct_of_var > 0.0) {
+            sentences.push(format!("{} contributes the most, {:.0}% of the VaR.", p.ticker, p.pct_of_var * 100.0));
+        }
+        if let Some(p) = report.positions.iter().min_by(by_share).filter(|p| p.pct_of_var < 0.0) {
+            sentences.push(format!("{} offsets {:.0}% of it.", p.ticker, -p.pct_of_var * 100.0));
+        }
+    }
+    if let Some(bt) = &report.backtest {
+        let times = match bt.breaches {
+            1 => "once".to_string(),
+            n => format!("{n} times"),
+        };
+        sentences.push(format!(
+            "Over the last {} days the loss exceeded the VaR {times}, against about {:.0} expected.",
+            bt.observations, bt.expected_breaches
+        ));
+    }
+    if let Some(worst) = report.scenarios.first().filter(|s| s.pnl < 0.0) {
+        sentences.push(format!("Replayed today, the worst day, {}, would lose {}.", worst.date, amount(worst.pnl, 2)));
+    }
+    sentences.join(" ")
+}
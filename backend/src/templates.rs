@@ -0,0 +1,155 @@
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{DateTime, Utc};
+use handlebars::{html_escape, Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{error::ApiError, locale::Locale, report::RiskReport, tenant::Tenant, AppState};
+
+/// Built-in HTML report; tenants replace it wholesale with their own template.
+pub const DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="{{locale}}">
+<head><meta charset="utf-8"><title>{{label "risk_report"}} – {{report.name}}</title></head>
+<body>
+<h1>{{label "risk_report"}}: {{report.name}}</h1>
+<p>{{label "as_of"}} {{date report.as_of}} · {{report.method}} VaR · {{label "confidence"}} {{pct report.confidence}} · {{label "generated"}} {{date generated_at}}</p>
+
+<h2>{{label "summary"}}</h2>
+<table>
+<tr><th>{{label "gross_value"}}</th><td>{{money report.gross_value}}</td></tr>
+<tr><th>{{label "net_value"}}</th><td>{{money report.net_value}}</td></tr>
+<tr><th>{{label "var"}}</th><td>{{money report.var}}</td></tr>
+<tr><th>{{label "es"}}</th><td>{{money report.es}}</td></tr>
+<tr><th>{{label "observations"}}</th><td>{{report.observations}}</td></tr>
+</table>
+
+<h2>{{label "positions"}}</h2>
+<table>
+<tr><th>{{label "ticker"}}</th><th>{{label "value"}}</th><th>{{label "weight"}}</th><th>{{label "standalone_var"}}</th><th>{{label "component_var"}}</th><th>{{label "pct_of_var"}}</th></tr>
+{{#each report.positions}}
+<tr><td>{{ticker}}</td><td>{{money value}}</td><td>{{pct weight}}</td><td>{{money standalone_var}}</td><td>{{money component_var}}</td><td>{{pct pct_of_var}}</td></tr>
+{{/each}}
+</table>
+
+{{#if report.backtest}}
+<h2>{{label "backtest"}}</h2>
+<table>
+<tr><th>{{label "breaches"}}</th><td>{{report.backtest.breaches}}</td></tr>
+<tr><th>{{label "expected"}}</th><td>{{money report.backtest.expected_breaches}}</td></tr>
+<tr><th>{{label "observations"}}</th><td>{{report.backtest.observations}}</td></tr>
+<tr><th>{{label "window"}}</th><td>{{report.backtest.window}}</td></tr>
+</table>
+{{/if}}
+
+<h2>{{label "worst_scenarios"}}</h2>
+<table>
+<tr><th>{{label "date"}}</th><th>{{label "pnl"}}</th></tr>
+{{#each report.scenarios}}
+<tr><td>{{date date}}</td><td>{{money pnl}}</td></tr>
+{{/each}}
+</table>
+
+<p><small>{{label "engine"}} {{report.engine.version}}, {{label "methodology"}} {{report.engine.methodology}}. {{label "disclaimer"}}</small></p>
+</body>
+</html>
+"#;
+
+/// Helper applying `f` to its first parameter, output HTML-escaped.
+fn value_helper(f: impl Fn(&Value) -> String + Send + Sync + 'static) -> Box<dyn HelperDef + Send + Sync> {
+    Box::new(
+        move |h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output| -> HelperResult {
+            let value = h.param(0).map_or(Value::Null, |p| p.value().clone());
+            out.write(&html_escape(&f(&value)))?;
+            Ok(())
+        },
+    )
+}
+
+fn registry(locale: Locale) -> Handlebars<'static> {
+    let mut hb = Handlebars::new();
+    hb.register_helper("money", value_helper(move |v| v.as_f64().map_or_else(String::new, |x| locale.number(x, 2))));
+    hb.register_helper("pct", value_helper(move |v| v.as_f64().map_or_else(String::new, |x| locale.percent(x))));
+    hb.register_helper("date", value_helper(move |v| v.as_str().map_or_else(String::new, |d| locale.date(d))));
+    hb.register_helper("label", value_helper(move |v| v.as_str().map_or_else(String::new, |k| locale.label(k).to_string())));
+    hb
+}
+
+// A tenant's uploaded report template
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReportTemplate {
+    pub tenant: String,
+    pub source: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+pub struct TemplateStore {
+    templates: Mutex<HashMap<String, ReportTemplate>>,
+}
+
+impl TemplateStore {
+    pub fn all(&self) -> Vec<ReportTemplate> {
+        self.templates.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn restore(&self, template: ReportTemplate) {
+        self.templates.lock().unwrap().insert(template.tenant.clone(), template);
+    }
+
+    fn remove(&self, tenant: &str) {
+        self.templates.lock().unwrap().remove(tenant);
+    }
+
+    fn source(&self, tenant: &str) -> Option<String> {
+        self.templates.lock().unwrap().get(tenant).map(|t| t.source.clone())
+    }
+
+    /// Renders a report through the tenant's template, or the default one.
+    /// Templates see `report`, `tenant`, `locale` and `generated_at`, plus
+    /// the localised `money`, `pct`, `date` and `label` helpers.
+    pub fn render(&self, tenant: &str, report: &RiskReport, locale: Locale) -> Result<String, String> {
+        let source = self.source(tenant).unwrap_or_else(|| DEFAULT_TEMPLATE.to_string());
+        let data = json!({
+            "report": report,
+            "tenant": tenant,
+            "locale": locale,
+            "generated_at": Utc::now().format("%Y-%m-%d %H:%M UTC").to_string(),
+        });
+        registry(locale).render_template(&source, &data).map_err(|e| e.to_string())
+    }
+}
+
+// Payload to upload a template
+#[derive(Deserialize)]
+pub struct TemplateBody {
+    source: String,
+}
+
+/// The caller's report template (the built-in one if none was uploaded)
+pub async fn get_template_handler(State(state): State<AppState>, Tenant(tenant): Tenant) -> Json<serde_json::Value> {
+    match state.templates.source(&tenant) {
+        Some(source) => Json(json!({ "custom": true, "source": source })),
+        None => Json(json!({ "custom": false, "source": DEFAULT_TEMPLATE })),
+    }
+}
+
+/// Upload the caller's report template; rejected if it does not compile
+pub async fn put_template_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Json(body): Json<TemplateBody>,
+) -> Result<Json<ReportTemplate>, ApiError> {
+    registry(Locale::default())
+        .register_template_string("report", &body.source)
+        .map_err(|e| ApiError::bad_request(format!("invalid template: {e}")))?;
+    let template = ReportTemplate { tenant, source: body.source, updated_at: Utc::now() };
+    state.templates.restore(template.clone());
+    Ok(Json(template))
+}
+
+/// Drop the caller's template, reverting to the built-in one
+pub async fn delete_template_handler(State(state): State<AppState>, Tenant(tenant): Tenant) -> StatusCode {
+    state.templates.remove(&tenant);
+    StatusCode::NO_CONTENT
+}
@@ -0,0 +1,23 @@
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use std::convert::Infallible;
+
+pub const DEFAULT_TENANT: &str = "default";
+
+/// Caller's tenant, taken from the `X-Tenant-Id` header.
+pub struct Tenant(pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Tenant {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let id = parts
+            .headers
+            .get("x-tenant-id")
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .unwrap_or(DEFAULT_TENANT);
+        Ok(Tenant(id.to_string()))
+    }
+}
@@ -0,0 +1,31 @@
+use chrono::{DateTime, Duration, Utc};
+use std::env;
+
+use crate::AppState;
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// When an entity deleted at `deleted_at` is purged for good, after
+/// `TRASH_RETENTION_DAYS` (default 30).
+pub fn purge_at(deleted_at: DateTime<Utc>) -> DateTime<Utc> {
+    deleted_at + Duration::days(env_or("TRASH_RETENTION_DAYS", 30))
+}
+
+/// Periodically purges soft-deleted entities past their retention; interval
+/// from `TRASH_PURGE_INTERVAL_SECS` (default 3600).
+pub fn spawn_purge(state: AppState) {
+    let secs = env_or("TRASH_PURGE_INTERVAL_SECS", 3600);
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(secs));
+        loop {
+            tick.tick().await;
+            let now = Utc::now();
+            let (lists, books) = (state.watchlists.purge(now), state.portfolios.purge(now));
+            if lists + books > 0 {
+                println!("🧹 Purged {} deleted watchlists and {} deleted portfolios", lists, books);
+            }
+        }
+    });
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+// Median |return| at or above which a series is taken to be in percent:
+// 20% typical daily moves are implausible as decimals
+const PERCENT_MEDIAN: f64 = 0.2;
+// Between this and PERCENT_MEDIAN the units are ambiguous
+const SUSPICIOUS_MEDIAN: f64 = 0.05;
+// Median |return| below which explicitly percent input looks like decimals
+const TINY_PERCENT_MEDIAN: f64 = 0.002;
+
+/// How client-supplied returns are expressed: `decimal` (0.01 = 1%),
+/// `percent` (1.0 = 1%) or `auto` (detected from the data).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    #[default]
+    Auto,
+    Decimal,
+    Percent,
+}
+
+/// Whether returns are simple, P₁/P₀ − 1, or logarithmic, ln(P₁/P₀). Log
+/// returns add up over time: a multi-day log return is the sum of the daily ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReturnType {
+    #[default]
+    Simple,
+    Log,
+}
+
+impl ReturnType {
+    pub fn is_simple(&self) -> bool {
+        *self == ReturnType::Simple
+    }
+
+    /// Return from price `from` to price `to`.
+    pub fn between(self, from: f64, to: f64) -> f64 {
+        match self {
+            ReturnType::Simple => (to - from) / from,
+            ReturnType::Log => (to / from).ln(),
+        }
+    }
+
+    /// Return over `total`'s period followed by `next`'s.
+    pub fn compound(self, total: f64, next: f64) -> f64 {
+        match self {
+            ReturnType::Simple => (1.0 + total) * (1.0 + next) - 1.0,
+            ReturnType::Log => total + next,
+        }
+    }
+
+    /// A positive loss in these returns as a fraction of value lost:
+    /// 1 − e^(−L) for a log-return loss L.
+    pub fn simple_loss(self, loss: f64) -> f64 {
+        match self {
+            ReturnType::Simple => loss,
+            ReturnType::Log => -(-loss).exp_m1(),
+        }
+    }
+}
+
+fn median_abs(returns: &[f64]) -> f64 {
+    let mut abs: Vec<f64> = returns.iter().map(|r| r.abs()).filter(|r| r.is_finite()).collect();
+    if abs.is_empty() {
+        return 0.0;
+    }
+    abs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    abs[abs.len() / 2]
+}
+
+/// Converts `returns` to decimals in place. Returns the units the input was
+/// read as, plus warnings whenever the data disagree with them.
+pub fn normalize(returns: &mut [f64], units: Units) -> (Units, Vec<String>) {
+    let median = median_abs(returns);
+    let mut warnings = Vec::new();
+    let resolved = match units {
+        Units::Auto if median >= PERCENT_MEDIAN => {
+            warnings.push(format!(
+                "returns look like percentages (median |r| = {median:.4}); converted to decimals, results are decimals. Pass units explicitly to silence this"
+            ));
+            Units::Percent
+        }
+        Units::Auto => {
+            if median >= SUSPICIOUS_MEDIAN {
+                warnings.push(format!(
+                    "median |r| = {median:.4} is unusually large for decimal returns; if these are percentages pass units: \"percent\""
+                ));
+            }
+            Units::Decimal
+        }
+        Units::Decimal if median >= PERCENT_MEDIAN => {
+            warnings.push(format!("units is decimal but median |r| = {median:.4} looks like percentages"));
+            Units::Decimal
+        }
+        Units::Percent if median < TINY_PERCENT_MEDIAN && median > 0.0 => {
+            warnings.push(format!("units is percent but median |r| = {median:.4} looks like decimals"));
+            Units::Percent
+        }
+        explicit => explicit,
+    };
+
+    if resolved == Units::Percent {
+        returns.iter_mut().for_each(|r| *r /= 100.0);
+    }
+    let below = returns.iter().filter(|r| **r < -1.0).count();
+    if below > 0 {
+        warnings.push(format!("{below} returns are below -100%; check the units"));
+    }
+    (resolved, warnings)
+}
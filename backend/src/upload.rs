@@ -0,0 +1,321 @@
+//! Client-supplied series: a CSV of dated prices or returns, uploaded as
+//! `multipart/form-data`, validated and cleaned into the same return series
+//! `fetch_returns` builds from a provider's closes.
+
+use axum::{
+    body::Bytes,
+    http::{header, HeaderMap, StatusCode},
+    Json,
+};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use validator::Validate;
+
+use crate::{
+    cleaning::{self, CleanOptions, Flag},
+    error::ApiError,
+    history::{self, HistoryPolicy},
+    multipart,
+    storage::Bar,
+    units::{self, Units},
+    validation, FetchResponse, PreviewRow,
+};
+
+// Headers taken for the date column, else the first column is
+const DATE_COLUMNS: &[&str] = &["date", "datetime", "timestamp", "time", "day"];
+// Headers recognised as a series without being named by `column`
+const PRICE_COLUMNS: &[&str] = &["adj_close", "adjclose", "adjusted_close", "close", "price", "last"];
+const RETURN_COLUMNS: &[&str] = &["return", "returns", "ret"];
+// Invalid lines listed in a rejection; the rest are only counted
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// What the value column of an upload holds.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeriesKind {
+    // Closes, turned into returns as fetched prices are
+    Prices,
+    // Period returns, used as they are
+    Returns,
+}
+
+impl SeriesKind {
+    fn of_header(name: &str) -> Option<SeriesKind> {
+        if PRICE_COLUMNS.contains(&name) {
+            Some(SeriesKind::Prices)
+        } else if RETURN_COLUMNS.contains(&name) {
+            Some(SeriesKind::Returns)
+        } else {
+            None
+        }
+    }
+}
+
+// Text fields sent next to the file
+#[derive(Deserialize, Validate)]
+pub struct UploadForm {
+    #[serde(flatten)]
+    #[validate(nested)]
+    cleaning: CleanOptions,
+    // What the value column holds (default: told by its header)
+    #[serde(default)]
+    kind: Option<SeriesKind>,
+    // Header of the value column, for files with several
+    #[serde(default)]
+    column: Option<String>,
+    // How uploaded returns are expressed; returns from prices are decimals
+    #[serde(default)]
+    units: Units,
+    // Returns required before the series counts as short (default: historical's minimum)
+    #[serde(default)]
+    min_history: Option<usize>,
+    #[serde(default)]
+    history_policy: Option<HistoryPolicy>,
+}
+
+fn header_key(name: &str) -> String {
+    name.trim().to_lowercase().replace([' ', '-'], "_")
+}
+
+/// The file's delimiter: whichever of `,`, `;` and tab its header has most of.
+fn delimiter(text: &str) -> u8 {
+    let header = text.lines().next().unwrap_or_default();
+    let count = |d: char| header.matches(d).count();
+    [b',', b';', b'\t'].into_iter().max_by_key(|&d| count(d as char)).filter(|&d| count(d as char) > 0).unwrap_or(b',')
+}
+
+/// The date and value columns and what the latter holds, from the header
+/// and the `column` / `kind` fields.
+fn columns(headers: &[String], form: &UploadForm) -> Result<(usize, usize, SeriesKind), String> {
+    let date = headers.iter().position(|h| DATE_COLUMNS.contains(&h.as_str())).unwrap_or(0);
+    let listed = || headers.join(", ");
+    let (value, kind) = match &form.column {
+        Some(column) => {
+            let key = header_key(column);
+            let value = headers
+                .iter()
+                .position(|h| *h == key)
+                .ok_or_else(|| format!("no column {column:?}; the header has {}", listed()))?;
+            let kind = form.kind.or_else(|| SeriesKind::of_header(&key)).ok_or_else(|| {
+                format!("can't tell whether {column:?} holds prices or returns; send kind: prices or returns")
+            })?;
+            (value, kind)
+        }
+        None => {
+            let others: Vec<usize> = (0..headers.len()).filter(|&i| i != date).collect();
+            let named = others.iter().find_map(|&i| {
+                let kind = SeriesKind::of_header(&headers[i])?;
+                form.kind.is_none_or(|k| k == kind).then_some((i, kind))
+            });
+            match (named, form.kind, others.as_slice()) {
+                (Some(found), _, _) => found,
+                (None, Some(kind), &[only]) => (only, kind),
+                _ => {
+                    return Err(format!(
+                        "can't tell which column holds the series (header: {}); name it close, price or return, \
+                         or send column and kind",
+                        listed()
+                    ))
+                }
+            }
+        }
+    };
+    if value == date {
+        return Err(format!("column {:?} can't hold both the dates and the series", headers[date]));
+    }
+    Ok((date, value, kind))
+}
+
+/// A date as the price store keeps it: `YYYY-MM-DD`, or `YYYY-MM-DDTHH:MM:SSZ`
+/// with a time of day. ISO dates (`-` or `/` separated, or compact) and
+/// RFC 3339 timestamps are read; times without an offset are taken as UTC.
+fn parse_date(raw: &str) -> Option<String> {
+    if let Some(day) = ["%Y-%m-%d", "%Y/%m/%d", "%Y%m%d"].iter().find_map(|f| NaiveDate::parse_from_str(raw, f).ok()) {
+        return Some(day.format("%Y-%m-%d").to_string());
+    }
+    let stamp = DateTime::parse_from_rfc3339(raw).map(|t| t.with_timezone(&Utc)).ok().or_else(|| {
+        ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+            .iter()
+            .find_map(|f| NaiveDateTime::parse_from_str(raw, f).ok())
+            .map(|t| t.and_utc())
+    })?;
+    Some(stamp.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+/// A value cell: `None` when it is blank or marked missing.
+fn parse_value(raw: &str) -> Result<Option<f64>, ()> {
+    match raw.to_lowercase().as_str() {
+        "" | "null" | "na" | "n/a" | "nan" | "-" => Ok(None),
+        _ => raw.parse().map(Some).map_err(|_| ()),
+    }
+}
+
+// Dated values as read, `None` where a value is missing
+type Rows = Vec<(String, Option<f64>)>;
+
+/// Dated values of the file in file order, what they are and the columns
+/// they came from, or every invalid line.
+fn read_rows(text: &str, form: &UploadForm) -> Result<(Rows, SeriesKind, Value), Vec<String>> {
+    let mut reader =
+        csv::ReaderBuilder::new().trim(csv::Trim::All).delimiter(delimiter(text)).from_reader(text.as_bytes());
+    let headers: Vec<String> = match reader.headers() {
+        Ok(headers) => headers.iter().map(header_key).collect(),
+        Err(e) => return Err(vec![format!("invalid CSV header: {e}")]),
+    };
+    let (date, value, kind) = columns(&headers, form).map_err(|e| vec![e])?;
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                errors.push(e.to_string());
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |p| p.line());
+        let (raw_date, raw_value) = (record.get(date).unwrap_or_default(), record.get(value).unwrap_or_default());
+        let Some(day) = parse_date(raw_date) else {
+            errors.push(format!("line {line}: {raw_date:?} is not a date (use YYYY-MM-DD or RFC 3339)"));
+            continue;
+        };
+        match parse_value(raw_value) {
+            Ok(v) => rows.push((day, v)),
+            Err(()) => errors.push(format!("line {line}: {:?} is not a number", raw_value)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let columns = json!({ "date": headers[date], "value": headers[value] });
+    Ok((rows, kind, columns))
+}
+
+fn invalid_file(mut errors: Vec<String>) -> ApiError {
+    if errors.len() > MAX_REPORTED_ERRORS {
+        let more = errors.len() - MAX_REPORTED_ERRORS;
+        errors.truncate(MAX_REPORTED_ERRORS);
+        errors.push(format!("… and {more} more invalid lines"));
+    }
+    ApiError::invalid(BTreeMap::from([("file".to_string(), errors)]))
+}
+
+/// Upload a CSV of `date,price` or `date,return` lines (a header is
+/// required) as a `file` field of a multipart form, with `fetch_returns`'
+/// cleaning options as text fields, and get the cleaned return series back
+/// in `fetch_returns`' shape, plus an `upload` section on what was read.
+pub async fn upload_returns_handler(headers: HeaderMap, body: Bytes) -> Result<Json<Value>, ApiError> {
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let boundary = multipart::boundary(content_type).ok_or_else(|| {
+        ApiError::new(StatusCode::UNSUPPORTED_MEDIA_TYPE, "send the CSV as multipart/form-data, in a file field")
+    })?;
+    let parts = multipart::parts(&body, &boundary).map_err(ApiError::bad_request)?;
+
+    // Text fields arrive as strings; numbers and booleans are read as such
+    let mut fields = serde_json::Map::new();
+    let mut file = None;
+    for part in &parts {
+        if part.filename.is_some() || part.name.as_deref() == Some("file") {
+            if file.replace(part).is_some() {
+                return Err(ApiError::bad_request("upload one file at a time"));
+            }
+            continue;
+        }
+        let Some(name) = &part.name else {
+            continue;
+        };
+        let text = part.text().map_err(ApiError::bad_request)?.trim();
+        let value = match serde_json::from_str(text) {
+            Ok(value @ (Value::Number(_) | Value::Bool(_))) => value,
+            _ => Value::String(text.to_string()),
+        };
+        fields.insert(name.clone(), value);
+    }
+    let form: UploadForm = serde_json::from_value(Value::Object(fields))
+        .map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, format!("invalid form field: {e}")))?;
+    validation::check(&form)?;
+    let file = file.ok_or_else(|| ApiError::bad_request("no CSV in the form; send it as a file field"))?;
+    let text = file.text().map_err(ApiError::bad_request)?;
+
+    let (mut rows, kind, columns) = read_rows(text, &form).map_err(invalid_file)?;
+    let mut warnings = Vec::new();
+    // Exports often run newest first; anything else out of order is sorted with a warning
+    if rows.windows(2).all(|w| w[0].0 >= w[1].0) && !rows.windows(2).all(|w| w[0].0 <= w[1].0) {
+        rows.reverse();
+    } else if !rows.windows(2).all(|w| w[0].0 <= w[1].0) {
+        rows.sort_by(|a, b| a.0.cmp(&b.0));
+        warnings.push("rows were not in date order; sorted by date".to_string());
+    }
+
+    let (dated, mut upload) = match kind {
+        SeriesKind::Prices => {
+            let bars: Vec<Bar> = rows.iter().map(|(date, close)| (date.clone(), close.unwrap_or(f64::NAN))).collect();
+            let cleaned = cleaning::clean(&bars, &form.cleaning);
+            let count = |flag: Flag| cleaned.iter().filter(|r| r.flags.contains(&flag)).count();
+            let upload = json!({
+                "filled": count(Flag::Filled),
+                "dropped": count(Flag::Dropped),
+                "winsorized": count(Flag::Winsorized),
+            });
+            (cleaned.into_iter().filter_map(|r| Some((r.date, r.ret?))).collect::<Vec<_>>(), upload)
+        }
+        SeriesKind::Returns => {
+            // Duplicate dates keep the last print, as for prices
+            let total = rows.len();
+            let kept: Vec<(String, f64)> = (0..total)
+                .filter(|&i| rows.get(i + 1).is_none_or(|next| next.0 != rows[i].0))
+                .filter_map(|i| Some((rows[i].0.clone(), rows[i].1.filter(|r| r.is_finite())?)))
+                .collect();
+            let (dates, mut returns): (Vec<String>, Vec<f64>) = kept.into_iter().unzip();
+            let (units, notes) = units::normalize(&mut returns, form.units);
+            warnings.extend(notes);
+            let bounds = form.cleaning.winsorize.and_then(|q| cleaning::winsorize_bounds(returns.iter().copied(), q));
+            let mut winsorized = 0;
+            if let Some((lo, hi)) = bounds {
+                for r in returns.iter_mut().filter(|r| **r < lo || **r > hi) {
+                    *r = r.clamp(lo, hi);
+                    winsorized += 1;
+                }
+            }
+            let upload = json!({
+                "units": units,
+                "filled": 0,
+                "dropped": total - dates.len(),
+                "winsorized": winsorized,
+            });
+            (dates.into_iter().zip(returns).collect(), upload)
+        }
+    };
+    if dated.len() < 2 {
+        return Err(invalid_file(vec![format!("need at least 2 returns, got {}", dated.len())]));
+    }
+    let policy = HistoryPolicy::resolve(form.history_policy, false);
+    let min_history = form.min_history.unwrap_or_else(|| history::min_history("historical"));
+    if let Some(warning) = history::check_series(policy, dated.len(), min_history)
+        .map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, e))?
+    {
+        warnings.push(warning);
+    }
+
+    let label = file.filename.clone().unwrap_or_else(|| "upload".to_string());
+    println!("📤 Uploaded {} returns from {} ({} rows of {:?})", dated.len(), label, rows.len(), kind);
+    upload["filename"] = json!(file.filename);
+    upload["kind"] = json!(kind);
+    upload["columns"] = columns;
+    upload["rows"] = json!(rows.len());
+    upload["from"] = json!(dated[0].0);
+    upload["to"] = json!(dated[dated.len() - 1].0);
+
+    let preview = dated[dated.len().saturating_sub(5)..]
+        .iter()
+        .map(|(date, ret)| PreviewRow { date: date.clone(), ret: *ret })
+        .collect();
+    let returns = dated.into_iter().map(|(_, r)| r).collect();
+    let return_type = form.cleaning.return_type;
+    let mut body = json!(FetchResponse { returns, return_type, preview, backfill: None, warnings });
+    body["upload"] = upload;
+    Ok(Json(body))
+}
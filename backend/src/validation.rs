@@ -0,0 +1,163 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts, Query, Request},
+    http::request::Parts,
+    Json,
+};
+use serde::de::DeserializeOwned;
+use std::{borrow::Cow, collections::BTreeMap};
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+use crate::{error::ApiError, var::METHODS};
+
+// Nested DTOs deserialised with `#[serde(flatten)]`: their fields appear at
+// the top level of the payload, so errors are reported without the prefix
+const FLATTENED: &[&str] = &["params", "options", "cleaning", "request"];
+const MAX_CONFIDENCES: usize = 10;
+
+/// JSON body deserialised and then validated; malformed bodies and failed
+/// checks both come back as JSON errors, the latter as 422 with `fields`.
+pub struct Valid<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Valid<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+        check(&value)?;
+        Ok(Valid(value))
+    }
+}
+
+/// Query string counterpart of [`Valid`].
+pub struct ValidQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+        check(&value)?;
+        Ok(ValidQuery(value))
+    }
+}
+
+/// Validates a payload that did not come through an extractor.
+pub fn check(value: &impl Validate) -> Result<(), ApiError> {
+    value.validate().map_err(|errors| {
+        let mut fields = BTreeMap::new();
+        collect(&errors, "", &mut fields);
+        ApiError::invalid(fields)
+    })
+}
+
+fn collect(errors: &ValidationErrors, prefix: &str, out: &mut BTreeMap<String, Vec<String>>) {
+    for (field, kind) in errors.errors() {
+        let path = match field.as_ref() {
+            f if FLATTENED.contains(&f) => prefix.to_string(),
+            f if prefix.is_empty() => f.to_string(),
+            f => format!("{prefix}.{f}"),
+        };
+        match kind {
+            ValidationErrorsKind::Field(errs) => {
+                for err in errs {
+                    out.entry(field_of(err).unwrap_or_else(|| path.clone())).or_default().push(describe(err));
+                }
+            }
+            ValidationErrorsKind::Struct(nested) => collect(nested, &path, out),
+            ValidationErrorsKind::List(items) => {
+                for (i, nested) in items {
+                    collect(nested, &format!("{path}[{i}]"), out);
+                }
+            }
+        }
+    }
+}
+
+// Struct-level checks name the field they are about (see `cross_field`)
+fn field_of(err: &ValidationError) -> Option<String> {
+    err.params.get("field").and_then(|f| f.as_str()).map(str::to_string)
+}
+
+fn describe(err: &ValidationError) -> String {
+    let message = err.message.as_deref().unwrap_or(&err.code).to_string();
+    match err.params.get("value") {
+        Some(value) if value.is_number() => format!("{message}, got {value}"),
+        _ => message,
+    }
+}
+
+/// Error from a struct-level check, reported against `field`.
+pub fn cross_field(field: &'static str, message: String) -> ValidationError {
+    let mut err = ValidationError::new("invalid");
+    err.message = Some(Cow::Owned(message));
+    err.add_param(Cow::Borrowed("field"), &field);
+    err
+}
+
+pub fn known_method(method: &str) -> Result<(), ValidationError> {
+    if METHODS.contains(&method) {
+        return Ok(());
+    }
+    let mut err = ValidationError::new("unknown_method");
+    err.message = Some(Cow::Owned(format!("unknown method {method}; expected one of {}", METHODS.join(", "))));
+    Err(err)
+}
+
+pub fn known_methods(methods: &[String]) -> Result<(), ValidationError> {
+    methods.iter().try_for_each(|m| known_method(m))
+}
+
+pub fn finite(values: &[f64]) -> Result<(), ValidationError> {
+    match values.iter().position(|v| !v.is_finite()) {
+        None => Ok(()),
+        Some(i) => {
+            let mut err = ValidationError::new("finite");
+            err.message = Some(Cow::Owned(format!("values must be finite; entry {i} is not")));
+            Err(err)
+        }
+    }
+}
+
+/// Confidence levels, each in (0, 1), at most `MAX_CONFIDENCES` of them.
+pub fn confidences(values: &[f64]) -> Result<(), ValidationError> {
+    let message = if values.len() > MAX_CONFIDENCES {
+        format!("at most {MAX_CONFIDENCES} confidences, got {}", values.len())
+    } else if let Some(c) = values.iter().find(|c| !(**c > 0.0 && **c < 1.0)) {
+        format!("confidences must be in (0, 1), got {c}")
+    } else {
+        return Ok(());
+    };
+    let mut err = ValidationError::new("confidences");
+    err.message = Some(Cow::Owned(message));
+    Err(err)
+}
+
+/// Finite, non-negative and not all zero.
+pub fn weights(values: &[f64]) -> Result<(), ValidationError> {
+    finite(values)?;
+    let message = if values.iter().any(|w| *w < 0.0) {
+        "weights must be non-negative"
+    } else if values.iter().sum::<f64>() <= 0.0 {
+        "weights must not all be zero"
+    } else {
+        return Ok(());
+    };
+    let mut err = ValidationError::new("weights");
+    err.message = Some(Cow::Borrowed(message));
+    Err(err)
+}
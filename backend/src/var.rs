@@ -1,36 +1,1224 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
 use rand_distr::{Distribution, Normal};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use statrs::distribution::{Continuous, ContinuousCDF, Normal as Gaussian, StudentsT};
+use validator::{Validate, ValidationError};
 
-#[derive(Deserialize)]
+use crate::{
+    budget,
+    cancel::Cancel,
+    defaults::{self, Source},
+    costs::TransactionCosts,
+    error::VarError,
+    garch,
+    history::{self, HistoryPolicy},
+    horizon::{self, DailyModel, HorizonScaling},
+    reduce::Summation,
+    sanity,
+    scenarios::{self, ScenarioGenerator},
+    steps,
+    summary::{self, SummaryOptions},
+    units::{self, ReturnType, Units},
+    validation::{self, cross_field},
+    verify, version,
+};
+
+/// Methods accepted by `compute_var_es`.
+pub const METHODS: &[&str] = &[
+    "historical",
+    "weighted_historical",
+    "filtered_historical",
+    "bootstrap",
+    "parametric",
+    "ewma",
+    "garch",
+    "parametric_t",
+    "cornish_fisher",
+    "montecarlo",
+];
+
+// Upper bound on paths when simulating towards a precision target
+pub const DEFAULT_MAX_PATHS: usize = 1_000_000;
+const MC_BATCH: usize = 10_000;
+// Draws of a fixed-size Monte Carlo run
+pub const MC_PATHS: usize = 10_000;
+// Resamples of a bootstrap run
+pub const DEFAULT_BOOTSTRAP_SAMPLES: usize = 1_000;
+pub const DEFAULT_BRW_LAMBDA: f64 = 0.98;
+// RiskMetrics' daily decay factor
+pub const DEFAULT_EWMA_LAMBDA: f64 = 0.94;
+// Cap on fitted Student-t degrees of freedom: beyond it the t is the normal
+const MAX_FITTED_DOF: f64 = 100.0;
+
+// Method-specific tuning, shared by every endpoint that runs a VaR method
+#[derive(Clone, Default, Serialize, Deserialize, Validate)]
+pub struct MethodParams {
+    // Decay factor for age-weighted methods (weighted_historical, ewma), in (0, 1)
+    #[serde(default)]
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "lambda must be in (0, 1)"))]
+    pub lambda: Option<f64>,
+    // Explicit per-observation weights (oldest first) for weighted historical
+    // simulation; normalised to sum to one
+    #[serde(default)]
+    #[validate(custom(function = "validation::weights"))]
+    pub weights: Option<Vec<f64>>,
+    // Seeds the simulation RNG so Monte Carlo and bootstrap results can be
+    // reproduced
+    #[serde(default)]
+    pub seed: Option<u64>,
+    // Draws of a fixed-size Monte Carlo run (default 10,000)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 100, max = 1_000_000, message = "n_sims must be between 100 and 1000000"))]
+    pub n_sims: Option<usize>,
+    // Student-t degrees of freedom for parametric_t (default: fitted)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(exclusive_min = 2.0, message = "dof must be greater than 2"))]
+    pub dof: Option<f64>,
+    // Sum in a fixed, compensated order so results are bit-reproducible
+    // across machines (see `reduce::Summation`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deterministic: bool,
+    // Resamples the bootstrap method averages over
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 10, max = 10_000, message = "bootstrap_samples must be between 10 and 10000"))]
+    pub bootstrap_samples: Option<usize>,
+    // Conditional volatility filtered historical simulation rescales by
+    // (default: ewma)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volatility_model: Option<VolatilityModel>,
+    // Runtime only: stops simulations and backtests run with these params
+    #[serde(skip)]
+    pub cancel: Cancel,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolatilityModel {
+    #[default]
+    Ewma,
+    Garch,
+}
+
+impl MethodParams {
+    /// Checks that explicit weights match a sample of `n` observations.
+    pub fn check_weights(&self, n: usize) -> Result<(), ValidationError> {
+        match &self.weights {
+            Some(weights) if weights.len() != n => Err(cross_field(
+                "weights",
+                format!("expected {n} weights (one per observation), got {}", weights.len()),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn summation(&self) -> Summation {
+        Summation::new(self.deterministic)
+    }
+
+    /// Draws of a fixed-size Monte Carlo run.
+    pub fn mc_paths(&self) -> usize {
+        self.n_sims.unwrap_or(MC_PATHS)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "check_sample"))]
 pub struct VarRequest {
+    #[validate(custom(function = "validation::known_method"))]
     pub method: String,
+    #[validate(length(min = 2, message = "need at least 2 returns"), custom(function = "validation::finite"))]
     pub returns: Vec<f64>,
+    // Most recent returns to use (default: picked from the series, see `defaults`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 2, message = "lookback must be at least 2"))]
+    pub lookback: Option<usize>,
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    pub confidence: f64,
+    // Further levels to report VaR/ES at, from the same data and draws
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[validate(custom(function = "validation::confidences"))]
+    pub confidences: Vec<f64>,
+    // Holding period; one-day VaR is scaled by sqrt(horizon_days)
+    #[serde(default = "default_horizon")]
+    #[validate(range(min = 1, message = "horizon_days must be at least 1"))]
+    pub horizon_days: u32,
+    // How the one-day VaR is taken to horizon_days (see `horizon`)
+    #[serde(default, skip_serializing_if = "HorizonScaling::is_sqrt_time")]
+    pub horizon_scaling: HorizonScaling,
+    // Position market value; adds the VaR in money terms to the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(exclusive_min = 0.0, message = "value must be positive"))]
+    pub value: Option<f64>,
+    // Short-sample handling: returns required (default: the method's
+    // minimum) and what to do below it (reject or warn, the default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_history: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_policy: Option<HistoryPolicy>,
+    // Profile the settings were expanded from (see `profiles`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    // How `returns` are expressed; normalised to decimals before any method runs
+    #[serde(default)]
+    pub units: Units,
+    // Simple or log returns; VaR and ES are reported as simple losses either way
+    #[serde(default, skip_serializing_if = "ReturnType::is_simple")]
+    pub return_type: ReturnType,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub params: MethodParams,
+    // Optional trading friction deducted from each period's return
+    #[serde(default)]
+    #[validate(nested)]
+    pub costs: Option<TransactionCosts>,
+    // Monte Carlo only: simulate in batches until the quantile's standard
+    // error drops below this target (or max_paths is reached)
+    #[serde(default)]
+    #[validate(range(exclusive_min = 0.0, message = "target_se must be positive"))]
+    pub target_se: Option<f64>,
+    #[serde(default)]
+    #[validate(range(min = 1, message = "max_paths must be at least 1"))]
+    pub max_paths: Option<usize>,
+    // Monte Carlo only: sample the loss tail more densely and reweight
+    // (max_paths, if set, is the path budget)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(nested)]
+    pub importance: Option<Importance>,
+    // Debug: recompute the result in high precision and report the gap
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub verify: bool,
+    // Adds the result in a few plain sentences (see `summary`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(nested)]
+    pub summary: Option<SummaryOptions>,
+    // Adds the intermediate values behind the result (see `steps`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub debug_steps: bool,
+}
+
+fn default_horizon() -> u32 {
+    1
+}
+
+// Importance sampling of a Monte Carlo run
+#[derive(Clone, Copy, Serialize, Deserialize, Validate)]
+pub struct Importance {
+    // Shift of the sampling distribution into the loss tail, in standard
+    // deviations (default: the confidence's normal quantile, which centres
+    // the draws on the VaR)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 0.0, max = 10.0, message = "tilt must be between 0 and 10"))]
+    pub tilt: Option<f64>,
+}
+
+fn check_sample(req: &VarRequest) -> Result<(), ValidationError> {
+    req.params.check_weights(req.returns.len())?;
+    if req.params.weights.is_some() && req.lookback.is_some_and(|l| l < req.returns.len()) {
+        return Err(cross_field("lookback", "weights cover the whole sample; drop lookback or trim both".into()));
+    }
+    if req.importance.is_some() && (req.method != "montecarlo" || req.target_se.is_some()) {
+        return Err(cross_field("importance", "importance sampling applies to fixed-budget montecarlo only".into()));
+    }
+    if req.params.n_sims.is_some() && req.target_se.is_some() {
+        return Err(cross_field("n_sims", "n_sims fixes the budget; a target_se run is bounded by max_paths".into()));
+    }
+    horizon::check(req, req.horizon_days)?;
+    check_memory(req)?;
+    if req.params.deterministic && is_simulated(&req.method) && req.params.seed.is_none() {
+        return Err(cross_field("seed", format!("deterministic {} needs a seed", req.method)));
+    }
+    req.short_history().map(|_| ()).map_err(|e| cross_field("returns", e))
+}
+
+// Peak memory of an evaluation, in f64s, against the budget: the returns in
+// a few copies (prepared, sorted, verified, simulated from), the method's
+// draws and a simulated horizon's paths
+fn check_memory(req: &VarRequest) -> Result<(), ValidationError> {
+    let fixed = 4 * req.returns.len();
+    let paths = defaults::mc_paths(req);
+    match (req.method.as_str(), req.target_se, req.importance) {
+        // Draws, their weights and outcomes, and the tail's weights
+        ("montecarlo", None, Some(_)) => {
+            let max_paths = req.max_paths.unwrap_or(paths);
+            let advice = format!("lower max_paths to at most {}", budget::fits(fixed, 4));
+            budget::check(fixed + 4 * max_paths, "max_paths", advice)
+        }
+        // Only the tail and the batch being drawn are held
+        ("montecarlo", Some(_), _) => {
+            let p = 1.0 - req.confidence;
+            let max_paths = req.max_paths.unwrap_or(DEFAULT_MAX_PATHS);
+            let most = (budget::fits(fixed + MC_BATCH, 1) as f64 / p) as usize;
+            let advice = format!("lower max_paths to at most {most}");
+            budget::check(fixed + MC_BATCH + (p * max_paths as f64) as usize, "max_paths", advice)
+        }
+        ("montecarlo", None, None) => {
+            let instead = match req.simulates_horizon() {
+                true => "use horizon_scaling sqrt_time, which simulates no paths",
+                false => "set target_se: an adaptive run keeps only the tail of its paths",
+            };
+            let per_path = if req.simulates_horizon() { 2 } else { 1 };
+            let advice = format!("lower n_sims to at most {}, or {instead}", budget::fits(fixed, per_path));
+            budget::check(fixed + paths * per_path, "n_sims", advice)
+        }
+        (method, ..) => {
+            // The resample being evaluated, and every resample's VaR and ES
+            let samples = req.params.bootstrap_samples.unwrap_or(DEFAULT_BOOTSTRAP_SAMPLES);
+            let fixed = if method == "bootstrap" { fixed + req.returns.len() + 2 * samples } else { fixed };
+            if !req.simulates_horizon() {
+                return budget::check(fixed, "returns", "send a shorter series".into());
+            }
+            let advice = format!(
+                "lower n_sims to at most {}, or use horizon_scaling sqrt_time, which simulates no paths",
+                budget::fits(fixed, 1)
+            );
+            budget::check(fixed + paths, "n_sims", advice)
+        }
+    }
+}
+
+impl VarRequest {
+    /// The history policy's verdict on the sample: a warning, if any, or
+    /// the reason it is refused.
+    pub fn short_history(&self) -> Result<Option<String>, String> {
+        let min = self.min_history.unwrap_or_else(|| history::min_history(&self.method));
+        let n = self.lookback.map_or(self.returns.len(), |l| l.min(self.returns.len()));
+        history::check_series(HistoryPolicy::resolve(self.history_policy, false), n, min)
+    }
+
+    /// Whether identical requests always yield identical results.
+    pub fn is_deterministic(&self) -> bool {
+        !self.is_simulated() || self.params.seed.is_some()
+    }
+
+    /// Whether the h-day VaR comes from simulated paths rather than √h; a
+    /// one-day horizon needs no scaling either way.
+    pub fn simulates_horizon(&self) -> bool {
+        self.horizon_scaling == HorizonScaling::Simulated && self.horizon_days > 1
+    }
+
+    /// Whether the request draws random numbers, in its method or its horizon.
+    pub fn is_simulated(&self) -> bool {
+        is_simulated(&self.method) || self.simulates_horizon()
+    }
+}
+
+/// Whether `method` draws random numbers, so repeats differ unless seeded.
+pub fn is_simulated(method: &str) -> bool {
+    matches!(method, "montecarlo" | "bootstrap")
+}
+
+// Outcome of a Monte Carlo run driven by a precision target
+pub struct McRun {
+    pub var: f64,
+    pub es: f64,
+    pub paths: usize,
+    pub std_error: f64,
+}
+
+/// Simulation RNG: seeded when the caller asked for reproducibility.
+fn rng_for(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Sizes the rayon pool simulations run on from `MC_THREADS` (default: one
+/// thread per core).
+pub fn init_simulation_pool() {
+    let threads = std::env::var("MC_THREADS").ok().and_then(|v| v.parse().ok()).unwrap_or(0);
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).thread_name(|i| format!("sim-{i}"));
+    if let Err(e) = pool.build_global() {
+        eprintln!("⚠️ Simulation pool: {e}");
+    }
+}
+
+/// Seed for a parallel simulation, whose chunks each derive their own
+/// generator from it.
+pub fn simulation_seed(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(rand::random)
+}
+
+pub fn mean_std(returns: &[f64]) -> (f64, f64) {
+    Summation::Sequential.mean_std(returns)
+}
+
+/// Exponential age weights for `n` observations ordered oldest first:
+/// w = λ^age (1 - λ) / (1 - λ^n), summing to one.
+pub fn exponential_weights(n: usize, lambda: f64) -> Vec<f64> {
+    assert!(lambda > 0.0 && lambda < 1.0, "lambda must be in (0, 1)");
+    let norm = (1.0 - lambda) / (1.0 - lambda.powi(n as i32));
+    (0..n).map(|i| norm * lambda.powi((n - 1 - i) as i32)).collect()
+}
+
+/// Weighted empirical VaR/ES: observations sorted ascending accumulate
+/// probability mass until the tail probability is reached.
+pub fn weighted_var_es(returns: &[f64], weights: &[f64], confidence: f64) -> (f64, f64) {
+    let alpha = 1.0 - confidence;
+    let mut pairs: Vec<(f64, f64)> = returns.iter().copied().zip(weights.iter().copied()).collect();
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let (mut mass, mut tail_sum) = (0.0, 0.0);
+    for &(r, w) in &pairs {
+        if mass + w >= alpha {
+            tail_sum += (alpha - mass) * r;
+            return (-r, -tail_sum / alpha);
+        }
+        mass += w;
+        tail_sum += w * r;
+    }
+    let worst = pairs.last().map_or(0.0, |p| p.0);
+    (-worst, -tail_sum / mass.max(f64::EPSILON))
+}
+
+/// Observation weights for weighted historical simulation: the client's
+/// explicit weights normalised to sum to one, else BRW age weights.
+pub fn historical_weights(n: usize, params: &MethodParams) -> Vec<f64> {
+    match &params.weights {
+        Some(weights) => {
+            assert_eq!(weights.len(), n, "one weight per observation");
+            let total = params.summation().sum(weights);
+            weights.iter().map(|w| w / total).collect()
+        }
+        None => exponential_weights(n, brw_lambda(params)),
+    }
+}
+
+/// Decay factor of BRW age weighting: the client's, else the default.
+pub fn brw_lambda(params: &MethodParams) -> f64 {
+    params.lambda.unwrap_or(DEFAULT_BRW_LAMBDA)
+}
+
+/// Decay factor of the EWMA volatility: the client's, else RiskMetrics' 0.94.
+pub fn ewma_lambda(params: &MethodParams) -> f64 {
+    params.lambda.unwrap_or(DEFAULT_EWMA_LAMBDA)
+}
+
+/// RiskMetrics volatility forecast σ² = Σ wᵢ rᵢ², with the exponential age
+/// weights of `exponential_weights` and the mean taken as zero: the
+/// normalised, finite-sample form of σ²ₜ = λσ²ₜ₋₁ + (1 − λ)r²ₜ₋₁.
+pub fn ewma_volatility(returns: &[f64], params: &MethodParams) -> f64 {
+    let weights = exponential_weights(returns.len(), ewma_lambda(params));
+    let terms: Vec<f64> = returns.iter().zip(&weights).map(|(r, w)| w * r * r).collect();
+    params.summation().sum(&terms).sqrt()
+}
+
+/// One-step-ahead RiskMetrics volatilities of `returns` around a zero mean,
+/// σ²ₜ = λσ²ₜ₋₁ + (1 − λ)r²ₜ₋₁ started at the mean square, and the next
+/// day's forecast.
+pub fn ewma_volatilities(returns: &[f64], params: &MethodParams) -> (Vec<f64>, f64) {
+    let lambda = ewma_lambda(params);
+    let squares: Vec<f64> = returns.iter().map(|r| r * r).collect();
+    let mut variance = params.summation().sum(&squares) / returns.len() as f64;
+    let mut path = Vec::with_capacity(returns.len());
+    for r in returns {
+        path.push(variance.sqrt());
+        variance = lambda * variance + (1.0 - lambda) * r * r;
+    }
+    (path, variance.sqrt())
+}
+
+// Returns of a filtered historical simulation with how they were filtered
+pub struct Filtered {
+    pub scenarios: Vec<f64>,
+    pub model: VolatilityModel,
+    pub forecast_volatility: f64,
+    pub garch: Option<garch::Garch>,
+}
+
+/// Filtered historical simulation (Barone-Adesi et al.): each return is
+/// standardised by its day's conditional volatility, zₜ = (rₜ − μ)/σₜ, and
+/// rescaled by the forecast for tomorrow, μ + σₙ₊₁zₜ, so the sample's shapes
+/// are kept at today's volatility. μ is zero under EWMA, the fitted mean
+/// under GARCH.
+pub fn filtered_returns(returns: &[f64], params: &MethodParams) -> Filtered {
+    let model = params.volatility_model.unwrap_or_default();
+    let (mean, garch, (path, forecast)) = match model {
+        VolatilityModel::Ewma => (0.0, None, ewma_volatilities(returns, params)),
+        VolatilityModel::Garch => {
+            let fit = garch::fit(returns, params);
+            (fit.mean, Some(fit), fit.volatilities(returns, params))
+        }
+    };
+    let scenarios = returns
+        .iter()
+        .zip(&path)
+        .map(|(r, s)| if *s > 0.0 { mean + forecast * (r - mean) / s } else { mean })
+        .collect();
+    Filtered { scenarios, model, forecast_volatility: forecast, garch }
+}
+
+/// Sample skewness m₃/σ³ and excess kurtosis m₄/σ⁴ − 3 (population moments).
+pub fn higher_moments(returns: &[f64], params: &MethodParams) -> (f64, f64) {
+    let sum = params.summation();
+    let (mean, std) = sum.mean_std(returns);
+    let n = returns.len() as f64;
+    let third: Vec<f64> = returns.iter().map(|r| (r - mean).powi(3)).collect();
+    let fourth: Vec<f64> = returns.iter().map(|r| (r - mean).powi(4)).collect();
+    (sum.sum(&third) / n / std.powi(3), sum.sum(&fourth) / n / std.powi(4) - 3.0)
+}
+
+/// Degrees of freedom of parametric_t: the client's, else the method-of-
+/// moments fit ν = 4 + 6/κ to the sample's excess kurtosis κ, capped at
+/// `MAX_FITTED_DOF` when the tails are no fatter than the normal's.
+pub fn student_t_dof(returns: &[f64], params: &MethodParams) -> f64 {
+    if let Some(dof) = params.dof {
+        return dof;
+    }
+    let (_, excess_kurtosis) = higher_moments(returns, params);
+    if excess_kurtosis > 6.0 / (MAX_FITTED_DOF - 4.0) {
+        4.0 + 6.0 / excess_kurtosis
+    } else {
+        MAX_FITTED_DOF
+    }
+}
+
+/// Standard-normal quantile the parametric method scales volatility by:
+/// Φ⁻¹(confidence), so 1.645 at 95% and 2.326 at 99%.
+pub fn parametric_z(confidence: f64) -> f64 {
+    Gaussian::standard().inverse_cdf(confidence)
+}
+
+/// VaR with its sign convention spelled out: `quantile_return` is the return
+/// at the tail quantile (negative for a loss), `loss_fraction` its negation.
+/// ES, the mean loss beyond the VaR, follows the same convention.
+#[derive(Serialize)]
+pub struct VarDetail {
+    pub convention: &'static str,
+    pub loss_fraction: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loss_amount: Option<f64>,
+    pub es_fraction: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub es_amount: Option<f64>,
+    pub quantile_return: f64,
+    pub confidence: f64,
+    pub horizon_days: u32,
+}
+
+impl VarDetail {
+    fn new(var: f64, es: f64, req: &VarRequest) -> Self {
+        VarDetail {
+            convention: "loss_positive",
+            loss_fraction: var,
+            loss_amount: req.value.map(|v| v * var),
+            es_fraction: es,
+            es_amount: req.value.map(|v| v * es),
+            quantile_return: -var,
+            confidence: req.confidence,
+            horizon_days: req.horizon_days,
+        }
+    }
+}
+
+/// The assumptions behind a VaR figure, flattened into the response next to
+/// it so every number can be shown with what produced it.
+#[derive(Serialize)]
+pub struct VarResult {
+    pub var: f64,
+    pub method: String,
     pub confidence: f64,
+    pub sample_size: usize,
+    // One-period moments of the returns the method saw (after units and costs)
+    pub mean: f64,
+    pub std_dev: f64,
+    // Zero-based rank of the VaR order statistic in the ascending sample (or
+    // in each resample, or in the simulated paths); empirical methods only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quantile_index: Option<usize>,
+    // Simulated paths, Monte Carlo only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n_sims: Option<usize>,
 }
 
-pub fn compute_var(method: &str, returns: &mut Vec<f64>, confidence: f64) -> f64 {
-    match method {
-        "historical" => {
-            returns.sort_by(|a, b| a.partial_cmp(b).unwrap());
-            let idx = ((1.0 - confidence) * returns.len() as f64).floor() as usize;
-            -returns[idx]
+// How much of the sample actually drives a weighted estimate
+#[derive(Serialize)]
+pub struct Weighting {
+    pub lambda: Option<f64>,
+    pub half_life: Option<f64>,
+    pub effective_observations: f64,
+    pub observations: usize,
+}
+
+/// Half-life (ln 0.5 / ln λ, age weighting only) and Kish effective sample
+/// size (Σw)² / Σw² for weighted methods; `None` for equally weighted ones.
+pub fn weighting(method: &str, n: usize, params: &MethodParams) -> Option<Weighting> {
+    let (lambda, weights) = match method {
+        "weighted_historical" => {
+            let lambda = params.weights.is_none().then(|| brw_lambda(params));
+            (lambda, historical_weights(n, params))
+        }
+        "ewma" => (Some(ewma_lambda(params)), exponential_weights(n, ewma_lambda(params))),
+        _ => return None,
+    };
+    let sum: f64 = weights.iter().sum();
+    let sum_sq: f64 = weights.iter().map(|w| w * w).sum();
+    Some(Weighting {
+        lambda,
+        half_life: lambda.map(|l| 0.5_f64.ln() / l.ln()),
+        effective_observations: sum * sum / sum_sq,
+        observations: n,
+    })
+}
+
+/// Rank of the VaR order statistic among `n` ascending values: ⌊(1 − c)·n⌋.
+pub fn quantile_index(n: usize, confidence: f64) -> usize {
+    ((1.0 - confidence) * n as f64).floor() as usize
+}
+
+/// The VaR order statistic and the ES (mean of the tail up to and including
+/// it), both as positive losses. Quickselect puts the order statistic in
+/// place with only smaller values before it, in O(n) rather than a sort's
+/// O(n log n); the values are left partially ordered.
+pub fn empirical_var_es(values: &mut [f64], confidence: f64, sum: Summation) -> (f64, f64) {
+    let idx = quantile_index(values.len(), confidence);
+    values.select_nth_unstable_by(idx, |a, b| a.total_cmp(b));
+    let tail = &values[..=idx];
+    (-values[idx], -sum.sum(tail) / tail.len() as f64)
+}
+
+// Outcome of a bootstrap run
+pub struct Bootstrap {
+    pub var: f64,
+    pub es: f64,
+    pub samples: usize,
+    // Spread of the resampled estimates: the standard error of a single
+    // historical VaR / ES, and the central 95% of the VaRs
+    pub var_std_error: f64,
+    pub es_std_error: f64,
+    pub var_interval: (f64, f64),
+}
+
+/// Bootstrap VaR/ES: `samples` resamples of the returns drawn with
+/// replacement, historical simulation on each, the estimates averaged.
+pub fn bootstrap_var_es(returns: &[f64], confidence: f64, params: &MethodParams) -> Result<Bootstrap, VarError> {
+    let samples = params.bootstrap_samples.unwrap_or(DEFAULT_BOOTSTRAP_SAMPLES);
+    let sum = params.summation();
+    let mut rng = rng_for(params.seed);
+    let n = returns.len();
+    let (mut vars, es): (Vec<f64>, Vec<f64>) = (0..samples)
+        .map(|_| {
+            params.cancel.check()?;
+            let mut resample: Vec<f64> = (0..n).map(|_| returns[rng.gen_range(0..n)]).collect();
+            Ok(empirical_var_es(&mut resample, confidence, sum))
+        })
+        .collect::<Result<Vec<_>, VarError>>()?
+        .into_iter()
+        .unzip();
+    let (var, var_std_error) = sum.mean_std(&vars);
+    let (es_mean, es_std_error) = sum.mean_std(&es);
+    vars.sort_by(|a, b| a.total_cmp(b));
+    let at = |q: f64| vars[((q * samples as f64).floor() as usize).min(samples - 1)];
+    Ok(Bootstrap { var, es: es_mean, samples, var_std_error, es_std_error, var_interval: (at(0.025), at(0.975)) })
+}
+
+/// Rejects samples no method can estimate from: fewer than two returns, or
+/// any that is not finite (a zero or missing price upstream).
+pub fn usable_sample(returns: &[f64]) -> Result<(), VarError> {
+    if returns.len() < 2 {
+        return Err(VarError::InsufficientData { needed: 2, got: returns.len() });
+    }
+    match returns.iter().position(|r| !r.is_finite()) {
+        Some(i) => Err(VarError::InvalidInput(format!("return {i} is not finite ({})", returns[i]))),
+        None => Ok(()),
+    }
+}
+
+/// VaR and expected shortfall for the given method. `returns` must be in
+/// chronological order for age-weighted methods.
+pub fn compute_var_es(
+    method: &str,
+    returns: &mut [f64],
+    confidence: f64,
+    params: &MethodParams,
+) -> Result<(f64, f64), VarError> {
+    usable_sample(returns)?;
+    Ok(match method {
+        "historical" => empirical_var_es(returns, confidence, params.summation()),
+        "bootstrap" => {
+            let run = bootstrap_var_es(returns, confidence, params)?;
+            (run.var, run.es)
+        }
+        "filtered_historical" => {
+            let mut scenarios = filtered_returns(returns, params).scenarios;
+            empirical_var_es(&mut scenarios, confidence, params.summation())
+        }
+        // Boudoukh–Richardson–Whitelaw age-weighted historical simulation
+        "weighted_historical" => {
+            params
+                .check_weights(returns.len())
+                .map_err(|e| VarError::InvalidInput(e.message.unwrap_or_default().into_owned()))?;
+            let weights = historical_weights(returns.len(), params);
+            weighted_var_es(returns, &weights, confidence)
         }
         "parametric" => {
-            let mean = returns.iter().sum::<f64>() / returns.len() as f64;
-            let std = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64).sqrt();
-            let z = 1.644853;
-            -(mean - z * std)
+            let (mean, std) = params.summation().mean_std(returns);
+            let z = parametric_z(confidence);
+            let density = (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt();
+            (-(mean - z * std), -(mean - std * density / (1.0 - confidence)))
+        }
+        // RiskMetrics: the normal model around a zero mean, with the
+        // volatility weighted towards recent returns
+        "ewma" => {
+            let std = ewma_volatility(returns, params);
+            let z = parametric_z(confidence);
+            let density = (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt();
+            (z * std, std * density / (1.0 - confidence))
+        }
+        "garch" => garch::fit(returns, params).var_es(confidence),
+        // Student-t scaled to the sample's variance: s = σ·√((ν − 2)/ν);
+        // ES of the standard t is g(q)/α · (ν + q²)/(ν − 1)
+        "parametric_t" => {
+            let (mean, std) = params.summation().mean_std(returns);
+            let dof = student_t_dof(returns, params);
+            let t = StudentsT::new(0.0, 1.0, dof)
+                .map_err(|e| VarError::Numerical(format!("Student-t with {dof} degrees of freedom: {e}")))?;
+            let q = t.inverse_cdf(confidence);
+            let scale = std * ((dof - 2.0) / dof).sqrt();
+            let tail = t.pdf(q) / (1.0 - confidence) * (dof + q * q) / (dof - 1.0);
+            (-(mean - scale * q), -(mean - scale * tail))
+        }
+        // Modified VaR: the normal quantile z = Φ⁻¹(α) corrected for skewness S
+        // and excess kurtosis K by the Cornish–Fisher expansion
+        //   z + (z² − 1)S/6 + (z³ − 3z)K/24 − (2z³ − 5z)S²/36,
+        // and ES as the same expansion's mean over the normal tail below z
+        "cornish_fisher" => {
+            let (mean, std) = params.summation().mean_std(returns);
+            let (s, k) = higher_moments(returns, params);
+            let alpha = 1.0 - confidence;
+            let z = -parametric_z(confidence);
+            let quantile = z + (z * z - 1.0) * s / 6.0 + (z.powi(3) - 3.0 * z) * k / 24.0
+                - (2.0 * z.powi(3) - 5.0 * z) * s * s / 36.0;
+            let density = (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt();
+            let tail_mean = -density / alpha
+                * (1.0 + z * s / 6.0 + (z * z - 1.0) * k / 24.0 - (2.0 * z * z - 1.0) * s * s / 36.0);
+            (-(mean + std * quantile), -(mean + std * tail_mean))
         }
         "montecarlo" => {
-            let mean = returns.iter().sum::<f64>() / returns.len() as f64;
-            let std = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64).sqrt();
-            let normal = Normal::new(mean, std).unwrap();
-            let mut rng = rand::thread_rng();
-            let mut sims: Vec<f64> = (0..10_000).map(|_| normal.sample(&mut rng)).collect();
-            sims.sort_by(|a, b| a.partial_cmp(b).unwrap());
-            let idx = ((1.0 - confidence) * sims.len() as f64).floor() as usize;
-            -sims[idx]
-        }
-        _ => panic!("Unknown method"),
+            let (mean, std) = params.summation().mean_std(returns);
+            let generator = scenarios::Normal::univariate(mean, std);
+            let seed = simulation_seed(params.seed);
+            let mut sims = generator.par_portfolio(&[1.0], params.mc_paths(), seed, &params.cancel)?;
+            empirical_var_es(&mut sims, confidence, params.summation())
+        }
+        _ => return Err(VarError::UnknownMethod(method.to_string())),
+    })
+}
+
+/// A request's returns as the methods see them: in decimals and net of costs.
+pub struct Prepared {
+    pub returns: Vec<f64>,
+    // Units the input was read as
+    pub units: Units,
+    pub warnings: Vec<String>,
+    pub cost_drag: Option<f64>,
+}
+
+pub fn prepare(req: &VarRequest) -> Result<Prepared, VarError> {
+    let mut returns = req.returns.clone();
+    let (units, warnings) = units::normalize(&mut returns, req.units);
+    usable_sample(&returns)?;
+    let cost_drag = req.costs.map(|c| c.apply(&mut returns));
+    Ok(Prepared { returns, units, warnings, cost_drag })
+}
+
+/// Runs a VaR request end to end (units, costs, method dispatch) into a JSON body
+/// stamped with the engine version.
+pub fn evaluate(req: &VarRequest) -> Result<Value, VarError> {
+    evaluate_with(req, None)
+}
+
+/// `evaluate`, with adaptive Monte Carlo progress checkpointed through
+/// `checkpoints` so an interrupted run can resume.
+pub fn evaluate_with(req: &VarRequest, checkpoints: Option<Checkpoints>) -> Result<Value, VarError> {
+    let (resolved, mut parameters) = defaults::resolve(req);
+    let truncation = defaults::truncation_warning(req);
+    let req = &resolved;
+    let Prepared { mut returns, units, mut warnings, cost_drag } = prepare(req)?;
+    warnings.extend(truncation);
+    warnings.extend(req.short_history().ok().flatten());
+    let (mean, std_dev) = req.params.summation().mean_std(&returns);
+    let n = returns.len();
+    // Square-root-of-time rule: daily returns are taken as i.i.d. Simulated
+    // horizons keep the one-day figures and take the h-day ones from paths.
+    let sqrt_time = f64::from(req.horizon_days).sqrt();
+    let scale = if req.simulates_horizon() { 1.0 } else { sqrt_time };
+    let daily = req.simulates_horizon().then(|| returns.clone());
+
+    // compute_var_es may sort the returns; verification needs them as given
+    let verifying = req.verify || verify::sampled();
+    let sample = verifying.then(|| returns.clone());
+    let steps_sample = req.debug_steps.then(|| returns.clone());
+
+    // Simulated paths (an adaptive run's once it stops), then where the
+    // VaR's order statistic sits among them
+    let mut n_sims = match (req.method.as_str(), req.target_se, req.importance) {
+        ("montecarlo", None, Some(_)) => Some(req.max_paths.unwrap_or_else(|| req.params.mc_paths())),
+        ("montecarlo", None, None) => Some(req.params.mc_paths()),
+        _ => None,
+    };
+    let mut quantile_index = match (req.method.as_str(), n_sims) {
+        ("historical" | "bootstrap" | "filtered_historical", _) => Some(quantile_index(n, req.confidence)),
+        ("montecarlo", Some(paths)) if req.importance.is_none() => Some(quantile_index(paths, req.confidence)),
+        _ => None,
+    };
+
+    let (var, es, mut body) = match (req.method.as_str(), req.target_se) {
+        ("montecarlo", None) if req.importance.is_some() => {
+            let tilt = req.importance.and_then(|i| i.tilt).unwrap_or_else(|| parametric_z(req.confidence));
+            let paths = n_sims.unwrap_or_default();
+            let run = montecarlo_importance(&returns, req.confidence, paths, tilt, &req.params)?;
+            let importance = json!({
+                "tilt": tilt,
+                "paths": paths,
+                "effective_sample_size": run.effective_sample_size,
+                "tail_paths": run.tail_paths,
+            });
+            (run.var, run.es, json!({ "importance": importance }))
+        }
+        ("montecarlo", Some(target_se)) => {
+            let max_paths = req.max_paths.unwrap_or(DEFAULT_MAX_PATHS);
+            let run = montecarlo_adaptive(&returns, req.confidence, target_se, max_paths, &req.params, checkpoints)?;
+            n_sims = Some(run.paths);
+            (run.var, run.es, json!({ "paths": run.paths, "std_error": run.std_error * scale }))
+        }
+        ("bootstrap", _) => {
+            let run = bootstrap_var_es(&returns, req.confidence, &req.params)?;
+            let bootstrap = json!({
+                "samples": run.samples,
+                "var_std_error": run.var_std_error * scale,
+                "es_std_error": run.es_std_error * scale,
+                "var_interval": [
+                    req.return_type.simple_loss(run.var_interval.0 * scale),
+                    req.return_type.simple_loss(run.var_interval.1 * scale),
+                ],
+            });
+            (run.var, run.es, json!({ "bootstrap": bootstrap }))
+        }
+        ("filtered_historical", _) => {
+            let mut filtered = filtered_returns(&returns, &req.params);
+            let (var, es) = empirical_var_es(&mut filtered.scenarios, req.confidence, req.params.summation());
+            let mut filter = json!({
+                "volatility_model": filtered.model,
+                "forecast_volatility": filtered.forecast_volatility,
+            });
+            match filtered.garch {
+                Some(fit) => filter["garch"] = json!(fit),
+                None => filter["lambda"] = json!(ewma_lambda(&req.params)),
+            }
+            (var, es, json!({ "filter": filter }))
+        }
+        // The fitted parameters are reported for diagnostics
+        ("garch", _) => {
+            let fit = garch::fit(&returns, &req.params);
+            let (var, es) = fit.var_es(req.confidence);
+            (var, es, json!({ "garch": fit }))
+        }
+        _ => {
+            let (var, es) = compute_var_es(&req.method, &mut returns, req.confidence, &req.params)?;
+            (var, es, json!({}))
+        }
+    };
+    let verification = sample
+        .map(|sample| verify::check(&req.method, &sample, req.confidence, &req.params, var, es, (mean, std_dev)));
+    let (one_day_var, one_day_es) = (var, es);
+    let (var, es, horizon) = match daily {
+        Some(daily) => {
+            let paths = req.params.mc_paths();
+            let model = DailyModel::fit(&req.method, &daily, &req.params)?;
+            let mut sims = model.simulate(&[req.horizon_days], paths, req.return_type, &req.params)?.remove(0);
+            let (var, es) = empirical_var_es(&mut sims, req.confidence, req.params.summation());
+            n_sims = Some(paths);
+            quantile_index = Some(self::quantile_index(paths, req.confidence));
+            let loss = |x: f64| req.return_type.simple_loss(x);
+            let horizon = json!({
+                "paths": paths,
+                "one_day_var": loss(one_day_var),
+                "one_day_es": loss(one_day_es),
+                "sqrt_time_var": loss(one_day_var * sqrt_time),
+                "sqrt_time_es": loss(one_day_es * sqrt_time),
+            });
+            (var, es, Some(horizon))
+        }
+        None => (var * scale, es * scale, None),
+    };
+    // Log-return losses L become fractions of value lost, 1 − e^(−L)
+    let log_returns = (!req.return_type.is_simple()).then(|| json!({ "var": var, "es": es }));
+    let (var, es) = (req.return_type.simple_loss(var), req.return_type.simple_loss(es));
+    // Bare positive loss kept for existing clients; var_detail labels it
+    let result = VarResult {
+        var,
+        method: req.method.clone(),
+        confidence: req.confidence,
+        sample_size: n,
+        mean,
+        std_dev,
+        quantile_index,
+        n_sims,
+    };
+    if let (Some(body), Value::Object(fields)) = (body.as_object_mut(), json!(result)) {
+        body.extend(fields);
+    }
+    body["es"] = json!(es);
+    body["var_detail"] = json!(VarDetail::new(var, es, req));
+    body["horizon_days"] = json!(req.horizon_days);
+    body["horizon_scaling"] = json!(req.horizon_scaling);
+    body["return_type"] = json!(req.return_type);
+    if let Some(log_returns) = log_returns {
+        body["log_returns"] = log_returns;
+    }
+    if let Some(horizon) = horizon {
+        body["horizon"] = horizon;
+    }
+    if let Some(profile) = &req.profile {
+        body["profile"] = json!(profile);
+    }
+    if let Some(w) = weighting(&req.method, returns.len(), &req.params) {
+        body["weighting"] = json!(w);
+    }
+    if req.method == "cornish_fisher" {
+        let (skewness, excess_kurtosis) = higher_moments(&returns, &req.params);
+        body["skewness"] = json!(skewness);
+        body["excess_kurtosis"] = json!(excess_kurtosis);
+    }
+    if req.method == "parametric_t" {
+        body["dof"] = json!(student_t_dof(&returns, &req.params));
+        body["dof_source"] = json!(if req.params.dof.is_some() { "supplied" } else { "fitted" });
+        let source = if req.params.dof.is_some() { Source::Supplied } else { Source::Fitted };
+        parameters.insert("dof", defaults::effective(&body["dof"], source));
+    }
+    if let Some(drag) = cost_drag {
+        body["cost_drag"] = json!(drag);
+    }
+    body["parameters"] = json!(parameters);
+    body["units"] = json!(units);
+    if req.params.deterministic {
+        body["summation"] = json!("fixed_order");
+    }
+    // The seed replays the run exactly
+    if req.is_simulated() {
+        body["seed"] = json!(req.params.seed);
+    }
+    if !warnings.is_empty() {
+        body["warnings"] = json!(warnings);
+    }
+    let sanity = sanity::check(var, Some(es));
+    if !sanity.is_empty() {
+        body["sanity"] = json!(sanity);
+    }
+    if let Some(v) = verification {
+        if !v.passed {
+            eprintln!(
+                "⚠️ High-precision verification of {} failed: relative error {:e} (tolerance {:e})",
+                req.method, v.max_relative_error, v.tolerance
+            );
+        }
+        if req.verify {
+            body["verification"] = json!(v);
+        }
+    }
+    if !req.confidences.is_empty() {
+        body["levels"] = json!(levels(req)?);
+    }
+    if let Some(sample) = steps_sample {
+        body["debug_steps"] = steps::steps(req, &sample, (one_day_var, one_day_es), &body)?;
+    }
+    if let Some(options) = &req.summary {
+        body["summary"] = json!(summary::var_summary(req, options, &body));
+    }
+    body["engine"] = json!(version::current());
+    Ok(body)
+}
+
+/// VaR/ES at each of `req.confidences`: the request re-run at that level, so
+/// a seeded simulation draws the same paths for every level.
+fn levels(req: &VarRequest) -> Result<Vec<Value>, VarError> {
+    req.confidences
+        .iter()
+        .map(|&confidence| {
+            let single =
+                VarRequest { confidence, confidences: Vec::new(), verify: false, summary: None, debug_steps: false, ..req.clone() };
+            let body = evaluate(&single)?;
+            let mut level = json!({
+                "confidence": confidence,
+                "var": body["var"],
+                "es": body["es"],
+                "var_detail": body["var_detail"],
+            });
+            if let Some(index) = body.get("quantile_index") {
+                level["quantile_index"] = index.clone();
+            }
+            Ok(level)
+        })
+        .collect()
+}
+
+// Outcome of an importance-sampled Monte Carlo run
+pub struct IsRun {
+    pub var: f64,
+    pub es: f64,
+    // Draws landing beyond the VaR, and their Kish effective sample size
+    // (Σw)² / Σw²; plain Monte Carlo would put (1 − confidence)·paths
+    // equally weighted draws there
+    pub tail_paths: usize,
+    pub effective_sample_size: f64,
+}
+
+/// Correlated multi-asset Monte Carlo: the assets' mean vector and
+/// covariance estimated from their aligned `columns`, Cholesky-factored
+/// (Σ = LLᵀ), `n_sims` joint draws μ + Lz aggregated into the portfolio's
+/// return Σ wᵢrᵢ, and the empirical quantile of that taken.
+pub fn portfolio_montecarlo(
+    columns: &[Vec<f64>],
+    weights: &[f64],
+    confidence: f64,
+    params: &MethodParams,
+) -> Result<(f64, f64), VarError> {
+    let generator = scenarios::Normal::fit(columns, params.summation());
+    let mut sims = generator.par_portfolio(weights, params.mc_paths(), simulation_seed(params.seed), &params.cancel)?;
+    Ok(empirical_var_es(&mut sims, confidence, params.summation()))
+}
+
+/// Monte Carlo VaR/ES with the normal draws shifted `tilt` standard
+/// deviations into the loss tail: u ~ N(−tilt, 1) instead of N(0, 1), each
+/// draw weighted by the likelihood ratio φ(u)/φ(u + tilt) = exp(tilt·u +
+/// tilt²/2) over the path count and the weighted quantile taken. The
+/// weights are not renormalised: their sum is dominated by the few draws
+/// on the profit side, which the tail never reads. Far more draws land in
+/// the tail, so 99%+ VaR and ES are much steadier for the same budget.
+pub fn montecarlo_importance(
+    returns: &[f64],
+    confidence: f64,
+    paths: usize,
+    tilt: f64,
+    params: &MethodParams,
+) -> Result<IsRun, VarError> {
+    let (mean, std) = params.summation().mean_std(returns);
+    let shifted = Normal::new(-tilt, 1.0).map_err(|e| VarError::Numerical(format!("tilt {tilt}: {e}")))?;
+    let mut rng = rng_for(params.seed);
+    let paths = paths.max(1);
+    let draws: Vec<f64> = (0..paths).map(|_| shifted.sample(&mut rng)).collect();
+    let weights: Vec<f64> = draws.iter().map(|u| (tilt * u + 0.5 * tilt * tilt).exp() / paths as f64).collect();
+    let outcomes: Vec<f64> = draws.iter().map(|u| mean + std * u).collect();
+    let (var, es) = weighted_var_es(&outcomes, &weights, confidence);
+
+    let tail: Vec<f64> = outcomes.iter().zip(&weights).filter(|(r, _)| **r <= -var).map(|(_, w)| *w).collect();
+    let sum = params.summation();
+    let squares: Vec<f64> = tail.iter().map(|w| w * w).collect();
+    let effective_sample_size = sum.sum(&tail).powi(2) / sum.sum(&squares);
+    Ok(IsRun { var, es, tail_paths: tail.len(), effective_sample_size })
+}
+
+/// Progress of an adaptive Monte Carlo run: paths drawn, the generator's
+/// state and the lowest draws, which are all the VaR and ES ever read.
+#[derive(Serialize, Deserialize)]
+pub struct McCheckpoint {
+    pub paths: usize,
+    pub rng: ChaCha12Rng,
+    pub tail: Vec<f64>,
+}
+
+/// Where an adaptive run saves its progress, every `every` batches, and the
+/// checkpoint it resumes from.
+pub struct Checkpoints<'a> {
+    pub every: usize,
+    pub resume: Option<McCheckpoint>,
+    pub save: &'a dyn Fn(&McCheckpoint),
+}
+
+/// Monte Carlo VaR simulated in batches until the asymptotic standard error of
+/// the quantile, sqrt(p(1-p)/n) / f(q), reaches `target_se` or `max_paths` is hit.
+/// Only the lowest p·max_paths + 1 draws are kept, selected rather than
+/// sorted: the quantile can never move past them, so the result equals
+/// sorting every draw.
+pub fn montecarlo_adaptive(
+    returns: &[f64],
+    confidence: f64,
+    target_se: f64,
+    max_paths: usize,
+    params: &MethodParams,
+    mut checkpoints: Option<Checkpoints>,
+) -> Result<McRun, VarError> {
+    let (mean, std) = params.summation().mean_std(returns);
+    let normal = scenarios::Normal::univariate(mean, std);
+    let max_paths = max_paths.max(1);
+    let p = 1.0 - confidence;
+    let keep = (p * max_paths as f64).floor() as usize + 1;
+
+    // A checkpoint that couldn't have come from this run is dropped: it would
+    // overrun max_paths or lack the draws the quantile needs
+    let resume = checkpoints.as_mut().and_then(|c| c.resume.take()).filter(|c| {
+        c.paths < max_paths && c.tail.len() >= keep.min((p * c.paths as f64).floor() as usize + 1)
+    });
+    let mut state = resume.unwrap_or_else(|| McCheckpoint {
+        paths: 0,
+        rng: match params.seed {
+            Some(seed) => ChaCha12Rng::seed_from_u64(seed),
+            None => ChaCha12Rng::from_entropy(),
+        },
+        tail: Vec::new(),
+    });
+    let mut batches = 0;
+    loop {
+        params.cancel.check()?;
+        let batch = MC_BATCH.min(max_paths - state.paths);
+        let rng = &mut state.rng;
+        state.tail.extend(normal.portfolio(&[1.0], batch, rng));
+        if state.tail.len() > keep {
+            state.tail.select_nth_unstable_by(keep - 1, |a, b| a.total_cmp(b));
+            state.tail.truncate(keep);
+        }
+        state.paths += batch;
+        let idx = (p * state.paths as f64).floor() as usize;
+        let q = *state.tail.select_nth_unstable_by(idx, |a, b| a.total_cmp(b)).1;
+
+        let density = (-0.5 * ((q - mean) / std).powi(2)).exp() / (std * (2.0 * std::f64::consts::PI).sqrt());
+        let std_error = (p * (1.0 - p) / state.paths as f64).sqrt() / density;
+        if std_error <= target_se || state.paths >= max_paths {
+            let es = -params.summation().sum(&state.tail[..=idx]) / (idx + 1) as f64;
+            return Ok(McRun { var: -q, es, paths: state.paths, std_error });
+        }
+        batches += 1;
+        if let Some(c) = checkpoints.as_ref().filter(|c| batches % c.every.max(1) == 0) {
+            (c.save)(&state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Φ⁻¹ to 16 significant digits
+    const QUANTILES: [(f64, f64); 4] = [
+        (0.90, 1.281551565544601),
+        (0.95, 1.644853626951473),
+        (0.99, 2.326347874040841),
+        (0.999, 3.090232306167814),
+    ];
+
+    #[test]
+    fn parametric_z_follows_confidence() {
+        for (confidence, z) in QUANTILES {
+            assert!((parametric_z(confidence) - z).abs() < 1e-9, "z at {confidence}");
+        }
+    }
+
+    #[test]
+    fn parametric_var_and_es_scale_with_confidence() {
+        // Mean 0, population standard deviation 1
+        let returns = [-1.0, 1.0];
+        let params = MethodParams::default();
+        for (confidence, z) in QUANTILES {
+            let (var, es) = compute_var_es("parametric", &mut returns.clone(), confidence, &params).unwrap();
+            let density = (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt();
+            assert!((var - z).abs() < 1e-9, "VaR at {confidence}");
+            assert!((es - density / (1.0 - confidence)).abs() < 1e-9, "ES at {confidence}");
+            assert!(es > var, "ES below VaR at {confidence}");
+        }
+    }
+
+    #[test]
+    fn unusable_inputs_are_errors_not_panics() {
+        let params = MethodParams::default();
+        let unknown = compute_var_es("nope", &mut [0.01, -0.02], 0.95, &params);
+        assert_eq!(unknown, Err(VarError::UnknownMethod("nope".into())));
+        let empty = compute_var_es("historical", &mut [], 0.95, &params);
+        assert_eq!(empty, Err(VarError::InsufficientData { needed: 2, got: 0 }));
+        let nan = compute_var_es("bootstrap", &mut [0.01, f64::NAN, -0.02], 0.95, &params);
+        assert!(matches!(nan, Err(VarError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn omitted_parameters_follow_the_series() {
+        let request = |method: &str, n: usize, confidence: f64| -> VarRequest {
+            let returns: Vec<f64> = (0..n).map(|i| 0.01 * ((i % 7) as f64 - 3.0)).collect();
+            serde_json::from_value(serde_json::json!({ "method": method, "returns": returns, "confidence": confidence }))
+                .unwrap()
+        };
+        // A short sample lowers the decay so its oldest return still counts
+        let (short, used) = defaults::resolve(&request("ewma", 50, 0.95));
+        let lambda = short.params.lambda.unwrap();
+        assert!(lambda < DEFAULT_EWMA_LAMBDA && lambda.powi(50) <= 0.01 + 1e-12, "lambda {lambda}");
+        assert_eq!(used["lambda"]["source"], "adaptive");
+        let (long, _) = defaults::resolve(&request("ewma", 5_000, 0.95));
+        assert_eq!(long.params.lambda, Some(DEFAULT_EWMA_LAMBDA));
+        assert_eq!(long.returns.len(), 1_000);
+        // Deep tails get more paths and a longer lookback
+        let (deep, used) = defaults::resolve(&request("montecarlo", 5_000, 0.999));
+        assert_eq!(deep.params.n_sims, Some(100_000));
+        assert_eq!(used["lookback"]["value"], 5_000);
+    }
+
+    #[test]
+    fn default_lookback_reports_what_it_left_out() {
+        let returns: Vec<f64> = (0..1_500).map(|i| 0.01 * ((i * 37 % 11) as f64 - 5.0)).collect();
+        let run = |lookback: Option<usize>| {
+            let mut req = serde_json::json!({ "method": "historical", "returns": returns, "confidence": 0.95 });
+            if let Some(lookback) = lookback {
+                req["lookback"] = serde_json::json!(lookback);
+            }
+            evaluate(&serde_json::from_value(req).unwrap()).unwrap()
+        };
+        let warned = |body: &Value| {
+            body["warnings"].as_array().into_iter().flatten().any(|w| w.as_str().unwrap().contains("default lookback"))
+        };
+
+        let cut = run(None);
+        assert_eq!(cut["sample_size"], 1_000);
+        let lookback = &cut["parameters"]["lookback"];
+        assert_eq!(lookback["value"], 1_000);
+        assert_eq!(lookback["source"], "adaptive");
+        assert_eq!(lookback["truncated"], true);
+        assert_eq!(lookback["available"], 1_500);
+        assert!(warned(&cut), "{}", cut["warnings"]);
+
+        // A lookback the request chose is flagged but not warned about
+        let chosen = run(Some(500));
+        assert_eq!(chosen["parameters"]["lookback"]["truncated"], true);
+        assert!(!warned(&chosen));
+        let whole = run(Some(1_500));
+        assert!(whole["parameters"]["lookback"].get("truncated").is_none());
+        assert!(!warned(&whole));
+    }
+
+    #[test]
+    fn debug_steps_reproduce_the_result() {
+        let returns: Vec<f64> = (0..200).map(|i| 0.01 * ((i * 37 % 11) as f64 - 5.0)).collect();
+        for method in ["historical", "parametric", "montecarlo"] {
+            let req: VarRequest = serde_json::from_value(serde_json::json!({
+                "method": method, "returns": returns, "confidence": 0.95, "horizon_days": 4, "seed": 1,
+                "debug_steps": true,
+            }))
+            .unwrap();
+            let body = evaluate(&req).unwrap();
+            let steps = &body["debug_steps"];
+            let one_day = match steps["method"]["quantile_return"].as_f64() {
+                Some(q) => (-q, -steps["method"]["tail_mean"].as_f64().unwrap()),
+                None => (steps["method"]["var"].as_f64().unwrap(), steps["method"]["es"].as_f64().unwrap()),
+            };
+            assert_eq!(steps["horizon"]["horizon_var"].as_f64(), Some(one_day.0 * 2.0), "{method}");
+            assert_eq!(steps["horizon"]["horizon_es"].as_f64(), Some(one_day.1 * 2.0), "{method}");
+            assert_eq!(body["var"], steps["horizon"]["horizon_var"], "{method}");
+        }
+    }
+
+    #[test]
+    fn a_checkpoint_past_the_path_budget_is_not_resumed() {
+        let returns: Vec<f64> = (0..250).map(|i| 0.01 * ((i * 37 % 11) as f64 - 5.0)).collect();
+        let params = MethodParams { seed: Some(3), ..Default::default() };
+        let fresh = montecarlo_adaptive(&returns, 0.99, 1e-9, 2 * MC_BATCH, &params, None).unwrap();
+
+        let rng = ChaCha12Rng::seed_from_u64(99);
+        for resume in [
+            McCheckpoint { paths: 3 * MC_BATCH, rng: rng.clone(), tail: vec![-0.05; 100] },
+            McCheckpoint { paths: MC_BATCH, rng, tail: Vec::new() },
+        ] {
+            let checkpoints = Checkpoints { every: 1, resume: Some(resume), save: &|_| {} };
+            let run = montecarlo_adaptive(&returns, 0.99, 1e-9, 2 * MC_BATCH, &params, Some(checkpoints)).unwrap();
+            assert_eq!((run.var, run.es, run.paths), (fresh.var, fresh.es, fresh.paths));
+        }
     }
 }
@@ -0,0 +1,185 @@
+use dashu_float::{round::mode::HalfEven, FBig};
+use serde::Serialize;
+use std::{env, sync::OnceLock};
+
+use crate::var::{brw_lambda, ewma_lambda, parametric_z, MethodParams};
+
+type Big = FBig<HalfEven, 2>;
+
+// Working precision of the reference computation, against f64's 53
+pub const PRECISION_BITS: usize = 256;
+// Largest relative gap between the f64 result and the reference that passes
+pub const TOLERANCE: f64 = 1e-9;
+
+/// Share of compute_var requests verified in the background, from
+/// `VERIFY_SAMPLE_RATE` (default 0).
+pub fn sample_rate() -> f64 {
+    static RATE: OnceLock<f64> = OnceLock::new();
+    *RATE.get_or_init(|| env::var("VERIFY_SAMPLE_RATE").ok().and_then(|v| v.parse().ok()).unwrap_or(0.0))
+}
+
+pub fn sampled() -> bool {
+    let rate = sample_rate();
+    rate > 0.0 && rand::random::<f64>() < rate
+}
+
+/// The f64 result next to a high-precision recomputation of it.
+#[derive(Serialize)]
+pub struct Verification {
+    pub precision_bits: usize,
+    pub tolerance: f64,
+    // What was recomputed: `var_es`, or for Monte Carlo the fitted
+    // `moments` the draws come from (the draws themselves are f64 by nature)
+    pub checked: &'static str,
+    pub reference: Vec<f64>,
+    pub computed: Vec<f64>,
+    pub max_relative_error: f64,
+    pub passed: bool,
+}
+
+fn big(x: f64) -> Big {
+    Big::try_from(x).expect("finite input").with_precision(PRECISION_BITS).value()
+}
+
+fn sum<'a>(values: impl IntoIterator<Item = &'a Big>) -> Big {
+    values.into_iter().fold(big(0.0), |acc, v| acc + v)
+}
+
+fn mean_std(values: &[f64]) -> (Big, Big) {
+    let values: Vec<Big> = values.iter().map(|v| big(*v)).collect();
+    let n = big(values.len() as f64);
+    let mean = sum(&values) / &n;
+    let squares: Vec<Big> = values.iter().map(|v| (v - &mean) * (v - &mean)).collect();
+    let std = (sum(&squares) / &n).sqrt();
+    (mean, std)
+}
+
+// π by Machin's formula, 16·atan(1/5) − 4·atan(1/239)
+fn pi() -> Big {
+    let atan_inv = |x: f64| {
+        let (x, x2) = (big(x), big(x * x));
+        let threshold = big(2f64.powi(-(PRECISION_BITS as i32) - 8));
+        let (mut power, mut total, mut k) = (big(1.0) / &x, big(0.0), 0u32);
+        loop {
+            let term = &power / big(f64::from(2 * k + 1));
+            if term < threshold {
+                return total;
+            }
+            total = if k % 2 == 0 { total + term } else { total - term };
+            power /= &x2;
+            k += 1;
+        }
+    };
+    big(16.0) * atan_inv(5.0) - big(4.0) * atan_inv(239.0)
+}
+
+// λ^age, oldest first, unnormalised
+fn age_weights(n: usize, lambda: f64) -> Vec<Big> {
+    let lambda = big(lambda);
+    let mut weights = vec![big(1.0); n];
+    for i in (0..n.saturating_sub(1)).rev() {
+        weights[i] = &weights[i + 1] * &lambda;
+    }
+    weights
+}
+
+// Standard normal density at z
+fn density(z: &Big) -> Big {
+    (-(z * z) / big(2.0)).exp() / (big(2.0) * pi()).sqrt()
+}
+
+/// VaR and ES by the definitions `var::compute_var_es` implements, in
+/// `PRECISION_BITS`-bit arithmetic.
+fn reference(method: &str, returns: &[f64], confidence: f64, params: &MethodParams) -> Option<(Big, Big)> {
+    let alpha = big(1.0 - confidence);
+    match method {
+        "historical" => {
+            let mut sorted = returns.to_vec();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let idx = ((1.0 - confidence) * sorted.len() as f64).floor() as usize;
+            let tail: Vec<Big> = sorted[..=idx].iter().map(|v| big(*v)).collect();
+            Some((-big(sorted[idx]), -sum(&tail) / big(tail.len() as f64)))
+        }
+        "weighted_historical" => {
+            let n = returns.len();
+            let raw: Vec<Big> = match &params.weights {
+                Some(weights) => weights.iter().map(|w| big(*w)).collect(),
+                None => age_weights(n, brw_lambda(params)),
+            };
+            let total = sum(&raw);
+            let mut pairs: Vec<(f64, Big)> = returns.iter().copied().zip(raw.into_iter().map(|w| w / &total)).collect();
+            pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            let (mut mass, mut tail_sum) = (big(0.0), big(0.0));
+            for (r, w) in &pairs {
+                let r = big(*r);
+                if &mass + w >= alpha {
+                    tail_sum += (&alpha - &mass) * &r;
+                    return Some((-r, -tail_sum / &alpha));
+                }
+                mass += w;
+                tail_sum += w * &r;
+            }
+            let worst = big(pairs.last().map_or(0.0, |p| p.0));
+            Some((-worst, -tail_sum / mass))
+        }
+        "parametric" => {
+            let (mean, std) = mean_std(returns);
+            let z = big(parametric_z(confidence));
+            Some((-(&mean - &z * &std), -(mean - std * density(&z) / alpha)))
+        }
+        "ewma" => {
+            let weights = age_weights(returns.len(), ewma_lambda(params));
+            let terms: Vec<Big> = returns.iter().zip(&weights).map(|(r, w)| w * big(*r) * big(*r)).collect();
+            let std = (sum(&terms) / sum(&weights)).sqrt();
+            let z = big(parametric_z(confidence));
+            Some((&z * &std, std * density(&z) / alpha))
+        }
+        _ => None,
+    }
+}
+
+fn relative_error(computed: f64, reference: f64) -> f64 {
+    let gap = (computed - reference).abs();
+    if reference == 0.0 {
+        gap
+    } else {
+        gap / reference.abs()
+    }
+}
+
+fn compare(checked: &'static str, computed: Vec<f64>, reference: Vec<f64>) -> Verification {
+    let max_relative_error =
+        computed.iter().zip(&reference).map(|(c, r)| relative_error(*c, *r)).fold(0.0, f64::max);
+    Verification {
+        precision_bits: PRECISION_BITS,
+        tolerance: TOLERANCE,
+        checked,
+        reference,
+        computed,
+        passed: max_relative_error <= TOLERANCE,
+        max_relative_error,
+    }
+}
+
+/// Recomputes one-day `var` and `es` of `returns` (as the method saw them,
+/// chronological) in high precision. Monte Carlo checks the mean and
+/// standard deviation it simulates from, `moments` as computed in f64.
+pub fn check(
+    method: &str,
+    returns: &[f64],
+    confidence: f64,
+    params: &MethodParams,
+    var: f64,
+    es: f64,
+    moments: (f64, f64),
+) -> Verification {
+    match reference(method, returns, confidence, params) {
+        Some((ref_var, ref_es)) => {
+            compare("var_es", vec![var, es], vec![ref_var.to_f64().value(), ref_es.to_f64().value()])
+        }
+        None => {
+            let (mean, std) = mean_std(returns);
+            compare("moments", vec![moments.0, moments.1], vec![mean.to_f64().value(), std.to_f64().value()])
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+pub const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Risk methodology version: bump the major component whenever the same
+/// inputs would produce different numbers (estimator, default or convention
+/// changes), the minor component when methods or outputs are added.
+pub const METHODOLOGY_VERSION: &str = "5.3";
+
+// Stamped on every computed result so stored numbers can be traced to the
+// code that produced them
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Engine {
+    pub version: String,
+    pub methodology: String,
+}
+
+pub fn current() -> Engine {
+    Engine {
+        version: CRATE_VERSION.to_string(),
+        methodology: METHODOLOGY_VERSION.to_string(),
+    }
+}
+
+/// Version of the running engine
+pub async fn version_handler() -> Json<Engine> {
+    Json(current())
+}
@@ -0,0 +1,306 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Mutex};
+use validator::Validate;
+
+use crate::{error::ApiError, load_prices, tenant::Tenant, trash, validation::ValidQuery, version, AppState};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Watchlist {
+    pub id: String,
+    pub tenant: String,
+    pub name: String,
+    pub tickers: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    // Set while the list sits in the trash awaiting restore or purge
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purge_at: Option<DateTime<Utc>>,
+}
+
+// Payload to create or replace a watchlist
+#[derive(Deserialize)]
+pub struct WatchlistBody {
+    name: String,
+    tickers: Vec<String>,
+}
+
+impl WatchlistBody {
+    fn normalized_tickers(&self) -> Result<Vec<String>, ApiError> {
+        let mut tickers: Vec<String> = Vec::new();
+        for t in &self.tickers {
+            let t = t.trim().to_uppercase();
+            if t.is_empty() {
+                return Err(ApiError::bad_request("tickers must not be blank"));
+            }
+            if !tickers.contains(&t) {
+                tickers.push(t);
+            }
+        }
+        Ok(tickers)
+    }
+}
+
+#[derive(Default)]
+pub struct WatchlistStore {
+    lists: Mutex<HashMap<String, Watchlist>>,
+}
+
+impl WatchlistStore {
+    pub fn all(&self) -> Vec<Watchlist> {
+        self.lists.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn restore(&self, list: Watchlist) {
+        self.lists.lock().unwrap().insert(list.id.clone(), list);
+    }
+
+    /// Drops lists whose trash retention has run out by `now`.
+    pub fn purge(&self, now: DateTime<Utc>) -> usize {
+        let mut lists = self.lists.lock().unwrap();
+        let before = lists.len();
+        lists.retain(|_, w| w.purge_at.is_none_or(|at| at > now));
+        before - lists.len()
+    }
+
+    fn find(&self, tenant: &str, id: &str, deleted: bool) -> Result<Watchlist, ApiError> {
+        self.lists
+            .lock()
+            .unwrap()
+            .get(id)
+            .filter(|w| w.tenant == tenant && w.deleted_at.is_some() == deleted)
+            .cloned()
+            .ok_or_else(|| ApiError::not_found(format!("watchlist {id} not found")))
+    }
+
+    fn get(&self, tenant: &str, id: &str) -> Result<Watchlist, ApiError> {
+        self.find(tenant, id, false)
+    }
+
+    /// Moves a live list to the trash until `trash::purge_at`.
+    fn trash(&self, tenant: &str, id: &str, now: DateTime<Utc>) -> Result<(), ApiError> {
+        let mut list = self.get(tenant, id)?;
+        list.deleted_at = Some(now);
+        list.purge_at = Some(trash::purge_at(now));
+        self.restore(list);
+        Ok(())
+    }
+
+    /// Takes a list back out of the trash.
+    fn untrash(&self, tenant: &str, id: &str) -> Result<Watchlist, ApiError> {
+        let mut list = self.find(tenant, id, true)?;
+        list.deleted_at = None;
+        list.purge_at = None;
+        list.updated_at = Utc::now();
+        self.restore(list.clone());
+        Ok(list)
+    }
+}
+
+// Query for listing watchlists
+#[derive(Deserialize)]
+pub struct ListQuery {
+    // List the trash instead of live watchlists
+    #[serde(default)]
+    deleted: bool,
+}
+
+/// Watchlists owned by the caller's tenant
+pub async fn list_watchlists_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Query(q): Query<ListQuery>,
+) -> Json<Vec<Watchlist>> {
+    let mut lists: Vec<Watchlist> = state
+        .watchlists
+        .all()
+        .into_iter()
+        .filter(|w| w.tenant == tenant && w.deleted_at.is_some() == q.deleted)
+        .collect();
+    lists.sort_by_key(|w| w.created_at);
+    Json(lists)
+}
+
+/// Create a watchlist
+pub async fn create_watchlist_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Json(body): Json<WatchlistBody>,
+) -> Result<(StatusCode, Json<Watchlist>), ApiError> {
+    let now = Utc::now();
+    let list = Watchlist {
+        id: format!("{:016x}", rand::random::<u64>()),
+        tenant,
+        tickers: body.normalized_tickers()?,
+        name: body.name,
+        created_at: now,
+        updated_at: now,
+        deleted_at: None,
+        purge_at: None,
+    };
+    state.watchlists.restore(list.clone());
+    Ok((StatusCode::CREATED, Json(list)))
+}
+
+/// Fetch one watchlist
+pub async fn get_watchlist_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+) -> Result<Json<Watchlist>, ApiError> {
+    state.watchlists.get(&tenant, &id).map(Json)
+}
+
+/// Replace a watchlist's name and tickers
+pub async fn update_watchlist_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+    Json(body): Json<WatchlistBody>,
+) -> Result<Json<Watchlist>, ApiError> {
+    let mut list = state.watchlists.get(&tenant, &id)?;
+    list.tickers = body.normalized_tickers()?;
+    list.name = body.name;
+    list.updated_at = Utc::now();
+    state.watchlists.restore(list.clone());
+    Ok(Json(list))
+}
+
+/// Move a watchlist to the trash; it stays restorable until purged
+pub async fn delete_watchlist_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state.watchlists.trash(&tenant, &id, Utc::now())?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Take a watchlist back out of the trash
+pub async fn restore_watchlist_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+) -> Result<Json<Watchlist>, ApiError> {
+    Ok(Json(state.watchlists.untrash(&tenant, &id)?))
+}
+
+// Query for watchlist risk
+#[derive(Deserialize, Validate)]
+pub struct RiskQuery {
+    #[serde(default = "default_confidence")]
+    #[validate(range(exclusive_min = 0.0, exclusive_max = 1.0, message = "confidence must be in (0, 1)"))]
+    confidence: f64,
+}
+
+fn default_confidence() -> f64 {
+    0.95
+}
+
+// Risk line for one ticker on a watchlist
+#[derive(Serialize)]
+struct TickerRisk {
+    ticker: String,
+    as_of: Option<String>,
+    var: Option<f64>,
+    vol: Option<f64>,
+    move_1d: Option<f64>,
+    observations: usize,
+}
+
+async fn ticker_risk(state: &AppState, tenant: &str, ticker: &str, confidence: f64) -> TickerRisk {
+    let mut snap = state.rolling.snapshot(ticker, confidence);
+    if snap.is_none() {
+        state.rolling.ingest(&*state.prices, ticker, &[]).await;
+        snap = state.rolling.snapshot(ticker, confidence);
+    }
+    if snap.as_ref().is_none_or(|s| s.count == 0) {
+        load_prices(state, tenant, ticker).await;
+        snap = state.rolling.snapshot(ticker, confidence);
+    }
+
+    match snap.filter(|s| s.count > 0) {
+        Some(s) => TickerRisk {
+            ticker: ticker.to_string(),
+            as_of: s.last_ts,
+            var: s.var,
+            vol: Some(s.std),
+            move_1d: s.last_return,
+            observations: s.count,
+        },
+        None => TickerRisk {
+            ticker: ticker.to_string(),
+            as_of: None,
+            var: None,
+            vol: None,
+            move_1d: None,
+            observations: 0,
+        },
+    }
+}
+
+/// Per-ticker historical VaR, daily vol and last 1-day move for a watchlist
+pub async fn watchlist_risk_handler(
+    State(state): State<AppState>,
+    Tenant(tenant): Tenant,
+    Path(id): Path<String>,
+    ValidQuery(q): ValidQuery<RiskQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let list = state.watchlists.get(&tenant, &id)?;
+    let rows = join_all(list.tickers.iter().map(|t| ticker_risk(&state, &tenant, t, q.confidence))).await;
+    Ok(Json(json!({
+        "watchlist": list.id,
+        "name": list.name,
+        "confidence": q.confidence,
+        "tickers": rows,
+        "engine": version::current(),
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn a_trashed_list_can_be_restored_until_it_is_purged() {
+        let store = WatchlistStore::default();
+        let created = Utc::now();
+        let list = Watchlist {
+            id: "tech".into(),
+            tenant: "acme".into(),
+            name: "Tech".into(),
+            tickers: vec!["AAPL".into(), "MSFT".into()],
+            created_at: created,
+            updated_at: created,
+            deleted_at: None,
+            purge_at: None,
+        };
+        store.restore(list);
+
+        store.trash("acme", "tech", created).unwrap();
+        assert_eq!(store.get("acme", "tech").err().unwrap().status, StatusCode::NOT_FOUND);
+        assert_eq!(store.trash("acme", "tech", created).err().unwrap().status, StatusCode::NOT_FOUND);
+        assert!(store.untrash("other", "tech").is_err());
+
+        let restored = store.untrash("acme", "tech").unwrap();
+        assert_eq!((restored.deleted_at, restored.purge_at), (None, None));
+        assert_eq!(store.get("acme", "tech").unwrap().tickers, ["AAPL", "MSFT"]);
+
+        // Purged only once its retention has run out
+        store.trash("acme", "tech", created).unwrap();
+        let purge_at = trash::purge_at(created);
+        assert_eq!(store.purge(purge_at - Duration::seconds(1)), 0);
+        assert_eq!(store.purge(purge_at), 1);
+        assert!(store.untrash("acme", "tech").is_err());
+    }
+}
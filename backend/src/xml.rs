@@ -0,0 +1,171 @@
+//! Just enough XML for the trade and position formats the import accepts:
+//! elements, attributes and text, with namespace prefixes dropped. No DTDs,
+//! CDATA or processing beyond skipping declarations and comments.
+
+/// A start or self-closing tag with its attributes, or an end tag.
+pub struct Tag {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub closing: bool,
+    pub self_closing: bool,
+}
+
+impl Tag {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+}
+
+// A tag, or the text between two tags
+enum Token {
+    Tag(Tag),
+    Text(String),
+}
+
+fn local(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn parse_tag(inner: &str) -> Result<Tag, String> {
+    let closing = inner.starts_with('/');
+    let self_closing = inner.ends_with('/');
+    let inner = inner.trim_start_matches('/').trim_end_matches('/').trim();
+    let name_end = inner.find(char::is_whitespace).unwrap_or(inner.len());
+    let name = local(&inner[..name_end]).to_string();
+    let mut attributes = Vec::new();
+    let mut rest = inner[name_end..].trim_start();
+    while !rest.is_empty() {
+        let eq = rest.find('=').ok_or_else(|| format!("malformed attribute in <{name}>"))?;
+        let key = local(rest[..eq].trim()).to_string();
+        let after = rest[eq + 1..].trim_start();
+        let quote = after.chars().next().filter(|c| *c == '"' || *c == '\'');
+        let quote = quote.ok_or_else(|| format!("unquoted attribute {key} in <{name}>"))?;
+        let close = after[1..].find(quote).ok_or_else(|| format!("unterminated attribute {key} in <{name}>"))?;
+        attributes.push((key, unescape(&after[1..=close])));
+        rest = after[close + 2..].trim_start();
+    }
+    Ok(Tag { name, attributes, closing, self_closing })
+}
+
+fn tokens(xml: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = xml;
+    while let Some(open) = rest.find('<') {
+        let text = rest[..open].trim();
+        if !text.is_empty() {
+            tokens.push(Token::Text(unescape(text)));
+        }
+        rest = &rest[open..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").ok_or("unterminated comment")?;
+            rest = &comment[end + 3..];
+            continue;
+        }
+        let end = rest.find('>').ok_or("unterminated tag")?;
+        let inner = &rest[1..end];
+        if !inner.starts_with('?') && !inner.starts_with('!') {
+            tokens.push(Token::Tag(parse_tag(inner)?));
+        }
+        rest = &rest[end + 1..];
+    }
+    Ok(tokens)
+}
+
+/// Every element tag in document order.
+pub fn tags(xml: &str) -> Result<Vec<Tag>, String> {
+    Ok(tokens(xml)?
+        .into_iter()
+        .filter_map(|t| match t {
+            Token::Tag(tag) => Some(tag),
+            Token::Text(_) => None,
+        })
+        .collect())
+}
+
+/// An element with its attributes, child elements and text.
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    pub text: String,
+}
+
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    /// The element reached by following child names, e.g. `["strike", "strikePrice"]`.
+    pub fn path(&self, names: &[&str]) -> Option<&Element> {
+        names.iter().try_fold(self, |e, name| e.child(name))
+    }
+
+    /// The first element of this name at any depth, searching depth first.
+    pub fn find(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find_map(|c| if c.name == name { Some(c) } else { c.find(name) })
+    }
+
+    /// Every element of this name at any depth, in document order.
+    pub fn find_all<'a>(&'a self, name: &str, out: &mut Vec<&'a Element>) {
+        for c in &self.children {
+            if c.name == name {
+                out.push(c);
+            }
+            c.find_all(name, out);
+        }
+    }
+}
+
+/// The document's root element.
+pub fn parse(xml: &str) -> Result<Element, String> {
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root = None;
+    for token in tokens(xml)? {
+        match token {
+            Token::Text(text) => {
+                if let Some(open) = stack.last_mut() {
+                    open.text.push_str(&text);
+                }
+            }
+            Token::Tag(tag) if tag.closing => {
+                let element = stack.pop().ok_or_else(|| format!("unexpected </{}>", tag.name))?;
+                if element.name != tag.name {
+                    return Err(format!("<{}> closed by </{}>", element.name, tag.name));
+                }
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => root = Some(element),
+                }
+            }
+            Token::Tag(tag) => {
+                let element =
+                    Element { name: tag.name, attributes: tag.attributes, children: Vec::new(), text: String::new() };
+                if !tag.self_closing {
+                    stack.push(element);
+                } else {
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => root = Some(element),
+                    }
+                }
+            }
+        }
+    }
+    if let Some(open) = stack.last() {
+        return Err(format!("unterminated <{}>", open.name));
+    }
+    root.ok_or_else(|| "no root element".to_string())
+}